@@ -2,6 +2,7 @@
 use std::env;
 use std::fs;
 use std::path::Path;
+use std::process::Command;
 
 fn main() {
     // 读取默认配置文件
@@ -20,4 +21,31 @@ fn main() {
         ),
     )
     .unwrap();
+
+    // 版本信息：git 提交、构建日期和目标平台
+    let git_commit = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=GIT_COMMIT={}", git_commit);
+    println!(
+        "cargo:rustc-env=BUILD_DATE={}",
+        chrono::Utc::now().format("%Y-%m-%d")
+    );
+    println!(
+        "cargo:rustc-env=BUILD_TARGET={}",
+        env::var("TARGET").unwrap()
+    );
+
+    // gRPC 接口，使用随依赖下载的 protoc，无需单独安装
+    env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().unwrap());
+    tonic_build::configure()
+        .build_client(false)
+        // 不输出 rerun-if-changed，保持修改任意文件后重新运行构建脚本（默认配置和 git 提交）
+        .emit_rerun_if_changed(false)
+        .compile_protos(&["proto/process_guard.proto"], &["proto"])
+        .expect("Failed to compile proto/process_guard.proto");
 }
@@ -0,0 +1,57 @@
+/* dwm_monitor.dll 的 C 接口，实现见 src/ffi.rs。字符串均为以 NUL 结尾的 UTF-8。 */
+#ifndef DWM_MONITOR_H
+#define DWM_MONITOR_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define DWM_OK 0
+#define DWM_INVALID_ARGUMENT (-1)
+#define DWM_NOT_FOUND (-2)
+#define DWM_ENUM_FAILED (-3)
+
+/* 内存单位为字节；private_bytes 即任务管理器的“提交大小”，阈值与它比较。 */
+typedef struct DwmProcessMemory {
+    uint32_t pid;
+    int32_t thread_count;
+    uint64_t private_bytes;
+    uint64_t working_set;
+    uint64_t pagefile_usage;
+} DwmProcessMemory;
+
+/* 只在回调期间有效；进程不存在时 pid 为 0。 */
+typedef struct DwmSample {
+    const char *name;
+    uint32_t pid;
+    uint64_t private_bytes;
+    uint64_t working_set;
+    uint64_t threshold_bytes;
+    bool exceeds_threshold;
+} DwmSample;
+
+typedef struct DwmMonitor DwmMonitor;
+
+/* 在监控线程上调用，user_data 原样传回。 */
+typedef void (*DwmSampleCallback)(const DwmSample *sample, void *user_data);
+
+int32_t dwm_get_process_memory(uint32_t pid, DwmProcessMemory *out);
+
+/* 不区分大小写，有多个同名进程时返回第一个。 */
+int32_t dwm_find_process_by_name(const char *name, DwmProcessMemory *out);
+
+/* restart 为 true 时超过阈值后结束进程并等待系统重新拉起；参数无效时返回 NULL。 */
+DwmMonitor *dwm_start_monitor(const char *name, uint64_t threshold_bytes, uint32_t interval_ms,
+                              bool restart, DwmSampleCallback callback, void *user_data);
+
+/* 等待监控线程退出后返回，之后不会再调用回调；不能在回调中调用。 */
+void dwm_stop_monitor(DwmMonitor *monitor);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -2,19 +2,21 @@ import shutil
 import os
 import subprocess
 
-# 定义源文件路径和目标文件路径
-exe_src = './target/release/process_guard.exe'
-exe_dst = './pack/process_guard.exe'
+# 需要打包的程序：服务和托盘程序
+exe_names = ['process_guard.exe', 'process_guard_tray.exe']
 
 # 定义 ISS 文件路径
 iss_file = './pack/pack_dwm_monitor.iss'  # 确保这个路径指向你的 .iss 文件
 
 # 复制文件
-try:
-    shutil.copy(exe_src, exe_dst)
-    print(f"Copied {exe_src} to {exe_dst}")
-except FileNotFoundError:
-    print(f"{exe_src} not found. Make sure to run 'just build' first.")
+for exe_name in exe_names:
+    exe_src = f'./target/release/{exe_name}'
+    exe_dst = f'./pack/{exe_name}'
+    try:
+        shutil.copy(exe_src, exe_dst)
+        print(f"Copied {exe_src} to {exe_dst}")
+    except FileNotFoundError:
+        print(f"{exe_src} not found. Make sure to run 'just build' first.")
 
 # 打包 .exe 文件
 if os.path.exists(iss_file):
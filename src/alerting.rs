@@ -0,0 +1,599 @@
+//! 值班告警：进程进入 `Backoff`（重启失败或重启后内存仍超过阈值）时在 PagerDuty / Opsgenie 中打开事件，
+//! 恢复到 `Healthy` 或 `Warning` 后自动解决。每个进程使用固定的去重键，重复检查不会打开多个事件。
+//! 设置 `suppress_restarts_when_reboot_pending` 且系统待重启时，事件以较低的级别打开。
+//! 设置 `quiet_hours` 时，免打扰时段内不打开事件，结束时每个渠道发送一条低级别的汇总。
+
+use log::info;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::io;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use crate::config_manager::Config;
+use crate::maintenance::DailyWindow;
+use crate::monitor::TargetState;
+use crate::monitor_state::{self, MonitorStatus, ProcessStatus};
+use crate::process_manager;
+use crate::reporter::machine_name;
+use crate::sink::{blocking, run_sink, FailureLog, Sink};
+use crate::templates::{self, Message};
+use crate::tr;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const PAGERDUTY_EVENTS_URL: &str = "https://events.pagerduty.com/v2/enqueue";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transition {
+    Trigger,
+    Resolve,
+}
+
+/// 按最新状态决定要发送的事件，`open` 为已打开事件的进程。
+pub fn transitions<'a>(
+    status: &'a MonitorStatus,
+    open: &HashSet<String>,
+) -> Vec<(&'a ProcessStatus, Transition)> {
+    status
+        .processes
+        .iter()
+        .filter_map(|process| {
+            let is_open = open.contains(&process.name);
+            let transition = match process.state {
+                TargetState::Backoff if !is_open => Transition::Trigger,
+                TargetState::Healthy | TargetState::Warning if is_open => Transition::Resolve,
+                _ => return None,
+            };
+            Some((process, transition))
+        })
+        .collect()
+}
+
+fn dedup_key(host: &str, process: &str) -> String {
+    format!("process_guard:{}:{}", host, process)
+}
+
+fn summary(host: &str, process: &ProcessStatus, reboot_pending: bool) -> String {
+    // 多用户主机上说明影响了哪些会话和用户
+    let sessions = if process.restart_sessions.is_empty() {
+        String::new()
+    } else {
+        format!(" for {}", process.restart_sessions.join(", "))
+    };
+    let values = [
+        ("name", process.name.clone()),
+        ("host", host.to_string()),
+        ("sessions", process.restart_sessions.join(", ")),
+        (
+            "private_mb",
+            (process.private_bytes / 1024 / 1024).to_string(),
+        ),
+        (
+            "working_set_mb",
+            (process.working_set / 1024 / 1024).to_string(),
+        ),
+        (
+            "threshold_mb",
+            (process.memory_threshold_bytes / 1024 / 1024).to_string(),
+        ),
+    ];
+    let summary = templates::format(Message::AlertSummary, &values, || {
+        format!(
+            "{} on {}{} could not be brought under its memory threshold by restarting ({} MB / {} MB)",
+            process.name,
+            host,
+            sessions,
+            process.private_bytes / 1024 / 1024,
+            process.memory_threshold_bytes / 1024 / 1024,
+        )
+    });
+    if reboot_pending {
+        format!("{}; a pending OS reboot should clear it", summary)
+    } else {
+        summary
+    }
+}
+
+// Opsgenie 的 details 只接受字符串值
+fn details(process: &ProcessStatus) -> Value {
+    json!({
+        "private_bytes": process.private_bytes.to_string(),
+        "working_set": process.working_set.to_string(),
+        "threshold_bytes": process.memory_threshold_bytes.to_string(),
+        "last_restart": process.last_restart.map(|t| t.to_string()).unwrap_or_default(),
+        "sessions": process.restart_sessions.join(", "),
+    })
+}
+
+/// PagerDuty Events API v2 的请求体，`reboot_pending` 时严重级别降为 `warning`。
+pub fn pagerduty_event(
+    routing_key: &str,
+    host: &str,
+    process: &ProcessStatus,
+    transition: Transition,
+    reboot_pending: bool,
+) -> Value {
+    let dedup_key = dedup_key(host, &process.name);
+    match transition {
+        Transition::Trigger => json!({
+            "routing_key": routing_key,
+            "event_action": "trigger",
+            "dedup_key": dedup_key,
+            "payload": {
+                "summary": summary(host, process, reboot_pending),
+                "source": host,
+                "severity": if reboot_pending { "warning" } else { "error" },
+                "component": process.name,
+                "custom_details": details(process),
+            },
+        }),
+        Transition::Resolve => json!({
+            "routing_key": routing_key,
+            "event_action": "resolve",
+            "dedup_key": dedup_key,
+        }),
+    }
+}
+
+fn digest_summary(host: &str, lines: &[String]) -> String {
+    format!(
+        "{} notification(s) held on {} during quiet hours",
+        lines.len(),
+        host
+    )
+}
+
+/// 免打扰时段结束时的汇总，严重级别为 `info`；不指定去重键，每次汇总都是新的事件。
+pub fn pagerduty_digest(routing_key: &str, host: &str, lines: &[String]) -> Value {
+    json!({
+        "routing_key": routing_key,
+        "event_action": "trigger",
+        "payload": {
+            "summary": digest_summary(host, lines),
+            "source": host,
+            "severity": "info",
+            "component": "process_guard",
+            "custom_details": { "events": lines },
+        },
+    })
+}
+
+// 去重键包含进程名，作为 Opsgenie 的路径参数时需要编码
+fn percent_encode(value: &str) -> String {
+    let mut encoded = String::new();
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+fn post(url: &str, authorization: Option<&str>, body: &Value) -> io::Result<()> {
+    let mut request = ureq::AgentBuilder::new()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .post(url);
+    if let Some(authorization) = authorization {
+        request = request.set("Authorization", authorization);
+    }
+    request
+        .set("Content-Type", "application/json")
+        .send_string(&body.to_string())
+        .map_err(|e| io::Error::other(e.to_string()))?;
+    Ok(())
+}
+
+fn send_pagerduty(
+    routing_key: &str,
+    host: &str,
+    process: &ProcessStatus,
+    transition: Transition,
+    reboot_pending: bool,
+) -> io::Result<()> {
+    post(
+        PAGERDUTY_EVENTS_URL,
+        None,
+        &pagerduty_event(routing_key, host, process, transition, reboot_pending),
+    )
+}
+
+fn send_opsgenie(
+    api_url: &str,
+    api_key: &str,
+    host: &str,
+    process: &ProcessStatus,
+    transition: Transition,
+    reboot_pending: bool,
+) -> io::Result<()> {
+    let api_url = api_url.trim_end_matches('/');
+    let authorization = format!("GenieKey {}", api_key);
+    let alias = dedup_key(host, &process.name);
+    match transition {
+        Transition::Trigger => post(
+            &format!("{}/v2/alerts", api_url),
+            Some(&authorization),
+            &json!({
+                "message": summary(host, process, reboot_pending),
+                "alias": alias,
+                "source": "process_guard",
+                "entity": host,
+                "priority": if reboot_pending { "P4" } else { "P2" },
+                "details": details(process),
+            }),
+        ),
+        Transition::Resolve => post(
+            &format!(
+                "{}/v2/alerts/{}/close?identifierType=alias",
+                api_url,
+                percent_encode(&alias)
+            ),
+            Some(&authorization),
+            &json!({ "source": "process_guard", "note": "Recovered" }),
+        ),
+    }
+}
+
+fn send_opsgenie_digest(
+    api_url: &str,
+    api_key: &str,
+    host: &str,
+    lines: &[String],
+) -> io::Result<()> {
+    post(
+        &format!("{}/v2/alerts", api_url.trim_end_matches('/')),
+        Some(&format!("GenieKey {}", api_key)),
+        &json!({
+            "message": digest_summary(host, lines),
+            "description": lines.join("\n"),
+            "source": "process_guard",
+            "entity": host,
+            "priority": "P5",
+        }),
+    )
+}
+
+// 一个告警渠道及其已打开事件的进程，发送成功后才更新，失败的下一轮重试
+struct Channel {
+    name: &'static str,
+    open: HashSet<String>,
+    failures: FailureLog,
+    // 免打扰时段内暂不打开事件的进程，以及结束时要汇总发送的记录
+    held: HashSet<String>,
+    digest: Vec<String>,
+}
+
+impl Channel {
+    fn new(name: &'static str) -> Channel {
+        Channel {
+            name,
+            open: HashSet::new(),
+            failures: FailureLog::default(),
+            held: HashSet::new(),
+            digest: Vec::new(),
+        }
+    }
+
+    // 免打扰时段内记录进入 Backoff 和之后恢复的进程，`time` 为当前的本地时间
+    fn hold(&mut self, status: &MonitorStatus, time: &str) {
+        for process in &status.processes {
+            let held = self.held.contains(&process.name);
+            match process.state {
+                TargetState::Backoff if !held && !self.open.contains(&process.name) => {
+                    info!(
+                        "{}",
+                        tr!(
+                            "免打扰时段，暂不在 {} 中打开 {} 的事件",
+                            "Quiet hours, not opening {} incident for {} yet",
+                            self.name,
+                            process.name
+                        )
+                    );
+                    self.held.insert(process.name.clone());
+                    self.digest.push(format!(
+                        "{} {} could not be brought under its memory threshold ({} MB / {} MB)",
+                        time,
+                        process.name,
+                        process.private_bytes / 1024 / 1024,
+                        process.memory_threshold_bytes / 1024 / 1024
+                    ));
+                }
+                TargetState::Healthy | TargetState::Warning if held => {
+                    self.held.remove(&process.name);
+                    self.digest
+                        .push(format!("{} {} recovered", time, process.name));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn failed(&mut self, subject: &str, e: io::Error) {
+        self.failures.failed(|| {
+            tr!(
+                "向 {} 发送 {} 的事件失败: {}",
+                "Failed to send {} event for {}: {}",
+                self.name,
+                subject,
+                e
+            )
+        });
+    }
+
+    fn succeeded(&mut self) {
+        self.failures.succeeded(|| {
+            tr!(
+                "已恢复向 {} 发送事件",
+                "Resumed sending events to {}",
+                self.name
+            )
+        });
+    }
+
+    fn update(
+        &mut self,
+        status: &MonitorStatus,
+        quiet: Option<&str>,
+        send: impl Fn(&ProcessStatus, Transition) -> io::Result<()>,
+        send_digest: impl Fn(&[String]) -> io::Result<()>,
+    ) {
+        match quiet {
+            Some(time) => self.hold(status, time),
+            None if !self.digest.is_empty() => match send_digest(&self.digest) {
+                Ok(()) => {
+                    info!(
+                        "{}",
+                        tr!(
+                            "免打扰时段结束，已向 {} 发送 {} 条汇总记录",
+                            "Quiet hours ended, sent a digest of {1} entries to {0}",
+                            self.name,
+                            self.digest.len()
+                        )
+                    );
+                    self.digest.clear();
+                    self.held.clear();
+                    self.succeeded();
+                }
+                Err(e) => self.failed("quiet hours digest", e),
+            },
+            None => {}
+        }
+        for (process, transition) in transitions(status, &self.open) {
+            // 解决事件不会在夜里吵醒值班人员，照常发送
+            if quiet.is_some() && transition == Transition::Trigger {
+                continue;
+            }
+            match send(process, transition) {
+                Ok(()) => {
+                    match transition {
+                        Transition::Trigger => {
+                            self.open.insert(process.name.clone());
+                            info!(
+                                "{}",
+                                tr!(
+                                    "已在 {} 中打开 {} 的事件",
+                                    "Opened {} incident for {}",
+                                    self.name,
+                                    process.name
+                                )
+                            );
+                        }
+                        Transition::Resolve => {
+                            self.open.remove(&process.name);
+                            info!(
+                                "{}",
+                                tr!(
+                                    "已在 {} 中解决 {} 的事件",
+                                    "Resolved {} incident for {}",
+                                    self.name,
+                                    process.name
+                                )
+                            );
+                        }
+                    }
+                    self.succeeded();
+                }
+                Err(e) => self.failed(&process.name, e),
+            }
+        }
+    }
+}
+
+struct Alerting {
+    channels: Option<(Channel, Channel)>,
+}
+
+impl Sink for Alerting {
+    fn interval_seconds(config: &Config) -> u64 {
+        config.interval_seconds
+    }
+
+    // 各渠道分别记录失败
+    async fn round(&mut self, config: &Mutex<Config>, _failures: &mut FailureLog) {
+        let (routing_key, opsgenie_api_url, opsgenie_api_key, check_reboot, quiet_hours) = {
+            let config = config.lock().unwrap_or_else(PoisonError::into_inner);
+            (
+                config.pagerduty_routing_key.clone(),
+                config.opsgenie_api_url.clone(),
+                config.opsgenie_api_key.clone(),
+                config.suppress_restarts_when_reboot_pending,
+                Some(config.quiet_hours.as_str())
+                    .filter(|quiet_hours| !quiet_hours.is_empty())
+                    .and_then(|quiet_hours| DailyWindow::parse(quiet_hours).ok()),
+            )
+        };
+        if routing_key.is_empty() && opsgenie_api_key.is_empty() {
+            return;
+        }
+        let status = monitor_state::snapshot();
+        let mut channels = self
+            .channels
+            .take()
+            .unwrap_or_else(|| (Channel::new("PagerDuty"), Channel::new("Opsgenie")));
+        // 发送请求会阻塞，放到阻塞线程池中执行
+        self.channels = blocking(move || {
+            let host = machine_name();
+            let reboot_pending = check_reboot && process_manager::reboot_pending();
+            let now = chrono::Local::now();
+            let time = now.format("%H:%M").to_string();
+            let quiet = quiet_hours
+                .is_some_and(|quiet_hours| quiet_hours.contains(now.time()))
+                .then_some(time.as_str());
+            if !routing_key.is_empty() {
+                channels.0.update(
+                    &status,
+                    quiet,
+                    |process, transition| {
+                        send_pagerduty(&routing_key, &host, process, transition, reboot_pending)
+                    },
+                    |lines| {
+                        post(
+                            PAGERDUTY_EVENTS_URL,
+                            None,
+                            &pagerduty_digest(&routing_key, &host, lines),
+                        )
+                    },
+                );
+            }
+            if !opsgenie_api_key.is_empty() {
+                channels.1.update(
+                    &status,
+                    quiet,
+                    |process, transition| {
+                        send_opsgenie(
+                            &opsgenie_api_url,
+                            &opsgenie_api_key,
+                            &host,
+                            process,
+                            transition,
+                            reboot_pending,
+                        )
+                    },
+                    |lines| {
+                        send_opsgenie_digest(&opsgenie_api_url, &opsgenie_api_key, &host, lines)
+                    },
+                );
+            }
+            channels
+        })
+        .await;
+    }
+}
+
+/// 未配置告警渠道时空转，配置重新加载后下一轮生效；服务停止时返回。
+/// 服务重启前打开的事件不会被自动解决。
+pub async fn run(config: Arc<Mutex<Config>>) {
+    run_sink(config, Alerting { channels: None }).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(name: &str, state: TargetState) -> ProcessStatus {
+        ProcessStatus {
+            name: name.to_string(),
+            state,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_transitions_trigger_on_backoff_and_resolve_on_recovery() {
+        let status = MonitorStatus {
+            processes: vec![
+                process("dwm.exe", TargetState::Backoff),
+                process("explorer.exe", TargetState::Healthy),
+                process("photos.exe", TargetState::Discovering),
+            ],
+            ..Default::default()
+        };
+        let names = |open: &HashSet<String>| {
+            transitions(&status, open)
+                .into_iter()
+                .map(|(process, transition)| (process.name.as_str(), transition))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(&HashSet::new()),
+            vec![("dwm.exe", Transition::Trigger)]
+        );
+        // 已打开的事件在恢复前不重复打开，进程消失不算恢复
+        let open = ["dwm.exe", "explorer.exe", "photos.exe"]
+            .map(String::from)
+            .into();
+        assert_eq!(names(&open), vec![("explorer.exe", Transition::Resolve)]);
+    }
+
+    #[test]
+    fn test_pagerduty_event_uses_stable_dedup_key() {
+        let process = ProcessStatus {
+            restart_sessions: vec!["session 2 (DOMAIN\\alice)".to_string()],
+            ..process("dwm.exe", TargetState::Backoff)
+        };
+        let trigger = pagerduty_event("key", "KIOSK-01", &process, Transition::Trigger, false);
+        let resolve = pagerduty_event("key", "KIOSK-01", &process, Transition::Resolve, false);
+        assert_eq!(trigger["event_action"], "trigger");
+        assert_eq!(trigger["payload"]["severity"], "error");
+        assert!(trigger["payload"]["summary"]
+            .as_str()
+            .unwrap()
+            .starts_with("dwm.exe on KIOSK-01 for session 2 (DOMAIN\\alice) could not"));
+        assert_eq!(trigger["payload"]["component"], "dwm.exe");
+        assert_eq!(resolve["event_action"], "resolve");
+        assert_eq!(trigger["dedup_key"], resolve["dedup_key"]);
+        assert_eq!(
+            percent_encode("process_guard:KIOSK-01:my app.exe"),
+            "process_guard%3AKIOSK-01%3Amy%20app.exe"
+        );
+
+        let downgraded = pagerduty_event("key", "KIOSK-01", &process, Transition::Trigger, true);
+        assert_eq!(downgraded["payload"]["severity"], "warning");
+    }
+
+    #[test]
+    fn test_quiet_hours_hold_triggers_until_digest() {
+        let sent = Mutex::new(Vec::new());
+        let send = |process: &ProcessStatus, transition| {
+            sent.lock()
+                .unwrap()
+                .push((process.name.clone(), transition));
+            Ok(())
+        };
+        let digests = Mutex::new(Vec::new());
+        let send_digest = |lines: &[String]| {
+            digests.lock().unwrap().push(lines.to_vec());
+            Ok(())
+        };
+        let status = |states: [TargetState; 2]| MonitorStatus {
+            processes: vec![process("dwm.exe", states[0]), process("app.exe", states[1])],
+            ..Default::default()
+        };
+        let mut channel = Channel::new("PagerDuty");
+        let backoff = status([TargetState::Backoff, TargetState::Backoff]);
+        channel.update(&backoff, Some("03:00"), send, send_digest);
+        channel.update(&backoff, Some("03:05"), send, send_digest);
+        let recovered = status([TargetState::Healthy, TargetState::Backoff]);
+        channel.update(&recovered, Some("03:40"), send, send_digest);
+        assert!(sent.lock().unwrap().is_empty());
+
+        // 结束时发送汇总，仍未恢复的进程照常打开事件
+        channel.update(&recovered, None, send, send_digest);
+        assert_eq!(
+            digests.lock().unwrap()[0],
+            vec![
+                "03:00 dwm.exe could not be brought under its memory threshold (0 MB / 0 MB)",
+                "03:00 app.exe could not be brought under its memory threshold (0 MB / 0 MB)",
+                "03:40 dwm.exe recovered",
+            ]
+        );
+        assert_eq!(
+            *sent.lock().unwrap(),
+            vec![("app.exe".to_string(), Transition::Trigger)]
+        );
+        channel.update(&recovered, None, send, send_digest);
+        assert_eq!(digests.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,149 @@
+//! 基线学习：`learn_threshold_hours` 大于 0 的进程在学习期内统计内存的分布，学习期结束时以
+//! p95 × `learn_threshold_factor` 作为阈值，写入运行中的配置和配置文件并结束学习。
+//!
+//! 学习期间仍按配置的 `memory_threshold_bytes` 重启；学习进度保存在状态文件中，服务重启后继续。
+
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Mutex, PoisonError};
+
+use crate::config_manager::{config_file_path, Config, ConfigManager, MonitoredProcess};
+use crate::monitor::Sample;
+use crate::monitor_state;
+use crate::tr;
+
+const MB: u64 = 1024 * 1024;
+const BASELINE_PERCENTILE: u64 = 95;
+/// 学习期的上限（30 天），更长的学习期意义不大，配置了更大的值时按上限计。
+pub const MAX_LEARN_THRESHOLD_HOURS: u64 = 30 * 24;
+
+/// 学习期内的内存分布，按 MB 统计采样次数，长时间学习也不会占用太多空间。
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Baseline {
+    // 开始学习的时间（Unix 时间戳）
+    pub started: u64,
+    pub histogram: BTreeMap<u64, u32>,
+}
+
+impl Baseline {
+    pub fn new(started: u64) -> Baseline {
+        Baseline {
+            started,
+            histogram: BTreeMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, private_bytes: u64) {
+        *self.histogram.entry(private_bytes / MB).or_default() += 1;
+    }
+
+    pub fn samples(&self) -> u64 {
+        self.histogram.values().map(|count| *count as u64).sum()
+    }
+
+    /// 不低于 `percent`% 采样的内存（字节，按所在 MB 的上限计），没有采样时为 `None`。
+    pub fn percentile(&self, percent: u64) -> Option<u64> {
+        let rank = (self.samples() * percent).div_ceil(100).max(1);
+        let mut seen = 0;
+        self.histogram.iter().find_map(|(mb, count)| {
+            seen += *count as u64;
+            (seen >= rank).then_some((mb + 1) * MB)
+        })
+    }
+
+    pub fn threshold(&self, factor: f64) -> Option<u64> {
+        self.percentile(BASELINE_PERCENTILE)
+            .map(|p95| (p95 as f64 * factor) as u64)
+    }
+
+    pub fn finished(&self, hours: u64, now: u64) -> bool {
+        now.saturating_sub(self.started) >= hours.min(MAX_LEARN_THRESHOLD_HOURS) * 3600
+    }
+}
+
+/// 每轮采样后调用：学习期间记录内存，学习期结束时应用学到的阈值。
+pub fn observe(config: &Mutex<Config>, process: &MonitoredProcess, sample: &Sample) {
+    if process.learn_threshold_hours == 0 {
+        monitor_state::clear_baseline(&process.name);
+        return;
+    }
+    if !sample.is_running() {
+        return;
+    }
+    let now = monitor_state::now_secs();
+    let baseline = monitor_state::record_baseline(&process.name, sample.private_bytes, now);
+    if !baseline.finished(process.learn_threshold_hours, now) {
+        return;
+    }
+    if let Some(threshold) = baseline.threshold(process.learn_threshold_factor) {
+        info!(
+            "{}",
+            tr!(
+                "{} 基线学习完成: {} 次采样的 p95 为 {} MB，内存阈值设为 {} MB（原 {} MB）",
+                "{} baseline learning finished: p95 of {} samples is {} MB, memory threshold set to {} MB (was {} MB)",
+                process.name,
+                baseline.samples(),
+                baseline.percentile(BASELINE_PERCENTILE).unwrap_or_default() / MB,
+                threshold / MB,
+                process.memory_threshold_bytes / MB
+            )
+        );
+        apply(config, &process.name, threshold);
+    }
+    monitor_state::clear_baseline(&process.name);
+}
+
+fn learned(process: &mut MonitoredProcess, threshold: u64) {
+    process.memory_threshold_bytes = threshold;
+    process.learn_threshold_hours = 0;
+}
+
+// 与 set-threshold --persist 相同，只修改配置文件中的对应项，避免把运行时的其他状态写回文件
+fn apply(config: &Mutex<Config>, name: &str, threshold: u64) {
+    if let Some(process) = config
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .find_monitor_process_mut(name)
+    {
+        learned(process, threshold);
+    }
+    let config_manager = ConfigManager::new(config_file_path());
+    let result = config_manager.load().and_then(|mut file_config| {
+        match file_config.find_monitor_process_mut(name) {
+            Some(process) => learned(process, threshold),
+            None => return Ok(()),
+        }
+        config_manager.save(&file_config)
+    });
+    if let Err(e) = result {
+        error!(
+            "{}",
+            tr!(
+                "学到的 {} 内存阈值未能写入配置文件，服务重启后将重新学习: {}",
+                "Failed to save the learned {} memory threshold, learning restarts with the service: {}",
+                name,
+                e
+            )
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_baseline_threshold() {
+        let mut baseline = Baseline::new(1000);
+        for mb in 1..=100 {
+            baseline.record(mb * MB + 512);
+        }
+        assert_eq!(baseline.samples(), 100);
+        assert_eq!(baseline.percentile(95), Some(96 * MB));
+        assert_eq!(baseline.threshold(1.5), Some(144 * MB));
+        assert!(!baseline.finished(24, 1000 + 23 * 3600));
+        assert!(baseline.finished(24, 1000 + 24 * 3600));
+        assert_eq!(Baseline::new(0).threshold(1.5), None);
+    }
+}
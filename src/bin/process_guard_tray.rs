@@ -0,0 +1,563 @@
+//! 托盘程序：通过命名管道读取运行中服务的状态，在通知区域显示各进程的内存和近期趋势，
+//! 并提供暂缓重启和立即重启的菜单，普通用户无需查看日志即可了解 dwm 的情况。
+//!
+//! 服务运行在会话 0，看不到用户会话的显示器；托盘程序发现显示配置变化（插拔显示器、分辨率、DPI）时
+//! 通知服务，作为注释写入采样历史，dwm 的泄漏常与扩展坞的连接和断开有关。
+//! 同样由托盘程序统计会话中可见的顶层窗口数并上报，服务将其与内存一起记录；
+//! 会话中是否有全屏游戏或演示也由托盘程序上报，服务可以据此推迟重启。
+//!
+//! 用户可以在菜单中选择当天不自动重启，选择写入当前用户的注册表，服务重启前读取。
+
+#![windows_subsystem = "windows"]
+
+use dwm_monitor::ipc::{send_request, Request, Response};
+use dwm_monitor::monitor_state::{now_secs, MonitorStatus};
+use dwm_monitor::session_manager;
+use lazy_static::lazy_static;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::Duration;
+use windows::core::{w, PCWSTR};
+use windows::Win32::Foundation::{BOOL, HWND, LPARAM, LRESULT, POINT, RECT, TRUE, WPARAM};
+use windows::Win32::Graphics::Gdi::{
+    EnumDisplayMonitors, GetMonitorInfoW, HDC, HMONITOR, MONITORINFO, MONITORINFOEXW,
+};
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::UI::HiDpi::{
+    GetDpiForMonitor, SetProcessDpiAwarenessContext, DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2,
+    MDT_EFFECTIVE_DPI,
+};
+use windows::Win32::UI::Shell::{
+    SHQueryUserNotificationState, Shell_NotifyIconW, NIF_ICON, NIF_INFO, NIF_MESSAGE, NIF_TIP,
+    NIIF_INFO, NIM_ADD, NIM_DELETE, NIM_MODIFY, NOTIFYICONDATAW, QUNS_BUSY, QUNS_PRESENTATION_MODE,
+    QUNS_RUNNING_D3D_FULL_SCREEN,
+};
+use windows::Win32::UI::WindowsAndMessaging::{
+    AppendMenuW, CreatePopupMenu, CreateWindowExW, DefWindowProcW, DestroyMenu, DispatchMessageW,
+    EnumWindows, GetCursorPos, GetMessageW, IsWindowVisible, LoadIconW, PostMessageW,
+    PostQuitMessage, RegisterClassW, RegisterWindowMessageW, SetForegroundWindow, TrackPopupMenu,
+    TranslateMessage, IDI_APPLICATION, MF_GRAYED, MF_SEPARATOR, MF_STRING, MSG, TPM_NONOTIFY,
+    TPM_RETURNCMD, TPM_RIGHTBUTTON, WINDOW_EX_STYLE, WINDOW_STYLE, WM_APP, WM_DESTROY,
+    WM_LBUTTONUP, WM_RBUTTONUP, WNDCLASSW,
+};
+
+const MB: u64 = 1024 * 1024;
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+// 每个进程的趋势保留最近的采样点数
+const HISTORY_LEN: usize = 30;
+const SNOOZE_SECS: u64 = 3600;
+const SPARK_CHARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+const WM_TRAY: u32 = WM_APP + 1;
+const WM_STATUS: u32 = WM_APP + 2;
+const WM_COMMAND_RESULT: u32 = WM_APP + 3;
+
+const ID_SNOOZE: usize = 1;
+const ID_RESUME: usize = 2;
+const ID_EXIT: usize = 3;
+const ID_OPT_OUT: usize = 4;
+const ID_OPT_IN: usize = 5;
+// 各进程的“立即重启”菜单项从这里开始编号
+const ID_RESTART_FIRST: usize = 100;
+
+#[derive(Default)]
+struct TrayState {
+    status: Option<MonitorStatus>,
+    error: Option<String>,
+    // 进程名 -> (上次采样时间, 最近几次采样的 Private Bytes)
+    history: HashMap<String, (Option<u64>, VecDeque<u64>)>,
+    // 等待以气泡通知显示的命令结果
+    message: Option<String>,
+}
+
+impl TrayState {
+    fn update(&mut self, result: Result<MonitorStatus, String>) {
+        match result {
+            Ok(status) => {
+                // 轮询比采样频繁，只在服务完成新一轮采样时记录
+                for process in &status.processes {
+                    let (last_sample, values) =
+                        self.history.entry(process.name.clone()).or_default();
+                    if process.last_sample.is_some() && process.last_sample != *last_sample {
+                        *last_sample = process.last_sample;
+                        values.push_back(process.private_bytes);
+                        if values.len() > HISTORY_LEN {
+                            values.pop_front();
+                        }
+                    }
+                }
+                self.status = Some(status);
+                self.error = None;
+            }
+            Err(e) => {
+                self.status = None;
+                self.error = Some(e);
+            }
+        }
+    }
+
+    // 每个进程一行：名称、内存/阈值和趋势
+    fn lines(&self) -> Vec<String> {
+        match (&self.status, &self.error) {
+            (Some(status), _) => status
+                .processes
+                .iter()
+                .map(|process| {
+                    let values: Vec<u64> = self
+                        .history
+                        .get(&process.name)
+                        .map(|(_, values)| values.iter().copied().collect())
+                        .unwrap_or_default();
+                    format!(
+                        "{} {}/{} MB {}",
+                        process.name,
+                        process.private_bytes / MB,
+                        process.memory_threshold_bytes / MB,
+                        sparkline(&values, process.memory_threshold_bytes)
+                    )
+                })
+                .collect(),
+            (None, Some(e)) => vec![format!("Service unavailable: {}", e)],
+            (None, None) => vec!["Connecting to the service...".to_string()],
+        }
+    }
+}
+
+lazy_static! {
+    static ref STATE: Mutex<TrayState> = Mutex::new(TrayState::default());
+    // 资源管理器重启后会广播该消息，需要重新添加图标
+    static ref TASKBAR_CREATED: u32 = unsafe { RegisterWindowMessageW(w!("TaskbarCreated")) };
+}
+
+fn state() -> MutexGuard<'static, TrayState> {
+    STATE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// 把数值按 `max` 缩放为一行方块字符，达到或超过 `max` 的显示为最高的方块。
+fn sparkline(values: &[u64], max: u64) -> String {
+    let top = SPARK_CHARS.len() as u128 - 1;
+    values
+        .iter()
+        .map(|&value| match max {
+            0 => SPARK_CHARS[0],
+            _ => SPARK_CHARS[(value.min(max) as u128 * top / max as u128) as usize],
+        })
+        .collect()
+}
+
+fn to_wide(text: &str) -> Vec<u16> {
+    text.encode_utf16().chain(Some(0)).collect()
+}
+
+// 复制到定长缓冲区，超出部分截断
+fn copy_wide(dest: &mut [u16], text: &str) {
+    let wide: Vec<u16> = text.encode_utf16().take(dest.len() - 1).collect();
+    dest[..wide.len()].copy_from_slice(&wide);
+    dest[wide.len()] = 0;
+}
+
+// 窗口句柄不能跨线程传递，以整数形式交给后台线程
+fn post(window: isize, message: u32) {
+    unsafe {
+        let _ = PostMessageW(Some(HWND(window as _)), message, WPARAM(0), LPARAM(0));
+    }
+}
+
+unsafe extern "system" fn collect_display(
+    monitor: HMONITOR,
+    _hdc: HDC,
+    _rect: *mut RECT,
+    data: LPARAM,
+) -> BOOL {
+    let displays = &mut *(data.0 as *mut Vec<String>);
+    let mut info = MONITORINFOEXW::default();
+    info.monitorInfo.cbSize = std::mem::size_of::<MONITORINFOEXW>() as u32;
+    if GetMonitorInfoW(
+        monitor,
+        &mut info as *mut MONITORINFOEXW as *mut MONITORINFO,
+    )
+    .as_bool()
+    {
+        let len = info
+            .szDevice
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or(info.szDevice.len());
+        let device = String::from_utf16_lossy(&info.szDevice[..len]);
+        let rect = info.monitorInfo.rcMonitor;
+        let (mut dpi_x, mut dpi_y) = (0, 0);
+        let _ = GetDpiForMonitor(monitor, MDT_EFFECTIVE_DPI, &mut dpi_x, &mut dpi_y);
+        displays.push(format!(
+            "{} {}x{} {}dpi",
+            device,
+            rect.right - rect.left,
+            rect.bottom - rect.top,
+            dpi_x
+        ));
+    }
+    TRUE
+}
+
+// 当前会话的各显示器：设备名、分辨率（物理像素）和缩放后的 DPI
+fn display_topology() -> Vec<String> {
+    let mut displays: Vec<String> = Vec::new();
+    unsafe {
+        let _ = EnumDisplayMonitors(
+            None,
+            None,
+            Some(collect_display),
+            LPARAM(&mut displays as *mut Vec<String> as isize),
+        );
+    }
+    displays.sort();
+    displays
+}
+
+unsafe extern "system" fn count_visible_window(window: HWND, data: LPARAM) -> BOOL {
+    if IsWindowVisible(window).as_bool() {
+        *(data.0 as *mut u32) += 1;
+    }
+    TRUE
+}
+
+// 当前桌面上可见的顶层窗口数，dwm 为每个窗口保留合成用的表面
+fn visible_window_count() -> u32 {
+    let mut count = 0u32;
+    unsafe {
+        let _ = EnumWindows(
+            Some(count_visible_window),
+            LPARAM(&mut count as *mut u32 as isize),
+        );
+    }
+    count
+}
+
+// 会话中是否有全屏的 Direct3D 程序（多为游戏，游戏模式此时启用）、其他全屏程序或处于演示模式
+fn full_screen() -> bool {
+    matches!(
+        unsafe { SHQueryUserNotificationState() },
+        Ok(QUNS_RUNNING_D3D_FULL_SCREEN | QUNS_BUSY | QUNS_PRESENTATION_MODE)
+    )
+}
+
+fn topology_annotation(previous: &[String], current: &[String]) -> String {
+    format!(
+        "Display topology changed: {} (was {})",
+        current.join(", "),
+        previous.join(", ")
+    )
+}
+
+fn poll_status(window: isize) {
+    // 上次通知服务时的显示配置，启动时的配置不记录
+    let mut displays = display_topology();
+    loop {
+        let current = display_topology();
+        if current != displays {
+            let annotation = topology_annotation(&displays, &current);
+            // 服务未运行时下一轮重试
+            if let Ok(Response::Message(_)) = send_request(&Request::Annotate(annotation)) {
+                displays = current;
+            }
+        }
+        let _ = send_request(&Request::VisibleWindows(visible_window_count()));
+        let _ = send_request(&Request::FullScreen(full_screen()));
+        let result = match send_request(&Request::Status) {
+            Ok(Response::Status(status)) => Ok(status),
+            Ok(Response::Error(e)) => Err(e),
+            Ok(response) => Err(format!("unexpected response {:?}", response)),
+            Err(e) => Err(e.to_string()),
+        };
+        state().update(result);
+        post(window, WM_STATUS);
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+// 立即重启可能耗时数十秒，在后台线程执行，结果以气泡通知显示
+fn send_command(window: HWND, request: Request) {
+    let window = window.0 as isize;
+    thread::spawn(move || {
+        let message = match send_request(&request) {
+            Ok(Response::Message(message)) => message,
+            Ok(Response::Error(e)) => format!("Command failed: {}", e),
+            Ok(response) => format!("Unexpected response: {:?}", response),
+            Err(e) => format!("Failed to connect to the service: {}", e),
+        };
+        state().message = Some(message);
+        post(window, WM_COMMAND_RESULT);
+    });
+}
+
+fn icon_data(window: HWND) -> NOTIFYICONDATAW {
+    NOTIFYICONDATAW {
+        cbSize: std::mem::size_of::<NOTIFYICONDATAW>() as u32,
+        hWnd: window,
+        uID: 1,
+        ..Default::default()
+    }
+}
+
+fn add_icon(window: HWND) {
+    let mut data = icon_data(window);
+    data.uFlags = NIF_ICON | NIF_MESSAGE | NIF_TIP;
+    data.uCallbackMessage = WM_TRAY;
+    data.hIcon = unsafe { LoadIconW(None, IDI_APPLICATION) }.unwrap_or_default();
+    copy_wide(&mut data.szTip, "ProcessGuard");
+    unsafe {
+        let _ = Shell_NotifyIconW(NIM_ADD, &data);
+    }
+}
+
+fn remove_icon(window: HWND) {
+    unsafe {
+        let _ = Shell_NotifyIconW(NIM_DELETE, &icon_data(window));
+    }
+}
+
+fn update_tip(window: HWND) {
+    let lines = state().lines();
+    let mut data = icon_data(window);
+    data.uFlags = NIF_TIP;
+    copy_wide(
+        &mut data.szTip,
+        &format!("ProcessGuard\n{}", lines.join("\n")),
+    );
+    unsafe {
+        let _ = Shell_NotifyIconW(NIM_MODIFY, &data);
+    }
+}
+
+fn show_message(window: HWND, message: &str) {
+    let mut data = icon_data(window);
+    data.uFlags = NIF_INFO;
+    data.dwInfoFlags = NIIF_INFO;
+    copy_wide(&mut data.szInfoTitle, "ProcessGuard");
+    copy_wide(&mut data.szInfo, message);
+    unsafe {
+        let _ = Shell_NotifyIconW(NIM_MODIFY, &data);
+    }
+}
+
+// 只写入当前用户的注册表，不经过服务，服务未运行时也可以设置
+fn set_opt_out(window: HWND, opt_out: bool) {
+    let message = match session_manager::set_current_user_opt_out(opt_out) {
+        Ok(()) if opt_out => {
+            "Automatic restarts are skipped in this session until tomorrow".to_string()
+        }
+        Ok(()) => "Automatic restarts are allowed again".to_string(),
+        Err(e) => format!("Failed to save the choice: {}", e),
+    };
+    show_message(window, &message);
+}
+
+fn show_menu(window: HWND) {
+    let (lines, names, snoozed) = {
+        let state = state();
+        let status = state.status.as_ref();
+        (
+            state.lines(),
+            status.map_or(Vec::new(), |s| {
+                s.processes.iter().map(|p| p.name.clone()).collect()
+            }),
+            status
+                .and_then(|s| s.snoozed_until)
+                .is_some_and(|until| until > now_secs()),
+        )
+    };
+    let mut items: Vec<(usize, String)> = Vec::new();
+    if snoozed {
+        items.push((ID_RESUME, "Resume restarts".to_string()));
+    } else {
+        items.push((ID_SNOOZE, "Snooze restarts for 1 hour".to_string()));
+    }
+    if session_manager::current_user_opted_out() {
+        items.push((ID_OPT_IN, "Allow automatic restarts today".to_string()));
+    } else {
+        items.push((ID_OPT_OUT, "Skip automatic restarts today".to_string()));
+    }
+    for (index, name) in names.iter().enumerate() {
+        items.push((ID_RESTART_FIRST + index, format!("Restart {} now", name)));
+    }
+    unsafe {
+        let menu = match CreatePopupMenu() {
+            Ok(menu) => menu,
+            Err(_) => return,
+        };
+        for line in &lines {
+            let _ = AppendMenuW(
+                menu,
+                MF_STRING | MF_GRAYED,
+                0,
+                PCWSTR(to_wide(line).as_ptr()),
+            );
+        }
+        let _ = AppendMenuW(menu, MF_SEPARATOR, 0, PCWSTR::null());
+        for (id, label) in &items {
+            let _ = AppendMenuW(menu, MF_STRING, *id, PCWSTR(to_wide(label).as_ptr()));
+        }
+        let _ = AppendMenuW(menu, MF_SEPARATOR, 0, PCWSTR::null());
+        let _ = AppendMenuW(menu, MF_STRING, ID_EXIT, w!("Exit"));
+        let mut point = POINT::default();
+        let _ = GetCursorPos(&mut point);
+        // 否则点击菜单外部时菜单不会关闭
+        let _ = SetForegroundWindow(window);
+        let selected = TrackPopupMenu(
+            menu,
+            TPM_RIGHTBUTTON | TPM_RETURNCMD | TPM_NONOTIFY,
+            point.x,
+            point.y,
+            None,
+            window,
+            None,
+        )
+        .0 as usize;
+        let _ = DestroyMenu(menu);
+        match selected {
+            0 => {}
+            ID_SNOOZE => send_command(window, Request::Snooze(SNOOZE_SECS)),
+            ID_RESUME => send_command(window, Request::Snooze(0)),
+            ID_OPT_OUT | ID_OPT_IN => set_opt_out(window, selected == ID_OPT_OUT),
+            ID_EXIT => {
+                remove_icon(window);
+                PostQuitMessage(0);
+            }
+            id => {
+                if let Some(name) = id
+                    .checked_sub(ID_RESTART_FIRST)
+                    .and_then(|index| names.get(index))
+                {
+                    send_command(window, Request::RestartNow(Some(name.clone())));
+                }
+            }
+        }
+    }
+}
+
+unsafe extern "system" fn window_proc(
+    window: HWND,
+    message: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    match message {
+        WM_TRAY => {
+            if matches!(lparam.0 as u32, WM_LBUTTONUP | WM_RBUTTONUP) {
+                show_menu(window);
+            }
+            LRESULT(0)
+        }
+        WM_STATUS => {
+            update_tip(window);
+            LRESULT(0)
+        }
+        WM_COMMAND_RESULT => {
+            let message = state().message.take();
+            if let Some(message) = message {
+                show_message(window, &message);
+            }
+            LRESULT(0)
+        }
+        WM_DESTROY => {
+            remove_icon(window);
+            PostQuitMessage(0);
+            LRESULT(0)
+        }
+        message if message == *TASKBAR_CREATED => {
+            add_icon(window);
+            update_tip(window);
+            LRESULT(0)
+        }
+        _ => DefWindowProcW(window, message, wparam, lparam),
+    }
+}
+
+fn main() {
+    unsafe {
+        // 按显示器的实际 DPI 读取分辨率和缩放，否则系统会把 DPI 虚拟化为 96
+        let _ = SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2);
+        let instance = GetModuleHandleW(None).unwrap_or_default();
+        let class = WNDCLASSW {
+            lpfnWndProc: Some(window_proc),
+            hInstance: instance.into(),
+            lpszClassName: w!("ProcessGuardTray"),
+            ..Default::default()
+        };
+        if RegisterClassW(&class) == 0 {
+            return;
+        }
+        // 不显示的顶层窗口，用于接收托盘图标和 TaskbarCreated 消息
+        let window = match CreateWindowExW(
+            WINDOW_EX_STYLE(0),
+            w!("ProcessGuardTray"),
+            w!("ProcessGuard"),
+            WINDOW_STYLE(0),
+            0,
+            0,
+            0,
+            0,
+            None,
+            None,
+            Some(instance.into()),
+            None,
+        ) {
+            Ok(window) => window,
+            Err(_) => return,
+        };
+        add_icon(window);
+        let handle = window.0 as isize;
+        thread::spawn(move || poll_status(handle));
+        let mut message = MSG::default();
+        while GetMessageW(&mut message, None, 0, 0).as_bool() {
+            let _ = TranslateMessage(&message);
+            DispatchMessageW(&message);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dwm_monitor::monitor_state::ProcessStatus;
+
+    #[test]
+    fn test_sparkline_scales_to_threshold() {
+        assert_eq!(sparkline(&[0, 50, 100, 200], 100), "▁▄██");
+        assert_eq!(sparkline(&[10], 0), "▁");
+        assert_eq!(sparkline(&[], 100), "");
+    }
+
+    #[test]
+    fn test_topology_annotation() {
+        let docked = vec![
+            r"\\.\DISPLAY1 1920x1080 96dpi".to_string(),
+            r"\\.\DISPLAY2 3840x2160 144dpi".to_string(),
+        ];
+        assert_eq!(
+            topology_annotation(&docked, &docked[..1]),
+            r"Display topology changed: \\.\DISPLAY1 1920x1080 96dpi (was \\.\DISPLAY1 1920x1080 96dpi, \\.\DISPLAY2 3840x2160 144dpi)"
+        );
+    }
+
+    #[test]
+    fn test_history_only_grows_on_new_samples() {
+        let status = |private_bytes: u64, last_sample: u64| MonitorStatus {
+            processes: vec![ProcessStatus {
+                name: "dwm.exe".to_string(),
+                private_bytes,
+                memory_threshold_bytes: 800 * MB,
+                last_sample: Some(last_sample),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let mut state = TrayState::default();
+        state.update(Ok(status(100 * MB, 1)));
+        state.update(Ok(status(100 * MB, 1)));
+        state.update(Ok(status(800 * MB, 2)));
+        assert_eq!(state.lines(), vec!["dwm.exe 800/800 MB ▁█"]);
+        state.update(Err("pipe not found".to_string()));
+        assert_eq!(state.lines(), vec!["Service unavailable: pipe not found"]);
+    }
+}
@@ -0,0 +1,812 @@
+use log::Level;
+
+use crate::config_manager::{parse_duration, parse_size, Config};
+use crate::log_tail::parse_level;
+
+pub const USAGE: &str = "Usage: process_guard [COMMAND]
+
+Commands:
+  --console [--threshold [NAME=]SIZE] [--interval SECONDS] [--no-db]
+               Run the monitor in the foreground (Ctrl+C to exit), overriding
+               the config for this run like the service start arguments
+  --once [--threshold [NAME=]SIZE] [--interval SECONDS] [--no-db] [--json]
+               Run a single sample-and-restart check and exit, e.g. from Task
+               Scheduler; exit code 0 healthy, 3 restarted, 4 not found,
+               5 over the threshold but not restarted, 1 check failed
+  version, --version
+               Print the version, git commit, build date and target
+  install [--start-type auto|delayed-auto|manual] [--account ACCOUNT]
+               Register ProcessGuard as a Windows service (default: auto,
+               LocalSystem), e.g. --account \"NT AUTHORITY\\LocalService\"
+  uninstall [--purge]
+               Stop and remove the Windows service and its state file;
+               --purge also deletes the config, logs and database
+  status [--json] [--host HOST]
+               Show the state of the running monitor
+  restart-now [NAME] [--host HOST] [--json]
+               Restart a monitored process immediately (default: the first one)
+  set-threshold <SIZE> [NAME] [--persist] [--json]
+               Change the memory threshold of the running monitor, e.g. 1500MB;
+               --persist also writes it to the config file
+  snooze <DURATION|off> [--host HOST] [--json]
+               Skip restarts (but keep sampling) for e.g. 2h, 30m; off cancels
+  reload-config [--host HOST] [--json]
+               Make the running monitor re-read its config file
+  validate-config [PATH] [--json]
+               Check a config file and print the effective settings
+  logs [--follow] [--level LEVEL]
+               Print the end of the log file, --follow keeps streaming new lines
+  self-update [--force]
+               Download the release from update_url, verify its checksum and
+               signature, replace the binary and restart the service
+  simulate [--threshold SIZE] [--step SIZE]
+               Run the monitor against a disposable test process that grows by
+               SIZE per second (default 200MB threshold, 50MB step) and check
+               that it gets restarted
+  export-diagnostics <ZIP> [--json]
+               Bundle logs, config, state and system info for bug reports
+
+--json prints a JSON object with at least \"ok\" and \"message\" instead of text.
+--host manages the monitor on another machine that has remote_control enabled,
+using the current Windows credentials.
+
+Run without a command to start under the Service Control Manager.";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StartType {
+    Auto,
+    DelayedAuto,
+    Manual,
+}
+
+impl StartType {
+    fn parse(value: &str) -> Result<StartType, String> {
+        match value {
+            "auto" => Ok(StartType::Auto),
+            "delayed-auto" => Ok(StartType::DelayedAuto),
+            "manual" => Ok(StartType::Manual),
+            other => Err(format!(
+                "Invalid start type: {} (expected auto, delayed-auto or manual)",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Service,
+    // 前台运行，附带只对本次运行生效的配置覆盖
+    Console(ConfigOverrides),
+    // 只检查一轮后退出，覆盖方式与 Console 相同
+    Once {
+        overrides: ConfigOverrides,
+        json: bool,
+    },
+    // json 为 true 时以 JSON 输出结果，供脚本解析
+    Version {
+        json: bool,
+    },
+    Install {
+        start_type: StartType,
+        account: Option<String>,
+    },
+    Uninstall {
+        purge: bool,
+    },
+    // host 不为空时管理该计算机上的监控
+    Status {
+        json: bool,
+        host: Option<String>,
+    },
+    RestartNow {
+        name: Option<String>,
+        host: Option<String>,
+        json: bool,
+    },
+    SetThreshold {
+        bytes: u64,
+        name: Option<String>,
+        persist: bool,
+        json: bool,
+    },
+    Snooze {
+        // 秒数，0 表示取消
+        duration_secs: u64,
+        host: Option<String>,
+        json: bool,
+    },
+    ReloadConfig {
+        host: Option<String>,
+        json: bool,
+    },
+    ValidateConfig {
+        path: Option<String>,
+        json: bool,
+    },
+    Logs {
+        follow: bool,
+        level: Option<Level>,
+    },
+    SelfUpdate {
+        force: bool,
+    },
+    Simulate {
+        threshold: u64,
+        step: u64,
+    },
+    // simulate 启动的测试进程
+    Balloon {
+        step: u64,
+    },
+    ExportDiagnostics {
+        path: String,
+        json: bool,
+    },
+}
+
+// 命令名之后的参数，按需取出，剩余未识别的参数视为错误
+struct ArgList {
+    args: Vec<String>,
+}
+
+impl ArgList {
+    fn flag(&mut self, name: &str) -> bool {
+        match self.args.iter().position(|a| a == name) {
+            Some(index) => {
+                self.args.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // --name value 形式的选项
+    fn option(&mut self, name: &str) -> Result<Option<String>, String> {
+        let index = match self.args.iter().position(|a| a == name) {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+        if index + 1 >= self.args.len() {
+            return Err(format!("{} requires a value", name));
+        }
+        self.args.remove(index);
+        Ok(Some(self.args.remove(index)))
+    }
+
+    fn positional(&mut self) -> Option<String> {
+        let index = self.args.iter().position(|a| !a.starts_with("--"))?;
+        Some(self.args.remove(index))
+    }
+
+    fn finish(self) -> Result<(), String> {
+        match self.args.first() {
+            Some(extra) => Err(format!("Unexpected argument: {}", extra)),
+            None => Ok(()),
+        }
+    }
+}
+
+// sc start 传入的服务启动参数，只对本次运行生效
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ConfigOverrides {
+    // (进程名, 阈值)，进程名为空时应用到所有进程
+    pub thresholds: Vec<(Option<String>, u64)>,
+    pub interval_seconds: Option<u64>,
+    pub insert_into_db: Option<bool>,
+}
+
+impl ConfigOverrides {
+    pub fn is_empty(&self) -> bool {
+        *self == ConfigOverrides::default()
+    }
+
+    pub fn apply(&self, config: &mut Config) -> Result<(), String> {
+        for (name, bytes) in &self.thresholds {
+            match name {
+                Some(name) => {
+                    config
+                        .find_monitor_process_mut(name)
+                        .ok_or_else(|| format!("{} is not a monitored process", name))?
+                        .memory_threshold_bytes = *bytes
+                }
+                None => {
+                    for process in &mut config.processes {
+                        process.memory_threshold_bytes = *bytes;
+                    }
+                }
+            }
+        }
+        if let Some(interval_seconds) = self.interval_seconds {
+            config.interval_seconds = interval_seconds;
+        }
+        if let Some(insert_into_db) = self.insert_into_db {
+            config.db_config.insert_into_db = insert_into_db;
+        }
+        Ok(())
+    }
+}
+
+// 例如：sc start ProcessMonitorService --threshold 1500MB --interval 10
+//      sc start ProcessMonitorService --threshold dwm.exe=1500MB --no-db
+pub fn parse_service_args<I>(args: I) -> Result<ConfigOverrides, String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut args = ArgList {
+        args: args.into_iter().collect(),
+    };
+    let mut overrides = ConfigOverrides::default();
+    while let Some(value) = args.option("--threshold")? {
+        let threshold = match value.split_once('=') {
+            Some((name, size)) => (Some(name.to_string()), parse_size(size)?),
+            None => (None, parse_size(&value)?),
+        };
+        overrides.thresholds.push(threshold);
+    }
+    if let Some(value) = args.option("--interval")? {
+        match value.parse::<u64>() {
+            Ok(seconds) if seconds > 0 => overrides.interval_seconds = Some(seconds),
+            _ => return Err(format!("Invalid interval: {}", value)),
+        }
+    }
+    if args.flag("--no-db") {
+        overrides.insert_into_db = Some(false);
+    }
+    args.finish()?;
+    Ok(overrides)
+}
+
+const ENV_THRESHOLD: &str = "PROCESS_GUARD_THRESHOLD";
+const ENV_INTERVAL: &str = "PROCESS_GUARD_INTERVAL";
+const ENV_NO_DB: &str = "PROCESS_GUARD_NO_DB";
+
+// 环境变量的写法与启动参数相同，多个阈值用逗号分隔，例如
+// PROCESS_GUARD_THRESHOLD=dwm.exe=1500MB,2GB PROCESS_GUARD_INTERVAL=10 PROCESS_GUARD_NO_DB=1
+pub fn env_overrides<I>(vars: I) -> Result<ConfigOverrides, String>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let mut args = Vec::new();
+    for (key, value) in vars {
+        // Windows 的环境变量名不区分大小写
+        match key.to_ascii_uppercase().as_str() {
+            ENV_THRESHOLD => {
+                for threshold in value.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+                    args.push("--threshold".to_string());
+                    args.push(threshold.to_string());
+                }
+            }
+            ENV_INTERVAL => {
+                args.push("--interval".to_string());
+                args.push(value.trim().to_string());
+            }
+            ENV_NO_DB => match value.trim().to_ascii_lowercase().as_str() {
+                "1" | "true" | "yes" => args.push("--no-db".to_string()),
+                "" | "0" | "false" | "no" => {}
+                _ => return Err(format!("Invalid {}: {}", ENV_NO_DB, value)),
+            },
+            _ => {}
+        }
+    }
+    parse_service_args(args)
+}
+
+// 优先级从低到高：配置文件、环境变量、命令行或服务启动参数
+pub fn apply_overrides(
+    config: &mut Config,
+    env: &ConfigOverrides,
+    args: &ConfigOverrides,
+) -> Result<(), String> {
+    env.apply(config)
+        .map_err(|e| format!("{} (from environment variables)", e))?;
+    args.apply(config)
+}
+
+pub fn parse_args<I>(args: I) -> Result<Command, String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut args = args.into_iter();
+    let command = match args.next() {
+        None => return Ok(Command::Service),
+        Some(arg) => arg,
+    };
+    let mut args = ArgList {
+        args: args.collect(),
+    };
+    let command = match command.as_str() {
+        "--console" => Command::Console(parse_service_args(std::mem::take(&mut args.args))?),
+        "--once" => Command::Once {
+            json: args.flag("--json"),
+            overrides: parse_service_args(std::mem::take(&mut args.args))?,
+        },
+        "version" | "--version" | "-V" => Command::Version {
+            json: args.flag("--json"),
+        },
+        "install" => Command::Install {
+            start_type: match args.option("--start-type")? {
+                Some(value) => StartType::parse(&value)?,
+                None => StartType::Auto,
+            },
+            account: args.option("--account")?,
+        },
+        "uninstall" => Command::Uninstall {
+            purge: args.flag("--purge"),
+        },
+        "status" => Command::Status {
+            json: args.flag("--json"),
+            host: args.option("--host")?,
+        },
+        "restart-now" => Command::RestartNow {
+            json: args.flag("--json"),
+            host: args.option("--host")?,
+            name: args.positional(),
+        },
+        "set-threshold" => {
+            let persist = args.flag("--persist");
+            let json = args.flag("--json");
+            let size = args
+                .positional()
+                .ok_or_else(|| "set-threshold requires a size, e.g. 1500MB".to_string())?;
+            Command::SetThreshold {
+                bytes: parse_size(&size)?,
+                name: args.positional(),
+                persist,
+                json,
+            }
+        }
+        "snooze" => {
+            let host = args.option("--host")?;
+            let json = args.flag("--json");
+            let duration = args
+                .positional()
+                .ok_or_else(|| "snooze requires a duration, e.g. 2h".to_string())?;
+            Command::Snooze {
+                duration_secs: match duration.as_str() {
+                    "off" => 0,
+                    _ => parse_duration(&duration)?,
+                },
+                host,
+                json,
+            }
+        }
+        "reload-config" => Command::ReloadConfig {
+            host: args.option("--host")?,
+            json: args.flag("--json"),
+        },
+        "validate-config" => Command::ValidateConfig {
+            json: args.flag("--json"),
+            path: args.positional(),
+        },
+        "logs" => Command::Logs {
+            follow: args.flag("--follow") || args.flag("-f"),
+            level: match args.option("--level")? {
+                Some(value) => Some(parse_level(&value)?),
+                None => None,
+            },
+        },
+        "self-update" => Command::SelfUpdate {
+            force: args.flag("--force"),
+        },
+        "simulate" => Command::Simulate {
+            threshold: match args.option("--threshold")? {
+                Some(value) => parse_size(&value)?,
+                None => 200 * 1024 * 1024,
+            },
+            step: match args.option("--step")? {
+                Some(value) => parse_size(&value)?,
+                None => 50 * 1024 * 1024,
+            },
+        },
+        "balloon" => Command::Balloon {
+            step: parse_size(&args.positional().ok_or("balloon requires a step size")?)?,
+        },
+        "export-diagnostics" => Command::ExportDiagnostics {
+            json: args.flag("--json"),
+            path: args.positional().ok_or_else(|| {
+                "export-diagnostics requires an output path, e.g. diagnostics.zip".to_string()
+            })?,
+        },
+        other => return Err(format!("Unknown command: {}", other)),
+    };
+    args.finish()?;
+    Ok(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        parse_args(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn test_parse_no_args_runs_service() {
+        assert_eq!(parse(&[]), Ok(Command::Service));
+    }
+
+    #[test]
+    fn test_parse_install_uninstall() {
+        assert_eq!(
+            parse(&["install"]),
+            Ok(Command::Install {
+                start_type: StartType::Auto,
+                account: None,
+            })
+        );
+        assert_eq!(
+            parse(&["uninstall"]),
+            Ok(Command::Uninstall { purge: false })
+        );
+        assert_eq!(
+            parse(&["uninstall", "--purge"]),
+            Ok(Command::Uninstall { purge: true })
+        );
+    }
+
+    #[test]
+    fn test_parse_install_start_type() {
+        assert_eq!(
+            parse(&["install", "--start-type", "delayed-auto"]),
+            Ok(Command::Install {
+                start_type: StartType::DelayedAuto,
+                account: None,
+            })
+        );
+        assert_eq!(
+            parse(&[
+                "install",
+                "--account",
+                "NT AUTHORITY\\LocalService",
+                "--start-type",
+                "manual"
+            ]),
+            Ok(Command::Install {
+                start_type: StartType::Manual,
+                account: Some("NT AUTHORITY\\LocalService".to_string()),
+            })
+        );
+        assert!(parse(&["install", "--start-type"]).is_err());
+        assert!(parse(&["install", "--start-type", "boot"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_console() {
+        assert_eq!(
+            parse(&["--console"]),
+            Ok(Command::Console(ConfigOverrides::default()))
+        );
+        assert_eq!(
+            parse(&["--console", "--interval", "10"]),
+            Ok(Command::Console(ConfigOverrides {
+                interval_seconds: Some(10),
+                ..Default::default()
+            }))
+        );
+        assert!(parse(&["--console", "--verbose"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_once() {
+        assert_eq!(
+            parse(&["--once"]),
+            Ok(Command::Once {
+                overrides: ConfigOverrides::default(),
+                json: false,
+            })
+        );
+        assert_eq!(
+            parse(&["--once", "--threshold", "dwm.exe=1GB", "--json", "--no-db"]),
+            Ok(Command::Once {
+                overrides: ConfigOverrides {
+                    thresholds: vec![(Some("dwm.exe".to_string()), 1024 * 1024 * 1024)],
+                    insert_into_db: Some(false),
+                    ..Default::default()
+                },
+                json: true,
+            })
+        );
+        assert!(parse(&["--once", "status"]).is_err());
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let vars = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<Vec<_>>()
+        };
+        let overrides = env_overrides(vars(&[
+            ("PATH", "C:\\Windows"),
+            ("PROCESS_GUARD_THRESHOLD", "dwm.exe=1500MB, 2GB"),
+            ("process_guard_interval", "10"),
+            ("PROCESS_GUARD_NO_DB", "true"),
+        ]))
+        .unwrap();
+        assert_eq!(
+            overrides,
+            ConfigOverrides {
+                thresholds: vec![
+                    (Some("dwm.exe".to_string()), 1500 * 1024 * 1024),
+                    (None, 2 * 1024 * 1024 * 1024),
+                ],
+                interval_seconds: Some(10),
+                insert_into_db: Some(false),
+            }
+        );
+        assert!(env_overrides(vars(&[])).unwrap().is_empty());
+        assert!(env_overrides(vars(&[("PROCESS_GUARD_INTERVAL", "0")])).is_err());
+        assert!(env_overrides(vars(&[("PROCESS_GUARD_NO_DB", "maybe")])).is_err());
+    }
+
+    #[test]
+    fn test_start_arguments_override_environment() {
+        let mut config: Config =
+            serde_json::from_str(crate::config_manager::DEFAULT_CONFIG_JSON).unwrap();
+        let env = ConfigOverrides {
+            thresholds: vec![(None, 1024)],
+            interval_seconds: Some(10),
+            ..Default::default()
+        };
+        let args = parse_service_args(["--interval".to_string(), "30".to_string()]).unwrap();
+        apply_overrides(&mut config, &env, &args).unwrap();
+        assert_eq!(config.interval_seconds, 30);
+        assert_eq!(config.processes[0].memory_threshold_bytes, 1024);
+        assert!(parse_service_args(["--threshold".to_string(), "0".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(parse(&["version"]), Ok(Command::Version { json: false }));
+        assert_eq!(parse(&["--version"]), Ok(Command::Version { json: false }));
+        assert_eq!(
+            parse(&["version", "--json"]),
+            Ok(Command::Version { json: true })
+        );
+    }
+
+    #[test]
+    fn test_parse_status() {
+        assert_eq!(
+            parse(&["status"]),
+            Ok(Command::Status {
+                json: false,
+                host: None,
+            })
+        );
+        assert_eq!(
+            parse(&["status", "--json", "--host", "kiosk-01"]),
+            Ok(Command::Status {
+                json: true,
+                host: Some("kiosk-01".to_string()),
+            })
+        );
+        assert!(parse(&["status", "--yaml"]).is_err());
+        assert!(parse(&["status", "--host"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_restart_now() {
+        assert_eq!(
+            parse(&["restart-now"]),
+            Ok(Command::RestartNow {
+                name: None,
+                host: None,
+                json: false,
+            })
+        );
+        assert_eq!(
+            parse(&["restart-now", "--host", "kiosk-01", "dwm.exe", "--json"]),
+            Ok(Command::RestartNow {
+                name: Some("dwm.exe".to_string()),
+                host: Some("kiosk-01".to_string()),
+                json: true,
+            })
+        );
+        assert!(parse(&["restart-now", "dwm.exe", "extra"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_set_threshold() {
+        assert_eq!(
+            parse(&["set-threshold", "1500MB"]),
+            Ok(Command::SetThreshold {
+                bytes: 1500 * 1024 * 1024,
+                name: None,
+                persist: false,
+                json: false,
+            })
+        );
+        assert_eq!(
+            parse(&["set-threshold", "--persist", "2GB", "dwm.exe", "--json"]),
+            Ok(Command::SetThreshold {
+                bytes: 2 * 1024 * 1024 * 1024,
+                name: Some("dwm.exe".to_string()),
+                persist: true,
+                json: true,
+            })
+        );
+        assert!(parse(&["set-threshold"]).is_err());
+        assert!(parse(&["set-threshold", "lots"]).is_err());
+        assert!(parse(&["set-threshold", "1GB", "--force"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_snooze() {
+        assert_eq!(
+            parse(&["snooze", "2h"]),
+            Ok(Command::Snooze {
+                duration_secs: 7200,
+                host: None,
+                json: false,
+            })
+        );
+        assert_eq!(
+            parse(&["snooze", "--json", "off", "--host", "kiosk-01"]),
+            Ok(Command::Snooze {
+                duration_secs: 0,
+                host: Some("kiosk-01".to_string()),
+                json: true,
+            })
+        );
+        assert!(parse(&["snooze"]).is_err());
+        assert!(parse(&["snooze", "soon"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_reload_config() {
+        assert_eq!(
+            parse(&["reload-config"]),
+            Ok(Command::ReloadConfig {
+                host: None,
+                json: false,
+            })
+        );
+        assert_eq!(
+            parse(&["reload-config", "--host", "kiosk-01", "--json"]),
+            Ok(Command::ReloadConfig {
+                host: Some("kiosk-01".to_string()),
+                json: true,
+            })
+        );
+        assert!(parse(&["reload-config", "kiosk-01"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_validate_config() {
+        assert_eq!(
+            parse(&["validate-config"]),
+            Ok(Command::ValidateConfig {
+                path: None,
+                json: false,
+            })
+        );
+        assert_eq!(
+            parse(&["validate-config", "--json", "C:\\guard\\config.json"]),
+            Ok(Command::ValidateConfig {
+                path: Some("C:\\guard\\config.json".to_string()),
+                json: true,
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_logs() {
+        assert_eq!(
+            parse(&["logs"]),
+            Ok(Command::Logs {
+                follow: false,
+                level: None,
+            })
+        );
+        assert_eq!(
+            parse(&["logs", "--level", "warn", "--follow"]),
+            Ok(Command::Logs {
+                follow: true,
+                level: Some(Level::Warn),
+            })
+        );
+        assert!(parse(&["logs", "--level", "loud"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_self_update() {
+        assert_eq!(
+            parse(&["self-update"]),
+            Ok(Command::SelfUpdate { force: false })
+        );
+        assert_eq!(
+            parse(&["self-update", "--force"]),
+            Ok(Command::SelfUpdate { force: true })
+        );
+    }
+
+    #[test]
+    fn test_parse_simulate() {
+        assert_eq!(
+            parse(&["simulate"]),
+            Ok(Command::Simulate {
+                threshold: 200 * 1024 * 1024,
+                step: 50 * 1024 * 1024,
+            })
+        );
+        assert_eq!(
+            parse(&["simulate", "--step", "10MB", "--threshold", "1GB"]),
+            Ok(Command::Simulate {
+                threshold: 1024 * 1024 * 1024,
+                step: 10 * 1024 * 1024,
+            })
+        );
+        assert_eq!(
+            parse(&["balloon", "1048576"]),
+            Ok(Command::Balloon { step: 1048576 })
+        );
+    }
+
+    #[test]
+    fn test_parse_export_diagnostics() {
+        assert_eq!(
+            parse(&["export-diagnostics", "out.zip"]),
+            Ok(Command::ExportDiagnostics {
+                path: "out.zip".to_string(),
+                json: false,
+            })
+        );
+        assert_eq!(
+            parse(&["export-diagnostics", "--json", "out.zip"]),
+            Ok(Command::ExportDiagnostics {
+                path: "out.zip".to_string(),
+                json: true,
+            })
+        );
+        assert!(parse(&["export-diagnostics"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_service_args() {
+        let args = [
+            "--threshold",
+            "1500MB",
+            "--threshold",
+            "app.exe=2GB",
+            "--no-db",
+        ];
+        let overrides = parse_service_args(args.iter().map(|s| s.to_string())).unwrap();
+        assert_eq!(
+            overrides.thresholds,
+            vec![
+                (None, 1500 * 1024 * 1024),
+                (Some("app.exe".to_string()), 2 * 1024 * 1024 * 1024),
+            ]
+        );
+        assert_eq!(overrides.insert_into_db, Some(false));
+
+        let mut config: Config =
+            serde_json::from_str(crate::config_manager::DEFAULT_CONFIG_JSON).unwrap();
+        assert!(overrides.apply(&mut config).is_err());
+        config.processes[1].name = "app.exe".to_string();
+        overrides.apply(&mut config).unwrap();
+        assert_eq!(
+            config.processes[0].memory_threshold_bytes,
+            1500 * 1024 * 1024
+        );
+        assert_eq!(
+            config.processes[1].memory_threshold_bytes,
+            2 * 1024 * 1024 * 1024
+        );
+        assert!(!config.db_config.insert_into_db);
+
+        assert!(parse_service_args(Vec::new()).unwrap().is_empty());
+        assert!(parse_service_args(vec!["--interval".to_string(), "0".to_string()]).is_err());
+        assert!(parse_service_args(vec!["--verbose".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_parse_rejects_unknown() {
+        assert!(parse(&["reinstall"]).is_err());
+        assert!(parse(&["install", "now"]).is_err());
+    }
+}
@@ -0,0 +1,218 @@
+//! AWS CloudWatch 输出：按 `report_interval_seconds` 通过 PutMetricData 把各进程的最新采样和重启次数
+//! 写入 `cloudwatch_namespace`，凭据按 AWS SDK 的标准顺序获取（环境变量、配置文件、EC2 实例角色等）。
+
+use aws_config::{BehaviorVersion, Region};
+use aws_sdk_cloudwatch::error::DisplayErrorContext;
+use aws_sdk_cloudwatch::primitives::DateTime;
+use aws_sdk_cloudwatch::types::{Dimension, MetricDatum, StandardUnit};
+use aws_sdk_cloudwatch::Client;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::config_manager::Config;
+use crate::monitor_state::{self, MonitorStatus};
+use crate::reporter::machine_name;
+use crate::sink::{run_sink, FailureLog, Sink};
+use crate::tr;
+
+// 单次 PutMetricData 请求最多包含的数据点
+const MAX_DATUMS_PER_REQUEST: usize = 1000;
+
+fn datum(
+    name: &str,
+    dimensions: &[Dimension],
+    value: f64,
+    unit: StandardUnit,
+    time: u64,
+) -> MetricDatum {
+    MetricDatum::builder()
+        .metric_name(name)
+        .set_dimensions(Some(dimensions.to_vec()))
+        .value(value)
+        .unit(unit)
+        .timestamp(DateTime::from_secs(time as i64))
+        .build()
+}
+
+/// 生成本轮要写入的数据点，维度为 `Host` 和 `Process`。`Restarts` 为 1 表示自上次写入后发生了重启，
+/// 判断方式与 Log Analytics 输出相同：`sent_restarts` 为已写入的各进程上次重启时间。
+pub fn build_metrics(
+    host: &str,
+    status: &MonitorStatus,
+    sent_restarts: &HashMap<String, u64>,
+) -> Vec<MetricDatum> {
+    let mut metrics = Vec::new();
+    for process in &status.processes {
+        let Some(last_sample) = process.last_sample else {
+            continue;
+        };
+        let dimensions = [
+            Dimension::builder().name("Host").value(host).build(),
+            Dimension::builder()
+                .name("Process")
+                .value(&process.name)
+                .build(),
+        ];
+        let restarted = process
+            .last_restart
+            .is_some_and(|time| sent_restarts.get(&process.name) != Some(&time));
+        metrics.extend([
+            datum(
+                "PrivateBytes",
+                &dimensions,
+                process.private_bytes as f64,
+                StandardUnit::Bytes,
+                last_sample,
+            ),
+            datum(
+                "WorkingSet",
+                &dimensions,
+                process.working_set as f64,
+                StandardUnit::Bytes,
+                last_sample,
+            ),
+            datum(
+                "ThresholdBytes",
+                &dimensions,
+                process.memory_threshold_bytes as f64,
+                StandardUnit::Bytes,
+                last_sample,
+            ),
+            datum(
+                "Restarts",
+                &dimensions,
+                if restarted { 1.0 } else { 0.0 },
+                StandardUnit::Count,
+                last_sample,
+            ),
+        ]);
+    }
+    metrics
+}
+
+// 未配置区域时按标准顺序获取（AWS_REGION、配置文件、EC2 实例元数据）
+async fn new_client(region: &str) -> Client {
+    let mut loader = aws_config::defaults(BehaviorVersion::latest());
+    if !region.is_empty() {
+        loader = loader.region(Region::new(region.to_string()));
+    }
+    Client::new(&loader.load().await)
+}
+
+async fn send(client: &Client, namespace: &str, metrics: Vec<MetricDatum>) -> Result<(), String> {
+    for chunk in metrics.chunks(MAX_DATUMS_PER_REQUEST) {
+        client
+            .put_metric_data()
+            .namespace(namespace)
+            .set_metric_data(Some(chunk.to_vec()))
+            .send()
+            .await
+            .map_err(|e| DisplayErrorContext(e).to_string())?;
+    }
+    Ok(())
+}
+
+struct CloudWatch {
+    sent_restarts: HashMap<String, u64>,
+    // 区域变化后重新创建客户端
+    cached: Option<(String, Client)>,
+}
+
+impl Sink for CloudWatch {
+    fn interval_seconds(config: &Config) -> u64 {
+        config.report_interval_seconds
+    }
+
+    async fn round(&mut self, config: &Mutex<Config>, failures: &mut FailureLog) {
+        let (namespace, region) = {
+            let config = config.lock().unwrap_or_else(PoisonError::into_inner);
+            (
+                config.cloudwatch_namespace.clone(),
+                config.cloudwatch_region.clone(),
+            )
+        };
+        if namespace.is_empty() {
+            return;
+        }
+        let client = match self.cached.take() {
+            Some((cached_region, client)) if cached_region == region => client,
+            _ => new_client(&region).await,
+        };
+        let status = monitor_state::snapshot();
+        let metrics = build_metrics(&machine_name(), &status, &self.sent_restarts);
+        match send(&client, &namespace, metrics).await {
+            Ok(()) => {
+                self.sent_restarts = status.latest(|p| p.last_restart);
+                failures.succeeded(|| {
+                    tr!(
+                        "已恢复向 CloudWatch 写入指标",
+                        "Resumed putting metrics to CloudWatch"
+                    )
+                });
+            }
+            Err(e) => failures.failed(|| {
+                tr!(
+                    "向 CloudWatch 命名空间 {} 写入指标失败: {}",
+                    "Failed to put metrics to CloudWatch namespace {}: {}",
+                    namespace,
+                    e
+                )
+            }),
+        }
+        self.cached = Some((region, client));
+    }
+}
+
+/// 未配置 `cloudwatch_namespace` 时空转，配置重新加载后下一轮生效；服务停止时返回。
+pub async fn run(config: Arc<Mutex<Config>>) {
+    // 启动前已发生的重启（从状态文件恢复）不再计入
+    let sent_restarts = monitor_state::snapshot().latest(|p| p.last_restart);
+    let cloudwatch = CloudWatch {
+        sent_restarts,
+        cached: None,
+    };
+    run_sink(config, cloudwatch).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor_state::ProcessStatus;
+
+    #[test]
+    fn test_build_metrics_counts_new_restarts_once() {
+        let status = MonitorStatus {
+            processes: vec![
+                ProcessStatus {
+                    name: "dwm.exe".to_string(),
+                    pid: Some(7),
+                    private_bytes: 1024,
+                    last_sample: Some(100),
+                    last_restart: Some(90),
+                    ..Default::default()
+                },
+                // 尚未采样的进程不写入
+                ProcessStatus {
+                    name: "explorer.exe".to_string(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let value = |metrics: &[MetricDatum], name: &str| {
+            metrics
+                .iter()
+                .find(|m| m.metric_name() == Some(name))
+                .and_then(|m| m.value())
+        };
+        let metrics = build_metrics("KIOSK-01", &status, &HashMap::new());
+        assert_eq!(metrics.len(), 4);
+        assert_eq!(value(&metrics, "PrivateBytes"), Some(1024.0));
+        assert_eq!(value(&metrics, "Restarts"), Some(1.0));
+        assert_eq!(metrics[0].dimensions()[1].value(), Some("dwm.exe"));
+        assert_eq!(metrics[0].timestamp(), Some(&DateTime::from_secs(100)));
+
+        let metrics = build_metrics("KIOSK-01", &status, &status.latest(|p| p.last_restart));
+        assert_eq!(value(&metrics, "Restarts"), Some(0.0));
+    }
+}
@@ -0,0 +1,534 @@
+use chrono::{Local, TimeZone};
+use log::{warn, Level};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::config_manager::{config_file_path, Config, ConfigManager};
+use crate::config_validator::{validate_config, ConfigIssue, ValidationReport};
+use crate::diagnostics;
+use crate::ipc::{send_request_to, Request, Response};
+use crate::log_tail;
+use crate::logging::log_file_path;
+use crate::monitor::{ProcessMonitor, TargetState};
+use crate::monitor_state::{self, now_secs, MonitorStatus};
+use crate::service_installer::{query_service_state, restart_service};
+use crate::tr;
+use crate::updater::{self, UpdateResult};
+use crate::version;
+
+const MB: u64 = 1024 * 1024;
+
+// --once 的退出码，数值越大越严重，多个进程时取最大的一个
+pub const ONCE_CHECK_FAILED: i32 = 1;
+pub const ONCE_RESTARTED: i32 = 3;
+pub const ONCE_NOT_FOUND: i32 = 4;
+pub const ONCE_NOT_RESTARTED: i32 = 5;
+
+fn format_timestamp(secs: Option<u64>) -> String {
+    match secs.and_then(|s| Local.timestamp_opt(s as i64, 0).single()) {
+        Some(time) => time.format("%Y-%m-%d %H:%M:%S").to_string(),
+        None => "-".to_string(),
+    }
+}
+
+fn print_status_table(status: &MonitorStatus) {
+    if status.paused {
+        println!("Monitoring is paused");
+    }
+    if let Some(until) = status.snoozed_until.filter(|until| *until > now_secs()) {
+        println!(
+            "Restarts are snoozed until {}",
+            format_timestamp(Some(until))
+        );
+    }
+    println!(
+        "{:<24} {:>8} {:>14} {:>14} {:>14}  {:<11} {:<19}",
+        "Process", "PID", "Private (MB)", "Working (MB)", "Threshold (MB)", "State", "Last restart"
+    );
+    for process in &status.processes {
+        let pid = process.pid.map_or("-".to_string(), |pid| pid.to_string());
+        println!(
+            "{:<24} {:>8} {:>14} {:>14} {:>14}  {:<11} {:<19}",
+            process.name,
+            pid,
+            process.private_bytes / MB,
+            process.working_set / MB,
+            process.memory_threshold_bytes / MB,
+            format!("{:?}", process.state),
+            format_timestamp(process.last_restart)
+        );
+    }
+    for process in &status.processes {
+        if let Some(until) = process.postponed_until.filter(|until| *until > now_secs()) {
+            println!(
+                "{}: restart postponed by the user until {}",
+                process.name,
+                format_timestamp(Some(until))
+            );
+        }
+    }
+    for process in status.processes.iter().filter(|p| p.stalled) {
+        println!(
+            "{}: sampling stalled, the watchdog is restarting its worker",
+            process.name
+        );
+    }
+    for process in status.processes.iter().filter(|p| p.worker_restarts > 0) {
+        println!(
+            "{}: monitoring worker restarted {} times after crashing or stalling",
+            process.name, process.worker_restarts
+        );
+    }
+    for process in &status.processes {
+        let Some(baseline) = &process.baseline else {
+            continue;
+        };
+        println!(
+            "{}: learning the baseline since {}, p95 of {} samples so far {} MB",
+            process.name,
+            format_timestamp(Some(baseline.started)),
+            baseline.samples(),
+            baseline.percentile(95).unwrap_or_default() / MB
+        );
+    }
+    for process in &status.processes {
+        let stats = &process.restart_stats;
+        let Some(reclaimed) = stats.mean_reclaimed_bytes() else {
+            continue;
+        };
+        let rebreach = match stats.mean_rebreach_seconds() {
+            Some(seconds) => format!("threshold hit again after {} min on average", seconds / 60),
+            None => "threshold not hit again yet".to_string(),
+        };
+        println!(
+            "{}: {} restarts reclaimed {} MB on average, {}",
+            process.name,
+            stats.reclaimed_count,
+            reclaimed / MB,
+            rebreach
+        );
+    }
+    if let Some(count) = status.visible_windows {
+        println!("Visible top-level windows: {}", count);
+    }
+    for process in status.processes.iter().filter(|p| !p.metrics.is_empty()) {
+        let metrics: Vec<String> = process
+            .metrics
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        println!("{}: {}", process.name, metrics.join(", "));
+    }
+}
+
+// status --json 的输出；字段只增不改，脚本可以用 ConvertFrom-Json 读取
+#[derive(Serialize)]
+struct StatusOutput {
+    service_state: Option<String>,
+    monitor: Option<MonitorStatus>,
+    error: Option<String>,
+}
+
+// validate-config --json 的输出
+#[derive(Serialize)]
+struct ValidationOutput<'a> {
+    path: String,
+    valid: bool,
+    errors: &'a [ConfigIssue],
+    warnings: &'a [ConfigIssue],
+    config: Option<&'a Config>,
+}
+
+// restart-now、snooze、set-threshold、reload-config 加 --json 时的输出，
+// 其他命令的输出也以这两个字段开头：ok 为命令是否成功，message 为结果或失败原因
+#[derive(Serialize)]
+struct CommandOutput {
+    ok: bool,
+    message: String,
+}
+
+// --once --json 的输出；ok 为检查完成且没有需要处理的进程（退出码 0 或 3）
+#[derive(Serialize)]
+struct CheckOutput {
+    ok: bool,
+    message: String,
+    exit_code: i32,
+    processes: Vec<CheckedProcess>,
+}
+
+#[derive(Serialize)]
+struct CheckedProcess {
+    name: String,
+    pid: Option<u32>,
+    private_bytes: u64,
+    threshold_bytes: u64,
+    state: Option<TargetState>,
+}
+
+// export-diagnostics --json 的输出
+#[derive(Serialize)]
+struct ExportOutput {
+    ok: bool,
+    message: String,
+    path: String,
+    entries: Vec<String>,
+}
+
+// version --json 的输出
+#[derive(Serialize)]
+struct VersionOutput {
+    version: &'static str,
+    git_commit: &'static str,
+    build_date: &'static str,
+    target: &'static str,
+}
+
+fn print_json<T: Serialize>(value: &T) {
+    println!("{}", serde_json::to_string_pretty(value).unwrap());
+}
+
+pub fn status(json: bool, host: Option<String>) -> i32 {
+    // 远程时无法查询服务控制管理器，只显示监控返回的状态
+    let service_state = match &host {
+        Some(host) => Err(format!("remote host {}", host)),
+        None => query_service_state()
+            .map(|state| format!("{:?}", state))
+            .map_err(|e| e.to_string()),
+    };
+    let monitor = match send_request_to(host.as_deref(), &Request::Status) {
+        Ok(Response::Status(status)) => Ok(status),
+        Ok(Response::Error(e)) => Err(format!("Service returned an error: {}", e)),
+        Ok(response) => Err(format!("Unexpected response: {:?}", response)),
+        Err(e) => Err(format!("Failed to connect to the running monitor: {}", e)),
+    };
+    let code = if monitor.is_ok() { 0 } else { 1 };
+    if json {
+        let (monitor, error) = match monitor {
+            Ok(status) => (Some(status), None),
+            Err(e) => (None, Some(e)),
+        };
+        print_json(&StatusOutput {
+            service_state: service_state.ok(),
+            monitor,
+            error,
+        });
+        return code;
+    }
+    match service_state {
+        Ok(state) => println!("Service state: {}", state),
+        Err(e) => println!("Service state: unknown ({})", e),
+    }
+    match monitor {
+        Ok(status) => print_status_table(&status),
+        Err(e) => eprintln!("{}", e),
+    }
+    code
+}
+
+pub fn version(json: bool) {
+    if json {
+        print_json(&VersionOutput {
+            version: version::VERSION,
+            git_commit: version::GIT_COMMIT,
+            build_date: version::BUILD_DATE,
+            target: version::BUILD_TARGET,
+        });
+    } else {
+        println!("{}", version::version_banner());
+    }
+}
+
+fn send_command(host: Option<String>, request: &Request, json: bool) -> i32 {
+    let result = match send_request_to(host.as_deref(), request) {
+        Ok(Response::Message(message)) => Ok(message),
+        Ok(Response::Error(e)) => Err(format!("Command failed: {}", e)),
+        Ok(response) => Err(format!("Unexpected response: {:?}", response)),
+        Err(e) => Err(format!("Failed to connect to the running monitor: {}", e)),
+    };
+    let code = if result.is_ok() { 0 } else { 1 };
+    match result {
+        Ok(message) if json => print_json(&CommandOutput { ok: true, message }),
+        Err(message) if json => print_json(&CommandOutput { ok: false, message }),
+        Ok(message) => println!("{}", message),
+        Err(message) => eprintln!("{}", message),
+    }
+    code
+}
+
+pub fn restart_now(name: Option<String>, host: Option<String>, json: bool) -> i32 {
+    if !json {
+        println!("Requesting restart, this may take a while...");
+    }
+    send_command(host, &Request::RestartNow(name), json)
+}
+
+pub fn snooze(duration_secs: u64, host: Option<String>, json: bool) -> i32 {
+    send_command(host, &Request::Snooze(duration_secs), json)
+}
+
+pub fn reload_config(host: Option<String>, json: bool) -> i32 {
+    send_command(host, &Request::ReloadConfig, json)
+}
+
+pub fn set_threshold(bytes: u64, name: Option<String>, persist: bool, json: bool) -> i32 {
+    send_command(
+        None,
+        &Request::SetThreshold {
+            bytes,
+            name,
+            persist,
+        },
+        json,
+    )
+}
+
+fn print_issue(level: &str, issue: &ConfigIssue) {
+    println!("{}: {}", level, issue);
+}
+
+pub fn validate_config_file(path: Option<String>, json: bool) -> i32 {
+    let path = path.map(PathBuf::from).unwrap_or_else(config_file_path);
+    if !json {
+        println!("Validating {}", path.display());
+    }
+    let report = match std::fs::read_to_string(&path) {
+        Ok(text) => validate_config(&text),
+        Err(e) if json => ValidationReport {
+            errors: vec![ConfigIssue {
+                key: String::new(),
+                line: None,
+                message: format!("failed to read {}: {}", path.display(), e),
+            }],
+            ..Default::default()
+        },
+        Err(e) => {
+            eprintln!("error: failed to read {}: {}", path.display(), e);
+            return 1;
+        }
+    };
+    let valid = report.config.is_some() && report.errors.is_empty();
+    if json {
+        print_json(&ValidationOutput {
+            path: path.display().to_string(),
+            valid,
+            errors: &report.errors,
+            warnings: &report.warnings,
+            config: report.config.as_ref().filter(|_| valid),
+        });
+        return if valid { 0 } else { 1 };
+    }
+    for warning in &report.warnings {
+        print_issue("warning", warning);
+    }
+    for error in &report.errors {
+        print_issue("error", error);
+    }
+    match report.config {
+        Some(config) if valid => {
+            println!("Effective settings:");
+            println!("{}", serde_json::to_string_pretty(&config).unwrap());
+            println!("Config is valid");
+            0
+        }
+        _ => {
+            println!("Config is invalid: {} error(s)", report.errors.len());
+            1
+        }
+    }
+}
+
+fn once_exit_code(states: &[TargetState]) -> i32 {
+    states
+        .iter()
+        .map(|state| match state {
+            TargetState::Healthy | TargetState::Warning => 0,
+            TargetState::Verifying => ONCE_RESTARTED,
+            TargetState::Discovering => ONCE_NOT_FOUND,
+            // 重启失败、暂缓期间或没有配置重启动作
+            TargetState::Breached | TargetState::Restarting | TargetState::Backoff => {
+                ONCE_NOT_RESTARTED
+            }
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+fn once_message(code: i32) -> &'static str {
+    match code {
+        0 => "All monitored processes are healthy",
+        ONCE_RESTARTED => "Restarted a process over its threshold",
+        ONCE_NOT_FOUND => "A monitored process is not running",
+        ONCE_NOT_RESTARTED => "A process is over its threshold but was not restarted",
+        _ => "The check failed",
+    }
+}
+
+/// `--once` 在开始检查之前失败时输出结果，返回 `code`。
+pub fn once_failed(json: bool, code: i32, message: &str) -> i32 {
+    if json {
+        print_json(&CheckOutput {
+            ok: false,
+            message: message.to_string(),
+            exit_code: code,
+            processes: Vec::new(),
+        });
+    }
+    code
+}
+
+/// 计划任务使用的单次检查：采样一轮，超过阈值时按配置重启，返回退出码；
+/// `json` 为 `true` 时把结果和各进程的采样以 JSON 输出。
+pub fn check_once(config: &Config, json: bool) -> i32 {
+    // 读取上次保存的暂缓截止时间和重启记录
+    monitor_state::load_state();
+    let mut monitor = ProcessMonitor::from_config(config);
+    monitor.resume_backoff();
+    let samples = monitor.check();
+    if samples.is_empty() && !config.get_monitor_processes().is_empty() {
+        return once_failed(json, ONCE_CHECK_FAILED, "Failed to sample the processes");
+    }
+    let states: Vec<TargetState> = samples
+        .iter()
+        .filter_map(|sample| monitor.state(&sample.name))
+        .collect();
+    if let Err(e) = monitor_state::save_state() {
+        warn!(
+            "{}",
+            tr!("保存状态文件失败: {}", "Failed to save state file: {}", e)
+        );
+    }
+    let code = once_exit_code(&states);
+    if json {
+        print_json(&CheckOutput {
+            ok: code == 0 || code == ONCE_RESTARTED,
+            message: once_message(code).to_string(),
+            exit_code: code,
+            processes: samples
+                .iter()
+                .map(|sample| CheckedProcess {
+                    name: sample.name.clone(),
+                    pid: sample.pid,
+                    private_bytes: sample.private_bytes,
+                    threshold_bytes: sample.threshold_bytes,
+                    state: monitor.state(&sample.name),
+                })
+                .collect(),
+        });
+    }
+    code
+}
+
+pub fn export_diagnostics(path: String, json: bool) -> i32 {
+    let result = diagnostics::export(Path::new(&path));
+    if json {
+        let code = if result.is_ok() { 0 } else { 1 };
+        let (message, entries) = match result {
+            Ok(entries) => (format!("Diagnostics written to {}", path), entries),
+            Err(e) => (
+                format!("Failed to export diagnostics to {}: {}", path, e),
+                Vec::new(),
+            ),
+        };
+        print_json(&ExportOutput {
+            ok: code == 0,
+            message,
+            path,
+            entries,
+        });
+        return code;
+    }
+    match result {
+        Ok(entries) => {
+            for entry in entries {
+                println!("  {}", entry);
+            }
+            println!("Diagnostics written to {}", path);
+            0
+        }
+        Err(e) => {
+            eprintln!("Failed to export diagnostics to {}: {}", path, e);
+            1
+        }
+    }
+}
+
+pub fn logs(follow: bool, level: Option<Level>) -> i32 {
+    let path = log_file_path();
+    println!("==> {} <==", path.display());
+    match log_tail::tail(&path, follow, level) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", path.display(), e);
+            1
+        }
+    }
+}
+
+pub fn self_update(force: bool) -> i32 {
+    let config = ConfigManager::new(config_file_path()).load_or_create_default();
+    if config.update_url.is_empty() {
+        eprintln!("update_url is not set in {}", config_file_path().display());
+        return 1;
+    }
+    println!("Checking {}", config.update_url);
+    let downloading = |manifest: &updater::UpdateManifest| {
+        println!("Downloading {} from {}", manifest.version, manifest.url)
+    };
+    match updater::update(&config.update_url, force, downloading) {
+        Ok(UpdateResult::UpToDate(latest)) => {
+            println!(
+                "Already up to date (current {}, latest {})",
+                version::VERSION,
+                latest
+            );
+            0
+        }
+        Ok(UpdateResult::Updated(version)) => {
+            println!("Updated to {}", version);
+            match query_service_state() {
+                Ok(_) => match restart_service() {
+                    Ok(()) => {
+                        println!("Service restarted");
+                        0
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to restart the service: {}", e);
+                        1
+                    }
+                },
+                // 未安装服务时只替换程序
+                Err(_) => 0,
+            }
+        }
+        Err(e) => {
+            eprintln!("Update failed: {}", e);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_once_exit_code_reports_the_worst_state() {
+        assert_eq!(once_exit_code(&[]), 0);
+        assert_eq!(
+            once_exit_code(&[TargetState::Healthy, TargetState::Warning]),
+            0
+        );
+        assert_eq!(
+            once_exit_code(&[TargetState::Healthy, TargetState::Verifying]),
+            ONCE_RESTARTED
+        );
+        assert_eq!(
+            once_exit_code(&[TargetState::Verifying, TargetState::Discovering]),
+            ONCE_NOT_FOUND
+        );
+        assert_eq!(
+            once_exit_code(&[TargetState::Backoff, TargetState::Discovering]),
+            ONCE_NOT_RESTARTED
+        );
+    }
+}
@@ -1,28 +1,255 @@
+use crate::config_validator::validate_config;
+use crate::error::{self, Error};
+use crate::file_security;
 use crate::process_manager::ProcessType;
+use crate::process_name;
+use crate::tr;
+use log::{error, info, warn};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
 use std::path::PathBuf;
 
 include!(concat!(env!("OUT_DIR"), "/default_config.rs"));
+
+pub const CONFIG_FILE_NAME: &str = "process_guard_config.json";
+
+// 未配置阈值时取物理内存的 10%，但不超过 4 GB；无法读取物理内存时使用 1000 MB
+const DEFAULT_THRESHOLD_PERCENT_OF_RAM: u64 = 10;
+const MAX_DEFAULT_THRESHOLD_BYTES: u64 = 4 * 1024 * 1024 * 1024;
+const FALLBACK_THRESHOLD_BYTES: u64 = 1000 * 1024 * 1024;
+
 // Structs and Enums
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct MonitoredProcess {
     pub name: String,
-    pub memory_threshold_bytes: u64, // Bytes
+    // Bytes；未配置时为 0，加载配置时按物理内存计算，见 Config::resolve_default_thresholds
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub memory_threshold_bytes: u64,
     #[serde(default)]
     pub process_type: ProcessType,
     #[serde(default = "default_auto_start")]
     pub auto_start: bool,
+    // 重启之后执行的插件动作，见 plugins 模块
+    #[serde(default)]
+    pub actions: Vec<ActionConfig>,
+    // 大于 0 时先学习该小时数的内存基线，再以 p95 × learn_threshold_factor 作为阈值，见 baseline 模块
+    #[serde(default)]
+    pub learn_threshold_hours: u64,
+    #[serde(default = "default_learn_threshold_factor")]
+    pub learn_threshold_factor: f64,
+    // 大于 0 时只在最近这么多次采样的内存仍在增长时重启，稳定在阈值以上的进程不重启
+    #[serde(default)]
+    pub require_growth_samples: u64,
+    // 远程桌面会话中的实例使用的阈值和动作，未配置时与本地控制台会话相同
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote_session: Option<SessionProfile>,
+    // 内存接近阈值时开始记录堆和 VirtualAlloc 的 ETW 跟踪，重启前保存，见 etw_trace 模块
+    #[serde(default)]
+    pub etw_trace: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// 某类会话中的实例单独使用的阈值和动作。远程会话中 dwm 的内存表现与本地不同，
+/// 重启时的闪烁还可能影响远程连接。
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SessionProfile {
+    // Bytes；为 0 时使用进程的 memory_threshold_bytes
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub memory_threshold_bytes: u64,
+    // 为 false 时该类会话中的实例超过阈值只记录和告警，不重启
+    #[serde(default = "default_session_restart")]
+    pub restart: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ActionConfig {
+    pub name: String,
+    #[serde(default)]
+    pub options: serde_json::Value,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Config {
     pub processes: Vec<MonitoredProcess>,
     #[serde(default = "default_interval_seconds")]
     pub interval_seconds: u64,
+    // 内存达到阈值的该百分比后改用 near_threshold_interval_seconds 采样
+    #[serde(default = "default_near_threshold_percent")]
+    pub near_threshold_percent: u64,
+    #[serde(default = "default_near_threshold_interval_seconds")]
+    pub near_threshold_interval_seconds: u64,
+    // 回差：接近阈值后回落到 near_threshold_percent 减去该百分比以下、超过阈值后回落到
+    // 100 减去该百分比以下才算恢复，避免在阈值附近反复切换
+    #[serde(default = "default_recovery_margin_percent")]
+    pub recovery_margin_percent: u64,
+    // 在整数倍采样间隔的时刻采样（如间隔 60 秒时每个整分钟），便于对齐多台机器的数据
+    #[serde(default)]
+    pub align_samples: bool,
+    // 每轮记录自身用量后清空自身工作集
+    #[serde(default)]
+    pub trim_own_working_set: bool,
+    #[serde(default)]
+    pub sampling_backend: SamplingBackend,
+    // 初始化完成后从服务令牌中移除监控和重启不需要的特权
+    #[serde(default = "default_drop_privileges")]
+    pub drop_privileges: bool,
+    // 以低优先级和后台模式运行
+    #[serde(default = "default_background_mode")]
+    pub background_mode: bool,
+    // 允许其他计算机上的管理员通过命名管道远程管理，默认关闭
+    #[serde(default)]
+    pub remote_control: bool,
     #[serde(default = "default_db_config")]
-    pub db_config:DBConfig,
+    pub db_config: DBConfig,
+    // self-update 使用的更新清单地址，为空时不可更新
+    #[serde(default)]
+    pub update_url: String,
+    // 集中收集端地址，为空时不上报
+    #[serde(default)]
+    pub report_url: String,
+    #[serde(default = "default_report_interval_seconds")]
+    pub report_interval_seconds: u64,
+    // Azure Log Analytics 工作区，两项都设置时才写入
+    #[serde(default)]
+    pub log_analytics_workspace_id: String,
+    #[serde(default)]
+    pub log_analytics_shared_key: String,
+    #[serde(default = "default_log_analytics_log_type")]
+    pub log_analytics_log_type: String,
+    // windows_exporter textfile 收集器读取的 .prom 文件，为空时不输出
+    #[serde(default)]
+    pub textfile_path: String,
+    // CloudWatch 命名空间，为空时不写入；区域为空时按 AWS 的标准顺序获取
+    #[serde(default)]
+    pub cloudwatch_namespace: String,
+    #[serde(default)]
+    pub cloudwatch_region: String,
+    // Kafka 代理地址（逗号分隔）和主题，都设置时才发布；需要以 kafka 功能编译
+    #[serde(default)]
+    pub kafka_brokers: String,
+    #[serde(default)]
+    pub kafka_topic: String,
+    // 原样传给 librdkafka 的属性，如 security.protocol、sasl.username
+    #[serde(default)]
+    pub kafka_properties: BTreeMap<String, String>,
+    // gRPC 监听地址（如 127.0.0.1:50051），为空时不开放；非回环地址必须配置双向 TLS
+    #[serde(default)]
+    pub grpc_address: String,
+    #[serde(default)]
+    pub grpc_tls_cert: String,
+    #[serde(default)]
+    pub grpc_tls_key: String,
+    // 签发客户端证书的 CA
+    #[serde(default)]
+    pub grpc_client_ca: String,
+    // 未使用双向 TLS 时控制请求需要携带的令牌所在的文件，为空时只开放状态查询
+    #[serde(default)]
+    pub grpc_token_file: String,
+    // 重启失败时打开事件的值班告警渠道，为空时不使用
+    #[serde(default)]
+    pub pagerduty_routing_key: String,
+    #[serde(default)]
+    pub opsgenie_api_key: String,
+    // 欧盟区域的账户使用 https://api.eu.opsgenie.com
+    #[serde(default = "default_opsgenie_api_url")]
+    pub opsgenie_api_url: String,
+    // 重启前在进程所在会话中弹出提示并等待的秒数，为 0 时不提示
+    #[serde(default)]
+    pub restart_warning_seconds: u64,
+    // 提示改为询问：用户可以立即重启或推迟 1 小时，restart_warning_seconds 内未选择时自动重启
+    #[serde(default)]
+    pub restart_confirmation: bool,
+    // 系统待重启以完成更新时不重启进程，值班告警降级
+    #[serde(default)]
+    pub suppress_restarts_when_reboot_pending: bool,
+    // 使用电池供电时推迟重启，内存达到阈值的 urgent_restart_percent% 时仍立即重启
+    #[serde(default)]
+    pub defer_restarts_on_battery: bool,
+    #[serde(default = "default_urgent_restart_percent")]
+    pub urgent_restart_percent: u64,
+    // 使用电池供电时的采样间隔，为 0 时与 interval_seconds 相同
+    #[serde(default)]
+    pub battery_interval_seconds: u64,
+    // 会话中有全屏游戏或演示、或 capture_processes 中的录屏和直播软件运行时推迟重启，
+    // 内存达到阈值的 urgent_restart_percent% 时仍立即重启
+    #[serde(default)]
+    pub defer_restarts_for_games_and_capture: bool,
+    #[serde(default = "default_capture_processes")]
+    pub capture_processes: Vec<String>,
+    // 这些进程（视频会议软件）运行时推迟重启，直到通话结束，为空时不检查；
+    // do_not_disturb_requires_media 时还需进程所在会话正在使用摄像头或麦克风
+    #[serde(default)]
+    pub do_not_disturb_processes: Vec<String>,
+    #[serde(default)]
+    pub do_not_disturb_requires_media: bool,
+    // 为 false 时忽略用户在托盘中“今天不自动重启”的选择，由管理员统一控制
+    #[serde(default = "default_allow_restart_opt_out")]
+    pub allow_restart_opt_out: bool,
+    // ETW 跟踪文件的保存目录，相对路径相对于程序目录
+    #[serde(default = "default_etw_trace_dir")]
+    pub etw_trace_dir: String,
+    // 开始跟踪后超过该分钟数仍未重启时取消跟踪
+    #[serde(default = "default_etw_trace_max_minutes")]
+    pub etw_trace_max_minutes: u64,
+    // etw_trace_dir 中最多保留的跟踪文件数和总大小，超过时删除最旧的文件
+    #[serde(default = "default_etw_trace_max_files")]
+    pub etw_trace_max_files: u64,
+    #[serde(default = "default_etw_trace_max_mb")]
+    pub etw_trace_max_mb: u64,
+    // Rhai 规则脚本，为空时只按阈值判断
+    #[serde(default)]
+    pub rules_script: String,
+    // 日志语言，zh-CN 或 en-US
+    #[serde(default)]
+    pub log_language: LogLanguage,
+    // 覆盖提示、日志和告警文本的消息模板文件，为空时使用内置文本
+    #[serde(default)]
+    pub message_templates: String,
+    // 本地时间的维护窗口（如 02:00-05:00），预计在窗口之外超过阈值时提前在窗口内重启，为空时不提前重启
+    #[serde(default)]
+    pub maintenance_window: String,
+    // 按星期覆盖维护窗口，键为 mon..sun，值为时间段、all-day 或空字符串（当天没有维护窗口）
+    #[serde(default)]
+    pub maintenance_window_weekdays: BTreeMap<String, String>,
+    // 节假日（YYYY-MM-DD），全天都是维护窗口
+    #[serde(default)]
+    pub holidays: Vec<String>,
+    // 超过阈值时也只在维护窗口内重启
+    #[serde(default)]
+    pub restarts_only_in_maintenance_window: bool,
+    // 本地时间的免打扰时段（如 22:00-07:00），期间不打开值班事件，结束时发送汇总，为空时不限制
+    #[serde(default)]
+    pub quiet_hours: String,
 }
-#[derive(Serialize, Deserialize, Debug)]
+// 优先使用的读取被监控进程内存的方式，不同版本和加固程度的 Windows 需要不同的方式；
+// 选定的方式失败时依次改用其他方式，见 memory_sampler
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SamplingBackend {
+    // 按句柄、进程快照、PDH、WMI 的顺序尝试
+    #[default]
+    Auto,
+    // 保持进程句柄，通过 GetProcessMemoryInfo 读取
+    Handle,
+    // 每轮重新创建进程快照
+    Snapshot,
+    // PDH 性能计数器
+    Pdh,
+    // WMI 性能计数器
+    Wmi,
+}
+
+// 日志文本使用的语言
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub enum LogLanguage {
+    #[default]
+    #[serde(rename = "zh-CN")]
+    ZhCn,
+    #[serde(rename = "en-US")]
+    EnUs,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DBConfig {
     #[serde(default)]
     pub insert_into_db: bool,
@@ -38,8 +265,8 @@ pub struct ConfigManager {
     path: PathBuf,
 }
 
-pub fn default_db_config() ->DBConfig{
-    DBConfig{
+pub fn default_db_config() -> DBConfig {
+    DBConfig {
         insert_into_db: true,
         db_cleanup_hours: default_db_cleanup_hours(),
         db_vacuum_threshold_mb: default_db_vacuum_threshold_mb(),
@@ -63,19 +290,230 @@ fn default_auto_start() -> bool {
     false
 }
 
-fn default_interval_seconds() -> u64 {
+fn default_session_restart() -> bool {
+    true
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+/// 未配置阈值时使用的默认阈值：物理内存的 10%，不超过 4 GB。
+pub fn default_threshold_bytes(total_physical_bytes: u64) -> u64 {
+    (total_physical_bytes * DEFAULT_THRESHOLD_PERCENT_OF_RAM / 100).min(MAX_DEFAULT_THRESHOLD_BYTES)
+}
+
+pub fn default_learn_threshold_factor() -> f64 {
+    1.5
+}
+
+fn default_drop_privileges() -> bool {
+    true
+}
+
+fn default_background_mode() -> bool {
+    true
+}
+
+fn default_allow_restart_opt_out() -> bool {
+    true
+}
+
+fn default_log_analytics_log_type() -> String {
+    "ProcessGuard".to_string()
+}
+
+fn default_opsgenie_api_url() -> String {
+    "https://api.opsgenie.com".to_string()
+}
+
+fn default_report_interval_seconds() -> u64 {
+    60
+}
+
+pub fn default_interval_seconds() -> u64 {
     60
 }
 
+pub fn default_near_threshold_percent() -> u64 {
+    80
+}
+
+pub fn default_near_threshold_interval_seconds() -> u64 {
+    10
+}
+
+pub fn default_recovery_margin_percent() -> u64 {
+    10
+}
+
+pub fn default_urgent_restart_percent() -> u64 {
+    150
+}
+
+pub fn default_etw_trace_dir() -> String {
+    "traces".to_string()
+}
+
+pub fn default_etw_trace_max_minutes() -> u64 {
+    30
+}
+
+pub fn default_etw_trace_max_files() -> u64 {
+    10
+}
+
+pub fn default_etw_trace_max_mb() -> u64 {
+    4096
+}
+
+pub fn default_capture_processes() -> Vec<String> {
+    [
+        "obs64.exe",
+        "obs32.exe",
+        "Streamlabs OBS.exe",
+        "XSplit.Core.exe",
+    ]
+    .map(String::from)
+    .to_vec()
+}
+
+// 程序目录下的文件；取不到程序路径时退化为当前目录
+pub fn program_file_path(file_name: &str) -> PathBuf {
+    match std::env::current_exe() {
+        Ok(mut path) => {
+            path.set_file_name(file_name);
+            path
+        }
+        Err(e) => {
+            eprintln!("Failed to locate the program directory: {}", e);
+            PathBuf::from(file_name)
+        }
+    }
+}
+
+pub fn config_file_path() -> PathBuf {
+    program_file_path(CONFIG_FILE_NAME)
+}
+
+// 解析 "1500MB"、"1.5GB"、"1048576" 这类大小，单位按 1024 进制
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("Invalid size: {}", text))?;
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1024,
+        "M" | "MB" => 1024 * 1024,
+        "G" | "GB" => 1024 * 1024 * 1024,
+        _ => return Err(format!("Invalid size unit: {}", unit)),
+    };
+    // 阈值为 0 时进程总是超过阈值，会被不停重启
+    match (number * multiplier as f64) as u64 {
+        0 => Err(format!("Size must be greater than 0: {}", text)),
+        bytes => Ok(bytes),
+    }
+}
+
+// 解析 "2h"、"30m"、"90s"、"1d" 这类时长，不带单位时按秒计算，返回秒数
+pub fn parse_duration(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("Invalid duration: {}", text))?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "s" => 1,
+        "m" | "min" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("Invalid duration unit: {}", unit)),
+    };
+    Ok((number * multiplier as f64) as u64)
+}
+
 // Config Methods
 impl Config {
     fn default() -> Config {
-        serde_json::from_str(DEFAULT_CONFIG_JSON).unwrap()
+        serde_json::from_str(DEFAULT_CONFIG_JSON).expect("built-in default config is valid")
     }
 
     pub fn get_monitor_processes(&self) -> &Vec<MonitoredProcess> {
         &self.processes
     }
+
+    // 接近阈值时加快采样，但不会比正常间隔更慢；使用电池供电时正常间隔改为 battery_interval_seconds
+    pub fn sample_interval_seconds(&self, near_threshold: bool, on_battery: bool) -> u64 {
+        let interval = if on_battery && self.battery_interval_seconds > 0 {
+            self.battery_interval_seconds
+        } else {
+            self.interval_seconds
+        };
+        if near_threshold {
+            self.near_threshold_interval_seconds.min(interval)
+        } else {
+            interval
+        }
+    }
+
+    /// 为未配置阈值的进程按物理内存设置默认阈值，在加载或重新加载配置后调用；
+    /// 只修改运行中的配置，配置文件中仍不写阈值，换到内存不同的机器上会重新计算。
+    pub fn resolve_default_thresholds(&mut self, total_physical_bytes: Option<u64>) {
+        for process in self
+            .processes
+            .iter_mut()
+            .filter(|p| p.memory_threshold_bytes == 0)
+        {
+            process.memory_threshold_bytes = match total_physical_bytes {
+                Some(total) => {
+                    let threshold = default_threshold_bytes(total);
+                    info!(
+                        "{}",
+                        tr!(
+                            "{} 未配置内存阈值，按物理内存 {} MB 使用 {} MB",
+                            "{} has no memory threshold configured, using {} MB based on {} MB of physical memory",
+                            process.name,
+                            total / 1024 / 1024,
+                            threshold / 1024 / 1024
+                        )
+                    );
+                    threshold
+                }
+                None => {
+                    warn!(
+                        "{}",
+                        tr!(
+                            "{} 未配置内存阈值，且无法读取物理内存大小，使用 {} MB",
+                            "{} has no memory threshold configured and physical memory is unknown, using {} MB",
+                            process.name,
+                            FALLBACK_THRESHOLD_BYTES / 1024 / 1024
+                        )
+                    );
+                    FALLBACK_THRESHOLD_BYTES
+                }
+            };
+        }
+    }
+
+    pub fn find_monitor_process(&self, name: &str) -> Option<&MonitoredProcess> {
+        self.processes
+            .iter()
+            .find(|p| process_name::eq(&p.name, name))
+    }
+
+    pub fn find_monitor_process_mut(&mut self, name: &str) -> Option<&mut MonitoredProcess> {
+        self.processes
+            .iter_mut()
+            .find(|p| process_name::eq(&p.name, name))
+    }
 }
 
 // ConfigManager Methods
@@ -84,19 +522,94 @@ impl ConfigManager {
         ConfigManager { path }
     }
 
+    // 配置文件不存在时写入默认配置；所有者或权限不可信时拒绝读取；
+    // 与 validate-config 使用相同的检查，有错误时拒绝使用，警告只记录日志
+    pub fn load(&self) -> error::Result<Config> {
+        let config_str = match file_security::verify_trusted(&self.path)
+            .and_then(|_| std::fs::read_to_string(&self.path))
+        {
+            Ok(config_str) => config_str,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let default_config = Config::default();
+                match self.save(&default_config) {
+                    Ok(()) => info!(
+                        "{}",
+                        tr!(
+                            "已创建默认配置 {}",
+                            "Created default config {}",
+                            self.path.display()
+                        )
+                    ),
+                    Err(e) => error!(
+                        "{}",
+                        tr!(
+                            "写入默认配置失败: {}",
+                            "Failed to write default config: {}",
+                            e
+                        )
+                    ),
+                }
+                return Ok(default_config);
+            }
+            Err(e) => return Err(Error::io(&self.path, e)),
+        };
+        let report = validate_config(&config_str);
+        for warning in &report.warnings {
+            warn!(
+                "{}",
+                tr!(
+                    "配置文件 {} 的问题: {}",
+                    "Config file {} warning: {}",
+                    self.path.display(),
+                    warning
+                )
+            );
+        }
+        match report.config {
+            Some(config) if report.errors.is_empty() => Ok(config),
+            _ => Err(Error::Invalid {
+                path: self.path.clone(),
+                issues: report.errors.iter().map(ToString::to_string).collect(),
+            }),
+        }
+    }
+
+    // 读取失败时记录错误并使用默认配置，保证服务继续运行
     pub fn load_or_create_default(&self) -> Config {
-        let config_str = std::fs::read_to_string(&self.path).unwrap_or_else(|_| {
-            let default_config = Config::default();
-            let default_config_str = serde_json::to_string_pretty(&default_config).unwrap();
-            std::fs::write(&self.path, &default_config_str).unwrap();
-            default_config_str
-        });
-        serde_json::from_str(&config_str).unwrap()
+        self.load().unwrap_or_else(|e| {
+            error!(
+                "{}",
+                tr!(
+                    "加载配置失败，使用默认配置: {}",
+                    "Failed to load config, using defaults: {}",
+                    e
+                )
+            );
+            Config::default()
+        })
     }
 
-    #[allow(dead_code)]
-    pub fn save(&self, config: &Config) {
-        let config_str = serde_json::to_string_pretty(config).unwrap();
-        std::fs::write(&self.path, &config_str).unwrap();
+    pub fn save(&self, config: &Config) -> error::Result<()> {
+        let config_str = serde_json::to_string_pretty(config).map_err(|source| Error::Config {
+            path: self.path.clone(),
+            source,
+        })?;
+        file_security::reject_reparse_point(&self.path).map_err(|e| Error::io(&self.path, e))?;
+        let created = !self.path.exists();
+        std::fs::write(&self.path, config_str).map_err(|e| Error::io(&self.path, e))?;
+        if created {
+            if let Err(e) = file_security::restrict_file(&self.path) {
+                warn!(
+                    "{}",
+                    tr!(
+                        "无法限制 {} 的访问权限: {}",
+                        "Failed to restrict access to {}: {}",
+                        self.path.display(),
+                        e
+                    )
+                );
+            }
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,624 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::baseline::MAX_LEARN_THRESHOLD_HOURS;
+use crate::config_manager::{
+    default_interval_seconds, default_near_threshold_percent, Config, DBConfig, MonitoredProcess,
+};
+use crate::maintenance::{self, DailyWindow, WEEKDAYS};
+use crate::process_name;
+
+// 使用电池供电时最长的采样间隔，更长时看门狗要等很久才能发现监控停滞
+const MAX_BATTERY_INTERVAL_SECONDS: u64 = 3600;
+// 重启前提示或询问的最长等待时间，期间监控任务停在这一步
+const MAX_RESTART_WARNING_SECONDS: u64 = 300;
+
+const CONFIG_KEYS: &[&str] = &[
+    "processes",
+    "interval_seconds",
+    "near_threshold_percent",
+    "near_threshold_interval_seconds",
+    "recovery_margin_percent",
+    "align_samples",
+    "trim_own_working_set",
+    "sampling_backend",
+    "drop_privileges",
+    "background_mode",
+    "remote_control",
+    "db_config",
+    "update_url",
+    "report_url",
+    "report_interval_seconds",
+    "restart_warning_seconds",
+    "restart_confirmation",
+    "log_analytics_workspace_id",
+    "log_analytics_shared_key",
+    "log_analytics_log_type",
+    "textfile_path",
+    "cloudwatch_namespace",
+    "cloudwatch_region",
+    "kafka_brokers",
+    "kafka_topic",
+    "kafka_properties",
+    "grpc_address",
+    "grpc_tls_cert",
+    "grpc_tls_key",
+    "grpc_client_ca",
+    "grpc_token_file",
+    "pagerduty_routing_key",
+    "opsgenie_api_key",
+    "opsgenie_api_url",
+    "suppress_restarts_when_reboot_pending",
+    "defer_restarts_on_battery",
+    "urgent_restart_percent",
+    "battery_interval_seconds",
+    "defer_restarts_for_games_and_capture",
+    "capture_processes",
+    "do_not_disturb_processes",
+    "do_not_disturb_requires_media",
+    "allow_restart_opt_out",
+    "etw_trace_dir",
+    "etw_trace_max_minutes",
+    "etw_trace_max_files",
+    "etw_trace_max_mb",
+    "rules_script",
+    "log_language",
+    "message_templates",
+    "maintenance_window",
+    "maintenance_window_weekdays",
+    "holidays",
+    "restarts_only_in_maintenance_window",
+    "quiet_hours",
+];
+const PROCESS_KEYS: &[&str] = &[
+    "name",
+    "memory_threshold_bytes",
+    "process_type",
+    "auto_start",
+    "actions",
+    "learn_threshold_hours",
+    "learn_threshold_factor",
+    "require_growth_samples",
+    "remote_session",
+    "etw_trace",
+];
+const SESSION_PROFILE_KEYS: &[&str] = &["memory_threshold_bytes", "restart"];
+const DB_CONFIG_KEYS: &[&str] = &[
+    "insert_into_db",
+    "db_cleanup_hours",
+    "db_vacuum_threshold_mb",
+    "cleanup_interval_hours",
+];
+
+#[derive(Debug, Serialize)]
+pub struct ConfigIssue {
+    pub key: String,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.key.is_empty(), self.line) {
+            (true, Some(line)) => write!(f, "line {}: {}", line, self.message),
+            (true, None) => write!(f, "config: {}", self.message),
+            (false, Some(line)) => write!(f, "{} (line {}): {}", self.key, line, self.message),
+            (false, None) => write!(f, "{}: {}", self.key, self.message),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    pub errors: Vec<ConfigIssue>,
+    pub warnings: Vec<ConfigIssue>,
+    pub config: Option<Config>,
+}
+
+struct Validator<'a> {
+    text: &'a str,
+    report: ValidationReport,
+}
+
+impl<'a> Validator<'a> {
+    // 按键名查找所在行，start_line 用于定位到某个进程条目之后
+    fn find_line(&self, needle: &str, start_line: usize) -> Option<usize> {
+        let quoted = format!("\"{}\"", needle);
+        self.text
+            .lines()
+            .enumerate()
+            .skip(start_line.saturating_sub(1))
+            .find(|(_, line)| line.contains(&quoted))
+            .map(|(index, _)| index + 1)
+    }
+
+    fn issue(&self, key: &str, leaf: &str, start_line: usize, message: String) -> ConfigIssue {
+        ConfigIssue {
+            key: key.to_string(),
+            line: self.find_line(leaf, start_line),
+            message,
+        }
+    }
+
+    fn error(&mut self, key: &str, leaf: &str, start_line: usize, message: String) {
+        let issue = self.issue(key, leaf, start_line, message);
+        self.report.errors.push(issue);
+    }
+
+    fn warning(&mut self, key: &str, leaf: &str, start_line: usize, message: String) {
+        let issue = self.issue(key, leaf, start_line, message);
+        self.report.warnings.push(issue);
+    }
+
+    fn check_unknown_keys(&mut self, value: &Value, known: &[&str], prefix: &str, start: usize) {
+        if let Value::Object(map) = value {
+            for key in map.keys().filter(|k| !known.contains(&k.as_str())) {
+                let path = format!("{}{}", prefix, key);
+                self.warning(
+                    &path,
+                    key,
+                    start,
+                    "unknown key, it will be ignored".to_string(),
+                );
+            }
+        }
+    }
+
+    fn deserialize<T: DeserializeOwned>(
+        &mut self,
+        value: &Value,
+        prefix: &str,
+        start: usize,
+    ) -> Option<T> {
+        match serde_path_to_error::deserialize::<_, T>(value) {
+            Ok(parsed) => Some(parsed),
+            Err(e) => {
+                let path = e.path().to_string();
+                let leaf = path.rsplit('.').next().unwrap_or("").to_string();
+                let key = if path == "." {
+                    prefix.trim_end_matches('.').to_string()
+                } else {
+                    format!("{}{}", prefix, path)
+                };
+                self.error(&key, &leaf, start, e.inner().to_string());
+                None
+            }
+        }
+    }
+
+    fn check_processes(&mut self, value: &Value) {
+        let processes = match value.get("processes") {
+            Some(Value::Array(processes)) => processes,
+            Some(_) => {
+                self.error("processes", "processes", 1, "must be an array".to_string());
+                return;
+            }
+            None => {
+                self.error("processes", "processes", 1, "missing field".to_string());
+                return;
+            }
+        };
+        let mut names = HashSet::new();
+        for (index, process) in processes.iter().enumerate() {
+            let prefix = format!("processes[{}].", index);
+            let start = process
+                .get("name")
+                .and_then(Value::as_str)
+                .and_then(|name| self.find_line(name, 1))
+                .unwrap_or(1);
+            self.check_unknown_keys(process, PROCESS_KEYS, &prefix, start);
+            if let Some(profile) = process.get("remote_session") {
+                self.check_unknown_keys(
+                    profile,
+                    SESSION_PROFILE_KEYS,
+                    &format!("{}remote_session.", prefix),
+                    start,
+                );
+            }
+            // 省略阈值时按物理内存计算，明确写为 0 多半是写错了
+            let threshold_configured = process.get("memory_threshold_bytes").is_some();
+            let process: MonitoredProcess = match self.deserialize(process, &prefix, start) {
+                Some(process) => process,
+                None => continue,
+            };
+            if process.name.trim().is_empty() {
+                self.error(
+                    &format!("{}name", prefix),
+                    "name",
+                    start,
+                    "must not be empty".to_string(),
+                );
+            } else if !names.insert(process_name::key(&process.name)) {
+                self.error(
+                    &format!("{}name", prefix),
+                    "name",
+                    start,
+                    format!("duplicate process name {}", process.name),
+                );
+            } else if process_name::is_protected(&process.name) {
+                self.error(
+                    &format!("{}name", prefix),
+                    "name",
+                    start,
+                    format!(
+                        "{} is a protected system process and will never be restarted",
+                        process.name
+                    ),
+                );
+            }
+            if threshold_configured && process.memory_threshold_bytes == 0 {
+                self.error(
+                    &format!("{}memory_threshold_bytes", prefix),
+                    "memory_threshold_bytes",
+                    start,
+                    "must be greater than 0, omit it to derive the threshold from physical memory"
+                        .to_string(),
+                );
+            }
+            if process.learn_threshold_factor <= 1.0 {
+                self.error(
+                    &format!("{}learn_threshold_factor", prefix),
+                    "learn_threshold_factor",
+                    start,
+                    "must be greater than 1".to_string(),
+                );
+            }
+            if process.learn_threshold_hours > MAX_LEARN_THRESHOLD_HOURS {
+                self.error(
+                    &format!("{}learn_threshold_hours", prefix),
+                    "learn_threshold_hours",
+                    start,
+                    format!("must be at most {}", MAX_LEARN_THRESHOLD_HOURS),
+                );
+            }
+            if process.require_growth_samples == 1 {
+                self.error(
+                    &format!("{}require_growth_samples", prefix),
+                    "require_growth_samples",
+                    start,
+                    "growth needs at least 2 samples, use 0 to disable".to_string(),
+                );
+            }
+        }
+        if processes.is_empty() {
+            self.warning(
+                "processes",
+                "processes",
+                1,
+                "no process is monitored".to_string(),
+            );
+        }
+    }
+
+    fn check_db_config(&mut self, value: &Value) {
+        let db_config = match value.get("db_config") {
+            Some(db_config) => db_config,
+            None => return,
+        };
+        let start = self.find_line("db_config", 1).unwrap_or(1);
+        self.check_unknown_keys(db_config, DB_CONFIG_KEYS, "db_config.", start);
+        let db_config: DBConfig = match self.deserialize(db_config, "db_config.", start) {
+            Some(db_config) => db_config,
+            None => return,
+        };
+        if db_config.db_cleanup_hours <= 0 {
+            self.error(
+                "db_config.db_cleanup_hours",
+                "db_cleanup_hours",
+                start,
+                "must be greater than 0".to_string(),
+            );
+        }
+        if db_config.cleanup_interval_hours <= 0 {
+            self.error(
+                "db_config.cleanup_interval_hours",
+                "cleanup_interval_hours",
+                start,
+                "must be greater than 0".to_string(),
+            );
+        }
+    }
+
+    fn check_schedule(&mut self, value: &Value) {
+        if let Some(Value::Object(weekdays)) = value.get("maintenance_window_weekdays") {
+            for (day, window) in weekdays {
+                let key = format!("maintenance_window_weekdays.{}", day);
+                if !WEEKDAYS.contains(&day.as_str()) {
+                    self.error(
+                        &key,
+                        day,
+                        1,
+                        format!("unknown weekday, use one of {}", WEEKDAYS.join(", ")),
+                    );
+                } else if let Some(Err(e)) = window.as_str().map(maintenance::parse_day) {
+                    self.error(&key, day, 1, e);
+                }
+            }
+        }
+        if let Some(Value::Array(holidays)) = value.get("holidays") {
+            for holiday in holidays.iter().filter_map(Value::as_str) {
+                if let Err(e) = maintenance::parse_holiday(holiday) {
+                    self.error("holidays", "holidays", 1, e);
+                }
+            }
+        }
+    }
+
+    fn validate(mut self) -> ValidationReport {
+        let value: Value = match serde_json::from_str(self.text) {
+            Ok(value) => value,
+            Err(e) => {
+                self.report.errors.push(ConfigIssue {
+                    key: String::new(),
+                    line: Some(e.line()),
+                    message: e.to_string(),
+                });
+                return self.report;
+            }
+        };
+        if !value.is_object() {
+            self.error("", "", 1, "config must be a JSON object".to_string());
+            return self.report;
+        }
+        self.check_unknown_keys(&value, CONFIG_KEYS, "", 1);
+        self.check_processes(&value);
+        self.check_db_config(&value);
+        for key in [
+            "interval_seconds",
+            "near_threshold_interval_seconds",
+            "report_interval_seconds",
+            "etw_trace_max_minutes",
+            "etw_trace_max_files",
+            "etw_trace_max_mb",
+        ] {
+            if let Some(interval) = value.get(key) {
+                match interval.as_u64() {
+                    Some(0) | None => {
+                        self.error(key, key, 1, "must be a positive integer".to_string())
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+        if let Some(percent) = value.get("near_threshold_percent") {
+            match percent.as_u64() {
+                Some(1..=100) => {}
+                _ => self.error(
+                    "near_threshold_percent",
+                    "near_threshold_percent",
+                    1,
+                    "must be an integer between 1 and 100".to_string(),
+                ),
+            }
+        }
+        if let Some(margin) = value.get("recovery_margin_percent") {
+            let near = value
+                .get("near_threshold_percent")
+                .and_then(Value::as_u64)
+                .unwrap_or_else(default_near_threshold_percent);
+            if margin.as_u64().is_none_or(|margin| margin >= near) {
+                self.error(
+                    "recovery_margin_percent",
+                    "recovery_margin_percent",
+                    1,
+                    format!("must be an integer below near_threshold_percent ({})", near),
+                );
+            }
+        }
+        if let Some(percent) = value.get("urgent_restart_percent") {
+            if percent.as_u64().is_none_or(|percent| percent <= 100) {
+                self.error(
+                    "urgent_restart_percent",
+                    "urgent_restart_percent",
+                    1,
+                    "must be an integer above 100".to_string(),
+                );
+            }
+        }
+        if let Some(battery) = value
+            .get("battery_interval_seconds")
+            .and_then(Value::as_u64)
+        {
+            let interval = value
+                .get("interval_seconds")
+                .and_then(Value::as_u64)
+                .unwrap_or_else(default_interval_seconds);
+            if battery > MAX_BATTERY_INTERVAL_SECONDS {
+                self.error(
+                    "battery_interval_seconds",
+                    "battery_interval_seconds",
+                    1,
+                    format!("must be at most {}", MAX_BATTERY_INTERVAL_SECONDS),
+                );
+            } else if battery > 0 && battery < interval {
+                self.warning(
+                    "battery_interval_seconds",
+                    "battery_interval_seconds",
+                    1,
+                    format!(
+                        "is shorter than interval_seconds ({}), samples more often on battery",
+                        interval
+                    ),
+                );
+            }
+        }
+        if let Some(url) = value.get("report_url").and_then(Value::as_str) {
+            if !url.is_empty() && !url.starts_with("http://") && !url.starts_with("https://") {
+                self.error(
+                    "report_url",
+                    "report_url",
+                    1,
+                    "must be an http:// or https:// URL".to_string(),
+                );
+            }
+        }
+        if let Some(path) = value.get("textfile_path").and_then(Value::as_str) {
+            if !path.is_empty() && !path.ends_with(".prom") {
+                self.warning(
+                    "textfile_path",
+                    "textfile_path",
+                    1,
+                    "windows_exporter only collects files ending in .prom".to_string(),
+                );
+            }
+        }
+        if let Some(warning) = value.get("restart_warning_seconds").and_then(Value::as_u64) {
+            if warning > MAX_RESTART_WARNING_SECONDS {
+                self.error(
+                    "restart_warning_seconds",
+                    "restart_warning_seconds",
+                    1,
+                    format!("must be at most {}", MAX_RESTART_WARNING_SECONDS),
+                );
+            }
+        }
+        if value.get("restart_confirmation") == Some(&Value::Bool(true))
+            && value
+                .get("restart_warning_seconds")
+                .and_then(Value::as_u64)
+                .unwrap_or_default()
+                == 0
+        {
+            self.warning(
+                "restart_confirmation",
+                "restart_confirmation",
+                1,
+                "has no effect unless restart_warning_seconds is greater than 0".to_string(),
+            );
+        }
+        self.check_schedule(&value);
+        for key in ["maintenance_window", "quiet_hours"] {
+            if let Some(window) = value
+                .get(key)
+                .and_then(Value::as_str)
+                .filter(|window| !window.is_empty())
+            {
+                if let Err(e) = DailyWindow::parse(window) {
+                    self.error(key, key, 1, e);
+                }
+            }
+        }
+        if self.report.errors.is_empty() {
+            self.report.config = self.deserialize(&value, "", 1);
+        }
+        self.report
+    }
+}
+
+pub fn validate_config(text: &str) -> ValidationReport {
+    Validator {
+        text,
+        report: ValidationReport::default(),
+    }
+    .validate()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_default_config() {
+        let report = validate_config(crate::config_manager::DEFAULT_CONFIG_JSON);
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert!(report.config.is_some());
+    }
+
+    #[test]
+    fn test_validate_sampling_backend() {
+        let text = r#"{
+  "processes": [],
+  "sampling_backend": "etw"
+}"#;
+        let report = validate_config(text);
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].key, "sampling_backend");
+        assert_eq!(report.errors[0].line, Some(3));
+    }
+
+    #[test]
+    fn test_validate_maintenance_window() {
+        let text = r#"{
+  "processes": [],
+  "maintenance_window": "02:00"
+}"#;
+        let report = validate_config(text);
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].key, "maintenance_window");
+        assert_eq!(report.errors[0].line, Some(3));
+    }
+
+    #[test]
+    fn test_validate_maintenance_schedule() {
+        let text = r#"{
+  "processes": [],
+  "maintenance_window_weekdays": { "sat": "all-day", "sun": "", "fri": "9-17", "weekend": "all-day" },
+  "holidays": ["2024-12-25", "12/26"]
+}"#;
+        let report = validate_config(text);
+        let keys: Vec<_> = report.errors.iter().map(|e| e.key.as_str()).collect();
+        assert_eq!(
+            keys,
+            vec![
+                "maintenance_window_weekdays.fri",
+                "maintenance_window_weekdays.weekend",
+                "holidays",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_syntax_error_has_line() {
+        let report = validate_config("{\n  \"processes\": [\n}");
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].line, Some(3));
+        assert!(report.config.is_none());
+    }
+
+    #[test]
+    fn test_validate_collects_every_error() {
+        let text = r#"{
+  "processes": [
+    {
+      "name": "dwm.exe",
+      "memory_threshold_bytes": "1GB"
+    },
+    {
+      "name": "app.exe",
+      "memory_threshold_bytes": 0,
+      "auto_restart": true,
+      "learn_threshold_hours": 100000
+    }
+  ],
+  "interval_seconds": 0,
+  "battery_interval_seconds": 86400,
+  "restart_warning_seconds": 3600,
+  "db_config": {
+    "cleanup_interval_hours": 0
+  }
+}"#;
+        let report = validate_config(text);
+        let keys: Vec<_> = report.errors.iter().map(|e| e.key.as_str()).collect();
+        assert_eq!(
+            keys,
+            vec![
+                "processes[0].memory_threshold_bytes",
+                "processes[1].memory_threshold_bytes",
+                "processes[1].learn_threshold_hours",
+                "db_config.cleanup_interval_hours",
+                "interval_seconds",
+                "battery_interval_seconds",
+                "restart_warning_seconds",
+            ]
+        );
+        assert_eq!(report.errors[0].line, Some(5));
+        assert_eq!(report.errors[1].line, Some(9));
+        assert_eq!(report.warnings.len(), 1);
+        assert_eq!(report.warnings[0].key, "processes[1].auto_restart");
+        assert_eq!(report.warnings[0].line, Some(10));
+        assert!(report.config.is_none());
+    }
+}
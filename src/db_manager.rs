@@ -1,23 +1,44 @@
 use lazy_static::lazy_static;
-use log::info;
+use log::{error, info};
 use rusqlite::{params, Connection, Result};
 use std::{fs, path::PathBuf, sync::Mutex};
 
+use crate::config_manager::program_file_path;
 use crate::process_manager::ProcessInfo;
+use crate::tr;
 
 lazy_static! {
-    pub static ref DB_CONNECTION: Mutex<DBConnection> = Mutex::new(DBConnection::new().unwrap());
+    pub static ref DB_CONNECTION: Mutex<DBConnection> =
+        Mutex::new(DBConnection::new().expect("in-memory database is always available"));
 }
 
 pub struct DBConnection {
     conn: Connection,
     file_path: PathBuf,
 }
+pub fn db_file_path() -> PathBuf {
+    program_file_path("process_info.db")
+}
+
 impl DBConnection {
+    // 数据库文件无法打开时退化为内存数据库，记录照常写入但不会保留
     fn new() -> Result<Self> {
-        let mut file_path = std::env::current_exe().unwrap();
-        file_path.set_file_name("process_info.db");
-        DBConnection::from_path(file_path)
+        DBConnection::from_path(db_file_path()).or_else(|e| {
+            error!(
+                "{}",
+                tr!(
+                    "打开数据库失败，改用内存数据库: {}",
+                    "Failed to open database, falling back to memory: {}",
+                    e
+                )
+            );
+            let result = DBConnection {
+                conn: Connection::open_in_memory()?,
+                file_path: PathBuf::new(),
+            };
+            result.create_table()?;
+            Ok(result)
+        })
     }
     fn from_path(file_path: PathBuf) -> Result<Self> {
         let conn = Connection::open(&file_path)?;
@@ -37,17 +58,43 @@ impl DBConnection {
             pid INTEGER NOT NULL,
             thread_count INTEGER NOT NULL,
             private_bytes INTEGER,
-            working_set INTEGER
+            working_set INTEGER,
+            visible_windows INTEGER
+        )",
+            [],
+        )?;
+        // 旧版本创建的表没有 visible_windows 列
+        if self
+            .conn
+            .prepare("SELECT visible_windows FROM process_info LIMIT 0")
+            .is_err()
+        {
+            self.conn.execute(
+                "ALTER TABLE process_info ADD COLUMN visible_windows INTEGER",
+                [],
+            )?;
+        }
+        // 与采样一起查看的事件（如插拔显示器），dwm 的泄漏常与扩展坞的连接和断开有关
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS annotations (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+            message TEXT NOT NULL
         )",
             [],
         )?;
         Ok(())
     }
-    pub fn execute_batch_insert(&mut self, process_infos: &[ProcessInfo]) -> Result<()> {
+    // visible_windows 为本轮采样时交互会话中可见的顶层窗口数，未知时为 NULL
+    pub fn execute_batch_insert(
+        &mut self,
+        process_infos: &[ProcessInfo],
+        visible_windows: Option<u32>,
+    ) -> Result<()> {
         let tx = self.conn.transaction()?;
         {
             let mut stmt = tx.prepare(
-                "INSERT INTO process_info (pid, name,thread_count, private_bytes, working_set ) VALUES (?1, ?2, ?3, ?4, ?5 )",
+                "INSERT INTO process_info (pid, name,thread_count, private_bytes, working_set, visible_windows ) VALUES (?1, ?2, ?3, ?4, ?5, ?6 )",
             )?;
             for process_info in process_infos {
                 stmt.execute(params![
@@ -56,12 +103,20 @@ impl DBConnection {
                     process_info.thread_count,
                     process_info.private_bytes,
                     process_info.working_set,
+                    visible_windows,
                 ])?;
             }
         }
         tx.commit()?;
         Ok(())
     }
+    pub fn insert_annotation(&mut self, message: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO annotations (message) VALUES (?1)",
+            params![message],
+        )?;
+        Ok(())
+    }
     pub fn cleanup_old_data(&mut self, hours: i64, vacuum_threshold_mb: u64) -> Result<()> {
         let tx = self.conn.transaction()?;
         {
@@ -69,13 +124,16 @@ impl DBConnection {
                 "DELETE FROM process_info WHERE timestamp < datetime('now', ?1 || ' hours')",
                 params![-hours],
             )?;
-            info!("Deleted rows: {}", changes);
+            info!("{}", tr!("已删除行数: {}", "Deleted rows: {}", changes));
+            tx.execute(
+                "DELETE FROM annotations WHERE timestamp < datetime('now', ?1 || ' hours')",
+                params![-hours],
+            )?;
         }
         tx.commit()?;
-        let metadata = fs::metadata(&self.file_path);
-        let file_size_mb = metadata.unwrap().len() / 1024 / 1024;
+        let file_size_mb = fs::metadata(&self.file_path).map_or(0, |m| m.len() / 1024 / 1024);
         if file_size_mb > vacuum_threshold_mb {
-            info!("Vacuuming database");
+            info!("{}", tr!("正在压缩数据库", "Vacuuming database"));
             self.conn.execute("VACUUM", [])?;
         }
         Ok(())
@@ -97,6 +155,7 @@ mod tests {
             name: "P1".to_string(),
             thread_count: 10,
             private_bytes: 2048,
+            pagefile_usage: 2048,
             working_set: 4096,
         }];
 
@@ -182,6 +241,7 @@ mod tests {
                 name: "P1".to_string(),
                 thread_count: 10,
                 private_bytes: 2048,
+                pagefile_usage: 2048,
                 working_set: 4096,
             },
             ProcessInfo {
@@ -189,12 +249,13 @@ mod tests {
                 name: "P2".to_string(),
                 thread_count: 20,
                 private_bytes: 4096,
+                pagefile_usage: 4096,
                 working_set: 8192,
             },
         ];
 
-        conn.execute_batch_insert(&process_infos).unwrap();
-        conn.execute_batch_insert(&process_infos).unwrap();
+        conn.execute_batch_insert(&process_infos, Some(12)).unwrap();
+        conn.execute_batch_insert(&process_infos, None).unwrap();
 
         let mut stmt = conn
             .conn
@@ -203,5 +264,25 @@ mod tests {
         let count: i64 = stmt.query_row([], |row| row.get(0)).unwrap();
 
         assert_eq!(count, 4);
+
+        let mut stmt = conn
+            .conn
+            .prepare("SELECT COUNT(visible_windows) FROM process_info")
+            .unwrap();
+        let count: i64 = stmt.query_row([], |row| row.get(0)).unwrap();
+        assert_eq!(count, 2);
+    }
+    #[test]
+    fn test_insert_annotation() {
+        let test_db = "test_annotations.db";
+        std::fs::remove_file(test_db).unwrap_or_default();
+        let mut conn = DBConnection::from_path(PathBuf::from(test_db)).unwrap();
+
+        conn.insert_annotation("Displays: 1920x1080 96dpi").unwrap();
+        let message: String = conn
+            .conn
+            .query_row("SELECT message FROM annotations", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(message, "Displays: 1920x1080 96dpi");
     }
 }
@@ -0,0 +1,34 @@
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+// 服务路径上的错误统一向上返回，由调用方记录日志后降级处理，而不是 panic
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("invalid config {}: {source}", path.display())]
+    Config {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+    // 格式正确但取值无效，如采样间隔为 0，见 config_validator
+    #[error("invalid config {}: {}", path.display(), issues.join("; "))]
+    Invalid { path: PathBuf, issues: Vec<String> },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn io(path: impl Into<PathBuf>, source: io::Error) -> Error {
+        Error::Io {
+            path: path.into(),
+            source,
+        }
+    }
+}
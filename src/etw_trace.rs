@@ -0,0 +1,376 @@
+//! ETW 跟踪：`etw_trace` 为 `true` 的进程内存接近阈值时，用系统自带的 Windows Performance
+//! Recorder（wpr.exe）开始记录堆和 VirtualAlloc 分配的调用栈，重启前停止并保存为 `etw_trace_dir`
+//! 下的 .etl 文件，开发者可以用 WPA 找到泄漏的分配位置。
+//!
+//! WPR 记录在内存中的环形缓冲区，只保留最近的事件，占用的内存有上限；同一时间只能有一个 WPR 会话，
+//! 已在为其他进程跟踪时不再开始。内存回落或跟踪超过 `etw_trace_max_minutes` 仍未重启时取消跟踪，
+//! 不保存文件。
+//!
+//! 本程序不生成转储文件，没有可以放在旁边的转储目录，因此跟踪单独保存在 `etw_trace_dir`。
+//! 每个 .etl 可能有数百 MB，保存后按 `etw_trace_max_files` 和 `etw_trace_max_mb` 删除最旧的文件。
+
+use chrono::Local;
+use lazy_static::lazy_static;
+use log::{info, warn};
+use std::cmp::Reverse;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::config_manager::{
+    default_etw_trace_dir, default_etw_trace_max_files, default_etw_trace_max_mb,
+    default_etw_trace_max_minutes, program_file_path, Config,
+};
+use crate::system_api::SystemApi;
+use crate::tr;
+
+// WPR 内置的记录配置：Heap 记录启用了堆跟踪的进程的堆分配，VirtualAllocation 记录所有进程的 VirtualAlloc
+const WPR_PROFILES: &[&str] = &["Heap", "VirtualAllocation"];
+/// 一次 wpr 命令的最长等待时间。停止时要合并缓冲区并收集模块信息，堆跟踪较大时需要数十秒；
+/// 重启流程在此期间阻塞，看门狗的停滞判断为此留出了时间。
+pub const WPR_TIMEOUT: Duration = Duration::from_secs(120);
+const WPR_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+lazy_static! {
+    static ref ACTIVE: Mutex<Option<Trace>> = Mutex::new(None);
+}
+
+struct Trace {
+    process: String,
+    started: Instant,
+    // 开始失败或超时取消后为 false，内存回落之前不再重新开始
+    recording: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceSettings {
+    pub dir: PathBuf,
+    pub max_duration: Duration,
+    // 保存后最多保留的 .etl 文件数和总字节数
+    pub max_files: usize,
+    pub max_bytes: u64,
+}
+
+impl Default for TraceSettings {
+    fn default() -> TraceSettings {
+        TraceSettings {
+            dir: program_file_path(&default_etw_trace_dir()),
+            max_duration: Duration::from_secs(default_etw_trace_max_minutes() * 60),
+            max_files: default_etw_trace_max_files() as usize,
+            max_bytes: default_etw_trace_max_mb() * 1024 * 1024,
+        }
+    }
+}
+
+impl TraceSettings {
+    pub fn from_config(config: &Config) -> TraceSettings {
+        let dir = Path::new(&config.etw_trace_dir);
+        TraceSettings {
+            dir: if dir.is_absolute() {
+                dir.to_path_buf()
+            } else {
+                program_file_path(&config.etw_trace_dir)
+            },
+            max_duration: Duration::from_secs(config.etw_trace_max_minutes * 60),
+            max_files: config.etw_trace_max_files as usize,
+            max_bytes: config.etw_trace_max_mb * 1024 * 1024,
+        }
+    }
+}
+
+/// 每轮采样后调用，`near` 为进程内存是否已接近或超过阈值。
+pub fn update(api: &dyn SystemApi, process: &str, near: bool, settings: &TraceSettings) {
+    let mut active = ACTIVE.lock().unwrap_or_else(PoisonError::into_inner);
+    match active.as_mut() {
+        None if near => *active = Some(start(api, process)),
+        Some(trace) if trace.process == process && !near => {
+            if trace.recording {
+                info!(
+                    "{}",
+                    tr!(
+                        "{} 内存已回落，取消 ETW 跟踪",
+                        "{} memory dropped back, cancelled the ETW trace",
+                        process
+                    )
+                );
+                cancel(api);
+            }
+            *active = None;
+        }
+        Some(trace)
+            if trace.process == process
+                && trace.recording
+                && trace.started.elapsed() >= settings.max_duration =>
+        {
+            warn!(
+                "{}",
+                tr!(
+                    "{} 的 ETW 跟踪已记录 {} 分钟仍未重启，取消跟踪",
+                    "The ETW trace of {} has been recording for {} minutes without a restart, cancelled it",
+                    process,
+                    settings.max_duration.as_secs() / 60
+                )
+            );
+            cancel(api);
+            trace.recording = false;
+        }
+        _ => {}
+    }
+}
+
+fn start(api: &dyn SystemApi, process: &str) -> Trace {
+    let result = api.start_trace();
+    match &result {
+        Ok(()) => info!(
+            "{}",
+            tr!(
+                "{} 内存接近阈值，开始记录 ETW 跟踪",
+                "{} memory is near the threshold, started recording an ETW trace",
+                process
+            )
+        ),
+        Err(e) => warn!(
+            "{}",
+            tr!(
+                "开始记录 {} 的 ETW 跟踪失败: {}",
+                "Failed to start recording an ETW trace of {}: {}",
+                process,
+                e
+            )
+        ),
+    }
+    Trace {
+        process: process.to_string(),
+        started: Instant::now(),
+        recording: result.is_ok(),
+    }
+}
+
+/// 重启前调用：停止该进程的跟踪并保存到 `settings.dir`，返回保存的文件。
+/// 必须在结束进程之前停止，WPR 停止时才能收集到进程的模块信息，调用栈才能解析。
+pub fn finish(api: &dyn SystemApi, process: &str, settings: &TraceSettings) -> Option<PathBuf> {
+    let mut active = ACTIVE.lock().unwrap_or_else(PoisonError::into_inner);
+    if active.as_ref()?.process != process {
+        return None;
+    }
+    if !active.take()?.recording {
+        return None;
+    }
+    let stem = Path::new(process)
+        .file_stem()
+        .map_or(process.into(), |stem| stem.to_string_lossy());
+    let path = settings.dir.join(format!(
+        "{}_{}.etl",
+        stem,
+        Local::now().format("%Y%m%d_%H%M%S")
+    ));
+    match std::fs::create_dir_all(&settings.dir).and_then(|_| api.stop_trace(Some(&path))) {
+        Ok(()) => {
+            info!(
+                "{}",
+                tr!(
+                    "已保存 {} 的 ETW 跟踪: {}",
+                    "Saved the ETW trace of {}: {}",
+                    process,
+                    path.display()
+                )
+            );
+            prune(&settings.dir, settings.max_files, settings.max_bytes);
+            Some(path)
+        }
+        Err(e) => {
+            warn!(
+                "{}",
+                tr!(
+                    "保存 {} 的 ETW 跟踪失败: {}",
+                    "Failed to save the ETW trace of {}: {}",
+                    process,
+                    e
+                )
+            );
+            cancel(api);
+            None
+        }
+    }
+}
+
+/// `finish` 之后用户推迟了重启时调用：重新开始记录，推迟结束后重启时保存的跟踪仍包含之前的分配。
+pub fn resume(api: &dyn SystemApi, process: &str) {
+    let mut active = ACTIVE.lock().unwrap_or_else(PoisonError::into_inner);
+    if active.is_none() {
+        *active = Some(start(api, process));
+    }
+}
+
+// 按修改时间从新到旧保留 .etl 文件，超过数量或总大小的较旧文件删除
+fn prune(dir: &Path, max_files: usize, max_bytes: u64) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut traces: Vec<(SystemTime, u64, PathBuf)> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            entry
+                .path()
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("etl"))
+        })
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            Some((metadata.modified().ok()?, metadata.len(), entry.path()))
+        })
+        .collect();
+    traces.sort_by_key(|(modified, _, _)| Reverse(*modified));
+    let mut total = 0u64;
+    for (index, (_, len, path)) in traces.into_iter().enumerate() {
+        total = total.saturating_add(len);
+        // 至少保留刚保存的文件
+        if index == 0 || (index < max_files && total <= max_bytes) {
+            continue;
+        }
+        match fs::remove_file(&path) {
+            Ok(()) => info!(
+                "{}",
+                tr!(
+                    "已删除较旧的 ETW 跟踪: {}",
+                    "Deleted an older ETW trace: {}",
+                    path.display()
+                )
+            ),
+            Err(e) => warn!(
+                "{}",
+                tr!(
+                    "删除较旧的 ETW 跟踪 {} 失败: {}",
+                    "Failed to delete the older ETW trace {}: {}",
+                    path.display(),
+                    e
+                )
+            ),
+        }
+    }
+}
+
+fn cancel(api: &dyn SystemApi) {
+    if let Err(e) = api.stop_trace(None) {
+        warn!(
+            "{}",
+            tr!(
+                "取消 ETW 跟踪失败: {}",
+                "Failed to cancel the ETW trace: {}",
+                e
+            )
+        );
+    }
+}
+
+/// 服务停止时调用，WPR 会话不随服务退出，需要取消仍在进行的跟踪。
+pub fn shutdown() {
+    let active = ACTIVE.lock().unwrap_or_else(PoisonError::into_inner);
+    if active.as_ref().is_some_and(|trace| trace.recording) {
+        let _ = stop_wpr(None);
+    }
+}
+
+// 运行 wpr，超过 WPR_TIMEOUT 时结束它并返回 TimedOut
+fn wpr(args: &[&str]) -> io::Result<()> {
+    let mut child = Command::new("wpr")
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // 在单独的线程中读取输出，避免管道写满后 wpr 阻塞
+    let stdout = read_output(child.stdout.take());
+    let stderr = read_output(child.stderr.take());
+    let deadline = Instant::now() + WPR_TIMEOUT;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!(
+                    "wpr {} did not finish within {} seconds",
+                    args.join(" "),
+                    WPR_TIMEOUT.as_secs()
+                ),
+            ));
+        }
+        thread::sleep(WPR_POLL_INTERVAL);
+    };
+    if status.success() {
+        return Ok(());
+    }
+    // WPR 把错误信息写到标准输出
+    Err(io::Error::other(format!(
+        "wpr {} failed: {}{}",
+        args.join(" "),
+        stdout.join().unwrap_or_default(),
+        stderr.join().unwrap_or_default()
+    )))
+}
+
+fn read_output(pipe: Option<impl Read + Send + 'static>) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut output);
+        }
+        String::from_utf8_lossy(&output).trim().to_string()
+    })
+}
+
+pub fn start_wpr() -> io::Result<()> {
+    let args: Vec<&str> = WPR_PROFILES
+        .iter()
+        .flat_map(|profile| ["-start", profile])
+        .collect();
+    wpr(&args)
+}
+
+pub fn stop_wpr(etl: Option<&Path>) -> io::Result<()> {
+    match etl {
+        Some(path) => wpr(&["-stop", &path.to_string_lossy()]),
+        None => wpr(&["-cancel"]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prune_keeps_newest_traces() {
+        let dir = std::env::temp_dir().join("process_guard_prune_test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let now = SystemTime::now();
+        for (index, name) in ["a.etl", "b.etl", "c.etl", "d.etl"].iter().enumerate() {
+            let file = fs::File::create(dir.join(name)).unwrap();
+            file.set_len(100).unwrap();
+            file.set_modified(now - Duration::from_secs(index as u64 * 60))
+                .unwrap();
+        }
+        fs::write(dir.join("notes.txt"), "not a trace").unwrap();
+
+        prune(&dir, 3, 1024);
+        assert!(!dir.join("d.etl").exists());
+        assert!(dir.join("c.etl").exists());
+
+        // 总大小超过上限时也删除，但保留最新的文件
+        prune(&dir, 3, 150);
+        assert!(dir.join("a.etl").exists());
+        assert!(!dir.join("b.etl").exists());
+        assert!(!dir.join("c.etl").exists());
+        prune(&dir, 3, 0);
+        assert!(dir.join("a.etl").exists());
+        assert!(dir.join("notes.txt").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
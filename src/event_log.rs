@@ -0,0 +1,32 @@
+use windows::core::PCWSTR;
+use windows::Win32::System::EventLog::{
+    DeregisterEventSource, RegisterEventSourceW, ReportEventW, EVENTLOG_ERROR_TYPE,
+};
+
+use crate::process_manager::to_wide_string;
+use crate::SERVICE_NAME;
+
+// 单条事件的字符串不能超过 31839 个字符
+const MAX_EVENT_CHARS: usize = 31_000;
+
+// 写入 Windows 应用程序日志，来源为服务名。未注册事件来源时事件查看器会附加一段说明，但消息内容完整
+pub fn report_error(message: &str) -> windows::core::Result<()> {
+    let source = to_wide_string(SERVICE_NAME);
+    let message: String = message.chars().take(MAX_EVENT_CHARS).collect();
+    let message = to_wide_string(&message);
+    unsafe {
+        let event_log = RegisterEventSourceW(PCWSTR::null(), PCWSTR(source.as_ptr()))?;
+        let result = ReportEventW(
+            event_log,
+            EVENTLOG_ERROR_TYPE,
+            0,
+            1,
+            None,
+            0,
+            Some(&[PCWSTR(message.as_ptr())]),
+            None,
+        );
+        let _ = DeregisterEventSource(event_log);
+        result
+    }
+}
@@ -0,0 +1,269 @@
+//! C 接口：库同时编译为 `dwm_monitor.dll`，同一台机器上的 C/C++/C# 工具可以复用采样和监控逻辑，
+//! 声明见 `include/dwm_monitor.h`。
+//!
+//! 字符串均为以 NUL 结尾的 UTF-8；返回 `i32` 的函数成功时返回 [`DWM_OK`]，失败时返回负数错误码。
+
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::monitor::{ProcessMonitor, RestartAction, Sample};
+use crate::process_manager::{get_all_processes, ProcessInfo, ProcessType};
+use crate::process_name;
+
+pub const DWM_OK: i32 = 0;
+pub const DWM_INVALID_ARGUMENT: i32 = -1;
+pub const DWM_NOT_FOUND: i32 = -2;
+pub const DWM_ENUM_FAILED: i32 = -3;
+
+/// 进程内存，单位为字节；`private_bytes` 即任务管理器的“提交大小”，阈值与它比较。
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct DwmProcessMemory {
+    pub pid: u32,
+    pub thread_count: i32,
+    pub private_bytes: u64,
+    pub working_set: u64,
+    pub pagefile_usage: u64,
+}
+
+impl From<&ProcessInfo> for DwmProcessMemory {
+    fn from(info: &ProcessInfo) -> Self {
+        DwmProcessMemory {
+            pid: info.pid,
+            thread_count: info.thread_count,
+            private_bytes: info.private_bytes as u64,
+            working_set: info.working_set as u64,
+            pagefile_usage: info.pagefile_usage as u64,
+        }
+    }
+}
+
+/// 监控回调收到的一次采样，只在回调期间有效；进程不存在时 `pid` 为 0。
+#[repr(C)]
+pub struct DwmSample {
+    pub name: *const c_char,
+    pub pid: u32,
+    pub private_bytes: u64,
+    pub working_set: u64,
+    pub threshold_bytes: u64,
+    pub exceeds_threshold: bool,
+}
+
+/// 每轮采样后在监控线程上调用，`user_data` 原样传回。
+pub type DwmSampleCallback = extern "C" fn(sample: *const DwmSample, user_data: *mut c_void);
+
+/// [`dwm_start_monitor`] 返回的句柄，交给 [`dwm_stop_monitor`] 停止并释放。
+pub struct DwmMonitor {
+    stop: Sender<()>,
+    thread: JoinHandle<()>,
+}
+
+// 由调用方保证 user_data 可以在监控线程上使用
+struct UserData(*mut c_void);
+
+unsafe impl Send for UserData {}
+
+unsafe fn read_name<'a>(name: *const c_char) -> Option<&'a str> {
+    if name.is_null() {
+        return None;
+    }
+    CStr::from_ptr(name)
+        .to_str()
+        .ok()
+        .filter(|name| !name.is_empty())
+}
+
+fn find_process(
+    processes: Option<Vec<ProcessInfo>>,
+    matches: impl Fn(&ProcessInfo) -> bool,
+    out: &mut DwmProcessMemory,
+) -> i32 {
+    let Some(processes) = processes else {
+        return DWM_ENUM_FAILED;
+    };
+    match processes.iter().find(|p| matches(p)) {
+        Some(process) => {
+            *out = process.into();
+            DWM_OK
+        }
+        None => DWM_NOT_FOUND,
+    }
+}
+
+/// 读取指定 PID 的进程内存。
+///
+/// # Safety
+///
+/// `out` 必须为空指针或指向可写的 `DwmProcessMemory`。
+#[no_mangle]
+pub unsafe extern "C" fn dwm_get_process_memory(pid: u32, out: *mut DwmProcessMemory) -> i32 {
+    let Some(out) = out.as_mut() else {
+        return DWM_INVALID_ARGUMENT;
+    };
+    find_process(get_all_processes(), |p| p.pid == pid, out)
+}
+
+/// 按名称查找进程（不区分大小写），有多个同名进程时返回第一个。
+///
+/// # Safety
+///
+/// `name` 必须为空指针或以 NUL 结尾的字符串，`out` 必须为空指针或指向可写的 `DwmProcessMemory`。
+#[no_mangle]
+pub unsafe extern "C" fn dwm_find_process_by_name(
+    name: *const c_char,
+    out: *mut DwmProcessMemory,
+) -> i32 {
+    let (Some(name), Some(out)) = (read_name(name), out.as_mut()) else {
+        return DWM_INVALID_ARGUMENT;
+    };
+    find_process(
+        get_all_processes(),
+        |p| process_name::eq(&p.name, name),
+        out,
+    )
+}
+
+fn call_back(callback: DwmSampleCallback, sample: &Sample, user_data: &UserData) {
+    // 进程名来自配置或调用方，不含 NUL
+    let name = CString::new(sample.name.as_str()).unwrap_or_default();
+    let sample = DwmSample {
+        name: name.as_ptr(),
+        pid: sample.pid.unwrap_or(0),
+        private_bytes: sample.private_bytes,
+        working_set: sample.working_set,
+        threshold_bytes: sample.threshold_bytes,
+        exceeds_threshold: sample.exceeds_threshold(),
+    };
+    callback(&sample, user_data.0);
+}
+
+/// 在后台线程中每 `interval_ms` 毫秒采样一次指定进程并调用 `callback`。`restart` 为真时，
+/// 内存超过 `threshold_bytes` 后与服务一样结束进程并等待系统重新拉起（包括暂缓重启）。
+/// 参数无效时返回空指针。
+///
+/// # Safety
+///
+/// `name` 必须为空指针或以 NUL 结尾的字符串；`user_data` 在 [`dwm_stop_monitor`] 返回前必须保持有效，
+/// 并且可以在其他线程上使用。
+#[no_mangle]
+pub unsafe extern "C" fn dwm_start_monitor(
+    name: *const c_char,
+    threshold_bytes: u64,
+    interval_ms: u32,
+    restart: bool,
+    callback: Option<DwmSampleCallback>,
+    user_data: *mut c_void,
+) -> *mut DwmMonitor {
+    let (Some(name), Some(callback)) = (read_name(name), callback) else {
+        return std::ptr::null_mut();
+    };
+    if interval_ms == 0 {
+        return std::ptr::null_mut();
+    }
+    let mut builder = ProcessMonitor::builder().process(name, threshold_bytes);
+    if restart {
+        builder = builder.action(RestartAction::new(ProcessType::System));
+    }
+    let mut monitor = builder.build();
+    let user_data = UserData(user_data);
+    let interval = Duration::from_millis(interval_ms as u64);
+    let (stop, stopped) = mpsc::channel();
+    let thread = std::thread::spawn(move || loop {
+        for sample in monitor.check() {
+            call_back(callback, &sample, &user_data);
+        }
+        match stopped.recv_timeout(interval) {
+            Err(RecvTimeoutError::Timeout) => {}
+            _ => break,
+        }
+    });
+    Box::into_raw(Box::new(DwmMonitor { stop, thread }))
+}
+
+/// 停止监控并等待后台线程退出（正在进行的一轮采样或回调会先完成），之后不会再调用回调。
+/// 不能在回调中调用。
+///
+/// # Safety
+///
+/// `monitor` 必须为空指针或 [`dwm_start_monitor`] 返回且尚未停止的句柄。
+#[no_mangle]
+pub unsafe extern "C" fn dwm_stop_monitor(monitor: *mut DwmMonitor) {
+    if monitor.is_null() {
+        return;
+    }
+    let monitor = Box::from_raw(monitor);
+    let _ = monitor.stop.send(());
+    let _ = monitor.thread.join();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(name: &str, pid: u32) -> ProcessInfo {
+        ProcessInfo {
+            name: name.to_string(),
+            pid,
+            thread_count: 4,
+            private_bytes: 200,
+            pagefile_usage: 100,
+            working_set: 300,
+        }
+    }
+
+    #[test]
+    fn test_invalid_arguments_are_rejected() {
+        let mut out = DwmProcessMemory::default();
+        unsafe {
+            assert_eq!(
+                dwm_get_process_memory(1, std::ptr::null_mut()),
+                DWM_INVALID_ARGUMENT
+            );
+            assert_eq!(
+                dwm_find_process_by_name(std::ptr::null(), &mut out),
+                DWM_INVALID_ARGUMENT
+            );
+            assert_eq!(
+                dwm_find_process_by_name(c"".as_ptr(), &mut out),
+                DWM_INVALID_ARGUMENT
+            );
+            assert!(dwm_start_monitor(
+                c"dwm.exe".as_ptr(),
+                1,
+                1000,
+                false,
+                None,
+                std::ptr::null_mut()
+            )
+            .is_null());
+            dwm_stop_monitor(std::ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn test_find_process_fills_memory() {
+        let mut out = DwmProcessMemory::default();
+        let processes = vec![process("a.exe", 1), process("dwm.exe", 2)];
+        assert_eq!(
+            find_process(Some(processes.clone()), |p| p.pid == 2, &mut out),
+            DWM_OK
+        );
+        assert_eq!(
+            out,
+            DwmProcessMemory {
+                pid: 2,
+                thread_count: 4,
+                private_bytes: 200,
+                working_set: 300,
+                pagefile_usage: 100,
+            }
+        );
+        assert_eq!(
+            find_process(Some(processes), |p| p.pid == 3, &mut out),
+            DWM_NOT_FOUND
+        );
+        assert_eq!(find_process(None, |_| true, &mut out), DWM_ENUM_FAILED);
+    }
+}
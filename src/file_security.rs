@@ -0,0 +1,227 @@
+//! 配置和日志文件的安全检查。服务以 LocalSystem 运行，程序目录下的这些文件只应由管理员和服务自身修改：
+//! 读取配置前检查所有者和访问权限，不跟随重解析点（符号链接、目录联接），新建的文件只授权给管理员和服务。
+
+use std::fs;
+use std::io;
+use std::os::windows::fs::MetadataExt;
+use std::path::Path;
+use windows::core::{Owned, PCWSTR};
+use windows::Win32::Foundation::{ERROR_SUCCESS, GENERIC_ALL, GENERIC_WRITE, HANDLE, HLOCAL};
+use windows::Win32::Security::Authorization::{
+    ConvertStringSecurityDescriptorToSecurityDescriptorW, GetNamedSecurityInfoW,
+    SetNamedSecurityInfoW, SDDL_REVISION_1, SE_FILE_OBJECT,
+};
+use windows::Win32::Security::{
+    EqualSid, GetAce, GetSecurityDescriptorDacl, GetTokenInformation, IsWellKnownSid, TokenUser,
+    WinAuthenticatedUserSid, WinBuiltinAdministratorsSid, WinBuiltinUsersSid, WinLocalSystemSid,
+    WinWorldSid, ACCESS_ALLOWED_ACE, ACE_HEADER, ACL, DACL_SECURITY_INFORMATION, INHERIT_ONLY_ACE,
+    OWNER_SECURITY_INFORMATION, PROTECTED_DACL_SECURITY_INFORMATION, PSECURITY_DESCRIPTOR, PSID,
+    TOKEN_QUERY, TOKEN_USER,
+};
+use windows::Win32::Storage::FileSystem::{
+    DELETE, FILE_APPEND_DATA, FILE_ATTRIBUTE_REPARSE_POINT, FILE_WRITE_DATA, WRITE_DAC, WRITE_OWNER,
+};
+use windows::Win32::System::SystemServices::ACCESS_ALLOWED_ACE_TYPE;
+use windows::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+use crate::process_manager::to_wide_string;
+
+// 只有 SYSTEM、Administrators 和文件所有者（服务账户）可以访问；目录中新建的文件继承同样的权限
+const FILE_SDDL: &str = "D:P(A;;FA;;;SY)(A;;FA;;;BA)(A;;FA;;;OW)";
+const DIRECTORY_SDDL: &str = "D:P(A;OICI;FA;;;SY)(A;OICI;FA;;;BA)(A;OICI;FA;;;OW)";
+
+// 授予这些权限就能篡改文件内容或权限
+const WRITE_ACCESS: u32 = FILE_WRITE_DATA.0
+    | FILE_APPEND_DATA.0
+    | DELETE.0
+    | WRITE_DAC.0
+    | WRITE_OWNER.0
+    | GENERIC_WRITE.0
+    | GENERIC_ALL.0;
+
+fn untrusted(path: &Path, reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!("{} {}", path.display(), reason),
+    )
+}
+
+/// 文件或其所在目录是重解析点时返回错误；文件不存在时不算错误。
+pub fn reject_reparse_point(path: &Path) -> io::Result<()> {
+    for path in [Some(path), path.parent()].into_iter().flatten() {
+        match fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.file_attributes() & FILE_ATTRIBUTE_REPARSE_POINT.0 != 0 => {
+                return Err(untrusted(path, "is a symbolic link or junction"));
+            }
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// 检查配置文件可以信任：不是重解析点，所有者是 SYSTEM、Administrators 或当前账户，
+/// 并且没有授予 Everyone、Users、Authenticated Users 写入权限。
+pub fn verify_trusted(path: &Path) -> io::Result<()> {
+    reject_reparse_point(path)?;
+    let name = to_wide_string(&path.to_string_lossy());
+    let mut owner = PSID::default();
+    let mut dacl: *mut ACL = std::ptr::null_mut();
+    let mut descriptor = PSECURITY_DESCRIPTOR::default();
+    let result = unsafe {
+        GetNamedSecurityInfoW(
+            PCWSTR(name.as_ptr()),
+            SE_FILE_OBJECT,
+            OWNER_SECURITY_INFORMATION | DACL_SECURITY_INFORMATION,
+            Some(&mut owner),
+            None,
+            Some(&mut dacl),
+            None,
+            &mut descriptor,
+        )
+    };
+    if result != ERROR_SUCCESS {
+        return Err(io::Error::from_raw_os_error(result.0 as i32));
+    }
+    // owner 和 dacl 指向 descriptor 内部，一起释放
+    let _descriptor = unsafe { Owned::new(HLOCAL(descriptor.0)) };
+
+    if !is_trusted_owner(owner)? {
+        return Err(untrusted(
+            path,
+            "is not owned by SYSTEM, Administrators or the service account",
+        ));
+    }
+    if dacl.is_null() {
+        return Err(untrusted(path, "has no access control list"));
+    }
+    if grants_write_to_everyone(dacl)? {
+        return Err(untrusted(path, "is writable by non-administrators"));
+    }
+    Ok(())
+}
+
+fn is_trusted_owner(owner: PSID) -> io::Result<bool> {
+    unsafe {
+        if IsWellKnownSid(owner, WinLocalSystemSid).as_bool()
+            || IsWellKnownSid(owner, WinBuiltinAdministratorsSid).as_bool()
+        {
+            return Ok(true);
+        }
+    }
+    let user = current_user()?;
+    let token_user = user.as_ptr() as *const TOKEN_USER;
+    Ok(unsafe { EqualSid(owner, (*token_user).User.Sid) }.is_ok())
+}
+
+// 当前进程的 TOKEN_USER，SID 位于返回的缓冲区内
+fn current_user() -> io::Result<Vec<u64>> {
+    unsafe {
+        let mut token = HANDLE::default();
+        OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token)?;
+        let token = Owned::new(token);
+        let mut size = 0;
+        // 第一次调用只获取缓冲区大小
+        let _ = GetTokenInformation(*token, TokenUser, None, 0, &mut size);
+        // 按 u64 分配以满足 TOKEN_USER 的对齐要求
+        let mut buffer = vec![0u64; (size as usize).div_ceil(8)];
+        GetTokenInformation(
+            *token,
+            TokenUser,
+            Some(buffer.as_mut_ptr() as _),
+            size,
+            &mut size,
+        )?;
+        Ok(buffer)
+    }
+}
+
+fn grants_write_to_everyone(dacl: *const ACL) -> io::Result<bool> {
+    let ace_count = unsafe { (*dacl).AceCount };
+    for index in 0..ace_count as u32 {
+        let mut ace: *mut std::ffi::c_void = std::ptr::null_mut();
+        unsafe { GetAce(dacl, index, &mut ace) }?;
+        let header = unsafe { &*(ace as *const ACE_HEADER) };
+        if header.AceType as u32 != ACCESS_ALLOWED_ACE_TYPE
+            || header.AceFlags as u32 & INHERIT_ONLY_ACE.0 != 0
+        {
+            continue;
+        }
+        let allowed = unsafe { &*(ace as *const ACCESS_ALLOWED_ACE) };
+        if allowed.Mask & WRITE_ACCESS == 0 {
+            continue;
+        }
+        let sid = PSID(&allowed.SidStart as *const u32 as *mut _);
+        let broad = [WinWorldSid, WinBuiltinUsersSid, WinAuthenticatedUserSid]
+            .into_iter()
+            .any(|kind| unsafe { IsWellKnownSid(sid, kind) }.as_bool());
+        if broad {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// 把文件的访问权限限制为 SYSTEM、Administrators 和文件所有者，不再继承目录的权限。
+pub fn restrict_file(path: &Path) -> io::Result<()> {
+    set_protected_dacl(path, FILE_SDDL)
+}
+
+/// 同 [`restrict_file`]，目录中之后创建的文件也继承这些权限。
+pub fn restrict_directory(path: &Path) -> io::Result<()> {
+    set_protected_dacl(path, DIRECTORY_SDDL)
+}
+
+fn set_protected_dacl(path: &Path, sddl: &str) -> io::Result<()> {
+    reject_reparse_point(path)?;
+    let sddl = to_wide_string(sddl);
+    let name = to_wide_string(&path.to_string_lossy());
+    let mut descriptor = PSECURITY_DESCRIPTOR::default();
+    unsafe {
+        ConvertStringSecurityDescriptorToSecurityDescriptorW(
+            PCWSTR(sddl.as_ptr()),
+            SDDL_REVISION_1,
+            &mut descriptor,
+            None,
+        )?;
+        let _descriptor = Owned::new(HLOCAL(descriptor.0));
+        let mut present = Default::default();
+        let mut defaulted = Default::default();
+        let mut dacl: *mut ACL = std::ptr::null_mut();
+        GetSecurityDescriptorDacl(descriptor, &mut present, &mut dacl, &mut defaulted)?;
+        let result = SetNamedSecurityInfoW(
+            PCWSTR(name.as_ptr()),
+            SE_FILE_OBJECT,
+            DACL_SECURITY_INFORMATION | PROTECTED_DACL_SECURITY_INFORMATION,
+            None,
+            None,
+            Some(dacl),
+            None,
+        );
+        if result != ERROR_SUCCESS {
+            return Err(io::Error::from_raw_os_error(result.0 as i32));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restricted_file_is_trusted() {
+        let path = std::env::temp_dir().join("process_guard_file_security_test.json");
+        fs::write(&path, "{}").unwrap();
+        restrict_file(&path).unwrap();
+        verify_trusted(&path).unwrap();
+        assert_eq!(
+            verify_trusted(&path.with_extension("missing"))
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::NotFound
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,439 @@
+//! gRPC 接口：在 `grpc_address` 上提供与命名管道相同的查询和控制请求，接口定义见
+//! `proto/process_guard.proto`，便于强类型的机群管理工具调用。
+//!
+//! 权限与远程命名管道相同：可以查询状态、立即重启、暂缓重启和重新加载配置，不能修改阈值。
+//! 监听本机回环以外的地址时必须配置双向 TLS，只接受由 `grpc_client_ca` 签发证书的客户端。
+//! 只监听回环地址而不使用 TLS 时，本机任何用户都能连接，控制请求必须携带 `grpc_token_file`
+//! 中的令牌；未配置令牌文件时只开放状态查询。
+
+use log::{error, info, warn};
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use tonic::transport::{Certificate, Identity, Server, ServerTlsConfig};
+use tonic::{Request, Response, Status};
+
+use crate::config_manager::{program_file_path, Config};
+use crate::file_security;
+use crate::ipc;
+use crate::monitor_state::{self, MonitorStatus, ProcessStatus};
+use crate::tr;
+
+pub mod proto {
+    tonic::include_proto!("process_guard.v1");
+}
+
+use proto::process_guard_server::{ProcessGuard, ProcessGuardServer};
+
+impl From<ProcessStatus> for proto::ProcessStatus {
+    fn from(process: ProcessStatus) -> Self {
+        proto::ProcessStatus {
+            name: process.name,
+            pid: process.pid,
+            private_bytes: process.private_bytes,
+            working_set: process.working_set,
+            memory_threshold_bytes: process.memory_threshold_bytes,
+            last_sample: process.last_sample,
+            last_restart: process.last_restart,
+            worker_restarts: process.worker_restarts,
+            stalled: process.stalled,
+            state: format!("{:?}", process.state),
+            metrics: process.metrics.into_iter().collect(),
+        }
+    }
+}
+
+impl From<MonitorStatus> for proto::Status {
+    fn from(status: MonitorStatus) -> Self {
+        proto::Status {
+            paused: status.paused,
+            snoozed_until: status.snoozed_until,
+            processes: status.processes.into_iter().map(Into::into).collect(),
+            os_build: status.os_build,
+            visible_windows: status.visible_windows,
+        }
+    }
+}
+
+fn client<T>(request: &Request<T>) -> String {
+    request
+        .remote_addr()
+        .map_or_else(|| "unknown".to_string(), |addr| addr.to_string())
+}
+
+// 控制请求的身份验证方式，状态查询不需要验证
+#[derive(Debug, Clone, PartialEq)]
+enum Auth {
+    // 双向 TLS 已经验证了客户端证书
+    MutualTls,
+    // 请求需要携带 `authorization: Bearer <令牌>`
+    Token(String),
+    // 不接受控制请求
+    ReadOnly,
+}
+
+// 逐字节比较全部内容，耗时不随第一个不同字节的位置变化
+fn token_matches(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn unauthenticated() -> Status {
+    Status::unauthenticated("Control requests require mutual TLS or the token from grpc_token_file")
+}
+
+fn authorized<T>(auth: &Auth, request: &Request<T>) -> bool {
+    match auth {
+        Auth::MutualTls => true,
+        Auth::Token(token) => request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|given| token_matches(given, token)),
+        Auth::ReadOnly => false,
+    }
+}
+
+struct GuardService {
+    config: Arc<Mutex<Config>>,
+    auth: Auth,
+}
+
+impl GuardService {
+    // 未通过验证时记录日志并返回 false
+    fn authorize<T>(&self, request: &Request<T>, command: &str) -> bool {
+        if authorized(&self.auth, request) {
+            return true;
+        }
+        warn!(
+            "{}",
+            tr!(
+                "拒绝来自 {} 的未经验证的 gRPC 请求: {}",
+                "Rejected unauthenticated gRPC request from {}: {}",
+                client(request),
+                command
+            )
+        );
+        false
+    }
+
+    // 重启请求会阻塞数十秒，放到阻塞线程池中执行
+    async fn command(
+        &self,
+        client: String,
+        request: ipc::Request,
+    ) -> Result<Response<proto::CommandReply>, Status> {
+        info!(
+            "{}",
+            tr!(
+                "收到来自 {} 的 gRPC 请求: {:?}",
+                "Received gRPC request from {}: {:?}",
+                client,
+                request
+            )
+        );
+        let config = self.config.clone();
+        let response = tokio::task::spawn_blocking(move || ipc::handle_request(&config, request))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+        match response {
+            ipc::Response::Message(message) => Ok(Response::new(proto::CommandReply { message })),
+            ipc::Response::Error(e) => Err(Status::failed_precondition(e)),
+            ipc::Response::Status(_) => Err(Status::internal("Unexpected status response")),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl ProcessGuard for GuardService {
+    async fn get_status(
+        &self,
+        _request: Request<proto::GetStatusRequest>,
+    ) -> Result<Response<proto::Status>, Status> {
+        Ok(Response::new(monitor_state::snapshot().into()))
+    }
+
+    async fn restart_now(
+        &self,
+        request: Request<proto::RestartNowRequest>,
+    ) -> Result<Response<proto::CommandReply>, Status> {
+        if !self.authorize(&request, "RestartNow") {
+            return Err(unauthenticated());
+        }
+        let client = client(&request);
+        let name = request.into_inner().name;
+        self.command(client, ipc::Request::RestartNow(name)).await
+    }
+
+    async fn set_threshold(
+        &self,
+        request: Request<proto::SetThresholdRequest>,
+    ) -> Result<Response<proto::CommandReply>, Status> {
+        warn!(
+            "{}",
+            tr!(
+                "拒绝来自 {} 的 gRPC 请求: SetThreshold",
+                "Rejected gRPC request from {}: SetThreshold",
+                client(&request)
+            )
+        );
+        Err(Status::permission_denied(
+            "This command requires a local administrator",
+        ))
+    }
+
+    async fn snooze(
+        &self,
+        request: Request<proto::SnoozeRequest>,
+    ) -> Result<Response<proto::CommandReply>, Status> {
+        if !self.authorize(&request, "Snooze") {
+            return Err(unauthenticated());
+        }
+        let client = client(&request);
+        let duration_seconds = request.into_inner().duration_seconds;
+        self.command(client, ipc::Request::Snooze(duration_seconds))
+            .await
+    }
+
+    async fn reload_config(
+        &self,
+        request: Request<proto::ReloadConfigRequest>,
+    ) -> Result<Response<proto::CommandReply>, Status> {
+        if !self.authorize(&request, "ReloadConfig") {
+            return Err(unauthenticated());
+        }
+        self.command(client(&request), ipc::Request::ReloadConfig)
+            .await
+    }
+}
+
+fn resolve(path: &str) -> PathBuf {
+    if Path::new(path).is_absolute() {
+        Path::new(path).to_path_buf()
+    } else {
+        program_file_path(path)
+    }
+}
+
+// 证书和私钥与规则脚本一样，相对路径相对于程序目录，所有者或权限不可信时拒绝读取
+fn read_trusted(path: &str) -> io::Result<Vec<u8>> {
+    let path = resolve(path);
+    file_security::verify_trusted(&path)?;
+    std::fs::read(&path)
+}
+
+/// 读取控制请求的令牌。读取前把文件的访问权限限制为管理员和服务自身，普通用户无法读到令牌。
+fn read_token(path: &str) -> io::Result<String> {
+    let path = resolve(path);
+    file_security::verify_trusted(&path)?;
+    file_security::restrict_file(&path)?;
+    let token = std::fs::read_to_string(&path)?.trim().to_string();
+    if token.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "grpc_token_file is empty",
+        ));
+    }
+    Ok(token)
+}
+
+/// 三项都未配置时返回 `None`（不使用 TLS），只配置了部分时返回错误。
+fn tls_config(cert: &str, key: &str, client_ca: &str) -> io::Result<Option<ServerTlsConfig>> {
+    if cert.is_empty() && key.is_empty() && client_ca.is_empty() {
+        return Ok(None);
+    }
+    if cert.is_empty() || key.is_empty() || client_ca.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "grpc_tls_cert, grpc_tls_key and grpc_client_ca must all be set",
+        ));
+    }
+    Ok(Some(
+        ServerTlsConfig::new()
+            .identity(Identity::from_pem(read_trusted(cert)?, read_trusted(key)?))
+            .client_ca_root(Certificate::from_pem(read_trusted(client_ca)?)),
+    ))
+}
+
+/// 未配置 `grpc_address` 时直接返回；地址和证书在启动时读取，修改后需要重启服务。
+pub async fn run(config: Arc<Mutex<Config>>) {
+    let (address, cert, key, client_ca, token_file) = {
+        let config = config.lock().unwrap_or_else(PoisonError::into_inner);
+        (
+            config.grpc_address.clone(),
+            config.grpc_tls_cert.clone(),
+            config.grpc_tls_key.clone(),
+            config.grpc_client_ca.clone(),
+            config.grpc_token_file.clone(),
+        )
+    };
+    if address.is_empty() {
+        return;
+    }
+    let address: SocketAddr = match address.parse() {
+        Ok(address) => address,
+        Err(e) => {
+            error!(
+                "{}",
+                tr!(
+                    "grpc_address {} 无效: {}",
+                    "Invalid grpc_address {}: {}",
+                    address,
+                    e
+                )
+            );
+            return;
+        }
+    };
+    let tls = match tls_config(&cert, &key, &client_ca) {
+        Ok(tls) => tls,
+        Err(e) => {
+            error!(
+                "{}",
+                tr!(
+                    "加载 gRPC TLS 设置失败: {}",
+                    "Failed to load gRPC TLS settings: {}",
+                    e
+                )
+            );
+            return;
+        }
+    };
+    let mut server = Server::builder();
+    let auth = match tls {
+        Some(tls) => {
+            server = match server.tls_config(tls) {
+                Ok(server) => server,
+                Err(e) => {
+                    error!(
+                        "{}",
+                        tr!(
+                            "配置 gRPC TLS 失败: {}",
+                            "Failed to configure gRPC TLS: {}",
+                            e
+                        )
+                    );
+                    return;
+                }
+            };
+            Auth::MutualTls
+        }
+        None if !address.ip().is_loopback() => {
+            warn!(
+                "{}",
+                tr!(
+                    "grpc_address {} 不是本机回环地址，未配置双向 TLS 时不开放 gRPC 接口",
+                    "grpc_address {} is not a loopback address, gRPC is not served without mutual TLS",
+                    address
+                )
+            );
+            return;
+        }
+        None if token_file.is_empty() => {
+            info!(
+                "{}",
+                tr!(
+                    "未配置 grpc_token_file，gRPC 接口只开放状态查询",
+                    "grpc_token_file is not set, gRPC only serves status queries"
+                )
+            );
+            Auth::ReadOnly
+        }
+        None => match read_token(&token_file) {
+            Ok(token) => Auth::Token(token),
+            Err(e) => {
+                error!(
+                    "{}",
+                    tr!(
+                        "读取 gRPC 令牌文件 {} 失败: {}",
+                        "Failed to read gRPC token file {}: {}",
+                        token_file,
+                        e
+                    )
+                );
+                return;
+            }
+        },
+    };
+    info!(
+        "{}",
+        tr!(
+            "正在 {} 上启动 gRPC 服务",
+            "Starting gRPC server on {}",
+            address
+        )
+    );
+    let result = server
+        .add_service(ProcessGuardServer::new(GuardService { config, auth }))
+        .serve_with_shutdown(address, monitor_state::shutdown_requested())
+        .await;
+    match result {
+        Ok(()) => info!("{}", tr!("gRPC 服务已停止", "gRPC server stopped")),
+        Err(e) => error!(
+            "{}",
+            tr!(
+                "{} 上的 gRPC 服务出错: {}",
+                "gRPC server on {} failed: {}",
+                address,
+                e
+            )
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::TargetState;
+
+    #[test]
+    fn test_status_converts_to_proto() {
+        let status = MonitorStatus {
+            snoozed_until: Some(200),
+            processes: vec![ProcessStatus {
+                name: "dwm.exe".to_string(),
+                pid: Some(7),
+                private_bytes: 1024,
+                state: TargetState::Warning,
+                metrics: [("gpu_bytes".to_string(), 2.0)].into(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let status = proto::Status::from(status);
+        assert_eq!(status.snoozed_until, Some(200));
+        let process = &status.processes[0];
+        assert_eq!(process.pid, Some(7));
+        assert_eq!(process.private_bytes, 1024);
+        assert_eq!(process.state, "Warning");
+        assert_eq!(process.last_restart, None);
+        assert_eq!(process.metrics.get("gpu_bytes"), Some(&2.0));
+
+        assert!(tls_config("", "", "").unwrap().is_none());
+        assert!(tls_config("server.pem", "", "").is_err());
+    }
+
+    #[test]
+    fn test_control_requests_require_token_without_tls() {
+        let with_token = |value: &str| {
+            let mut request = Request::new(());
+            request
+                .metadata_mut()
+                .insert("authorization", value.parse().unwrap());
+            request
+        };
+        let auth = Auth::Token("secret".to_string());
+        assert!(authorized(&auth, &with_token("Bearer secret")));
+        assert!(!authorized(&auth, &with_token("Bearer secreT")));
+        assert!(!authorized(&auth, &with_token("secret")));
+        assert!(!authorized(&auth, &Request::new(())));
+        assert!(!authorized(&Auth::ReadOnly, &with_token("Bearer secret")));
+        assert!(authorized(&Auth::MutualTls, &Request::new(())));
+    }
+}
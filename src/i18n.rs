@@ -0,0 +1,63 @@
+//! 日志语言：每条日志同时提供中文和英文文本，按配置中的 `log_language` 输出其中一种，
+//! 便于不懂中文的用户阅读，也便于日志管道按固定文本解析。
+//!
+//! 加载配置之前（启动横幅、系统信息）使用默认的中文。
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::config_manager::LogLanguage;
+
+static ENGLISH: AtomicBool = AtomicBool::new(false);
+
+/// 加载或重新加载配置后调用，之后的日志使用新的语言。
+pub fn set_language(language: LogLanguage) {
+    ENGLISH.store(language == LogLanguage::EnUs, Ordering::Relaxed);
+}
+
+pub fn is_english() -> bool {
+    ENGLISH.load(Ordering::Relaxed)
+}
+
+/// 按当前语言格式化日志文本，两种文本使用相同的参数：
+///
+/// ```
+/// use dwm_monitor::tr;
+///
+/// let message = tr!("{} 进程已成功重启", "{} restarted successfully", "dwm.exe");
+/// assert_eq!(message, "dwm.exe 进程已成功重启");
+/// ```
+///
+/// 以 `@in` 开头时使用指定的语言（`true` 为英文），不读取全局设置：
+///
+/// ```
+/// use dwm_monitor::tr;
+///
+/// assert_eq!(tr!(@in true, "已暂缓", "Snoozed"), "Snoozed");
+/// ```
+#[macro_export]
+macro_rules! tr {
+    ($zh:literal, $en:literal $(, $arg:expr)* $(,)?) => {
+        $crate::tr!(@in $crate::i18n::is_english(), $zh, $en $(, $arg)*)
+    };
+    (@in $english:expr, $zh:literal, $en:literal $(, $arg:expr)* $(,)?) => {
+        if $english {
+            format!($en $(, $arg)*)
+        } else {
+            format!($zh $(, $arg)*)
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 全局语言由所有并行运行的测试共享，这里显式指定语言，不修改全局设置
+    #[test]
+    fn test_tr_follows_language() {
+        let message = |english| tr!(@in english, "已暂缓 {} 秒", "Snoozed for {} seconds", 60);
+        assert_eq!(message(true), "Snoozed for 60 seconds");
+        assert_eq!(message(false), "已暂缓 60 秒");
+        assert!(!is_english());
+    }
+}
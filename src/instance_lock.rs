@@ -0,0 +1,62 @@
+use std::io;
+use std::path::Path;
+use windows::core::{Owned, PCWSTR};
+use windows::Win32::Foundation::{GetLastError, ERROR_ALREADY_EXISTS, HANDLE};
+use windows::Win32::System::Threading::CreateMutexW;
+
+use crate::process_manager::to_wide_string;
+
+// 持有期间同一配置文件不能再启动第二个监控实例（服务或控制台），drop 时释放
+pub struct InstanceLock {
+    _handle: Owned<HANDLE>,
+}
+
+// 互斥体名称由配置文件路径决定，FNV-1a 保证不同版本的程序得到相同名称
+fn mutex_name(config_path: &Path) -> String {
+    let path = config_path.to_string_lossy().to_lowercase();
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in path.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("Global\\process_guard_{:016x}", hash)
+}
+
+// 已有实例运行时返回 Ok(None)
+pub fn acquire(config_path: &Path) -> io::Result<Option<InstanceLock>> {
+    let name = to_wide_string(&mutex_name(config_path));
+    unsafe {
+        let handle = Owned::new(CreateMutexW(None, false, PCWSTR(name.as_ptr()))?);
+        if GetLastError() == ERROR_ALREADY_EXISTS {
+            return Ok(None);
+        }
+        Ok(Some(InstanceLock { _handle: handle }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mutex_name_ignores_case() {
+        assert_eq!(
+            mutex_name(Path::new("C:\\Guard\\process_guard_config.json")),
+            mutex_name(Path::new("c:\\guard\\PROCESS_GUARD_CONFIG.JSON"))
+        );
+        assert_ne!(
+            mutex_name(Path::new("C:\\a\\process_guard_config.json")),
+            mutex_name(Path::new("C:\\b\\process_guard_config.json"))
+        );
+    }
+
+    #[test]
+    fn test_second_acquire_fails() {
+        let path = Path::new("C:\\instance_lock_test\\config.json");
+        let lock = acquire(path).unwrap();
+        assert!(lock.is_some());
+        assert!(acquire(path).unwrap().is_none());
+        drop(lock);
+        assert!(acquire(path).unwrap().is_some());
+    }
+}
@@ -0,0 +1,723 @@
+//! 运行中监控的命名管道接口：服务端随服务运行，客户端供命令行和托盘程序查询状态、发送控制命令。
+
+use lazy_static::lazy_static;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::windows::fs::OpenOptionsExt;
+use std::os::windows::io::AsRawHandle;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader as AsyncBufReader};
+use tokio::net::windows::named_pipe::{NamedPipeServer, ServerOptions};
+use windows::core::{w, Owned, PCWSTR};
+use windows::Win32::{
+    Foundation::{BOOL, ERROR_PIPE_BUSY, ERROR_SUCCESS, GENERIC_READ, HANDLE, HLOCAL},
+    Security::{
+        Authorization::{ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1},
+        CheckTokenMembership, CreateWellKnownSid, RevertToSelf, WinBuiltinAdministratorsSid,
+        PSECURITY_DESCRIPTOR, PSID, SECURITY_ATTRIBUTES, SECURITY_MAX_SID_SIZE,
+    },
+    Storage::FileSystem::{FlushFileBuffers, FILE_WRITE_DATA},
+    System::{
+        Pipes::{
+            GetNamedPipeClientComputerNameW, GetNamedPipeClientSessionId,
+            ImpersonateNamedPipeClient,
+        },
+        Registry::{RegGetValueW, HKEY_LOCAL_MACHINE, RRF_RT_REG_DWORD},
+    },
+};
+
+use crate::config_manager::{config_file_path, Config, ConfigManager};
+use crate::db_manager::DB_CONNECTION;
+use crate::monitor_state::{self, MonitorStatus};
+use crate::process_manager::{restart_processing, to_wide_string};
+use crate::{i18n, process_name, system_info_printer, templates, tr};
+
+pub const PIPE_NAME: &str = r"\\.\pipe\process_guard";
+// 管理员和服务账户完全控制；本机交互用户（托盘程序）只能连接和读写，不能创建管道实例
+const LOCAL_PIPE_SDDL: &str = "D:P(A;;GA;;;SY)(A;;GA;;;BA)(A;;GA;;;OW)(A;;0x12008b;;;IU)";
+// 客户端打开管道时只申请读写数据，不申请创建管道实例的权限
+const CLIENT_ACCESS: u32 = GENERIC_READ.0 | FILE_WRITE_DATA.0;
+// 远程管理使用单独的管道，客户端通过 \\<主机>\pipe\process_guard_remote 连接，
+// 由 SMB 完成身份验证和加密
+pub const REMOTE_PIPE: &str = "process_guard_remote";
+// 只有 SYSTEM、Administrators 和服务账户（创建管道的所有者）可以连接
+const REMOTE_PIPE_SDDL: &str = "D:P(A;;GA;;;SY)(A;;GA;;;BA)(A;;GA;;;OW)";
+const PIPE_BUFFER_SIZE: u32 = 64 * 1024;
+// 请求行的长度上限和读取时限：本机交互用户都能写入管道，不能让客户端占用无限的内存或一直占着管道实例
+const MAX_REQUEST_BYTES: u64 = 64 * 1024;
+const REQUEST_READ_TIMEOUT: Duration = Duration::from_secs(5);
+// 本机普通用户（托盘程序）立即重启和添加注释的频率上限：每个时间窗口内的最多次数；管理员不受限制
+const USER_RESTART_LIMIT: (usize, Duration) = (3, Duration::from_secs(60 * 60));
+const USER_ANNOTATE_LIMIT: (usize, Duration) = (30, Duration::from_secs(60 * 60));
+
+type OverrideFn = Box<dyn Fn(&mut Config) -> Result<(), String> + Send + Sync>;
+
+lazy_static! {
+    // 服务启动时应用的环境变量和启动参数覆盖，重新加载配置时同样应用
+    static ref CONFIG_OVERRIDES: Mutex<Option<OverrideFn>> = Mutex::new(None);
+    // 本机普通用户最近的立即重启和注释请求的时间
+    static ref USER_RESTARTS: Mutex<VecDeque<Instant>> = Mutex::new(VecDeque::new());
+    static ref USER_ANNOTATIONS: Mutex<VecDeque<Instant>> = Mutex::new(VecDeque::new());
+}
+
+/// 设置重新加载配置时在配置文件上应用的覆盖，与启动时的处理保持一致。
+pub fn set_config_overrides<F>(overrides: F)
+where
+    F: Fn(&mut Config) -> Result<(), String> + Send + Sync + 'static,
+{
+    *CONFIG_OVERRIDES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) = Some(Box::new(overrides));
+}
+
+fn apply_config_overrides(config: &mut Config) -> Result<(), String> {
+    match CONFIG_OVERRIDES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .as_ref()
+    {
+        Some(overrides) => overrides(config),
+        None => Ok(()),
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Request {
+    Status,
+    // 未指定进程名时重启配置中的第一个进程
+    RestartNow(Option<String>),
+    SetThreshold {
+        bytes: u64,
+        name: Option<String>,
+        persist: bool,
+    },
+    // 暂缓重启的秒数，0 表示取消
+    Snooze(u64),
+    ReloadConfig,
+    // 在采样历史中添加注释，如托盘程序在用户会话中发现的显示配置变化
+    Annotate(String),
+    // 托盘程序定期上报交互会话中可见的顶层窗口数，与内存一起记录
+    VisibleWindows(u32),
+    // 托盘程序定期上报所在会话中是否有全屏游戏或演示，期间可以推迟该会话中进程的重启
+    FullScreen(bool),
+}
+
+impl Request {
+    // 修改阈值只对本机管理员开放；查看状态、重新加载配置、暂缓和立即重启
+    // 也可以由远程管理员和本机的普通交互用户（托盘程序）执行
+    fn requires_local_admin(&self) -> bool {
+        matches!(self, Request::SetThreshold { .. })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Response {
+    Status(MonitorStatus),
+    Message(String),
+    Error(String),
+}
+
+fn restart_now(config: &Mutex<Config>, name: Option<String>) -> Response {
+    let config = config
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    let process = match &name {
+        Some(name) => config.find_monitor_process(name),
+        None => config.get_monitor_processes().first(),
+    };
+    let process = match (process, name) {
+        (Some(process), _) => process,
+        (None, Some(name)) => {
+            return Response::Error(format!("{} is not a monitored process", name))
+        }
+        (None, None) => return Response::Error("No monitored process configured".to_string()),
+    };
+    info!(
+        "{}",
+        tr!(
+            "收到立即重启 {} 的请求",
+            "Received request to restart {} now",
+            process.name
+        )
+    );
+    if process_name::is_protected(&process.name) {
+        return Response::Error(format!(
+            "{} is a protected system process and will not be restarted",
+            process.name
+        ));
+    }
+    if restart_processing(&process.name, &process.process_type) {
+        Response::Message(format!("{} restarted", process.name))
+    } else {
+        Response::Error(format!("{} did not come back after restart", process.name))
+    }
+}
+
+fn set_threshold(
+    config: &Mutex<Config>,
+    bytes: u64,
+    name: Option<String>,
+    persist: bool,
+) -> Response {
+    let name = {
+        let mut config = config.lock().unwrap_or_else(PoisonError::into_inner);
+        let process = match &name {
+            Some(name) => config.find_monitor_process_mut(name),
+            None => config.processes.first_mut(),
+        };
+        let process = match process {
+            Some(process) => process,
+            None => return Response::Error("No matching monitored process".to_string()),
+        };
+        info!(
+            "{}",
+            tr!(
+                "{} 内存阈值调整为 {} MB（原 {} MB）",
+                "{} memory threshold changed to {} MB (was {} MB)",
+                process.name,
+                bytes / 1024 / 1024,
+                process.memory_threshold_bytes / 1024 / 1024
+            )
+        );
+        process.memory_threshold_bytes = bytes;
+        process.name.clone()
+    };
+    if persist {
+        // 只修改配置文件中的对应项，避免把运行时的其他状态写回文件
+        let config_manager = ConfigManager::new(config_file_path());
+        // 配置文件读取失败时不能用默认配置覆盖它
+        let mut file_config = match config_manager.load() {
+            Ok(file_config) => file_config,
+            Err(e) => return Response::Error(format!("Threshold applied, but {}", e)),
+        };
+        match file_config.find_monitor_process_mut(&name) {
+            Some(process) => process.memory_threshold_bytes = bytes,
+            None => {
+                return Response::Error(format!(
+                    "Threshold applied, but {} is not in the config file",
+                    name
+                ))
+            }
+        }
+        if let Err(e) = config_manager.save(&file_config) {
+            error!(
+                "{}",
+                tr!("保存配置失败: {}", "Failed to save config: {}", e)
+            );
+            return Response::Error(format!("Threshold applied, but {}", e));
+        }
+        info!(
+            "{}",
+            tr!(
+                "{} 内存阈值已写入配置文件",
+                "{} memory threshold saved to the config file",
+                name
+            )
+        );
+    }
+    Response::Message(format!(
+        "{} threshold set to {} MB{}",
+        name,
+        bytes / 1024 / 1024,
+        if persist { " and saved" } else { "" }
+    ))
+}
+
+fn snooze(duration_secs: u64, admin: bool) -> Response {
+    let snoozed = if admin {
+        Ok(monitor_state::snooze(duration_secs))
+    } else {
+        monitor_state::user_snooze(duration_secs)
+    };
+    match snoozed {
+        Ok(Some(until)) => {
+            let duration_secs = until.saturating_sub(monitor_state::now_secs());
+            info!(
+                "{}",
+                tr!(
+                    "重启已暂缓 {} 秒",
+                    "Restarts snoozed for {} seconds",
+                    duration_secs
+                )
+            );
+            Response::Message(format!(
+                "Restarts snoozed for {} minutes, sampling continues",
+                duration_secs / 60
+            ))
+        }
+        Ok(None) => {
+            info!("{}", tr!("已取消暂缓重启", "Snooze cancelled"));
+            Response::Message("Snooze cancelled, restarts resumed".to_string())
+        }
+        Err(e) => Response::Error(e),
+    }
+}
+
+// 重新读取配置文件并应用启动时的覆盖，各进程的监控任务在下一轮采样时使用新配置；
+// 新增到配置中的进程需要重启服务后才会开始监控
+fn reload_config(config: &Mutex<Config>) -> Response {
+    let loaded = ConfigManager::new(config_file_path())
+        .load()
+        .map_err(|e| e.to_string())
+        .and_then(|mut file_config| {
+            apply_config_overrides(&mut file_config)?;
+            Ok(file_config)
+        });
+    match loaded {
+        Ok(mut file_config) => {
+            i18n::set_language(file_config.log_language);
+            templates::load(&file_config);
+            file_config.resolve_default_thresholds(system_info_printer::total_physical_memory());
+            *config.lock().unwrap_or_else(PoisonError::into_inner) = file_config;
+            info!("{}", tr!("已重新加载配置文件", "Config file reloaded"));
+            Response::Message("Config reloaded".to_string())
+        }
+        Err(e) => {
+            error!(
+                "{}",
+                tr!(
+                    "重新加载配置失败，继续使用当前配置: {}",
+                    "Failed to reload config, keeping the current one: {}",
+                    e
+                )
+            );
+            Response::Error(format!(
+                "Failed to reload config, keeping the current one: {}",
+                e
+            ))
+        }
+    }
+}
+
+// 托盘程序以普通用户身份发送，限制长度避免写入过大的记录
+const MAX_ANNOTATION_CHARS: usize = 1000;
+
+fn annotate(message: &str) -> Response {
+    let message: String = message.chars().take(MAX_ANNOTATION_CHARS).collect();
+    info!(
+        "{}",
+        tr!("记录注释: {}", "Recording annotation: {}", message)
+    );
+    let result = DB_CONNECTION
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert_annotation(&message);
+    match result {
+        Ok(()) => Response::Message("Annotation recorded".to_string()),
+        Err(e) => {
+            error!(
+                "{}",
+                tr!("写入注释失败: {}", "Failed to insert annotation: {}", e)
+            );
+            Response::Error(format!("Failed to record annotation: {}", e))
+        }
+    }
+}
+
+/// 执行一个请求，不检查权限；重启请求会阻塞到进程恢复。gRPC 接口也通过它执行请求。
+pub fn handle_request(config: &Mutex<Config>, request: Request) -> Response {
+    match request {
+        Request::Status => Response::Status(monitor_state::snapshot()),
+        Request::RestartNow(name) => restart_now(config, name),
+        Request::SetThreshold {
+            bytes,
+            name,
+            persist,
+        } => set_threshold(config, bytes, name, persist),
+        Request::Snooze(duration_secs) => snooze(duration_secs, true),
+        Request::ReloadConfig => reload_config(config),
+        Request::Annotate(message) => annotate(&message),
+        Request::VisibleWindows(count) => {
+            monitor_state::set_visible_windows(count);
+            Response::Message("Window count recorded".to_string())
+        }
+        // 全屏状态按客户端所在会话记录，只能通过本机管道上报
+        Request::FullScreen(_) => {
+            Response::Error("Full-screen state is only accepted from the local pipe".to_string())
+        }
+    }
+}
+
+// 在 times 中记录一次请求，窗口内的请求已达到上限时返回 false
+fn within_limit(times: &Mutex<VecDeque<Instant>>, (limit, window): (usize, Duration)) -> bool {
+    let mut times = times.lock().unwrap_or_else(PoisonError::into_inner);
+    let now = Instant::now();
+    while times
+        .front()
+        .is_some_and(|time| now.duration_since(*time) >= window)
+    {
+        times.pop_front();
+    }
+    if times.len() >= limit {
+        return false;
+    }
+    times.push_back(now);
+    true
+}
+
+// 执行本机普通用户（托盘程序）的请求：暂缓受时长上限约束且不能改动管理员的暂缓，
+// 立即重启和注释限制频率
+fn handle_user_request(config: &Mutex<Config>, request: Request) -> Response {
+    let limit = match &request {
+        Request::Snooze(duration_secs) => return snooze(*duration_secs, false),
+        Request::RestartNow(_) => Some((&*USER_RESTARTS, USER_RESTART_LIMIT)),
+        Request::Annotate(_) => Some((&*USER_ANNOTATIONS, USER_ANNOTATE_LIMIT)),
+        _ => None,
+    };
+    if let Some((times, (count, window))) = limit {
+        if !within_limit(times, (count, window)) {
+            warn!(
+                "{}",
+                tr!(
+                    "本机非管理员用户的请求过于频繁，已拒绝: {:?}",
+                    "Rejected a local non-admin request over the rate limit: {:?}",
+                    request
+                )
+            );
+            return Response::Error(format!(
+                "At most {} such requests per {} minutes are allowed without an administrator",
+                count,
+                window.as_secs() / 60
+            ));
+        }
+    }
+    handle_request(config, request)
+}
+
+// 远程客户端的计算机名，用于记录日志
+fn client_computer_name(pipe: &NamedPipeServer) -> String {
+    let mut name = [0u16; 256];
+    let result = unsafe {
+        GetNamedPipeClientComputerNameW(
+            HANDLE(pipe.as_raw_handle()),
+            windows::core::PWSTR(name.as_mut_ptr()),
+            (name.len() * 2) as u32,
+        )
+    };
+    if !result.as_bool() {
+        return "unknown".to_string();
+    }
+    let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+    String::from_utf16_lossy(&name[..len])
+}
+
+// 本机客户端所在的会话
+fn client_session_id(pipe: &NamedPipeServer) -> Option<u32> {
+    let mut session_id = 0;
+    unsafe { GetNamedPipeClientSessionId(HANDLE(pipe.as_raw_handle()), &mut session_id) }.ok()?;
+    Some(session_id)
+}
+
+// 以客户端身份检查其是否属于 Administrators，需先从管道读取过数据才能模拟
+fn client_is_admin(pipe: &NamedPipeServer) -> bool {
+    unsafe {
+        if ImpersonateNamedPipeClient(HANDLE(pipe.as_raw_handle())).is_err() {
+            return false;
+        }
+        let mut sid = [0u8; SECURITY_MAX_SID_SIZE as usize];
+        let mut size = sid.len() as u32;
+        let psid = PSID(sid.as_mut_ptr() as _);
+        let mut member = BOOL(0);
+        let admin = CreateWellKnownSid(WinBuiltinAdministratorsSid, None, Some(psid), &mut size)
+            .is_ok()
+            && CheckTokenMembership(None, psid, &mut member).is_ok()
+            && member.as_bool();
+        // 无法恢复自身身份时不能继续以客户端身份运行
+        if RevertToSelf().is_err() {
+            std::process::abort();
+        }
+        admin
+    }
+}
+
+async fn serve_client(
+    config: Arc<Mutex<Config>>,
+    pipe: NamedPipeServer,
+    remote: bool,
+) -> io::Result<()> {
+    let client = if remote {
+        Some(client_computer_name(&pipe))
+    } else {
+        None
+    };
+    let mut reader = AsyncBufReader::new(pipe);
+    let mut line = String::new();
+    let read = tokio::time::timeout(
+        REQUEST_READ_TIMEOUT,
+        (&mut reader).take(MAX_REQUEST_BYTES).read_line(&mut line),
+    )
+    .await;
+    let error = match read {
+        Ok(Ok(_)) if line.ends_with('\n') => None,
+        Ok(Ok(_)) => Some(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "request is not a line of at most {} bytes",
+                MAX_REQUEST_BYTES
+            ),
+        )),
+        Ok(Err(e)) => Some(e),
+        Err(_) => Some(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!(
+                "no request within {} seconds",
+                REQUEST_READ_TIMEOUT.as_secs()
+            ),
+        )),
+    };
+    // 超时、超长或没有换行的请求直接断开，不作回应
+    if let Some(error) = error {
+        let _ = reader.get_ref().disconnect();
+        return Err(error);
+    }
+    let local_admin = !remote && client_is_admin(reader.get_ref());
+    let request = serde_json::from_str::<Request>(&line);
+    // 远程管道只允许管理员连接
+    let admin = remote || local_admin;
+    let response = match request {
+        Ok(request) if request.requires_local_admin() && !local_admin => {
+            warn!(
+                "{}",
+                tr!(
+                    "拒绝来自 {} 的请求: {:?}",
+                    "Rejected request from {}: {:?}",
+                    client
+                        .clone()
+                        .unwrap_or_else(|| tr!("本机非管理员用户", "a local non-admin user")),
+                    request
+                )
+            );
+            Response::Error("This command requires a local administrator".to_string())
+        }
+        Ok(Request::FullScreen(full_screen)) if !remote => {
+            match client_session_id(reader.get_ref()) {
+                Some(session) => {
+                    monitor_state::set_full_screen(session, full_screen);
+                    Response::Message("Full-screen state recorded".to_string())
+                }
+                None => Response::Error("Cannot determine the client session".to_string()),
+            }
+        }
+        // 重启请求会阻塞数十秒，放到阻塞线程池中执行，不影响其他客户端
+        Ok(request) => {
+            if let Some(client) = &client {
+                info!(
+                    "{}",
+                    tr!(
+                        "收到来自 {} 的远程请求: {:?}",
+                        "Received remote request from {}: {:?}",
+                        client,
+                        request
+                    )
+                );
+            }
+            tokio::task::spawn_blocking(move || {
+                if admin {
+                    handle_request(&config, request)
+                } else {
+                    handle_user_request(&config, request)
+                }
+            })
+            .await
+            .map_err(io::Error::other)?
+        }
+        Err(e) => Response::Error(format!("Invalid request: {}", e)),
+    };
+    let mut data = serde_json::to_vec(&response)?;
+    data.push(b'\n');
+    let pipe = reader.get_mut();
+    pipe.write_all(&data).await?;
+    // 等客户端读完再断开，否则未读取的数据会被丢弃
+    unsafe {
+        let _ = FlushFileBuffers(HANDLE(pipe.as_raw_handle()));
+    }
+    pipe.disconnect()
+}
+
+// 服务器只有启用 SMB 加密（Set-SmbServerConfiguration -EncryptData $true）时
+// 才开放远程管道，避免请求和状态以明文在网络上传输
+fn smb_encryption_enabled() -> bool {
+    let mut value = 0u32;
+    let mut size = std::mem::size_of::<u32>() as u32;
+    let result = unsafe {
+        RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            w!(r"SYSTEM\CurrentControlSet\Services\LanmanServer\Parameters"),
+            w!("EncryptData"),
+            RRF_RT_REG_DWORD,
+            None,
+            Some(&mut value as *mut u32 as *mut _),
+            Some(&mut size),
+        )
+    };
+    result == ERROR_SUCCESS && value != 0
+}
+
+// 管道的安全描述符，创建管道前需保持其存活
+fn pipe_security(sddl: &str) -> io::Result<Owned<HLOCAL>> {
+    let sddl = to_wide_string(sddl);
+    let mut descriptor = PSECURITY_DESCRIPTOR::default();
+    unsafe {
+        ConvertStringSecurityDescriptorToSecurityDescriptorW(
+            PCWSTR(sddl.as_ptr()),
+            SDDL_REVISION_1,
+            &mut descriptor,
+            None,
+        )?;
+        Ok(Owned::new(HLOCAL(descriptor.0)))
+    }
+}
+
+fn create_pipe(remote: bool) -> io::Result<NamedPipeServer> {
+    let mut options = ServerOptions::new();
+    options
+        .reject_remote_clients(!remote)
+        .in_buffer_size(PIPE_BUFFER_SIZE)
+        .out_buffer_size(PIPE_BUFFER_SIZE);
+    let (name, sddl) = if remote {
+        (format!(r"\\.\pipe\{}", REMOTE_PIPE), REMOTE_PIPE_SDDL)
+    } else {
+        (PIPE_NAME.to_string(), LOCAL_PIPE_SDDL)
+    };
+    let descriptor = pipe_security(sddl)?;
+    let mut attributes = SECURITY_ATTRIBUTES {
+        nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+        lpSecurityDescriptor: descriptor.0,
+        bInheritHandle: false.into(),
+    };
+    unsafe {
+        options.create_with_security_attributes_raw(
+            name,
+            &mut attributes as *mut SECURITY_ATTRIBUTES as *mut _,
+        )
+    }
+}
+
+// 每个客户端连接后立即创建下一个管道实例，服务停止时退出
+async fn serve(config: Arc<Mutex<Config>>, remote: bool) {
+    let name = if remote { REMOTE_PIPE } else { PIPE_NAME };
+    while !monitor_state::is_shutting_down() {
+        let server = match create_pipe(remote) {
+            Ok(server) => server,
+            Err(e) => {
+                error!(
+                    "{}",
+                    tr!(
+                        "创建命名管道 {} 失败: {}",
+                        "Failed to create named pipe {}: {}",
+                        name,
+                        e
+                    )
+                );
+                tokio::select! {
+                    _ = monitor_state::shutdown_requested() => break,
+                    _ = tokio::time::sleep(Duration::from_secs(5)) => continue,
+                }
+            }
+        };
+        tokio::select! {
+            _ = monitor_state::shutdown_requested() => break,
+            result = server.connect() => {
+                if let Err(e) = result {
+                    error!(
+                        "{}",
+                        tr!("连接命名管道失败: {}", "Failed to connect named pipe: {}", e)
+                    );
+                    continue;
+                }
+            }
+        }
+        let config = config.clone();
+        tokio::spawn(async move {
+            if let Err(e) = serve_client(config, server, remote).await {
+                error!(
+                    "{}",
+                    tr!(
+                        "处理 IPC 客户端请求失败: {}",
+                        "Failed to serve IPC client: {}",
+                        e
+                    )
+                );
+            }
+        });
+    }
+}
+
+pub async fn run_server(config: Arc<Mutex<Config>>) {
+    info!(
+        "{}",
+        tr!(
+            "正在 {} 上启动 IPC 服务",
+            "Starting IPC server on {}",
+            PIPE_NAME
+        )
+    );
+    let remote_control = config
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .remote_control;
+    let remote = async {
+        if !remote_control {
+            return;
+        }
+        if !smb_encryption_enabled() {
+            warn!(
+                "{}",
+                tr!(
+                    "remote_control 已启用，但本机未开启 SMB 加密，不开放远程管理",
+                    "remote_control is enabled but SMB encryption is off, remote control is not served"
+                )
+            );
+            return;
+        }
+        info!(
+            "{}",
+            tr!(
+                "远程管理已开放: \\\\<主机>\\pipe\\{}",
+                "Remote control is served at \\\\<host>\\pipe\\{}",
+                REMOTE_PIPE
+            )
+        );
+        serve(config.clone(), true).await
+    };
+    tokio::join!(serve(config.clone(), false), remote);
+    info!("{}", tr!("IPC 服务已停止", "IPC server stopped"));
+}
+
+fn open_pipe(name: &str) -> io::Result<File> {
+    let mut retries = 0;
+    loop {
+        match OpenOptions::new().access_mode(CLIENT_ACCESS).open(name) {
+            Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY.0 as i32) && retries < 10 => {
+                retries += 1;
+                thread::sleep(Duration::from_millis(200));
+            }
+            result => return result,
+        }
+    }
+}
+
+pub fn send_request(request: &Request) -> io::Result<Response> {
+    send_request_to(None, request)
+}
+
+/// 向本机（`host` 为 `None`）或指定计算机上运行的监控发送请求；
+/// 远程连接使用当前 Windows 账户的凭据。
+pub fn send_request_to(host: Option<&str>, request: &Request) -> io::Result<Response> {
+    let pipe = match host {
+        Some(host) => open_pipe(&format!(r"\\{}\pipe\{}", host, REMOTE_PIPE))?,
+        None => open_pipe(PIPE_NAME)?,
+    };
+    let mut writer = &pipe;
+    serde_json::to_writer(&mut writer, request)?;
+    writer.write_all(b"\n")?;
+    let mut line = String::new();
+    BufReader::new(&pipe).read_line(&mut line)?;
+    serde_json::from_str(&line).map_err(io::Error::from)
+}
@@ -0,0 +1,155 @@
+//! Kafka 输出（需要以 `kafka` 功能编译）：每隔 `interval_seconds` 把各进程的新采样和重启以 JSON 发布到
+//! `kafka_topic`，消息键为主机名，便于接入已有的流式遥测管道。
+
+#[cfg(not(feature = "kafka"))]
+use log::warn;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::config_manager::Config;
+use crate::tr;
+
+#[cfg(feature = "kafka")]
+mod producer {
+    use rdkafka::config::ClientConfig;
+    use rdkafka::producer::{FutureProducer, FutureRecord};
+    use std::collections::{BTreeMap, HashMap};
+    use std::time::Duration;
+
+    use super::*;
+    use crate::monitor_state;
+    use crate::reporter::machine_name;
+    use crate::sink::{build_records, run_sink, FailureLog, Record, Sink};
+
+    // 消息在本地队列中等待确认的最长时间
+    const MESSAGE_TIMEOUT_MS: &str = "10000";
+
+    // 代理地址和 librdkafka 属性，变化后重新创建生产者
+    type ProducerSettings = (String, BTreeMap<String, String>);
+
+    fn new_producer(
+        brokers: &str,
+        properties: &BTreeMap<String, String>,
+    ) -> Result<FutureProducer, String> {
+        let mut client_config = ClientConfig::new();
+        for (key, value) in properties {
+            client_config.set(key, value);
+        }
+        client_config
+            .set("bootstrap.servers", brokers)
+            .set("message.timeout.ms", MESSAGE_TIMEOUT_MS)
+            .create()
+            .map_err(|e| e.to_string())
+    }
+
+    async fn send(
+        producer: &FutureProducer,
+        topic: &str,
+        records: &[Record],
+    ) -> Result<(), String> {
+        for record in records {
+            let payload = serde_json::to_string(record).map_err(|e| e.to_string())?;
+            producer
+                .send(
+                    FutureRecord::to(topic).key(&record.host).payload(&payload),
+                    Duration::from_secs(0),
+                )
+                .await
+                .map_err(|(e, _)| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    struct Kafka {
+        sent_samples: HashMap<String, u64>,
+        sent_restarts: HashMap<String, u64>,
+        cached: Option<(ProducerSettings, FutureProducer)>,
+    }
+
+    impl Sink for Kafka {
+        fn interval_seconds(config: &Config) -> u64 {
+            config.interval_seconds
+        }
+
+        async fn round(&mut self, config: &Mutex<Config>, failures: &mut FailureLog) {
+            let (brokers, topic, properties) = {
+                let config = config.lock().unwrap_or_else(PoisonError::into_inner);
+                (
+                    config.kafka_brokers.clone(),
+                    config.kafka_topic.clone(),
+                    config.kafka_properties.clone(),
+                )
+            };
+            if brokers.is_empty() || topic.is_empty() {
+                return;
+            }
+            let settings = (brokers, properties);
+            let producer = match self.cached.take() {
+                Some((cached_settings, producer)) if cached_settings == settings => Ok(producer),
+                _ => new_producer(&settings.0, &settings.1),
+            };
+            let status = monitor_state::snapshot();
+            let records = build_records(
+                &machine_name(),
+                &status,
+                &self.sent_samples,
+                &self.sent_restarts,
+            );
+            let result = match &producer {
+                Ok(producer) => send(producer, &topic, &records).await,
+                Err(e) => Err(e.clone()),
+            };
+            match result {
+                Ok(()) => {
+                    self.sent_samples = status.latest(|p| p.last_sample);
+                    self.sent_restarts = status.latest(|p| p.last_restart);
+                    failures.succeeded(|| {
+                        tr!(
+                            "已恢复向 Kafka 发布记录",
+                            "Resumed publishing records to Kafka"
+                        )
+                    });
+                }
+                Err(e) => failures.failed(|| {
+                    tr!(
+                        "向 Kafka 主题 {} 发布记录失败: {}",
+                        "Failed to publish records to Kafka topic {}: {}",
+                        topic,
+                        e
+                    )
+                }),
+            }
+            if let Ok(producer) = producer {
+                self.cached = Some((settings, producer));
+            }
+        }
+    }
+
+    /// 未配置 `kafka_brokers` 或 `kafka_topic` 时空转，配置重新加载后下一轮生效；服务停止时返回。
+    pub async fn run(config: Arc<Mutex<Config>>) {
+        // 启动前已发生的重启（从状态文件恢复）不再发布
+        let kafka = Kafka {
+            sent_samples: HashMap::new(),
+            sent_restarts: monitor_state::snapshot().latest(|p| p.last_restart),
+            cached: None,
+        };
+        run_sink(config, kafka).await
+    }
+}
+
+#[cfg(feature = "kafka")]
+pub use producer::run;
+
+/// 未启用 kafka 功能时只在配置了代理地址时提示一次。
+#[cfg(not(feature = "kafka"))]
+pub async fn run(config: Arc<Mutex<Config>>) {
+    let config = config.lock().unwrap_or_else(PoisonError::into_inner);
+    if !config.kafka_brokers.is_empty() {
+        warn!(
+            "{}",
+            tr!(
+                "已配置 kafka_brokers，但程序编译时未启用 kafka 功能，不会发布到 Kafka",
+                "kafka_brokers is set but this build does not include the kafka feature, nothing is published to Kafka"
+            )
+        );
+    }
+}
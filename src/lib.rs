@@ -0,0 +1,31 @@
+//! 进程内存监控库：枚举进程、采样内存、按阈值判断并执行重启等动作。
+//!
+//! Windows 服务（`process_guard`）只是该库的一个使用者，入口见 [`monitor::ProcessMonitor`]。
+
+pub mod baseline;
+pub mod config_manager;
+pub mod config_validator;
+pub mod db_manager;
+pub mod error;
+pub mod etw_trace;
+pub mod ffi;
+pub mod file_security;
+pub mod i18n;
+pub mod ipc;
+pub mod maintenance;
+pub mod memory_sampler;
+pub mod monitor;
+pub mod monitor_state;
+pub mod os_build;
+pub mod plugins;
+pub mod process_manager;
+pub mod process_name;
+pub mod rules;
+pub mod self_usage;
+pub mod session_manager;
+pub mod supervisor;
+pub mod system_api;
+pub mod system_info_printer;
+pub mod templates;
+mod tests;
+pub mod watchdog;
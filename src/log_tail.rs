@@ -0,0 +1,118 @@
+use log::Level;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+use std::path::Path;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+const INITIAL_LINES: usize = 20;
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// 日志格式为 "2024-01-01 12:00:00 - INFO - message"
+fn line_level(line: &str) -> Option<Level> {
+    let mut fields = line.splitn(3, " - ");
+    fields.next()?;
+    Level::from_str(fields.next()?.trim()).ok()
+}
+
+// 按级别过滤，没有级别的续行跟随上一行的结果
+struct LevelFilter {
+    min_level: Option<Level>,
+    last_shown: bool,
+}
+
+impl LevelFilter {
+    fn show(&mut self, line: &str) -> bool {
+        let min_level = match self.min_level {
+            Some(level) => level,
+            None => return true,
+        };
+        if let Some(level) = line_level(line) {
+            self.last_shown = level <= min_level;
+        }
+        self.last_shown
+    }
+}
+
+pub fn parse_level(value: &str) -> Result<Level, String> {
+    Level::from_str(value).map_err(|_| {
+        format!(
+            "Invalid log level: {} (expected error, warn, info, debug or trace)",
+            value
+        )
+    })
+}
+
+fn print_lines(reader: &mut BufReader<File>, filter: &mut LevelFilter) -> io::Result<()> {
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 {
+        // 写入到一半的行等下一轮再输出
+        if !line.ends_with('\n') {
+            reader.seek(SeekFrom::Current(-(line.len() as i64)))?;
+            break;
+        }
+        if filter.show(&line) {
+            print!("{}", line);
+        }
+        line.clear();
+    }
+    Ok(())
+}
+
+pub fn tail(path: &Path, follow: bool, min_level: Option<Level>) -> io::Result<()> {
+    let mut filter = LevelFilter {
+        min_level,
+        last_shown: true,
+    };
+    let data = std::fs::read(path)?;
+    let text = String::from_utf8_lossy(&data);
+    let lines: Vec<&str> = text.lines().filter(|line| filter.show(line)).collect();
+    for line in &lines[lines.len().saturating_sub(INITIAL_LINES)..] {
+        println!("{}", line);
+    }
+    if !follow {
+        return Ok(());
+    }
+
+    let mut reader = BufReader::new(File::open(path)?);
+    reader.seek(SeekFrom::Start(data.len() as u64))?;
+    loop {
+        print_lines(&mut reader, &mut filter)?;
+        thread::sleep(POLL_INTERVAL);
+        // 日志滚动后文件变小，从头读取新文件
+        let position = reader.stream_position()?;
+        match std::fs::metadata(path) {
+            Ok(metadata) if metadata.len() < position => {
+                reader = BufReader::new(File::open(path)?);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_level() {
+        assert_eq!(
+            line_level("2024-01-01 12:00:00 - WARN - threshold exceeded"),
+            Some(Level::Warn)
+        );
+        assert_eq!(line_level("  continuation of a message"), None);
+    }
+
+    #[test]
+    fn test_level_filter_keeps_continuations() {
+        let mut filter = LevelFilter {
+            min_level: Some(Level::Warn),
+            last_shown: true,
+        };
+        assert!(!filter.show("2024-01-01 12:00:00 - INFO - sample"));
+        assert!(!filter.show("details"));
+        assert!(filter.show("2024-01-01 12:00:01 - ERROR - restart failed"));
+        assert!(filter.show("details"));
+    }
+}
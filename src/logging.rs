@@ -1,38 +1,207 @@
+use log::{error, warn};
 use log4rs::{
-    append::rolling_file::{
-        policy::compound::{
-            roll::fixed_window::FixedWindowRoller, trigger::size::SizeTrigger, CompoundPolicy,
+    append::{
+        console::ConsoleAppender,
+        rolling_file::{
+            policy::compound::{
+                roll::fixed_window::FixedWindowRoller, trigger::size::SizeTrigger, CompoundPolicy,
+            },
+            RollingFileAppender,
         },
-        RollingFileAppender,
     },
     config::{Appender, Root},
     encode::pattern::PatternEncoder,
 };
+use std::backtrace::Backtrace;
+use std::path::{Path, PathBuf};
 
-pub fn configure_logging() -> Result<(), Box<dyn std::error::Error>> {
-    let mut log_path = std::env::current_exe()?;
-    log_path.set_file_name("process_guard.log");
+use crate::config_manager::program_file_path;
+use crate::event_log;
+use crate::file_security;
+use crate::tr;
 
-    let window_roller = FixedWindowRoller::builder().build("process_guard.{}.log", 5)?; // Keep 5 backup files
+const LOG_PATTERN: &str = "{d(%Y-%m-%d %H:%M:%S)} - {l} - {m}\n";
+pub const LOG_FILE_NAME: &str = "process_guard.log";
+const LOG_BACKUP_COUNT: u32 = 5;
 
-    let size_trigger = SizeTrigger::new(20 * 1024 * 1024); // Rotate after 10 MB
+// 程序目录不可写（如 Program Files 权限不足）时改写到 %ProgramData%\dwm_monitor\logs
+pub fn fallback_log_file_path() -> PathBuf {
+    let program_data = std::env::var_os("ProgramData").unwrap_or_else(|| "C:\\ProgramData".into());
+    PathBuf::from(program_data)
+        .join("dwm_monitor")
+        .join("logs")
+        .join(LOG_FILE_NAME)
+}
+
+// 程序目录下的日志优先
+pub fn log_file_candidates() -> [PathBuf; 2] {
+    [program_file_path(LOG_FILE_NAME), fallback_log_file_path()]
+}
 
-    let compound_policy = CompoundPolicy::new(Box::new(size_trigger), Box::new(window_roller));
+// 服务当前写入的日志：两处都有时取最近修改的一个
+pub fn log_file_path() -> PathBuf {
+    let [primary, fallback] = log_file_candidates();
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    match (modified(&primary), modified(&fallback)) {
+        (None, Some(_)) => fallback,
+        (Some(primary_time), Some(fallback_time)) if fallback_time > primary_time => fallback,
+        _ => primary,
+    }
+}
 
-    let logfile = RollingFileAppender::builder()
-        .encoder(Box::new(PatternEncoder::new(
-            "{d(%Y-%m-%d %H:%M:%S)} - {l} - {m}\n",
-        )))
-        .build(log_path, Box::new(compound_policy))?;
+// 滚动后的备份日志，与当前日志放在同一目录
+fn rotated_log_pattern(log_path: &Path) -> String {
+    log_path
+        .with_file_name("process_guard.{}.log")
+        .to_string_lossy()
+        .into_owned()
+}
 
+pub fn rotated_log_paths(log_path: &Path) -> Vec<PathBuf> {
+    let pattern = rotated_log_pattern(log_path);
+    (0..LOG_BACKUP_COUNT)
+        .map(|index| PathBuf::from(pattern.replace("{}", &index.to_string())))
+        .filter(|path| path.exists())
+        .collect()
+}
+
+// 只输出到控制台，不写入服务的日志文件
+pub fn configure_console_logging() -> Result<(), Box<dyn std::error::Error>> {
+    let stdout = ConsoleAppender::builder()
+        .encoder(Box::new(PatternEncoder::new(LOG_PATTERN)))
+        .build();
     let config = log4rs::Config::builder()
-        .appender(Appender::builder().build("logfile", Box::new(logfile)))
+        .appender(Appender::builder().build("stdout", Box::new(stdout)))
         .build(
             Root::builder()
-                .appender("logfile")
+                .appender("stdout")
                 .build(log::LevelFilter::Info),
         )?;
-
     log4rs::init_config(config)?;
     Ok(())
 }
+
+// 依次尝试程序目录和 ProgramData 下的日志文件；都无法写入时原因写入 Windows 事件日志，
+// 服务仍然运行（前台运行时只输出到控制台）
+pub fn configure_logging(console: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let [primary, fallback] = log_file_candidates();
+    let primary_error = match logging_config(Some(&primary), console) {
+        Ok(config) => {
+            log4rs::init_config(config)?;
+            restrict_log_file(&primary);
+            return Ok(());
+        }
+        Err(e) => e,
+    };
+    let fallback_result = fallback
+        .parent()
+        .map_or(Ok(()), create_log_directory)
+        .map_err(Box::from)
+        .and_then(|_| logging_config(Some(&fallback), console));
+    match fallback_result {
+        Ok(config) => {
+            log4rs::init_config(config)?;
+            restrict_log_file(&fallback);
+            error!(
+                "{}",
+                tr!(
+                    "无法写入日志文件 {}: {}，改为写入 {}",
+                    "Failed to write log file {}: {}, logging to {} instead",
+                    primary.display(),
+                    primary_error,
+                    fallback.display()
+                )
+            );
+        }
+        Err(fallback_error) => {
+            log4rs::init_config(logging_config(None, console)?)?;
+            let message = format!(
+                "File logging is disabled: failed to write {} ({}) and {} ({})",
+                primary.display(),
+                primary_error,
+                fallback.display(),
+                fallback_error
+            );
+            error!("{}", message);
+            if let Err(e) = event_log::report_error(&message) {
+                eprintln!("{}\nFailed to write to the event log: {}", message, e);
+            }
+        }
+    }
+    Ok(())
+}
+
+// 目录由任何用户都能写入的 ProgramData 下创建，必须先确认不是他人预先放置的联接并收紧权限
+fn create_log_directory(dir: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    file_security::restrict_directory(dir)
+}
+
+fn restrict_log_file(log_path: &Path) {
+    if let Err(e) = file_security::restrict_file(log_path) {
+        warn!(
+            "{}",
+            tr!(
+                "无法限制日志文件 {} 的访问权限: {}",
+                "Failed to restrict access to log file {}: {}",
+                log_path.display(),
+                e
+            )
+        );
+    }
+}
+
+// log_path 为 None 时不写文件
+fn logging_config(
+    log_path: Option<&Path>,
+    console: bool,
+) -> Result<log4rs::Config, Box<dyn std::error::Error>> {
+    let mut builder = log4rs::Config::builder();
+    let mut root = Root::builder();
+    if let Some(log_path) = log_path {
+        // 以 LocalSystem 运行时不能跟随链接写到其他位置
+        file_security::reject_reparse_point(log_path)?;
+        let window_roller =
+            FixedWindowRoller::builder().build(&rotated_log_pattern(log_path), LOG_BACKUP_COUNT)?; // Keep 5 backup files
+
+        let size_trigger = SizeTrigger::new(20 * 1024 * 1024); // Rotate after 10 MB
+
+        let compound_policy = CompoundPolicy::new(Box::new(size_trigger), Box::new(window_roller));
+
+        let logfile = RollingFileAppender::builder()
+            .encoder(Box::new(PatternEncoder::new(LOG_PATTERN)))
+            .build(log_path, Box::new(compound_policy))?;
+        builder = builder.appender(Appender::builder().build("logfile", Box::new(logfile)));
+        root = root.appender("logfile");
+    }
+    // 前台运行时同时输出到控制台
+    if console {
+        let stdout = ConsoleAppender::builder()
+            .encoder(Box::new(PatternEncoder::new(LOG_PATTERN)))
+            .build();
+        builder = builder.appender(Appender::builder().build("stdout", Box::new(stdout)));
+        root = root.appender("stdout");
+    }
+    Ok(builder.build(root.build(log::LevelFilter::Info))?)
+}
+
+// panic 信息和调用栈写入日志和 Windows 事件日志，服务运行时看不到默认输出到 stderr 的内容
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let message = format!("{}\n{}", info, Backtrace::force_capture());
+        error!("{}", tr!("程序崩溃: {}", "Panic: {}", message));
+        log::logger().flush();
+        if let Err(e) = event_log::report_error(&message) {
+            error!(
+                "{}",
+                tr!(
+                    "无法将崩溃信息写入事件日志: {}",
+                    "Failed to write panic to the event log: {}",
+                    e
+                )
+            );
+        }
+        default_hook(info);
+    }));
+}
@@ -1,107 +1,681 @@
-mod config_manager;
-mod db_manager;
+mod alerting;
+mod cli;
+mod cloudwatch;
+mod commands;
+mod diagnostics;
+mod event_log;
+mod grpc;
+mod instance_lock;
+mod kafka;
+mod log_analytics;
+mod log_tail;
 mod logging;
-mod process_manager;
-mod system_info_printer;
-mod tests;
+mod reporter;
+mod service_account;
+mod service_installer;
+mod service_status;
+mod simulate;
+mod sink;
+mod textfile_exporter;
+mod updater;
+mod version;
 
-use log::{error, info};
-use process_manager::monitor_processes;
-use std::sync::Arc;
+use dwm_monitor::process_manager::RESTART_LOCK;
+use dwm_monitor::supervisor::{restart_on_panic, WORKER_RESTART_DELAY};
+use dwm_monitor::{
+    config_manager, config_validator, db_manager, etw_trace, file_security, i18n, ipc, monitor,
+    monitor_state, os_build, plugins, process_manager, process_name, session_manager, supervisor,
+    system_info_printer, templates, tr,
+};
+use log::{error, info, warn};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, TryLockError};
 use std::time::Duration;
 use std::{ffi::OsString, thread};
+use windows::Win32::{
+    Foundation::{
+        BOOL, ERROR_FAILED_SERVICE_CONTROLLER_CONNECT, ERROR_INVALID_PARAMETER, ERROR_OPEN_FAILED,
+        ERROR_SERVICE_ALREADY_RUNNING, FALSE, TRUE,
+    },
+    System::Console::{SetConsoleCtrlHandler, CTRL_BREAK_EVENT, CTRL_CLOSE_EVENT, CTRL_C_EVENT},
+};
 use windows_service::{
     define_windows_service,
     service::{
-        ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus,
-        ServiceType,
+        PowerEventParam, ServiceControl, ServiceState, SessionChangeParam, SessionChangeReason,
     },
     service_control_handler::{self, ServiceControlHandlerResult},
     service_dispatcher,
 };
 
-use crate::config_manager::Config;
-use crate::logging::configure_logging;
+use crate::cli::{
+    apply_overrides, env_overrides, parse_args, parse_service_args, Command, ConfigOverrides, USAGE,
+};
+use crate::config_manager::{config_file_path, Config, DBConfig};
+use crate::logging::{configure_console_logging, configure_logging, install_panic_hook};
 use crate::system_info_printer::print_all_system_info;
 
-const SERVICE_NAME: &str = "ProcessMonitorService";
-const CONFIG_FILE_NAME: &str = "process_guard_config.json";
+pub const SERVICE_NAME: &str = "ProcessMonitorService";
+const START_WAIT_HINT: Duration = Duration::from_secs(30);
+// 重启流程最长约 40 秒
+const STOP_WAIT_HINT: Duration = Duration::from_secs(60);
+
+static STOPPING: AtomicBool = AtomicBool::new(false);
+static STOPPED: AtomicBool = AtomicBool::new(false);
 
 define_windows_service!(ffi_service_main, service_main);
 
-fn load_config() -> Config {
-    let mut current_path = std::env::current_exe().unwrap();
-    current_path.set_file_name(CONFIG_FILE_NAME);
-    let config_manager = config_manager::ConfigManager::new(current_path);
-    config_manager.load_or_create_default()
+// 自定义构建在这里注册自己的插件（指标和动作），见 dwm_monitor::plugins
+fn register_plugins() {
+    plugins::register_action("command", plugins::command_action);
 }
 
-fn service_main(_arguments: Vec<OsString>) {
-    if let Err(e) = configure_logging() {
-        eprintln!("Failed to init logger: {}", e);
-        return;
+// 配置文件之上依次叠加环境变量和启动参数（--console 或 sc start 传入），只对本次运行生效
+fn load_config(args: ConfigOverrides) -> Result<Config, String> {
+    let config_manager = config_manager::ConfigManager::new(config_file_path());
+    let mut config = config_manager.load_or_create_default();
+    let env = env_overrides(std::env::vars())?;
+    apply_overrides(&mut config, &env, &args)?;
+    ipc::set_config_overrides({
+        let (env, args) = (env.clone(), args.clone());
+        move |config: &mut Config| apply_overrides(config, &env, &args)
+    });
+    i18n::set_language(config.log_language);
+    templates::load(&config);
+    config.resolve_default_thresholds(system_info_printer::total_physical_memory());
+    for (source, overrides) in [
+        (tr!("环境变量", "Environment variables"), &env),
+        (tr!("启动参数", "Start arguments"), &args),
+    ] {
+        if !overrides.is_empty() {
+            info!(
+                "{}",
+                tr!(
+                    "{}覆盖了本次运行的配置: {:?}",
+                    "{} override the config for this run: {:?}",
+                    source,
+                    overrides
+                )
+            );
+        }
     }
-    info!("{} starting...", SERVICE_NAME);
-    print_all_system_info();
+    Ok(config)
+}
+
+fn service_main(arguments: Vec<OsString>) {
     let event_handler = move |control_event| -> ServiceControlHandlerResult {
         match control_event {
-            ServiceControl::Stop => {
-                info!("Service is stopping...");
-                std::process::exit(0); // 立即退出程序
+            ServiceControl::Stop | ServiceControl::Preshutdown | ServiceControl::Shutdown => {
+                // Preshutdown 之后可能还会收到 Stop/Shutdown
+                if !STOPPING.swap(true, Ordering::SeqCst) {
+                    info!(
+                        "{}",
+                        tr!(
+                            "服务正在停止 ({:?})...",
+                            "Service is stopping ({:?})...",
+                            control_event
+                        )
+                    );
+                    service_status::set_pending(ServiceState::StopPending, 1, STOP_WAIT_HINT);
+                    monitor_state::request_shutdown();
+                }
+                ServiceControlHandlerResult::NoError
             }
+            ServiceControl::Pause => {
+                monitor_state::set_paused(true);
+                info!(
+                    "{}",
+                    tr!(
+                        "服务已暂停，监控暂停",
+                        "Service paused, monitoring suspended"
+                    )
+                );
+                service_status::set_state(ServiceState::Paused);
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Continue => {
+                monitor_state::set_paused(false);
+                info!(
+                    "{}",
+                    tr!(
+                        "服务已继续，监控恢复",
+                        "Service continued, monitoring resumed"
+                    )
+                );
+                service_status::set_state(ServiceState::Running);
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::PowerEvent(event) => {
+                match event {
+                    PowerEventParam::ResumeAutomatic | PowerEventParam::ResumeSuspend => {
+                        info!(
+                            "{}",
+                            tr!(
+                                "系统已从睡眠中恢复 ({:?})，立即采样",
+                                "System resumed from sleep ({:?}), sampling immediately",
+                                event
+                            )
+                        );
+                        monitor_state::wake_monitor();
+                    }
+                    PowerEventParam::Suspend => {
+                        info!("{}", tr!("系统即将进入睡眠", "System is entering sleep"))
+                    }
+                    _ => {}
+                }
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::SessionChange(param) => {
+                handle_session_change(param);
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
             _ => ServiceControlHandlerResult::NotImplemented,
         }
     };
 
+    // 先注册控制回调，初始化期间才能向 SCM 报告 StartPending 进度
     let status_handle = match service_control_handler::register(SERVICE_NAME, event_handler) {
         Ok(handle) => handle,
         Err(e) => {
-            error!("Failed to register service control handler: {}", e);
+            eprintln!("Failed to register service control handler: {}", e);
             return;
         }
     };
+    service_status::init(status_handle);
+    service_status::set_pending(ServiceState::StartPending, 1, START_WAIT_HINT);
+    if let Err(e) = configure_logging(false) {
+        eprintln!("Failed to init logger: {}", e);
+        service_status::set_stopped_with_error(ERROR_OPEN_FAILED.0);
+        return;
+    }
+    install_panic_hook();
+    info!("{}", tr!("{} 正在启动...", "{} starting...", SERVICE_NAME));
+    info!("{}", version::version_banner());
+
+    service_status::set_pending(ServiceState::StartPending, 2, START_WAIT_HINT);
+    print_all_system_info();
 
-    let next_status = ServiceStatus {
-        service_type: ServiceType::OWN_PROCESS,
-        current_state: ServiceState::Running,
-        controls_accepted: ServiceControlAccept::STOP,
-        exit_code: ServiceExitCode::Win32(0),
-        checkpoint: 0,
-        wait_hint: Duration::default(),
-        process_id: None,
+    service_status::set_pending(ServiceState::StartPending, 3, START_WAIT_HINT);
+    let _instance_lock = match acquire_instance_lock() {
+        Some(lock) => lock,
+        None => {
+            service_status::set_stopped_with_error(ERROR_SERVICE_ALREADY_RUNNING.0);
+            return;
+        }
+    };
+    // 第一个参数是服务名
+    let args = arguments
+        .into_iter()
+        .skip(1)
+        .map(|arg| arg.to_string_lossy().into_owned());
+    let config = match parse_service_args(args).and_then(load_config) {
+        Ok(config) => config,
+        Err(e) => {
+            error!(
+                "{}",
+                tr!("配置覆盖无效: {}", "Invalid config overrides: {}", e)
+            );
+            service_status::set_stopped_with_error(ERROR_INVALID_PARAMETER.0);
+            return;
+        }
     };
 
-    if let Err(e) = status_handle.set_service_status(next_status) {
-        error!("Failed to set service status: {}", e);
+    if !service_status::set_state(ServiceState::Running) {
         return;
     }
-    let config = load_config();
+    run_monitor(config);
+    finish_monitor();
+    service_status::set_state(ServiceState::Stopped);
+}
+
+fn acquire_instance_lock() -> Option<instance_lock::InstanceLock> {
+    let config_path = config_file_path();
+    match instance_lock::acquire(&config_path) {
+        Ok(Some(lock)) => Some(lock),
+        Ok(None) => {
+            error!(
+                "{}",
+                tr!(
+                    "已有另一个实例在使用 {} 监控，退出",
+                    "Another instance is already monitoring with {}, exiting",
+                    config_path.display()
+                )
+            );
+            None
+        }
+        Err(e) => {
+            error!(
+                "{}",
+                tr!(
+                    "创建实例互斥体失败: {}",
+                    "Failed to create instance mutex: {}",
+                    e
+                )
+            );
+            None
+        }
+    }
+}
+
+fn handle_session_change(param: SessionChangeParam) {
+    let session_id = param.notification.session_id;
+    let user = session_manager::session_user_name(session_id).unwrap_or_else(|| "-".to_string());
+    info!(
+        "{}",
+        tr!(
+            "会话变化: {:?}，会话 {} ({})",
+            "Session change: {:?}, session {} ({})",
+            param.reason,
+            session_id,
+            user
+        )
+    );
+    match param.reason {
+        SessionChangeReason::SessionLogon
+        | SessionChangeReason::SessionLogoff
+        | SessionChangeReason::SessionCreate
+        | SessionChangeReason::SessionTerminate
+        | SessionChangeReason::ConsoleConnect
+        | SessionChangeReason::ConsoleDisconnect
+        | SessionChangeReason::RemoteConnect
+        | SessionChangeReason::RemoteDisconnect => monitor_state::wake_monitor(),
+        _ => {}
+    }
+}
+
+// 监控循环退出后的收尾：等待进行中的重启完成并保存状态
+fn finish_monitor() {
+    let mut checkpoint = 2;
+    service_status::set_pending(ServiceState::StopPending, checkpoint, STOP_WAIT_HINT);
+    // IPC 触发的重启可能仍在进行，避免目标进程被杀掉后没有拉起；等待期间持续递增 checkpoint
+    loop {
+        match RESTART_LOCK.try_lock() {
+            Ok(_) | Err(TryLockError::Poisoned(_)) => break,
+            Err(TryLockError::WouldBlock) => {
+                thread::sleep(Duration::from_secs(1));
+                checkpoint += 1;
+                service_status::set_pending(ServiceState::StopPending, checkpoint, STOP_WAIT_HINT);
+            }
+        }
+    }
+    etw_trace::shutdown();
+    match monitor_state::save_state() {
+        Ok(_) => info!("{}", tr!("状态已保存", "State saved")),
+        Err(e) => error!("{}", tr!("保存状态失败: {}", "Failed to save state: {}", e)),
+    }
+    info!("{}", tr!("{} 已停止", "{} stopped", SERVICE_NAME));
+    log::logger().flush();
+    STOPPED.store(true, Ordering::SeqCst);
+}
 
+fn run_monitor(config: Config) {
     info!("{:#?}", config);
-    // 启动一个独立的线程，进行数据库清理工作
-    let db_cleanup_interval = config.db_config.cleanup_interval_hours;
-    let db_cleanup_hours = config.db_config.db_cleanup_hours;
-    let db_vacuum_threshold_mb = config.db_config.db_vacuum_threshold_mb;
-
-    let db_connection = Arc::new(&db_manager::DB_CONNECTION);
-
-    thread::spawn(move || {
-        loop {
-            {
-                info!("Starting database cleanup...");
-                let mut db_conn = db_connection.lock().unwrap();
-                match db_conn.cleanup_old_data(db_cleanup_hours, db_vacuum_threshold_mb) {
-                    Ok(_) => info!("Database cleanup completed successfully."),
-                    Err(e) => error!("Database cleanup failed: {}", e),
+    updater::remove_previous_binary();
+    match process_manager::enable_debug_privilege() {
+        Ok(()) => info!(
+            "{}",
+            tr!("已启用 SeDebugPrivilege", "SeDebugPrivilege enabled")
+        ),
+        Err(e) => warn!(
+            "{}",
+            tr!(
+                "无法启用 SeDebugPrivilege: {}，部分系统进程可能无法打开",
+                "Failed to enable SeDebugPrivilege: {}, some system processes may not be opened",
+                e
+            )
+        ),
+    }
+    monitor_state::load_state();
+    monitor_state::set_os_build(os_build::check().map(|build| build.to_string()));
+    if config.drop_privileges {
+        drop_privileges();
+    }
+    if config.background_mode {
+        match process_manager::enter_background_mode() {
+            Ok(()) => info!(
+                "{}",
+                tr!(
+                    "已切换到低优先级后台模式",
+                    "Switched to low priority background mode"
+                )
+            ),
+            Err(e) => warn!(
+                "{}",
+                tr!(
+                    "切换到后台模式失败: {}",
+                    "Failed to enter background mode: {}",
+                    e
+                )
+            ),
+        }
+    }
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            error!(
+                "{}",
+                tr!(
+                    "启动异步运行时失败: {}",
+                    "Failed to start async runtime: {}",
+                    e
+                )
+            );
+            return;
+        }
+    };
+    runtime.block_on(async move {
+        let db_config = config.db_config.clone();
+        let config = Arc::new(Mutex::new(config));
+        // 各任务在收到停止请求后各自退出，panic 时重新启动
+        tokio::join!(
+            restart_on_panic("Monitoring loop", WORKER_RESTART_DELAY, || {
+                supervisor::run(config.clone())
+            }),
+            restart_on_panic("IPC server", WORKER_RESTART_DELAY, || {
+                ipc::run_server(config.clone())
+            }),
+            restart_on_panic("Database cleanup", WORKER_RESTART_DELAY, || {
+                run_db_cleanup(db_config.clone())
+            }),
+            restart_on_panic("Reporter", WORKER_RESTART_DELAY, || {
+                reporter::run(config.clone())
+            }),
+            restart_on_panic("Log Analytics", WORKER_RESTART_DELAY, || {
+                log_analytics::run(config.clone())
+            }),
+            restart_on_panic("Textfile exporter", WORKER_RESTART_DELAY, || {
+                textfile_exporter::run(config.clone())
+            }),
+            restart_on_panic("CloudWatch", WORKER_RESTART_DELAY, || {
+                cloudwatch::run(config.clone())
+            }),
+            restart_on_panic("Kafka producer", WORKER_RESTART_DELAY, || {
+                kafka::run(config.clone())
+            }),
+            restart_on_panic("gRPC server", WORKER_RESTART_DELAY, || {
+                grpc::run(config.clone())
+            }),
+            restart_on_panic("Alerting", WORKER_RESTART_DELAY, || {
+                alerting::run(config.clone())
+            }),
+        );
+    });
+    // 仍在进行的阻塞调用（如 IPC 触发的重启）由 finish_monitor 等待
+    runtime.shutdown_background();
+}
+
+// 定期清理数据库中的旧数据
+async fn run_db_cleanup(db_config: DBConfig) {
+    let interval = Duration::from_secs((db_config.cleanup_interval_hours * 3600) as u64);
+    loop {
+        info!(
+            "{}",
+            tr!("正在清理数据库...", "Starting database cleanup...")
+        );
+        let result = tokio::task::spawn_blocking(move || {
+            db_manager::DB_CONNECTION
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .cleanup_old_data(db_config.db_cleanup_hours, db_config.db_vacuum_threshold_mb)
+        })
+        .await;
+        match result {
+            Ok(Ok(_)) => info!(
+                "{}",
+                tr!("数据库清理完成", "Database cleanup completed successfully.")
+            ),
+            Ok(Err(e)) => error!(
+                "{}",
+                tr!("数据库清理失败: {}", "Database cleanup failed: {}", e)
+            ),
+            Err(e) => error!(
+                "{}",
+                tr!("数据库清理失败: {}", "Database cleanup failed: {}", e)
+            ),
+        }
+        tokio::select! {
+            _ = monitor_state::shutdown_requested() => break,
+            _ = tokio::time::sleep(interval) => {}
+        }
+    }
+}
+
+// 初始化完成后只保留监控、结束和重启进程所需的特权，其余的从令牌中移除
+fn drop_privileges() {
+    let keep: Vec<&str> = service_account::REQUIRED_PRIVILEGES
+        .iter()
+        .copied()
+        // 几乎所有文件操作都依赖它；LocalSystem 在用户会话中重启进程（WTSQueryUserToken）需要
+        // SeTcbPrivilege，其他账户的令牌中没有它
+        .chain(["SeChangeNotifyPrivilege", "SeTcbPrivilege"])
+        .collect();
+    match process_manager::remove_privileges_except(&keep) {
+        Ok(removed) if removed.is_empty() => info!(
+            "{}",
+            tr!(
+                "服务令牌中没有需要移除的特权",
+                "No privileges to drop from the service token"
+            )
+        ),
+        Ok(removed) => info!(
+            "{}",
+            tr!(
+                "已从服务令牌中移除特权: {}",
+                "Dropped privileges from the service token: {}",
+                removed.join(", ")
+            )
+        ),
+        Err(e) => warn!(
+            "{}",
+            tr!(
+                "移除不需要的特权失败: {}",
+                "Failed to drop unneeded privileges: {}",
+                e
+            )
+        ),
+    }
+}
+
+unsafe extern "system" fn console_ctrl_handler(ctrl_type: u32) -> BOOL {
+    match ctrl_type {
+        CTRL_C_EVENT | CTRL_BREAK_EVENT | CTRL_CLOSE_EVENT => {
+            if !STOPPING.swap(true, Ordering::SeqCst) {
+                info!(
+                    "{}",
+                    tr!(
+                        "控制台已中断，正在退出...",
+                        "Console interrupted, exiting..."
+                    )
+                );
+                monitor_state::request_shutdown();
+            }
+            // 关闭窗口时回调返回后进程即被结束，需在此等待收尾完成
+            if ctrl_type == CTRL_CLOSE_EVENT {
+                while !STOPPED.load(Ordering::SeqCst) {
+                    thread::sleep(Duration::from_millis(100));
                 }
             }
-            // 休眠指定的时间间隔
-            thread::sleep(Duration::from_secs((db_cleanup_interval * 3600) as u64));
+            TRUE
         }
-    });
-    monitor_processes(&config);
+        _ => FALSE,
+    }
+}
+
+fn run_console(overrides: ConfigOverrides) {
+    if let Err(e) = configure_logging(true) {
+        eprintln!("Failed to init logger: {}", e);
+        return;
+    }
+    install_panic_hook();
+    info!(
+        "{}",
+        tr!(
+            "{} 正在以控制台模式启动...",
+            "{} starting in console mode...",
+            SERVICE_NAME
+        )
+    );
+    info!("{}", version::version_banner());
+    print_all_system_info();
+    if let Err(e) = unsafe { SetConsoleCtrlHandler(Some(Some(console_ctrl_handler)), true) } {
+        error!(
+            "{}",
+            tr!(
+                "设置控制台控制处理程序失败: {}",
+                "Failed to set console control handler: {}",
+                e
+            )
+        );
+    }
+    let _instance_lock = match acquire_instance_lock() {
+        Some(lock) => lock,
+        None => std::process::exit(1),
+    };
+    let config = match load_config(overrides) {
+        Ok(config) => config,
+        Err(e) => {
+            error!(
+                "{}",
+                tr!("配置覆盖无效: {}", "Invalid config overrides: {}", e)
+            );
+            std::process::exit(2);
+        }
+    };
+    run_monitor(config);
+    finish_monitor();
+}
+
+// 计划任务模式：检查一轮后以退出码报告结果
+fn run_once(overrides: ConfigOverrides, json: bool) -> i32 {
+    // 输出 JSON 时日志只写入文件，标准输出只有结果
+    if let Err(e) = configure_logging(!json) {
+        eprintln!("Failed to init logger: {}", e);
+        return commands::once_failed(
+            json,
+            commands::ONCE_CHECK_FAILED,
+            &format!("Failed to init logger: {}", e),
+        );
+    }
+    install_panic_hook();
+    info!(
+        "{}",
+        tr!(
+            "{} 正在执行单次检查...",
+            "{} running a single check...",
+            SERVICE_NAME
+        )
+    );
+    info!("{}", version::version_banner());
+    // 服务正在运行时由服务负责，避免同一进程被重复重启
+    let _instance_lock = match acquire_instance_lock() {
+        Some(lock) => lock,
+        None => {
+            return commands::once_failed(
+                json,
+                commands::ONCE_CHECK_FAILED,
+                "Another instance is already monitoring, see the log",
+            )
+        }
+    };
+    let config = match load_config(overrides) {
+        Ok(config) => config,
+        Err(e) => {
+            error!(
+                "{}",
+                tr!("配置覆盖无效: {}", "Invalid config overrides: {}", e)
+            );
+            return commands::once_failed(json, 2, &format!("Invalid config overrides: {}", e));
+        }
+    };
+    if let Err(e) = process_manager::enable_debug_privilege() {
+        warn!(
+            "{}",
+            tr!(
+                "无法启用 SeDebugPrivilege: {}，部分系统进程可能无法打开",
+                "Failed to enable SeDebugPrivilege: {}, some system processes may not be opened",
+                e
+            )
+        );
+    }
+    let code = commands::check_once(&config, json);
+    info!(
+        "{}",
+        tr!(
+            "单次检查完成，退出码 {}",
+            "Single check finished with exit code {}",
+            code
+        )
+    );
+    log::logger().flush();
+    code
 }
 
 fn main() -> Result<(), windows_service::Error> {
-    service_dispatcher::start(SERVICE_NAME, ffi_service_main)
+    register_plugins();
+    let command = match parse_args(std::env::args().skip(1)) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+    match command {
+        Command::Service => {
+            let result = service_dispatcher::start(SERVICE_NAME, ffi_service_main);
+            if let Err(windows_service::Error::Winapi(e)) = &result {
+                if e.raw_os_error() == Some(ERROR_FAILED_SERVICE_CONTROLLER_CONNECT.0 as i32) {
+                    eprintln!("Not started by the Service Control Manager, use --console to run in the foreground.");
+                }
+            }
+            result
+        }
+        Command::Console(overrides) => {
+            run_console(overrides);
+            Ok(())
+        }
+        Command::Once { overrides, json } => std::process::exit(run_once(overrides, json)),
+        Command::Install {
+            start_type,
+            account,
+        } => service_installer::install_service(start_type, account),
+        Command::Uninstall { purge } => service_installer::uninstall_service(purge),
+        Command::Status { json, host } => std::process::exit(commands::status(json, host)),
+        Command::RestartNow { name, host, json } => {
+            std::process::exit(commands::restart_now(name, host, json))
+        }
+        Command::SetThreshold {
+            bytes,
+            name,
+            persist,
+            json,
+        } => std::process::exit(commands::set_threshold(bytes, name, persist, json)),
+        Command::Snooze {
+            duration_secs,
+            host,
+            json,
+        } => std::process::exit(commands::snooze(duration_secs, host, json)),
+        Command::ReloadConfig { host, json } => {
+            std::process::exit(commands::reload_config(host, json))
+        }
+        Command::ValidateConfig { path, json } => {
+            std::process::exit(commands::validate_config_file(path, json))
+        }
+        Command::Version { json } => {
+            commands::version(json);
+            Ok(())
+        }
+        Command::Logs { follow, level } => std::process::exit(commands::logs(follow, level)),
+        Command::SelfUpdate { force } => std::process::exit(commands::self_update(force)),
+        Command::Simulate { threshold, step } => {
+            if let Err(e) = configure_console_logging() {
+                eprintln!("Failed to init logger: {}", e);
+            }
+            std::process::exit(simulate::run(threshold, step))
+        }
+        Command::Balloon { step } => simulate::balloon(step),
+        Command::ExportDiagnostics { path, json } => {
+            std::process::exit(commands::export_diagnostics(path, json))
+        }
+    }
 }
@@ -0,0 +1,323 @@
+//! 预测性重启：按最近的采样估算泄漏速度，预计在维护窗口之外超过阈值时，
+//! 在此之前最后一个维护窗口内提前重启，避免在使用高峰时重启。
+//!
+//! 维护窗口为本地时间的 `HH:MM-HH:MM`，结束时间早于开始时间时跨越午夜（如 `22:00-02:00`）。
+//! 可以按星期单独设置（如周末全天），节假日全天都是维护窗口；设置 `restarts_only_in_maintenance_window`
+//! 时超过阈值也只在维护窗口内重启。
+
+use chrono::{Datelike, Duration as ChronoDuration, NaiveDate, NaiveDateTime, NaiveTime};
+use std::collections::{BTreeSet, VecDeque};
+
+use crate::config_manager::Config;
+
+// 只用最近这段时间的采样估算泄漏速度，更早的采样可能来自不同的使用情况
+const LEAK_RATE_WINDOW_SECS: u64 = 2 * 3600;
+// 采样覆盖的时间不足时不估算，避免把短时波动当作泄漏
+const MIN_LEAK_RATE_SPAN_SECS: u64 = 10 * 60;
+// 一周之后的预测不可靠，不据此安排重启
+const MAX_FORECAST_SECS: u64 = 7 * 24 * 3600;
+
+/// `maintenance_window_weekdays` 的键，从周一开始。
+pub const WEEKDAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+/// 表示全天的时间段。
+pub const ALL_DAY: &str = "all-day";
+
+/// 每天重复的本地时间段，用于维护窗口和告警的免打扰时段。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DailyWindow {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl DailyWindow {
+    pub fn parse(text: &str) -> Result<DailyWindow, String> {
+        if text == ALL_DAY {
+            return Ok(DailyWindow {
+                start: NaiveTime::MIN,
+                end: NaiveTime::MIN,
+            });
+        }
+        let (start, end) = text
+            .split_once('-')
+            .ok_or_else(|| format!("invalid time window {}, expected HH:MM-HH:MM", text))?;
+        let time = |part: &str| {
+            NaiveTime::parse_from_str(part.trim(), "%H:%M")
+                .map_err(|_| format!("invalid time {} in {}", part, text))
+        };
+        let window = DailyWindow {
+            start: time(start)?,
+            end: time(end)?,
+        };
+        if window.start == window.end {
+            return Err(format!("time window {} is empty", text));
+        }
+        Ok(window)
+    }
+
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start < self.end {
+            self.start <= time && time < self.end
+        } else {
+            // 开始和结束相同时为全天
+            time >= self.start || time < self.end || self.start == self.end
+        }
+    }
+
+    fn length(&self) -> ChronoDuration {
+        let length = self.end.signed_duration_since(self.start);
+        if length <= ChronoDuration::zero() {
+            length + ChronoDuration::days(1)
+        } else {
+            length
+        }
+    }
+}
+
+/// 某一天的维护窗口：空字符串表示当天没有维护窗口。
+pub fn parse_day(text: &str) -> Result<Option<DailyWindow>, String> {
+    if text.is_empty() {
+        return Ok(None);
+    }
+    DailyWindow::parse(text).map(Some)
+}
+
+pub fn parse_holiday(text: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .map_err(|_| format!("invalid holiday {}, expected YYYY-MM-DD", text))
+}
+
+/// 按星期和节假日确定每天的维护窗口。
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MaintenanceSchedule {
+    default: Option<DailyWindow>,
+    // 从周一开始，`Some(None)` 表示当天没有维护窗口，`None` 表示使用默认的维护窗口
+    weekdays: [Option<Option<DailyWindow>>; 7],
+    holidays: BTreeSet<NaiveDate>,
+    /// 超过阈值时也只在维护窗口内重启。
+    pub restarts_only_in_window: bool,
+}
+
+impl MaintenanceSchedule {
+    fn window_on(&self, date: NaiveDate) -> Option<DailyWindow> {
+        if self.holidays.contains(&date) {
+            return DailyWindow::parse(ALL_DAY).ok();
+        }
+        self.weekdays[date.weekday().num_days_from_monday() as usize].unwrap_or(self.default)
+    }
+
+    // 在 `date` 这一天开始的那次维护窗口
+    fn occurrence(&self, date: NaiveDate) -> Option<(NaiveDateTime, NaiveDateTime)> {
+        let window = self.window_on(date)?;
+        let start = date.and_time(window.start);
+        Some((start, start + window.length()))
+    }
+
+    pub fn contains(&self, time: NaiveDateTime) -> bool {
+        [time.date().pred_opt(), Some(time.date())]
+            .into_iter()
+            .flatten()
+            .filter_map(|date| self.occurrence(date))
+            .any(|(start, end)| start <= time && time < end)
+    }
+
+    /// 预计在 `breach` 超过阈值时应在哪次维护窗口内重启，返回该次窗口的开始时间（可能早于 `now`，
+    /// 即窗口已经开始）。超过阈值的时间本身在窗口内，或 `breach` 之前已没有尚未结束的窗口时返回 `None`，
+    /// 按超过阈值处理。
+    pub fn restart_before(
+        &self,
+        now: NaiveDateTime,
+        breach: NaiveDateTime,
+    ) -> Option<NaiveDateTime> {
+        if self.contains(breach) {
+            return None;
+        }
+        let earliest = now.date().pred_opt()?;
+        let mut date = breach.date();
+        while date >= earliest {
+            if let Some((start, end)) = self.occurrence(date).filter(|(start, _)| *start <= breach)
+            {
+                return (end > now).then_some(start);
+            }
+            date = date.pred_opt()?;
+        }
+        None
+    }
+}
+
+/// 按最近的采样估算一个进程的内存增长速度，进程重启（PID 变化）后重新开始。
+#[derive(Debug, Default)]
+pub struct LeakRate {
+    pid: Option<u32>,
+    samples: VecDeque<(u64, u64)>,
+}
+
+impl LeakRate {
+    pub fn record(&mut self, pid: u32, time: u64, private_bytes: u64) {
+        if self.pid != Some(pid) {
+            self.pid = Some(pid);
+            self.samples.clear();
+        }
+        self.samples.push_back((time, private_bytes));
+        while self
+            .samples
+            .front()
+            .is_some_and(|(first, _)| time.saturating_sub(*first) > LEAK_RATE_WINDOW_SECS)
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    /// 每秒增长的字节数，内存没有增长或采样不足时为 `None`。
+    pub fn bytes_per_second(&self) -> Option<f64> {
+        let (first_time, first_bytes) = *self.samples.front()?;
+        let (last_time, last_bytes) = *self.samples.back()?;
+        let span = last_time.saturating_sub(first_time);
+        if span < MIN_LEAK_RATE_SPAN_SECS || last_bytes <= first_bytes {
+            return None;
+        }
+        Some((last_bytes - first_bytes) as f64 / span as f64)
+    }
+
+    /// 按当前速度预计多少秒后超过阈值，超过一周时为 `None`。
+    pub fn seconds_until(&self, private_bytes: u64, threshold_bytes: u64) -> Option<u64> {
+        let rate = self.bytes_per_second()?;
+        let seconds = (threshold_bytes.saturating_sub(private_bytes) as f64 / rate) as u64;
+        (seconds <= MAX_FORECAST_SECS).then_some(seconds)
+    }
+}
+
+/// 配置的维护窗口；没有配置任何维护窗口时返回 `None`，不提前重启。
+/// 格式无效的项（`validate` 命令会报告）视为未配置。
+pub fn schedule(config: &Config) -> Option<MaintenanceSchedule> {
+    let mut schedule = MaintenanceSchedule {
+        default: parse_day(&config.maintenance_window).ok().flatten(),
+        restarts_only_in_window: config.restarts_only_in_maintenance_window,
+        ..Default::default()
+    };
+    for (day, window) in &config.maintenance_window_weekdays {
+        if let (Some(index), Ok(window)) = (
+            WEEKDAYS.iter().position(|weekday| weekday == day),
+            parse_day(window),
+        ) {
+            schedule.weekdays[index] = Some(window);
+        }
+    }
+    schedule.holidays = config
+        .holidays
+        .iter()
+        .filter_map(|holiday| parse_holiday(holiday).ok())
+        .collect();
+    let configured = schedule.default.is_some()
+        || schedule
+            .weekdays
+            .iter()
+            .any(|day| matches!(day, Some(Some(_))))
+        || !schedule.holidays.is_empty();
+    configured.then_some(schedule)
+}
+
+/// 把 Unix 时间戳转换为本地时间。
+pub fn local_time(secs: u64) -> NaiveDateTime {
+    chrono::DateTime::from_timestamp(secs as i64, 0)
+        .unwrap_or_default()
+        .with_timezone(&chrono::Local)
+        .naive_local()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 5, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse_maintenance_window() {
+        let window = DailyWindow::parse("22:00-02:00").unwrap();
+        assert!(window.contains(at(1, 23, 30).time()));
+        assert!(window.contains(at(1, 1, 59).time()));
+        assert!(!window.contains(at(1, 2, 0).time()));
+        assert!(DailyWindow::parse("02:00").is_err());
+        assert!(DailyWindow::parse("25:00-03:00").is_err());
+        assert!(DailyWindow::parse("02:00-02:00").is_err());
+    }
+
+    fn schedule(window: &str) -> MaintenanceSchedule {
+        MaintenanceSchedule {
+            default: parse_day(window).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_restart_before() {
+        let schedule = schedule("02:00-04:00");
+        // 预计 14:00 超过阈值，在当天 02:00 开始的窗口内重启
+        assert_eq!(
+            schedule.restart_before(at(1, 3, 0), at(1, 14, 0)),
+            Some(at(1, 2, 0))
+        );
+        assert_eq!(
+            schedule.restart_before(at(1, 0, 30), at(1, 14, 0)),
+            Some(at(1, 2, 0))
+        );
+        // 下一个窗口之后才超过阈值，等到下一个窗口
+        assert_eq!(
+            schedule.restart_before(at(1, 3, 0), at(2, 9, 0)),
+            Some(at(2, 2, 0))
+        );
+        // 在窗口内超过阈值，或之前已没有窗口
+        assert_eq!(schedule.restart_before(at(1, 1, 0), at(1, 3, 0)), None);
+        assert_eq!(schedule.restart_before(at(1, 5, 0), at(1, 14, 0)), None);
+    }
+
+    #[test]
+    fn test_weekday_and_holiday_schedule() {
+        // 2024-05-04 是周六
+        let mut schedule = schedule("02:00-04:00");
+        schedule.weekdays[5] = Some(parse_day(ALL_DAY).unwrap());
+        schedule.weekdays[6] = Some(parse_day(ALL_DAY).unwrap());
+        schedule.weekdays[2] = Some(parse_day("").unwrap());
+        schedule
+            .holidays
+            .insert(parse_holiday("2024-05-01").unwrap());
+        assert!(schedule.contains(at(4, 15, 0)));
+        assert!(schedule.contains(at(5, 23, 59)));
+        assert!(!schedule.contains(at(6, 15, 0)));
+        assert!(schedule.contains(at(6, 3, 0)));
+        // 周三是节假日，平时周三没有维护窗口
+        assert!(schedule.contains(at(1, 15, 0)));
+        assert!(!schedule.contains(at(8, 3, 0)));
+        // 周一凌晨 1 点超过阈值，周日全天都可以提前重启；上午超过阈值则等到周一的维护窗口
+        assert_eq!(
+            schedule.restart_before(at(5, 15, 0), at(6, 1, 0)),
+            Some(at(5, 0, 0))
+        );
+        assert_eq!(
+            schedule.restart_before(at(5, 15, 0), at(6, 10, 0)),
+            Some(at(6, 2, 0))
+        );
+        assert!(parse_holiday("2024-13-01").is_err());
+    }
+
+    #[test]
+    fn test_leak_rate() {
+        let mut rate = LeakRate::default();
+        rate.record(10, 0, 1000 * 1024 * 1024);
+        assert_eq!(rate.bytes_per_second(), None);
+        rate.record(10, 1800, 1180 * 1024 * 1024);
+        // 每 30 分钟增长 180 MB，再增长 120 MB 需要 20 分钟
+        assert_eq!(
+            rate.seconds_until(1180 * 1024 * 1024, 1300 * 1024 * 1024),
+            Some(1200)
+        );
+        // 重启后重新估算
+        rate.record(11, 1900, 200 * 1024 * 1024);
+        assert_eq!(rate.bytes_per_second(), None);
+    }
+}
@@ -0,0 +1,304 @@
+//! 读取单个进程内存的几种方式。不同版本和加固程度的 Windows 上各有无法使用的情况（如受保护的
+//! dwm 无法打开、性能计数器被禁用），[`SamplerChain`] 把它们按配置排成一列，当前方式失败时
+//! 依次改用下一种。
+
+use log::{info, warn};
+use std::collections::{HashMap, HashSet};
+use std::io;
+
+use crate::config_manager::SamplingBackend;
+use crate::process_manager::{
+    counter_memory, pdh_memory, snapshot_memory, ProcessHandle, ProcessInfo,
+};
+use crate::tr;
+
+pub trait MemorySampler: Send {
+    /// 日志中显示的名称。
+    fn name(&self) -> &'static str;
+
+    /// 读取进程当前的内存。进程已退出时返回 `io::ErrorKind::NotFound`，其他错误表示这种方式
+    /// 对该进程不可用。
+    fn sample(&mut self, process: &ProcessInfo) -> io::Result<ProcessInfo>;
+
+    /// 取出为进程保持的句柄，重启时用它等待进程退出。
+    fn take_handle(&mut self, pid: u32) -> Option<ProcessHandle> {
+        let _ = pid;
+        None
+    }
+}
+
+/// PSAPI：按 PID 保持进程句柄，之后直接通过句柄调用 GetProcessMemoryInfo。
+#[derive(Default)]
+pub struct PsapiSampler {
+    handles: HashMap<u32, ProcessHandle>,
+}
+
+impl MemorySampler for PsapiSampler {
+    fn name(&self) -> &'static str {
+        "PSAPI"
+    }
+
+    fn sample(&mut self, process: &ProcessInfo) -> io::Result<ProcessInfo> {
+        let handle = match self.handles.entry(process.pid) {
+            std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                entry.insert(ProcessHandle::open(process)?)
+            }
+        };
+        let result = handle.sample();
+        if result.is_err() {
+            self.handles.remove(&process.pid);
+        }
+        result
+    }
+
+    fn take_handle(&mut self, pid: u32) -> Option<ProcessHandle> {
+        self.handles.remove(&pid)
+    }
+}
+
+/// Toolhelp：每次重新创建进程快照确认进程仍在运行，再临时打开进程读取内存。
+pub struct ToolhelpSampler;
+
+impl MemorySampler for ToolhelpSampler {
+    fn name(&self) -> &'static str {
+        "Toolhelp"
+    }
+
+    fn sample(&mut self, process: &ProcessInfo) -> io::Result<ProcessInfo> {
+        snapshot_memory(process)
+    }
+}
+
+/// PDH：读取 Process 性能计数器，不需要打开进程。
+pub struct PdhSampler;
+
+impl MemorySampler for PdhSampler {
+    fn name(&self) -> &'static str {
+        "PDH"
+    }
+
+    fn sample(&mut self, process: &ProcessInfo) -> io::Result<ProcessInfo> {
+        pdh_memory(process)
+    }
+}
+
+/// WMI：查询 Win32_PerfFormattedData_PerfProc_Process，不需要打开进程。
+pub struct WmiSampler;
+
+impl MemorySampler for WmiSampler {
+    fn name(&self) -> &'static str {
+        "WMI"
+    }
+
+    fn sample(&mut self, process: &ProcessInfo) -> io::Result<ProcessInfo> {
+        counter_memory(process).ok_or_else(|| {
+            io::Error::other(format!(
+                "no performance counter data for PID {}",
+                process.pid
+            ))
+        })
+    }
+}
+
+/// 按顺序尝试的采样方式，记住每个进程最后成功的方式，之后从它开始尝试。
+pub struct SamplerChain {
+    samplers: Vec<Box<dyn MemorySampler>>,
+    active: HashMap<u32, usize>,
+    // 已记录过失败的 (PID, 方式)，每个进程的每种方式只警告一次
+    warned: HashSet<(u32, usize)>,
+}
+
+impl SamplerChain {
+    pub fn new(samplers: Vec<Box<dyn MemorySampler>>) -> SamplerChain {
+        SamplerChain {
+            samplers,
+            active: HashMap::new(),
+            warned: HashSet::new(),
+        }
+    }
+
+    /// 配置的方式排在最前，其余按 PSAPI、Toolhelp、PDH、WMI 的顺序作为后备。
+    pub fn from_backend(backend: SamplingBackend) -> SamplerChain {
+        let mut samplers: Vec<Box<dyn MemorySampler>> = vec![
+            Box::new(PsapiSampler::default()),
+            Box::new(ToolhelpSampler),
+            Box::new(PdhSampler),
+            Box::new(WmiSampler),
+        ];
+        let first = match backend {
+            SamplingBackend::Auto | SamplingBackend::Handle => 0,
+            SamplingBackend::Snapshot => 1,
+            SamplingBackend::Pdh => 2,
+            SamplingBackend::Wmi => 3,
+        };
+        samplers[..=first].rotate_right(1);
+        SamplerChain::new(samplers)
+    }
+
+    /// 从进程上次成功的方式开始依次尝试，全部失败或进程已退出时返回 `None`，调用方应重新枚举。
+    pub fn sample(&mut self, process: &ProcessInfo) -> Option<ProcessInfo> {
+        let start = self.active.get(&process.pid).copied().unwrap_or(0);
+        for index in start..self.samplers.len() {
+            let sampler = &mut self.samplers[index];
+            match sampler.sample(process) {
+                Ok(info) => {
+                    if self.active.insert(process.pid, index) != Some(index) {
+                        info!(
+                            "{}",
+                            tr!(
+                                "使用 {} 采样 {} (PID {}) 的内存",
+                                "Sampling memory of {1} (PID {2}) with {0}",
+                                sampler.name(),
+                                process.name,
+                                process.pid
+                            )
+                        );
+                    }
+                    return Some(info);
+                }
+                // 进程已退出，其他方式同样读不到
+                Err(e) if e.kind() == io::ErrorKind::NotFound => break,
+                Err(e) => {
+                    if self.warned.insert((process.pid, index)) {
+                        warn!(
+                            "{}",
+                            tr!(
+                                "用 {} 采样 {} (PID {}) 失败: {}，改用下一种方式",
+                                "Failed to sample {1} (PID {2}) with {0}: {3}, trying the next method",
+                                sampler.name(),
+                                process.name,
+                                process.pid,
+                                e
+                            )
+                        );
+                    }
+                }
+            }
+        }
+        self.active.remove(&process.pid);
+        self.warned.retain(|(pid, _)| *pid != process.pid);
+        None
+    }
+
+    /// 取出为这些进程保持的句柄。
+    pub fn take_handles(&mut self, pids: &[u32]) -> Vec<ProcessHandle> {
+        pids.iter()
+            .filter_map(|pid| {
+                self.samplers
+                    .iter_mut()
+                    .find_map(|sampler| sampler.take_handle(*pid))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    // 按预设结果返回的采样方式，记录被调用的次数
+    struct FakeSampler {
+        result: fn(&ProcessInfo) -> io::Result<ProcessInfo>,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl MemorySampler for FakeSampler {
+        fn name(&self) -> &'static str {
+            "fake"
+        }
+
+        fn sample(&mut self, process: &ProcessInfo) -> io::Result<ProcessInfo> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            (self.result)(process)
+        }
+    }
+
+    fn process() -> ProcessInfo {
+        ProcessInfo {
+            name: "chain_test.exe".to_string(),
+            pid: 7,
+            thread_count: 1,
+            private_bytes: 0,
+            pagefile_usage: 0,
+            working_set: 0,
+        }
+    }
+
+    #[test]
+    fn test_chain_falls_back_on_any_failure() {
+        let calls: Vec<Arc<AtomicUsize>> = (0..3).map(|_| Default::default()).collect();
+        let results: [fn(&ProcessInfo) -> io::Result<ProcessInfo>; 3] = [
+            |_| Err(io::ErrorKind::PermissionDenied.into()),
+            |_| Err(io::Error::other("counters disabled")),
+            |process| {
+                Ok(ProcessInfo {
+                    private_bytes: 42,
+                    ..process.clone()
+                })
+            },
+        ];
+        let mut chain = SamplerChain::new(
+            results
+                .into_iter()
+                .zip(&calls)
+                .map(|(result, calls)| {
+                    Box::new(FakeSampler {
+                        result,
+                        calls: Arc::clone(calls),
+                    }) as Box<dyn MemorySampler>
+                })
+                .collect(),
+        );
+        assert_eq!(chain.sample(&process()).unwrap().private_bytes, 42);
+        // 之后直接从成功的方式开始
+        assert_eq!(chain.sample(&process()).unwrap().private_bytes, 42);
+        let counts: Vec<usize> = calls
+            .iter()
+            .map(|calls| calls.load(Ordering::SeqCst))
+            .collect();
+        assert_eq!(counts, vec![1, 1, 2]);
+    }
+
+    #[test]
+    fn test_chain_stops_when_process_exited() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut chain = SamplerChain::new(vec![
+            Box::new(FakeSampler {
+                result: |_| Err(io::ErrorKind::NotFound.into()),
+                calls: calls.clone(),
+            }),
+            Box::new(FakeSampler {
+                result: |process| Ok(process.clone()),
+                calls: calls.clone(),
+            }),
+        ]);
+        assert!(chain.sample(&process()).is_none());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_configured_backend_comes_first() {
+        let names = |backend| -> Vec<&'static str> {
+            SamplerChain::from_backend(backend)
+                .samplers
+                .iter()
+                .map(|sampler| sampler.name())
+                .collect()
+        };
+        assert_eq!(
+            names(SamplingBackend::Auto),
+            vec!["PSAPI", "Toolhelp", "PDH", "WMI"]
+        );
+        assert_eq!(
+            names(SamplingBackend::Pdh),
+            vec!["PDH", "PSAPI", "Toolhelp", "WMI"]
+        );
+        assert_eq!(
+            names(SamplingBackend::Wmi),
+            vec!["WMI", "PSAPI", "Toolhelp", "PDH"]
+        );
+    }
+}
@@ -0,0 +1,2466 @@
+//! 监控核心：采样、阈值判断和超限后的动作。
+//!
+//! 每个被监控的进程按 [`TargetState`] 描述的生命周期推进，状态变化写入日志并通过 `status` 查询。
+//!
+//! ```no_run
+//! use dwm_monitor::monitor::{ProcessMonitor, RestartAction};
+//! use dwm_monitor::process_manager::ProcessType;
+//!
+//! let mut monitor = ProcessMonitor::builder()
+//!     .process("dwm.exe", 1000 * 1024 * 1024)
+//!     .action(RestartAction::new(ProcessType::System))
+//!     .build();
+//! for sample in monitor.check() {
+//!     println!("{} {:?} {}", sample.name, sample.pid, sample.private_bytes);
+//! }
+//! ```
+
+use chrono::NaiveDateTime;
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::Stream;
+
+use crate::config_manager::{
+    default_near_threshold_percent, default_recovery_margin_percent,
+    default_urgent_restart_percent, Config, MonitoredProcess, SessionProfile,
+};
+use crate::db_manager::DB_CONNECTION;
+use crate::etw_trace::{self, TraceSettings};
+use crate::maintenance::{self, LeakRate, MaintenanceSchedule};
+use crate::monitor_state;
+use crate::plugins;
+use crate::process_manager::{
+    is_process_running, process_instances, restart_with, ProcessInfo, ProcessType,
+};
+use crate::process_name;
+use crate::rules::{self, ScriptRule};
+use crate::system_api::{SystemApi, WindowsApi};
+use crate::templates::{self, Message};
+use crate::tr;
+
+/// 一次采样中某个被监控进程的结果，进程不存在时 `pid` 为 `None`。
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub name: String,
+    pub pid: Option<u32>,
+    pub private_bytes: u64,
+    pub working_set: u64,
+    pub threshold_bytes: u64,
+    /// 同名进程的所有实例 (PID, Private Bytes)；有多个实例时上面的字段为内存最多的一个。
+    pub instances: Vec<(u32, u64)>,
+    /// 按会话类型设置了不同阈值时各实例的阈值，上面的字段此时为相对自身阈值内存最高的实例；
+    /// 为空时所有实例都使用 `threshold_bytes`。
+    pub instance_thresholds: HashMap<u32, u64>,
+}
+
+impl Sample {
+    fn new(name: &str, process: Option<&ProcessInfo>, threshold_bytes: u64) -> Sample {
+        Sample {
+            name: name.to_string(),
+            pid: process.map(|p| p.pid),
+            private_bytes: process.map_or(0, |p| p.private_bytes as u64),
+            working_set: process.map_or(0, |p| p.working_set as u64),
+            threshold_bytes,
+            instances: process
+                .map(|p| (p.pid, p.private_bytes as u64))
+                .into_iter()
+                .collect(),
+            instance_thresholds: HashMap::new(),
+        }
+    }
+
+    // 按内存最多的实例判断是否超过阈值，同时记录所有实例
+    fn of_instances(name: &str, processes: &[ProcessInfo], threshold_bytes: u64) -> Sample {
+        let running = is_process_running(name, processes);
+        Sample {
+            instances: process_instances(name, processes)
+                .iter()
+                .map(|p| (p.pid, p.private_bytes as u64))
+                .collect(),
+            ..Sample::new(name, running.as_ref(), threshold_bytes)
+        }
+    }
+
+    /// 需要重启的实例：超过阈值的所有实例；没有实例超过阈值（如规则要求重启）时为内存最多的实例。
+    pub fn offending_pids(&self) -> Vec<u32> {
+        let over: Vec<u32> = self
+            .instances
+            .iter()
+            .filter(|(pid, private_bytes)| *private_bytes > self.threshold_of(*pid))
+            .map(|(pid, _)| *pid)
+            .collect();
+        if over.is_empty() {
+            self.pid.into_iter().collect()
+        } else {
+            over
+        }
+    }
+
+    /// 某个实例适用的阈值。
+    pub fn threshold_of(&self, pid: u32) -> u64 {
+        self.instance_thresholds
+            .get(&pid)
+            .copied()
+            .unwrap_or(self.threshold_bytes)
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.pid.is_some()
+    }
+
+    pub fn exceeds_threshold(&self) -> bool {
+        self.is_running() && self.private_bytes > self.threshold_bytes
+    }
+
+    /// 内存是否已达到阈值的 `percent`%。
+    pub fn is_near_threshold(&self, percent: u64) -> bool {
+        self.is_running()
+            && self.private_bytes as u128 * 100 >= self.threshold_bytes as u128 * percent as u128
+    }
+}
+
+/// 最近若干次采样的内存，用于判断进程是否仍在增长；进程重启（PID 变化）后重新开始。
+#[derive(Debug, Default)]
+struct Growth {
+    // 需要的采样次数，为 0 时不检查
+    required: usize,
+    pid: Option<u32>,
+    samples: VecDeque<u64>,
+    // 已记录因没有增长而不重启，恢复增长之前不重复记录
+    held_logged: bool,
+}
+
+impl Growth {
+    fn new(required: usize) -> Growth {
+        Growth {
+            required,
+            ..Default::default()
+        }
+    }
+
+    fn record(&mut self, pid: u32, private_bytes: u64) {
+        if self.required == 0 {
+            return;
+        }
+        if self.pid != Some(pid) {
+            self.pid = Some(pid);
+            self.samples.clear();
+            self.held_logged = false;
+        }
+        self.samples.push_back(private_bytes);
+        if self.samples.len() > self.required {
+            self.samples.pop_front();
+        }
+    }
+
+    /// 最近的采样按最小二乘拟合的斜率是否为正；采样不足时视为没有增长。
+    fn is_growing(&self) -> bool {
+        if self.required == 0 {
+            return true;
+        }
+        if self.samples.len() < self.required {
+            return false;
+        }
+        let n = self.samples.len() as f64;
+        let mean_x = (n - 1.0) / 2.0;
+        let mean_y = self.samples.iter().map(|bytes| *bytes as f64).sum::<f64>() / n;
+        let covariance: f64 = self
+            .samples
+            .iter()
+            .enumerate()
+            .map(|(x, y)| (x as f64 - mean_x) * (*y as f64 - mean_y))
+            .sum();
+        covariance > 0.0
+    }
+}
+
+/// 重启失败或重启后内存仍超过阈值时，在这段时间内不再重启。
+pub const RESTART_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+/// 用户在重启确认中选择推迟时，在这段时间内不重启该进程。
+pub const RESTART_POSTPONE: Duration = Duration::from_secs(3600);
+
+// 用户选择了今天不自动重启时，改为提示的消息在会话中显示的时间
+const OPT_OUT_NOTICE: Duration = Duration::from_secs(60);
+
+/// 被监控进程的生命周期：
+/// `Discovering → Healthy → Warning → Breached → Restarting → Verifying → Healthy`，
+/// 重启失败或没有效果时进入 `Backoff`。
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TargetState {
+    /// 尚未找到进程（开机时尚未启动或已退出）。
+    #[default]
+    Discovering,
+    Healthy,
+    /// 内存已接近阈值。
+    Warning,
+    /// 超过阈值，等待执行动作（暂缓重启期间停留在此状态）。
+    Breached,
+    /// 正在执行动作。
+    Restarting,
+    /// 动作已完成，等待下一轮采样确认内存已回落。
+    Verifying,
+    /// 动作失败或没有效果，[`RESTART_BACKOFF`] 内不再执行动作。
+    Backoff,
+}
+
+impl TargetState {
+    /// 按本轮采样决定下一个状态，`breached` 为本轮是否按超限处理；`Restarting` 由执行动作时进入，
+    /// 不在这里产生。
+    fn next(
+        self,
+        sample: &Sample,
+        breached: bool,
+        near_threshold_percent: u64,
+        recovery_margin_percent: u64,
+        backoff_expired: bool,
+    ) -> Self {
+        if !sample.is_running() {
+            return TargetState::Discovering;
+        }
+        if !breached {
+            // 回落到各自的恢复线以下才离开，内存在阈值附近波动时不会反复切换
+            if self == TargetState::Backoff
+                && sample.is_near_threshold(100u64.saturating_sub(recovery_margin_percent))
+            {
+                return TargetState::Backoff;
+            }
+            let near = sample.is_near_threshold(near_threshold_percent)
+                || self == TargetState::Warning
+                    && sample.is_near_threshold(
+                        near_threshold_percent.saturating_sub(recovery_margin_percent),
+                    );
+            return if near {
+                TargetState::Warning
+            } else {
+                TargetState::Healthy
+            };
+        }
+        match self {
+            // 重启后仍超过阈值，再次重启多半也没有用
+            TargetState::Verifying => TargetState::Backoff,
+            TargetState::Backoff if !backoff_expired => TargetState::Backoff,
+            _ => TargetState::Breached,
+        }
+    }
+}
+
+/// 提供进程列表，默认实现枚举系统中的所有进程；测试或其他前端可以替换。
+pub trait Sampler: Send {
+    fn processes(&mut self) -> Option<Vec<ProcessInfo>>;
+
+    /// 只需要指定名称的进程时调用，结果中可以不包含其他进程；默认与 `processes` 相同。
+    fn find(&mut self, names: &[&str]) -> Option<Vec<ProcessInfo>> {
+        let _ = names;
+        self.processes()
+    }
+}
+
+/// 通过 [`SystemApi`] 采样：记住已找到的进程（同名的所有实例），之后只重新读取它们的内存，
+/// 有实例退出或读取失败时才重新枚举；另外定期重新枚举，以发现新登录的会话中的实例。
+pub struct SystemSampler {
+    api: Arc<dyn SystemApi>,
+    found: HashMap<String, Vec<ProcessInfo>>,
+    enumerated: Option<Instant>,
+}
+
+// 即使已找到的实例都还在，也每隔这段时间重新枚举一次
+const REDISCOVER_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+impl SystemSampler {
+    pub fn new(api: Arc<dyn SystemApi>) -> SystemSampler {
+        SystemSampler {
+            api,
+            found: HashMap::new(),
+            enumerated: None,
+        }
+    }
+}
+
+impl Default for SystemSampler {
+    fn default() -> Self {
+        SystemSampler::new(Arc::new(WindowsApi::default()))
+    }
+}
+
+impl Sampler for SystemSampler {
+    fn processes(&mut self) -> Option<Vec<ProcessInfo>> {
+        self.api.processes()
+    }
+
+    fn find(&mut self, names: &[&str]) -> Option<Vec<ProcessInfo>> {
+        let mut found = Vec::new();
+        let mut missing = Vec::new();
+        let rediscover = self
+            .enumerated
+            .is_some_and(|enumerated| enumerated.elapsed() >= REDISCOVER_INTERVAL);
+        for name in names {
+            let key = process_name::key(name);
+            let sampled = self
+                .found
+                .get(&key)
+                .filter(|_| !rediscover)
+                .and_then(|infos| {
+                    infos
+                        .iter()
+                        .map(|info| self.api.memory(info))
+                        .collect::<Option<Vec<_>>>()
+                });
+            match sampled {
+                Some(infos) => found.extend(infos),
+                None => {
+                    self.found.remove(&key);
+                    missing.push(key);
+                }
+            }
+        }
+        if missing.is_empty() {
+            debug!(
+                "{}",
+                tr!(
+                    "所有被监控进程均已采样，无需枚举进程",
+                    "All monitored processes sampled without enumeration"
+                )
+            );
+            return Some(found);
+        }
+
+        self.enumerated = Some(Instant::now());
+        for info in self.processes()? {
+            let key = process_name::key(&info.name);
+            if missing.contains(&key) {
+                // 新找到的进程也按所选方式读取内存，枚举时可能读不到（如受保护的进程）
+                let info = self.api.memory(&info).unwrap_or(info);
+                self.found.entry(key).or_default().push(info.clone());
+                found.push(info);
+            }
+        }
+        Some(found)
+    }
+}
+
+/// 进程内存超过阈值时执行的动作，返回是否成功。
+pub trait Action: Send + Sync {
+    fn run(&self, sample: &Sample) -> bool;
+}
+
+/// [`Rule::decide`] 的结果。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Decision {
+    /// 按阈值判断。
+    #[default]
+    Default,
+    /// 按超限处理，执行动作。
+    Restart,
+    /// 本轮不按超限处理。
+    Ignore,
+}
+
+/// 替换按阈值判断是否超限的策略，只对正在运行的进程调用，见 [`crate::rules::ScriptRule`]。
+pub trait Rule: Send {
+    fn decide(&mut self, sample: &Sample) -> Decision;
+}
+
+/// 结束进程并按 `ProcessType` 重新拉起（即服务默认的重启流程）。
+pub struct RestartAction {
+    process_type: ProcessType,
+    api: Arc<dyn SystemApi>,
+}
+
+impl RestartAction {
+    pub fn new(process_type: ProcessType) -> RestartAction {
+        RestartAction::with_api(process_type, Arc::new(WindowsApi::default()))
+    }
+
+    pub fn with_api(process_type: ProcessType, api: Arc<dyn SystemApi>) -> RestartAction {
+        RestartAction { process_type, api }
+    }
+}
+
+impl Action for RestartAction {
+    // 只结束超过阈值的实例，同名的其他实例（如其他会话的 dwm）不受影响
+    fn run(&self, sample: &Sample) -> bool {
+        restart_with(
+            self.api.as_ref(),
+            &sample.name,
+            &sample.offending_pids(),
+            &self.process_type,
+        )
+    }
+}
+
+// 消息模板中可用的采样数值
+fn template_values(sample: &Sample) -> Vec<(&'static str, String)> {
+    vec![
+        ("name", sample.name.clone()),
+        (
+            "pid",
+            sample.pid.map(|pid| pid.to_string()).unwrap_or_default(),
+        ),
+        (
+            "private_mb",
+            (sample.private_bytes / 1024 / 1024).to_string(),
+        ),
+        (
+            "working_set_mb",
+            (sample.working_set / 1024 / 1024).to_string(),
+        ),
+        (
+            "threshold_mb",
+            (sample.threshold_bytes / 1024 / 1024).to_string(),
+        ),
+    ]
+}
+
+/// 重启前通过 `WTSSendMessage` 在进程所在的会话中弹出提示，等待一段时间后再执行后续动作。
+/// 不依赖通知中心，精简版系统上同样可用；提示失败不影响重启。
+pub struct RestartWarningAction {
+    warning: Duration,
+    api: Arc<dyn SystemApi>,
+    confirm: bool,
+}
+
+impl RestartWarningAction {
+    pub fn new(warning: Duration, api: Arc<dyn SystemApi>) -> RestartWarningAction {
+        RestartWarningAction {
+            warning,
+            api,
+            confirm: false,
+        }
+    }
+
+    /// 改为询问用户是否立即重启：选择推迟时 [`RESTART_POSTPONE`] 内不重启，`warning` 内未选择时照常重启。
+    pub fn confirming(mut self, confirm: bool) -> RestartWarningAction {
+        self.confirm = confirm;
+        self
+    }
+
+    fn ask(&self, sample: &Sample, pids: Vec<u32>, values: &[(&str, String)]) -> bool {
+        let message = templates::format(Message::RestartConfirmation, values, || {
+            tr!(
+                "{} 占用内存过多，需要重新启动，屏幕可能会短暂闪烁。\n\n选择“是”立即重启，选择“否”推迟 {} 分钟；{} 秒内未选择将自动重启。",
+                "{} is using too much memory and needs to restart, the screen may flicker briefly.\n\nChoose Yes to restart now or No to postpone for {} minutes; it restarts automatically if nothing is chosen within {} seconds.",
+                sample.name,
+                RESTART_POSTPONE.as_secs() / 60,
+                self.warning.as_secs()
+            )
+        });
+        // 各会话同时询问，总等待时间不超过 `warning`
+        let answers: Vec<(u32, std::io::Result<bool>)> = std::thread::scope(|scope| {
+            let asks: Vec<_> = pids
+                .into_iter()
+                .map(|pid| {
+                    let message = &message;
+                    let ask =
+                        scope.spawn(move || self.api.ask_in_session(pid, message, self.warning));
+                    (pid, ask)
+                })
+                .collect();
+            asks.into_iter()
+                .map(|(pid, ask)| {
+                    let answer = ask.join().unwrap_or_else(|e| std::panic::resume_unwind(e));
+                    (pid, answer)
+                })
+                .collect()
+        });
+        let mut postponed_by = None;
+        for (pid, answer) in answers {
+            match answer {
+                Ok(true) => {}
+                Ok(false) => {
+                    postponed_by.get_or_insert(pid);
+                }
+                Err(e) => warn!(
+                    "{}",
+                    tr!(
+                        "无法询问 {} (PID {}) 所在会话: {}",
+                        "Failed to ask the session of {} (PID {}): {}",
+                        sample.name,
+                        pid,
+                        e
+                    )
+                ),
+            }
+        }
+        // 任一用户选择推迟即推迟；推迟记录在状态中，run_actions 据此停止后续动作
+        if let Some(pid) = postponed_by {
+            let until = monitor_state::postpone(&sample.name, RESTART_POSTPONE.as_secs());
+            info!(
+                "{}",
+                tr!(
+                    "{} (PID {}) 所在会话的用户推迟了重启，{} 之前不重启",
+                    "The user in the session of {} (PID {}) postponed the restart until {}",
+                    sample.name,
+                    pid,
+                    maintenance::local_time(until).format("%Y-%m-%d %H:%M")
+                )
+            );
+        }
+        true
+    }
+}
+
+impl Action for RestartWarningAction {
+    fn run(&self, sample: &Sample) -> bool {
+        let pids = sample.offending_pids();
+        if pids.is_empty() {
+            return true;
+        }
+        let mut values = template_values(sample);
+        values.push(("seconds", self.warning.as_secs().to_string()));
+        if self.confirm {
+            return self.ask(sample, pids, &values);
+        }
+        let message = templates::format(Message::RestartWarning, &values, || {
+            tr!(
+                "{} 占用内存过多，将在 {} 秒后重新启动，屏幕可能会短暂闪烁。请保存正在进行的工作。",
+                "{} is using too much memory and will restart in {} seconds, the screen may flicker briefly. Please save your work.",
+                sample.name,
+                self.warning.as_secs()
+            )
+        });
+        // 只提示要重启的实例所在的会话
+        for pid in pids {
+            match self.api.send_session_message(pid, &message, self.warning) {
+                Ok(()) => info!(
+                    "{}",
+                    tr!(
+                        "已提示 {} (PID {}) 所在会话即将重启",
+                        "Warned the session of {} (PID {}) about the upcoming restart",
+                        sample.name,
+                        pid
+                    )
+                ),
+                Err(e) => warn!(
+                    "{}",
+                    tr!(
+                        "无法提示 {} (PID {}) 所在会话: {}",
+                        "Failed to warn the session of {} (PID {}): {}",
+                        sample.name,
+                        pid,
+                        e
+                    )
+                ),
+            }
+        }
+        self.api.sleep(self.warning);
+        true
+    }
+}
+
+struct Target {
+    name: String,
+    threshold_bytes: u64,
+    // 进程不存在时用于启动它
+    start: Option<ProcessType>,
+    actions: Vec<Box<dyn Action>>,
+    // 是否已找到过该进程；之前没找到时（如开机时尚未启动）只提示一次，不每轮告警
+    seen: bool,
+    waiting_logged: bool,
+    state: TargetState,
+    backoff_until: Option<Instant>,
+    leak_rate: LeakRate,
+    growth: Growth,
+    // 已记录的提前重启计划（维护窗口的开始时间），计划不变时不重复记录
+    planned_restart: Option<NaiveDateTime>,
+    // 已提示用户因其选择跳过了重启，用户取消选择或次日之前不重复提示
+    opt_out_notified: bool,
+    // 远程桌面会话中的实例使用的阈值和动作
+    remote_session: Option<SessionProfile>,
+    // 本轮采样中在远程桌面会话中的实例
+    remote_pids: Vec<u32>,
+    // 内存接近阈值时记录 ETW 跟踪，重启前保存
+    trace: Option<TraceSettings>,
+}
+
+impl Target {
+    fn set_state(&mut self, state: TargetState) {
+        if state == self.state {
+            return;
+        }
+        info!(
+            "{}",
+            tr!(
+                "{} 状态: {:?} -> {:?}",
+                "{} state: {:?} -> {:?}",
+                self.name,
+                self.state,
+                state
+            )
+        );
+        if state == TargetState::Backoff {
+            warn!(
+                "{}",
+                tr!(
+                    "{} 重启未成功或没有效果，{} 秒内不再重启",
+                    "{} restart failed or had no effect, not restarting again for {} seconds",
+                    self.name,
+                    RESTART_BACKOFF.as_secs()
+                )
+            );
+            self.backoff_until = Some(Instant::now() + RESTART_BACKOFF);
+        }
+        self.state = state;
+        monitor_state::record_state(&self.name, state);
+    }
+
+    // 超过阈值时检查内存是否仍在增长，没有增长时不重启并记录一次
+    fn still_growing(&mut self, sample: &Sample) -> bool {
+        let growing = self.growth.is_growing();
+        if growing {
+            self.growth.held_logged = false;
+        } else if !self.growth.held_logged {
+            info!(
+                "{}",
+                tr!(
+                    "{} 内存 {} MB 超过阈值 {} MB，但最近 {} 次采样没有增长，暂不重启",
+                    "{} uses {} MB, above the {} MB threshold, but has not grown over the last {} samples, not restarting",
+                    self.name,
+                    sample.private_bytes / 1024 / 1024,
+                    self.threshold_bytes / 1024 / 1024,
+                    self.growth.required
+                )
+            );
+            self.growth.held_logged = true;
+        }
+        growing
+    }
+
+    // 设置了 remote_session 时按会话类型确定各实例的阈值，并以相对自身阈值内存最高的实例代表该进程
+    fn apply_session_profile(
+        &mut self,
+        sample: &mut Sample,
+        processes: &[ProcessInfo],
+        api: &dyn SystemApi,
+    ) {
+        self.remote_pids.clear();
+        let Some(profile) = &self.remote_session else {
+            return;
+        };
+        self.remote_pids = sample
+            .instances
+            .iter()
+            .map(|(pid, _)| *pid)
+            .filter(|pid| api.is_remote_session(*pid))
+            .collect();
+        if self.remote_pids.is_empty() || profile.memory_threshold_bytes == self.threshold_bytes {
+            return;
+        }
+        sample.instance_thresholds = sample
+            .instances
+            .iter()
+            .map(|(pid, _)| {
+                let threshold = if self.remote_pids.contains(pid) {
+                    profile.memory_threshold_bytes
+                } else {
+                    self.threshold_bytes
+                };
+                (*pid, threshold)
+            })
+            .collect();
+        let critical = sample
+            .instances
+            .iter()
+            .max_by_key(|(pid, private_bytes)| {
+                *private_bytes as u128 * 10_000 / sample.threshold_of(*pid).max(1) as u128
+            })
+            .and_then(|(pid, _)| processes.iter().find(|p| p.pid == *pid));
+        if let Some(process) = critical {
+            sample.threshold_bytes = sample.threshold_of(process.pid);
+            sample.pid = Some(process.pid);
+            sample.private_bytes = process.private_bytes as u64;
+            sample.working_set = process.working_set as u64;
+        }
+    }
+
+    // 远程桌面会话中的实例配置为不重启时，从要重启的实例中去掉；剩下的都不重启时返回 None
+    fn restartable(&self, sample: &Sample) -> Option<Sample> {
+        let mut sample = sample.clone();
+        if self.remote_pids.is_empty()
+            || self
+                .remote_session
+                .as_ref()
+                .is_none_or(|profile| profile.restart)
+        {
+            return Some(sample);
+        }
+        if sample
+            .offending_pids()
+            .iter()
+            .all(|pid| self.remote_pids.contains(pid))
+        {
+            return None;
+        }
+        sample
+            .instances
+            .retain(|(pid, _)| !self.remote_pids.contains(pid));
+        Some(sample)
+    }
+
+    fn backoff_expired(&self) -> bool {
+        self.backoff_until
+            .is_none_or(|until| Instant::now() >= until)
+    }
+
+    // 按泄漏速度预计超过阈值的时间不在维护窗口内时，安排在此之前最后一个维护窗口内提前重启，
+    // 返回是否现在就重启
+    fn plan_restart(&mut self, schedule: &MaintenanceSchedule, sample: &Sample, now: u64) -> bool {
+        let planned = self
+            .leak_rate
+            .seconds_until(sample.private_bytes, self.threshold_bytes)
+            .map(|seconds| maintenance::local_time(now + seconds))
+            .and_then(|breach| {
+                schedule
+                    .restart_before(maintenance::local_time(now), breach)
+                    .map(|start| (breach, start))
+            });
+        let Some((breach, start)) = planned else {
+            self.planned_restart = None;
+            return false;
+        };
+        if start <= maintenance::local_time(now) {
+            return true;
+        }
+        if self.planned_restart != Some(start) {
+            info!(
+                "{}",
+                tr!(
+                    "预计 {} 在 {} 超过阈值，不在维护窗口内，计划在 {} 开始的维护窗口内提前重启",
+                    "{} is expected to exceed the threshold at {}, outside the maintenance window, planning to restart it in the maintenance window starting at {}",
+                    self.name,
+                    breach.format("%Y-%m-%d %H:%M"),
+                    start.format("%Y-%m-%d %H:%M")
+                )
+            );
+            self.planned_restart = Some(start);
+        }
+        false
+    }
+}
+
+/// 监控过程中发生的事件，通过 [`ProcessMonitor::on_event`] 订阅。
+#[derive(Debug, Clone, PartialEq)]
+pub enum MonitorEvent {
+    /// 内存超过阈值（每次从其他状态进入 `Breached` 时发出一次）。
+    ThresholdBreached(Sample),
+    RestartStarted(Sample),
+    RestartSucceeded(Sample),
+    /// 动作失败，随后进入 `Backoff`。
+    RestartFailed(Sample),
+}
+
+type Subscriber = Box<dyn Fn(MonitorEvent) + Send>;
+
+fn emit(subscribers: &[Subscriber], event: MonitorEvent) {
+    for subscriber in subscribers {
+        subscriber(event.clone());
+    }
+}
+
+/// 按阈值监控一组进程，每次调用 [`ProcessMonitor::check`] 完成一轮采样。
+pub struct ProcessMonitor {
+    targets: Vec<Target>,
+    sampler: Box<dyn Sampler>,
+    rule: Option<Box<dyn Rule>>,
+    insert_into_db: bool,
+    near_threshold_percent: u64,
+    recovery_margin_percent: u64,
+    subscribers: Vec<Subscriber>,
+    // 执行动作前查询系统是否待重启、是否使用电池、是否正在通话、用户是否选择了不重启
+    api: Arc<dyn SystemApi>,
+    deferrals: Deferrals,
+    maintenance_schedule: Option<MaintenanceSchedule>,
+}
+
+/// 执行动作前检查的推迟条件：条件成立时进程保持 `Breached`，条件解除后的下一轮再执行动作。
+#[derive(Debug, Clone, PartialEq)]
+pub struct Deferrals {
+    /// 系统等待重启以完成更新时不执行动作，由系统重启释放内存。
+    pub reboot_pending: bool,
+    /// 内存达到阈值的该百分比时视为紧急，不再因下面的条件推迟。
+    pub urgent_percent: u64,
+    /// 使用电池供电时推迟。
+    pub on_battery: bool,
+    /// 托盘程序报告要重启的实例所在会话中有全屏游戏或演示、或 `capture_processes` 中的进程运行时推迟。
+    pub games_and_capture: bool,
+    /// 录屏和直播软件的进程名，支持 `*` 通配符。
+    pub capture_processes: Vec<String>,
+    /// 这些进程（视频会议软件）运行时推迟，支持 `*` 通配符，为空时不检查。
+    pub do_not_disturb_processes: Vec<String>,
+    /// 同时要求要重启的实例所在会话正在使用摄像头或麦克风。
+    pub do_not_disturb_requires_media: bool,
+    /// 跳过所在会话的用户选择了今天不自动重启的实例。
+    pub user_opt_out: bool,
+}
+
+impl Default for Deferrals {
+    fn default() -> Deferrals {
+        Deferrals {
+            reboot_pending: false,
+            urgent_percent: default_urgent_restart_percent(),
+            on_battery: false,
+            games_and_capture: false,
+            capture_processes: Vec::new(),
+            do_not_disturb_processes: Vec::new(),
+            do_not_disturb_requires_media: false,
+            user_opt_out: true,
+        }
+    }
+}
+
+impl Deferrals {
+    pub fn from_config(config: &Config) -> Deferrals {
+        Deferrals {
+            reboot_pending: config.suppress_restarts_when_reboot_pending,
+            urgent_percent: config.urgent_restart_percent,
+            on_battery: config.defer_restarts_on_battery,
+            games_and_capture: config.defer_restarts_for_games_and_capture,
+            capture_processes: config.capture_processes.clone(),
+            do_not_disturb_processes: config.do_not_disturb_processes.clone(),
+            do_not_disturb_requires_media: config.do_not_disturb_requires_media,
+            user_opt_out: config.allow_restart_opt_out,
+        }
+    }
+}
+
+pub struct ProcessMonitorBuilder {
+    targets: Vec<Target>,
+    sampler: Box<dyn Sampler>,
+    rule: Option<Box<dyn Rule>>,
+    // monitored() 添加的重启动作与默认采样器共用，重启时可以复用采样时打开的进程句柄
+    api: Arc<dyn SystemApi>,
+    insert_into_db: bool,
+    near_threshold_percent: u64,
+    recovery_margin_percent: u64,
+    // monitored() 添加的进程重启前提前提示用户的时间，为 0 时不提示
+    restart_warning: Duration,
+    restart_confirmation: bool,
+    // monitored() 添加的设置了 etw_trace 的进程使用的跟踪设置
+    trace_settings: TraceSettings,
+    deferrals: Deferrals,
+    maintenance_schedule: Option<MaintenanceSchedule>,
+}
+
+impl ProcessMonitorBuilder {
+    /// 添加一个被监控的进程，之后的 `action`/`auto_start` 作用于该进程。
+    pub fn process(mut self, name: &str, threshold_bytes: u64) -> Self {
+        self.targets.push(Target {
+            name: name.to_string(),
+            threshold_bytes,
+            start: None,
+            actions: Vec::new(),
+            seen: false,
+            waiting_logged: false,
+            state: TargetState::Discovering,
+            backoff_until: None,
+            leak_rate: LeakRate::default(),
+            growth: Growth::default(),
+            planned_restart: None,
+            opt_out_notified: false,
+            remote_session: None,
+            remote_pids: Vec::new(),
+            trace: None,
+        });
+        self
+    }
+
+    pub fn action<A: Action + 'static>(mut self, action: A) -> Self {
+        self.last_target().actions.push(Box::new(action));
+        self
+    }
+
+    pub fn auto_start(mut self, process_type: ProcessType) -> Self {
+        self.last_target().start = Some(process_type);
+        self
+    }
+
+    /// 只在最近 `samples` 次采样的内存仍在增长时按阈值执行动作，为 0 时不检查（默认）。
+    /// 规则脚本明确要求重启时不受此限制。
+    pub fn require_growth(mut self, samples: usize) -> Self {
+        self.last_target().growth = Growth::new(samples);
+        self
+    }
+
+    /// 远程桌面会话中的实例改用 `threshold_bytes`；`restart` 为 `false` 时这些实例超过阈值只记录和告警，
+    /// 不执行动作，同名进程在本地会话中的实例不受影响。
+    pub fn remote_session(mut self, threshold_bytes: u64, restart: bool) -> Self {
+        self.last_target().remote_session = Some(SessionProfile {
+            memory_threshold_bytes: threshold_bytes,
+            restart,
+        });
+        self
+    }
+
+    /// 内存接近阈值时开始记录 ETW 跟踪，重启前保存到 `settings.dir`，见 [`etw_trace`]。
+    pub fn etw_trace(mut self, settings: TraceSettings) -> Self {
+        self.last_target().trace = Some(settings);
+        self
+    }
+
+    /// 按配置中的一项添加进程：超过阈值时（按 `restart_warning` 提示用户后）重启并执行配置的插件动作，
+    /// `auto_start` 时负责启动。
+    pub fn monitored(self, process: &MonitoredProcess) -> Self {
+        let api = self.api.clone();
+        let warning = self.restart_warning;
+        let confirm = self.restart_confirmation;
+        let trace_settings = self.trace_settings.clone();
+        let mut builder = self
+            .process(&process.name, process.memory_threshold_bytes)
+            .require_growth(process.require_growth_samples as usize);
+        if let Some(profile) = &process.remote_session {
+            let threshold_bytes = match profile.memory_threshold_bytes {
+                0 => process.memory_threshold_bytes,
+                threshold_bytes => threshold_bytes,
+            };
+            builder = builder.remote_session(threshold_bytes, profile.restart);
+        }
+        // 受保护的进程只监控，不添加任何动作，也不会在重启前提示用户
+        if process_name::is_protected(&process.name) {
+            error!(
+                "{}",
+                tr!(
+                    "{} 是受保护的系统进程，只监控不重启",
+                    "{} is a protected system process, monitoring it without restarting",
+                    process.name
+                )
+            );
+            return builder;
+        }
+        if process.etw_trace {
+            builder = builder.etw_trace(trace_settings);
+        }
+        if !warning.is_zero() {
+            builder =
+                builder.action(RestartWarningAction::new(warning, api.clone()).confirming(confirm));
+        }
+        builder = builder.action(RestartAction::with_api(process.process_type.clone(), api));
+        for action in &process.actions {
+            match plugins::create_action(action) {
+                Ok(action) => builder.last_target().actions.push(action),
+                Err(e) => warn!(
+                    "{}",
+                    tr!(
+                        "{} 的动作 {} 无法创建，已跳过: {}",
+                        "Skipping action {1} of {0}, it could not be created: {2}",
+                        process.name,
+                        action.name,
+                        e
+                    )
+                ),
+            }
+        }
+        if process.auto_start {
+            builder.auto_start(process.process_type.clone())
+        } else {
+            builder
+        }
+    }
+
+    /// 替换系统接口，同时用于默认采样器和之后 `monitored` 添加的重启动作。
+    pub fn api(mut self, api: Arc<dyn SystemApi>) -> Self {
+        self.sampler = Box::new(SystemSampler::new(api.clone()));
+        self.api = api;
+        self
+    }
+
+    pub fn sampler<S: Sampler + 'static>(mut self, sampler: S) -> Self {
+        self.sampler = Box::new(sampler);
+        self
+    }
+
+    /// 用规则决定是否超限，作用于所有被监控的进程。
+    pub fn rule<R: Rule + 'static>(mut self, rule: R) -> Self {
+        self.rule = Some(Box::new(rule));
+        self
+    }
+
+    /// 是否把每轮采样到的全部进程写入数据库，默认不写入。
+    pub fn insert_into_db(mut self, insert_into_db: bool) -> Self {
+        self.insert_into_db = insert_into_db;
+        self
+    }
+
+    /// 之后 `monitored` 添加的进程在重启前先在所在会话中提示用户，等待 `warning` 后再重启。
+    pub fn restart_warning(mut self, warning: Duration) -> Self {
+        self.restart_warning = warning;
+        self
+    }
+
+    /// 重启前的提示改为询问，用户可以推迟重启，见 [`RestartWarningAction::confirming`]。
+    pub fn restart_confirmation(mut self, confirm: bool) -> Self {
+        self.restart_confirmation = confirm;
+        self
+    }
+
+    /// 之后 `monitored` 添加的设置了 `etw_trace` 的进程使用的跟踪设置。
+    pub fn trace_settings(mut self, settings: TraceSettings) -> Self {
+        self.trace_settings = settings;
+        self
+    }
+
+    /// 系统等待重启以完成更新时不执行动作（进程保持 `Breached`），由系统重启释放内存，默认不等待。
+    pub fn hold_restarts_for_reboot(mut self, hold: bool) -> Self {
+        self.deferrals.reboot_pending = hold;
+        self
+    }
+
+    /// 设置全部推迟条件（包括 `hold_restarts_for_reboot`），默认不推迟。
+    pub fn deferrals(mut self, deferrals: Deferrals) -> Self {
+        self.deferrals = deferrals;
+        self
+    }
+
+    /// 内存达到阈值的该百分比时进入 `Warning` 状态，默认与配置的默认值相同。
+    pub fn near_threshold_percent(mut self, percent: u64) -> Self {
+        self.near_threshold_percent = percent;
+        self
+    }
+
+    /// 离开 `Warning` 和 `Backoff` 的回差，默认与配置的默认值相同。
+    pub fn recovery_margin_percent(mut self, percent: u64) -> Self {
+        self.recovery_margin_percent = percent;
+        self
+    }
+
+    /// 按泄漏速度预计在维护窗口之外超过阈值时，提前在窗口内执行动作，默认不提前；
+    /// 设置了 `restarts_only_in_window` 时超过阈值也等到维护窗口内再执行。
+    pub fn maintenance_schedule(mut self, schedule: Option<MaintenanceSchedule>) -> Self {
+        self.maintenance_schedule = schedule;
+        self
+    }
+
+    pub fn build(self) -> ProcessMonitor {
+        ProcessMonitor {
+            targets: self.targets,
+            sampler: self.sampler,
+            rule: self.rule,
+            insert_into_db: self.insert_into_db,
+            near_threshold_percent: self.near_threshold_percent,
+            recovery_margin_percent: self.recovery_margin_percent,
+            subscribers: Vec::new(),
+            api: self.api,
+            deferrals: self.deferrals,
+            maintenance_schedule: self.maintenance_schedule,
+        }
+    }
+
+    fn last_target(&mut self) -> &mut Target {
+        self.targets
+            .last_mut()
+            .expect("call process() before configuring it")
+    }
+}
+
+impl ProcessMonitor {
+    pub fn builder() -> ProcessMonitorBuilder {
+        let api: Arc<dyn SystemApi> = Arc::new(WindowsApi::default());
+        ProcessMonitorBuilder {
+            targets: Vec::new(),
+            sampler: Box::new(SystemSampler::new(api.clone())),
+            rule: None,
+            api,
+            insert_into_db: false,
+            near_threshold_percent: default_near_threshold_percent(),
+            recovery_margin_percent: default_recovery_margin_percent(),
+            restart_warning: Duration::ZERO,
+            restart_confirmation: false,
+            trace_settings: TraceSettings::default(),
+            deferrals: Deferrals::default(),
+            maintenance_schedule: None,
+        }
+    }
+
+    /// 按配置文件构建，与服务的行为一致。
+    pub fn from_config(config: &Config) -> ProcessMonitor {
+        let mut builder = ProcessMonitor::builder()
+            .api(Arc::new(WindowsApi::new(config.sampling_backend)))
+            .insert_into_db(config.db_config.insert_into_db)
+            .near_threshold_percent(config.near_threshold_percent)
+            .recovery_margin_percent(config.recovery_margin_percent)
+            .restart_warning(Duration::from_secs(config.restart_warning_seconds))
+            .restart_confirmation(config.restart_confirmation)
+            .trace_settings(TraceSettings::from_config(config))
+            .deferrals(Deferrals::from_config(config))
+            .maintenance_schedule(maintenance::schedule(config));
+        for process in config.get_monitor_processes() {
+            builder = builder.monitored(process);
+        }
+        if let Some(path) = rules::script_path(config) {
+            builder = builder.rule(ScriptRule::new(path));
+        }
+        builder.build()
+    }
+
+    /// 订阅监控事件；回调在 [`ProcessMonitor::check`] 中同步调用，应尽快返回。
+    pub fn on_event<F: Fn(MonitorEvent) + Send + 'static>(&mut self, subscriber: F) {
+        self.subscribers.push(Box::new(subscriber));
+    }
+
+    /// 指定进程当前的生命周期状态。
+    pub fn state(&self, name: &str) -> Option<TargetState> {
+        self.targets
+            .iter()
+            .find(|t| process_name::eq(&t.name, name))
+            .map(|t| t.state)
+    }
+
+    /// 按状态文件中的上次重启时间恢复冷却：距今不到 [`RESTART_BACKOFF`] 的进程从 `Backoff` 开始。
+    /// 每次只检查一轮的 `--once` 在 [`monitor_state::load_state`] 之后调用，
+    /// 避免计划任务的每次运行都重启同一个进程。
+    pub fn resume_backoff(&mut self) {
+        let now = monitor_state::now_secs();
+        let status = monitor_state::snapshot();
+        for target in &mut self.targets {
+            let elapsed = status
+                .processes
+                .iter()
+                .find(|process| process_name::eq(&process.name, &target.name))
+                .and_then(|process| process.last_restart)
+                .map(|time| Duration::from_secs(now.saturating_sub(time)));
+            let Some(remaining) = elapsed
+                .and_then(|elapsed| RESTART_BACKOFF.checked_sub(elapsed))
+                .filter(|remaining| !remaining.is_zero())
+            else {
+                continue;
+            };
+            info!(
+                "{}",
+                tr!(
+                    "{} 在 {} 秒前重启过，{} 秒内不再重启",
+                    "{} was restarted {} seconds ago, not restarting again for {} seconds",
+                    target.name,
+                    (RESTART_BACKOFF - remaining).as_secs(),
+                    remaining.as_secs()
+                )
+            );
+            target.state = TargetState::Backoff;
+            target.backoff_until = Some(Instant::now() + remaining);
+        }
+    }
+
+    /// 完成一轮采样：记录状态，超过阈值时执行动作（暂缓期间跳过），返回各进程的采样结果。
+    pub fn check(&mut self) -> Vec<Sample> {
+        // 写入数据库时需要全部进程，否则只采样被监控的进程
+        let process_infos = if self.insert_into_db {
+            self.sampler.processes()
+        } else {
+            let names: Vec<&str> = self.targets.iter().map(|t| t.name.as_str()).collect();
+            self.sampler.find(&names)
+        };
+        let process_infos = match process_infos {
+            Some(infos) => infos,
+            None => {
+                error!(
+                    "{}",
+                    tr!("获取进程信息失败", "Failed to retrieve process information")
+                );
+                return Vec::new();
+            }
+        };
+
+        let visible_windows = monitor_state::visible_windows();
+        if let Some(count) = visible_windows {
+            info!(
+                "{}",
+                tr!(
+                    "交互会话中可见的顶层窗口: {}",
+                    "Visible top-level windows in the interactive session: {}",
+                    count
+                )
+            );
+        }
+        if self.insert_into_db {
+            match DB_CONNECTION.lock() {
+                Ok(mut conn) => {
+                    if let Err(e) =
+                        conn.execute_batch_insert(process_infos.as_slice(), visible_windows)
+                    {
+                        error!(
+                            "{}",
+                            tr!(
+                                "写入进程信息到数据库失败: {:?}",
+                                "Failed to insert process info into DB: {:?}",
+                                e
+                            )
+                        )
+                    }
+                }
+                Err(e) => error!(
+                    "{}",
+                    tr!(
+                        "获取数据库连接失败: {:?}",
+                        "Failed to get DB connection: {:?}",
+                        e
+                    )
+                ),
+            }
+        }
+
+        let mut samples = Vec::new();
+        for target in &mut self.targets {
+            let running = is_process_running(&target.name, process_infos.as_slice());
+            monitor_state::record_sample(&target.name, running.as_ref(), target.threshold_bytes);
+            let mut sample =
+                Sample::of_instances(&target.name, &process_infos, target.threshold_bytes);
+            target.apply_session_profile(&mut sample, &process_infos, &*self.api);
+            match running {
+                Some(process) => {
+                    target.seen = true;
+                    info!(
+                        "{}",
+                        tr!(
+                            "{} 进程 ID: {}, 内存阈值: {} MB",
+                            "{} process ID: {}, memory threshold: {} MB",
+                            &target.name,
+                            process.pid,
+                            target.threshold_bytes / 1024 / 1024
+                        )
+                    );
+                    process.print_process_memory_info();
+                    let metrics = plugins::read_metrics(&sample);
+                    if !metrics.is_empty() {
+                        info!(
+                            "{}",
+                            tr!(
+                                "{} 插件指标: {:?}",
+                                "{} plugin metrics: {:?}",
+                                &target.name,
+                                metrics
+                            )
+                        );
+                        monitor_state::record_metrics(&target.name, metrics);
+                    }
+                }
+                None => {
+                    if target.seen {
+                        warn!(
+                            "{}",
+                            tr!("未找到 {} 进程...", "{} process not found...", &target.name)
+                        );
+                    } else if !target.waiting_logged {
+                        info!(
+                            "{}",
+                            tr!(
+                                "尚未找到 {} 进程，系统可能仍在启动，继续等待...",
+                                "{} process not found yet, the system may still be starting, waiting...",
+                                &target.name
+                            )
+                        );
+                        target.waiting_logged = true;
+                    } else {
+                        debug!(
+                            "{}",
+                            tr!("尚未找到 {} 进程", "{} process not found yet", &target.name)
+                        );
+                    }
+                    if let Some(process_type) = &target.start {
+                        info!(
+                            "{}",
+                            tr!(
+                                "正在启动 {} 进程...",
+                                "Starting {} process...",
+                                &target.name
+                            )
+                        );
+                        match process_type.execute() {
+                            Ok(output) => info!(
+                                "{}",
+                                tr!("成功执行命令: {:?}", "Command executed: {:?}", output)
+                            ),
+                            Err(error) => error!(
+                                "{}",
+                                tr!("执行命令失败: {:?}", "Command failed: {:?}", error)
+                            ),
+                        }
+                    }
+                }
+            }
+            let decision = match &mut self.rule {
+                Some(rule) if sample.is_running() => rule.decide(&sample),
+                _ => Decision::Default,
+            };
+            if let Some(pid) = sample.pid {
+                target.growth.record(pid, sample.private_bytes);
+            }
+            let breached = match decision {
+                Decision::Default => sample.exceeds_threshold() && target.still_growing(&sample),
+                Decision::Restart => true,
+                Decision::Ignore => false,
+            };
+            if sample.is_running() {
+                monitor_state::record_restart_effect(&target.name, sample.private_bytes, breached);
+            }
+            let next = target.state.next(
+                &sample,
+                breached,
+                self.near_threshold_percent,
+                self.recovery_margin_percent,
+                target.backoff_expired(),
+            );
+            let breached = next == TargetState::Breached && target.state != next;
+            target.set_state(next);
+            if let Some(settings) = &target.trace {
+                let near = matches!(target.state, TargetState::Warning | TargetState::Breached);
+                etw_trace::update(&*self.api, &target.name, near, settings);
+            }
+            if breached {
+                emit(
+                    &self.subscribers,
+                    MonitorEvent::ThresholdBreached(sample.clone()),
+                );
+            }
+            let early = match (&self.maintenance_schedule, sample.pid) {
+                (Some(schedule), Some(pid)) => {
+                    let now = monitor_state::now_secs();
+                    target.leak_rate.record(pid, now, sample.private_bytes);
+                    matches!(target.state, TargetState::Healthy | TargetState::Warning)
+                        && target.plan_restart(schedule, &sample, now)
+                }
+                _ => false,
+            };
+            if target.state == TargetState::Breached || early {
+                run_actions(
+                    target,
+                    &sample,
+                    &self.subscribers,
+                    &*self.api,
+                    &self.deferrals,
+                    self.maintenance_schedule.as_ref(),
+                    early,
+                );
+            }
+            samples.push(sample);
+        }
+        samples
+    }
+}
+
+/// 只采样、不执行动作也不记录状态：按间隔读取一组进程的内存，以异步流的形式提供给其他 Rust 程序。
+///
+/// ```no_run
+/// use dwm_monitor::monitor::MemorySampler;
+/// use std::time::Duration;
+/// use tokio_stream::StreamExt;
+///
+/// # async fn run() {
+/// let mut samples = MemorySampler::new()
+///     .process("dwm.exe", 1000 * 1024 * 1024)
+///     .interval(Duration::from_secs(5))
+///     .stream();
+/// while let Some(sample) = samples.next().await {
+///     println!("{} {}", sample.name, sample.private_bytes);
+/// }
+/// # }
+/// ```
+pub struct MemorySampler {
+    // 进程名与阈值
+    processes: Vec<(String, u64)>,
+    interval: Duration,
+    sampler: Box<dyn Sampler>,
+}
+
+impl Default for MemorySampler {
+    fn default() -> Self {
+        MemorySampler::new()
+    }
+}
+
+impl MemorySampler {
+    pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(5);
+
+    pub fn new() -> MemorySampler {
+        MemorySampler {
+            processes: Vec::new(),
+            interval: MemorySampler::DEFAULT_INTERVAL,
+            sampler: Box::new(SystemSampler::default()),
+        }
+    }
+
+    /// 添加一个进程，阈值只用于填写 [`Sample::threshold_bytes`]。
+    pub fn process(mut self, name: &str, threshold_bytes: u64) -> Self {
+        self.processes.push((name.to_string(), threshold_bytes));
+        self
+    }
+
+    /// 按配置中的一项添加进程。
+    pub fn monitored(self, process: &MonitoredProcess) -> Self {
+        self.process(&process.name, process.memory_threshold_bytes)
+    }
+
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn api(mut self, api: Arc<dyn SystemApi>) -> Self {
+        self.sampler = Box::new(SystemSampler::new(api));
+        self
+    }
+
+    pub fn sampler<S: Sampler + 'static>(mut self, sampler: S) -> Self {
+        self.sampler = Box::new(sampler);
+        self
+    }
+
+    /// 采样一轮，每个进程一项；无法枚举进程时返回空列表。
+    pub fn sample(&mut self) -> Vec<Sample> {
+        let names: Vec<&str> = self
+            .processes
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        let Some(process_infos) = self.sampler.find(&names) else {
+            return Vec::new();
+        };
+        self.processes
+            .iter()
+            .map(|(name, threshold_bytes)| {
+                Sample::of_instances(name, &process_infos, *threshold_bytes)
+            })
+            .collect()
+    }
+
+    /// 在后台线程中每隔 `interval` 采样一轮，依次产生各进程的采样结果。采样是阻塞调用，
+    /// 因此不占用异步运行时的线程；流被丢弃后，后台线程在下一轮采样前退出。
+    pub fn stream(mut self) -> impl Stream<Item = Sample> + Send + Unpin {
+        let (sender, receiver) = tokio::sync::mpsc::channel(self.processes.len().max(1));
+        std::thread::spawn(move || {
+            while !sender.is_closed() {
+                for sample in self.sample() {
+                    if sender.blocking_send(sample).is_err() {
+                        return;
+                    }
+                }
+                std::thread::sleep(self.interval);
+            }
+        });
+        ReceiverStream::new(receiver)
+    }
+}
+
+// 不紧急的重启需要推迟时返回原因：使用电池、正在游戏或录屏、正在通话
+fn deferral_reason(api: &dyn SystemApi, sample: &Sample, deferrals: &Deferrals) -> Option<String> {
+    if sample.is_near_threshold(deferrals.urgent_percent) {
+        return None;
+    }
+    if deferrals.on_battery && api.on_battery() {
+        return Some(tr!("正在使用电池供电", "running on battery"));
+    }
+    if deferrals.games_and_capture {
+        // 只考虑要重启的实例所在会话中的全屏程序
+        if sample
+            .offending_pids()
+            .into_iter()
+            .filter_map(|pid| api.session_id(pid))
+            .any(monitor_state::full_screen)
+        {
+            return Some(tr!(
+                "会话中正在运行全屏游戏或演示",
+                "a full-screen game or presentation is running in the session"
+            ));
+        }
+        if let Some(name) = running_process(api, &deferrals.capture_processes) {
+            return Some(tr!("{} 正在运行", "{} is running", name));
+        }
+    }
+    let name = running_process(api, &deferrals.do_not_disturb_processes)?;
+    // 会议软件常驻后台时，只在要重启的实例所在会话正在使用摄像头或麦克风时推迟
+    if deferrals.do_not_disturb_requires_media
+        && !sample
+            .offending_pids()
+            .into_iter()
+            .any(|pid| api.media_in_use(pid))
+    {
+        return None;
+    }
+    Some(tr!("{} 正在通话", "{} is in a call", name))
+}
+
+// patterns 中任一进程正在运行时返回其名称
+fn running_process(api: &dyn SystemApi, patterns: &[String]) -> Option<String> {
+    if patterns.is_empty() {
+        return None;
+    }
+    api.processes()?
+        .into_iter()
+        .find(|process| {
+            patterns
+                .iter()
+                .any(|pattern| process_name::matches(pattern, &process.name))
+        })
+        .map(|process| process.name)
+}
+
+// 所在会话的用户选择了今天不自动重启的实例从要重启的实例中去掉，并提示这些用户；
+// 剩下的都不重启时返回 None，不执行动作，也不进入 Backoff，用户取消选择或次日照常重启
+fn without_opted_out(
+    target: &mut Target,
+    sample: &Sample,
+    api: &dyn SystemApi,
+    deferrals: &Deferrals,
+) -> Option<Sample> {
+    let offending = sample.offending_pids();
+    let opted_out: Vec<u32> = if deferrals.user_opt_out {
+        offending
+            .iter()
+            .copied()
+            .filter(|pid| api.restart_opted_out(*pid))
+            .collect()
+    } else {
+        Vec::new()
+    };
+    if opted_out.is_empty() {
+        target.opt_out_notified = false;
+        return Some(sample.clone());
+    }
+    let skip_all = offending.iter().all(|pid| opted_out.contains(pid));
+    if skip_all {
+        warn!(
+            "{}",
+            tr!(
+                "内存使用超过阈值 {} MB，用户已选择今天不自动重启，跳过重启 {}",
+                "Memory usage exceeds the {} MB threshold, restart of {} skipped by user request for today",
+                target.threshold_bytes / 1024 / 1024,
+                &target.name
+            )
+        );
+    } else {
+        info!(
+            "{}",
+            tr!(
+                "{} 的实例 {:?} 所在会话的用户已选择今天不自动重启，只重启其他实例",
+                "The users in the sessions of {} instances {:?} chose to skip automatic restarts today, restarting the other instances only",
+                &target.name,
+                opted_out
+            )
+        );
+    }
+    if !target.opt_out_notified {
+        let message = templates::format(Message::RestartOptedOut, &template_values(sample), || {
+            tr!(
+                "{} 占用内存 {} MB，已超过 {} MB。按您的选择今天不会自动重启它，如果屏幕变慢或卡顿，可以在托盘菜单中取消该选择。",
+                "{} is using {} MB of memory, above {} MB. As you chose, it will not be restarted automatically today; if the screen becomes slow or stutters, you can undo that choice from the tray menu.",
+                sample.name,
+                sample.private_bytes / 1024 / 1024,
+                sample.threshold_bytes / 1024 / 1024
+            )
+        });
+        for pid in &opted_out {
+            if let Err(e) = api.send_session_message(*pid, &message, OPT_OUT_NOTICE) {
+                warn!(
+                    "{}",
+                    tr!(
+                        "无法提示 {} (PID {}) 所在会话: {}",
+                        "Failed to notify the session of {} (PID {}): {}",
+                        sample.name,
+                        pid,
+                        e
+                    )
+                );
+            }
+        }
+        target.opt_out_notified = true;
+    }
+    if skip_all {
+        return None;
+    }
+    let mut sample = sample.clone();
+    sample.instances.retain(|(pid, _)| !opted_out.contains(pid));
+    Some(sample)
+}
+
+// Breached 时执行动作：Restarting 之后成功进入 Verifying，失败进入 Backoff
+fn run_actions(
+    target: &mut Target,
+    sample: &Sample,
+    subscribers: &[Subscriber],
+    api: &dyn SystemApi,
+    deferrals: &Deferrals,
+    schedule: Option<&MaintenanceSchedule>,
+    // 按泄漏速度在维护窗口内提前重启，尚未超过阈值
+    early: bool,
+) {
+    if target.actions.is_empty() {
+        return;
+    }
+    if monitor_state::is_snoozed() {
+        warn!(
+            "{}",
+            tr!(
+                "内存使用超过阈值 {} MB，重启已暂缓，跳过重启 {}",
+                "Memory usage exceeds the {} MB threshold, restarts are snoozed, skipping restart of {}",
+                target.threshold_bytes / 1024 / 1024,
+                &target.name
+            )
+        );
+        return;
+    }
+    if monitor_state::is_postponed(&target.name) {
+        warn!(
+            "{}",
+            tr!(
+                "内存使用超过阈值 {} MB，用户已推迟重启，跳过重启 {}",
+                "Memory usage exceeds the {} MB threshold, the user postponed the restart, skipping restart of {}",
+                target.threshold_bytes / 1024 / 1024,
+                &target.name
+            )
+        );
+        return;
+    }
+    if deferrals.reboot_pending && api.reboot_pending() {
+        warn!(
+            "{}",
+            tr!(
+                "内存使用超过阈值 {} MB，系统待重启，跳过重启 {}",
+                "Memory usage exceeds the {} MB threshold, a system reboot is pending, skipping restart of {}",
+                target.threshold_bytes / 1024 / 1024,
+                &target.name
+            )
+        );
+        return;
+    }
+    if let Some(reason) = deferral_reason(api, sample, deferrals) {
+        warn!(
+            "{}",
+            tr!(
+                "内存使用超过阈值 {} MB，{}，推迟重启 {}",
+                "Memory usage exceeds the {} MB threshold, {}, deferring restart of {}",
+                target.threshold_bytes / 1024 / 1024,
+                reason,
+                &target.name
+            )
+        );
+        return;
+    }
+    if schedule.is_some_and(|schedule| {
+        schedule.restarts_only_in_window
+            && !schedule.contains(maintenance::local_time(monitor_state::now_secs()))
+    }) {
+        warn!(
+            "{}",
+            tr!(
+                "内存使用超过阈值 {} MB，不在维护窗口内，推迟重启 {}",
+                "Memory usage exceeds the {} MB threshold, outside the maintenance window, deferring restart of {}",
+                target.threshold_bytes / 1024 / 1024,
+                &target.name
+            )
+        );
+        return;
+    }
+    let Some(sample) = target.restartable(sample) else {
+        warn!(
+            "{}",
+            tr!(
+                "内存使用超过阈值 {} MB，超过阈值的实例都在远程桌面会话中，按配置不重启 {}",
+                "Memory usage exceeds the {} MB threshold, the offending instances are all in remote desktop sessions, not restarting {} as configured",
+                sample.threshold_bytes / 1024 / 1024,
+                &target.name
+            )
+        );
+        return;
+    };
+    let Some(sample) = without_opted_out(target, &sample, api, deferrals) else {
+        return;
+    };
+    let sample = &sample;
+    let message = templates::format(Message::RestartLog, &template_values(sample), || {
+        if early {
+            tr!(
+                "预计 {} 将在维护窗口之外超过阈值 {} MB，在维护窗口内提前重启",
+                "{} is expected to exceed the {} MB threshold outside the maintenance window, restarting it early within the maintenance window",
+                &target.name,
+                target.threshold_bytes / 1024 / 1024
+            )
+        } else {
+            tr!(
+                "内存使用超过阈值 {} MB，正在重启 {}",
+                "Memory usage exceeds the {} MB threshold, restarting {}",
+                target.threshold_bytes / 1024 / 1024,
+                &target.name
+            )
+        }
+    });
+    warn!("{}", message);
+    // 在结束进程之前保存跟踪，见 etw_trace::finish
+    let traced = target
+        .trace
+        .as_ref()
+        .is_some_and(|settings| etw_trace::finish(api, &target.name, settings).is_some());
+    target.set_state(TargetState::Restarting);
+    emit(subscribers, MonitorEvent::RestartStarted(sample.clone()));
+    let mut succeeded = true;
+    for action in &target.actions {
+        succeeded &= action.run(sample);
+        // 用户在确认提示中选择推迟时不再执行后续动作，保持超限状态，推迟结束后再处理
+        if monitor_state::is_postponed(&target.name) {
+            if traced {
+                etw_trace::resume(api, &target.name);
+            }
+            target.set_state(TargetState::Breached);
+            return;
+        }
+    }
+    if succeeded {
+        monitor_state::record_restart_started(&target.name, sample.private_bytes);
+        target.set_state(TargetState::Verifying);
+        emit(subscribers, MonitorEvent::RestartSucceeded(sample.clone()));
+    } else {
+        target.set_state(TargetState::Backoff);
+        emit(subscribers, MonitorEvent::RestartFailed(sample.clone()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_manager::default_learn_threshold_factor;
+    use crate::system_api::fake::FakeSystem;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct FakeSampler(Vec<ProcessInfo>);
+
+    impl Sampler for FakeSampler {
+        fn processes(&mut self) -> Option<Vec<ProcessInfo>> {
+            Some(self.0.clone())
+        }
+    }
+
+    struct CountAction(Arc<AtomicUsize>);
+
+    impl Action for CountAction {
+        fn run(&self, _sample: &Sample) -> bool {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            true
+        }
+    }
+
+    struct FailingAction(Arc<AtomicUsize>);
+
+    impl Action for FailingAction {
+        fn run(&self, _sample: &Sample) -> bool {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            false
+        }
+    }
+
+    fn process(name: &str, private_bytes: usize) -> ProcessInfo {
+        ProcessInfo {
+            name: name.to_string(),
+            pid: 7,
+            thread_count: 1,
+            private_bytes,
+            pagefile_usage: private_bytes,
+            working_set: private_bytes,
+        }
+    }
+
+    fn monitored(name: &str, threshold_bytes: u64) -> MonitoredProcess {
+        MonitoredProcess {
+            name: name.to_string(),
+            memory_threshold_bytes: threshold_bytes,
+            process_type: ProcessType::System,
+            auto_start: false,
+            actions: Vec::new(),
+            learn_threshold_hours: 0,
+            learn_threshold_factor: default_learn_threshold_factor(),
+            require_growth_samples: 0,
+            remote_session: None,
+            etw_trace: false,
+        }
+    }
+
+    // 由 system 采样并重启、阈值为 1024 的进程，各测试再添加推迟、跟踪等设置
+    fn restarting(system: &Arc<FakeSystem>, name: &str) -> ProcessMonitorBuilder {
+        ProcessMonitor::builder()
+            .api(system.clone())
+            .process(name, 1024)
+            .action(RestartAction::with_api(ProcessType::System, system.clone()))
+    }
+
+    #[test]
+    fn test_check_runs_actions_over_threshold() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let mut monitor = ProcessMonitor::builder()
+            .sampler(FakeSampler(vec![
+                process("monitor_test_big.exe", 2048),
+                process("monitor_test_small.exe", 512),
+            ]))
+            .process("monitor_test_big.exe", 1024)
+            .action(CountAction(count.clone()))
+            .process("monitor_test_small.exe", 1024)
+            .action(CountAction(count.clone()))
+            .process("monitor_test_missing.exe", 1024)
+            .build();
+
+        let samples = monitor.check();
+        assert_eq!(samples.len(), 3);
+        assert!(samples[0].exceeds_threshold());
+        assert!(!samples[1].exceeds_threshold());
+        assert!(!samples[2].is_running());
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_is_near_threshold() {
+        let sample = Sample::new("near_test.exe", Some(&process("near_test.exe", 800)), 1000);
+        assert!(sample.is_near_threshold(80));
+        assert!(!sample.is_near_threshold(81));
+        assert!(!Sample::new("near_test.exe", None, 1000).is_near_threshold(0));
+    }
+
+    #[test]
+    fn test_system_sampler_reuses_found_processes() {
+        let system = Arc::new(FakeSystem::new(vec![
+            process("sampler_test.exe", 512),
+            process("sampler_test_other.exe", 512),
+        ]));
+        let mut sampler = SystemSampler::new(system.clone());
+
+        assert_eq!(sampler.find(&["sampler_test.exe"]).unwrap().len(), 1);
+        assert_eq!(system.enumerations(), 1);
+        system.set_private_bytes("sampler_test.exe", 2048);
+        let found = sampler.find(&["sampler_test.exe"]).unwrap();
+        assert_eq!(found[0].private_bytes, 2048);
+        assert_eq!(system.enumerations(), 1);
+
+        system.terminate("sampler_test.exe").unwrap();
+        assert!(sampler.find(&["sampler_test.exe"]).unwrap().is_empty());
+        assert_eq!(system.enumerations(), 2);
+    }
+
+    #[test]
+    fn test_restart_action_terminates_process_over_threshold() {
+        let system =
+            Arc::new(FakeSystem::new(vec![process("restart_test.exe", 2048)]).respawning());
+        let mut monitor = ProcessMonitor::builder()
+            .sampler(SystemSampler::new(system.clone()))
+            .process("restart_test.exe", 1024)
+            .action(RestartAction::with_api(ProcessType::System, system.clone()))
+            .build();
+
+        assert!(monitor.check()[0].exceeds_threshold());
+        assert_eq!(system.terminated(), vec!["restart_test.exe"]);
+
+        // 重新拉起的进程内存回落，不再触发重启
+        let samples = monitor.check();
+        assert_eq!(samples[0].pid, Some(1007));
+        assert!(!samples[0].exceeds_threshold());
+        assert_eq!(system.terminated().len(), 1);
+    }
+
+    #[test]
+    fn test_restart_only_offending_instance() {
+        let system = Arc::new(
+            FakeSystem::new(vec![
+                process("instance_test.exe", 2048),
+                ProcessInfo {
+                    pid: 8,
+                    ..process("instance_test.exe", 512)
+                },
+            ])
+            .respawning(),
+        );
+        let mut monitor = ProcessMonitor::builder()
+            .sampler(SystemSampler::new(system.clone()))
+            .process("instance_test.exe", 1024)
+            .action(RestartAction::with_api(ProcessType::System, system.clone()))
+            .build();
+
+        let samples = monitor.check();
+        assert_eq!(samples[0].pid, Some(7));
+        assert_eq!(samples[0].instances, vec![(7, 2048), (8, 512)]);
+        // 只结束超过阈值的实例，另一个会话的实例继续运行
+        assert_eq!(system.terminated(), vec!["instance_test.exe"]);
+        assert_eq!(system.pids(), vec![8, 1007]);
+    }
+
+    #[test]
+    fn test_remote_session_profile() {
+        let system = Arc::new(
+            FakeSystem::new(vec![
+                process("remote_test.exe", 2048),
+                ProcessInfo {
+                    pid: 8,
+                    ..process("remote_test.exe", 256)
+                },
+            ])
+            .respawning(),
+        );
+        system.set_remote(7);
+        let mut monitor = ProcessMonitor::builder()
+            .api(system.clone())
+            .process("remote_test.exe", 1024)
+            .remote_session(4096, false)
+            .action(RestartAction::with_api(ProcessType::System, system.clone()))
+            .build();
+
+        // 远程会话中的实例超过本地阈值但未超过远程阈值
+        let samples = monitor.check();
+        assert_eq!(samples[0].pid, Some(7));
+        assert_eq!(samples[0].threshold_bytes, 4096);
+        assert_eq!(monitor.state("remote_test.exe"), Some(TargetState::Healthy));
+
+        // 两个实例都超过各自的阈值，只重启本地会话中的实例
+        system.set_private_bytes("remote_test.exe", 5000);
+        let samples = monitor.check();
+        assert_eq!(samples[0].pid, Some(8));
+        assert_eq!(samples[0].offending_pids(), vec![7, 8]);
+        assert_eq!(system.terminated(), vec!["remote_test.exe"]);
+        assert_eq!(system.pids(), vec![7, 1008]);
+    }
+
+    #[test]
+    fn test_require_growth_skips_stable_process() {
+        let system = Arc::new(FakeSystem::new(vec![process("growth_test.exe", 2048)]).respawning());
+        let mut monitor = ProcessMonitor::builder()
+            .sampler(SystemSampler::new(system.clone()))
+            .process("growth_test.exe", 1024)
+            .require_growth(3)
+            .action(RestartAction::with_api(ProcessType::System, system.clone()))
+            .build();
+
+        // 稳定在阈值以上，不重启
+        for _ in 0..4 {
+            monitor.check();
+        }
+        assert!(system.terminated().is_empty());
+
+        system.set_private_bytes("growth_test.exe", 2100);
+        monitor.check();
+        assert_eq!(system.terminated(), vec!["growth_test.exe"]);
+    }
+
+    #[test]
+    fn test_monitored_restarts_through_builder_api() {
+        let system =
+            Arc::new(FakeSystem::new(vec![process("monitored_test.exe", 2048)]).respawning());
+        let mut monitor = ProcessMonitor::builder()
+            .api(system.clone())
+            .monitored(&monitored("monitored_test.exe", 1024))
+            .build();
+
+        assert!(monitor.check()[0].exceeds_threshold());
+        assert_eq!(system.terminated(), vec!["monitored_test.exe"]);
+        assert_eq!(monitor.check()[0].pid, Some(1007));
+    }
+
+    #[test]
+    fn test_restart_warning_precedes_restart() {
+        let system =
+            Arc::new(FakeSystem::new(vec![process("warning_test.exe", 2048)]).respawning());
+        system.set_session(7, "session 2 (DOMAIN\\alice)");
+        let mut monitor = ProcessMonitor::builder()
+            .api(system.clone())
+            .restart_warning(Duration::from_secs(30))
+            .monitored(&monitored("warning_test.exe", 1024))
+            .build();
+
+        monitor.check();
+        let messages = system.messages();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].0, 7);
+        assert!(messages[0].1.contains("30"));
+        assert_eq!(system.terminated(), vec!["warning_test.exe"]);
+        // 重启记录中注明被结束的进程属于哪个会话和用户
+        let status = monitor_state::snapshot();
+        let entry = status
+            .processes
+            .iter()
+            .find(|p| p.name == "warning_test.exe")
+            .unwrap();
+        assert_eq!(entry.restart_sessions, vec!["session 2 (DOMAIN\\alice)"]);
+    }
+
+    #[test]
+    fn test_restart_postponed_by_user() {
+        let system =
+            Arc::new(FakeSystem::new(vec![process("postpone_test.exe", 2048)]).respawning());
+        system.set_postpone(true);
+        let count = Arc::new(AtomicUsize::new(0));
+        let mut monitor = ProcessMonitor::builder()
+            .api(system.clone())
+            .restart_warning(Duration::from_secs(30))
+            .restart_confirmation(true)
+            .monitored(&monitored("postpone_test.exe", 1024))
+            .action(CountAction(count.clone()))
+            .build();
+
+        monitor.check();
+        assert_eq!(system.messages().len(), 1);
+        assert!(system.terminated().is_empty());
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+        assert_eq!(
+            monitor.state("postpone_test.exe"),
+            Some(TargetState::Breached)
+        );
+        // 推迟期间不再询问
+        monitor.check();
+        assert_eq!(system.messages().len(), 1);
+        assert!(monitor_state::is_postponed("postpone_test.exe"));
+    }
+
+    #[test]
+    fn test_restart_skipped_when_user_opted_out() {
+        let system =
+            Arc::new(FakeSystem::new(vec![process("opt_out_test.exe", 2048)]).respawning());
+        let pid = system.pids()[0];
+        system.set_opted_out(pid);
+        let count = Arc::new(AtomicUsize::new(0));
+        let mut monitor = ProcessMonitor::builder()
+            .api(system.clone())
+            .monitored(&monitored("opt_out_test.exe", 1024))
+            .action(CountAction(count.clone()))
+            .build();
+
+        monitor.check();
+        monitor.check();
+        // 只提示一次，不重启也不执行其他动作
+        assert_eq!(system.messages().len(), 1);
+        assert_eq!(system.messages()[0].0, pid);
+        assert!(system.terminated().is_empty());
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+        assert_eq!(
+            monitor.state("opt_out_test.exe"),
+            Some(TargetState::Breached)
+        );
+    }
+
+    #[test]
+    fn test_opted_out_sessions_skipped_other_instances_restarted() {
+        let instances = || {
+            vec![
+                process("opt_out_instances.exe", 2048),
+                ProcessInfo {
+                    pid: 8,
+                    ..process("opt_out_instances.exe", 2048)
+                },
+            ]
+        };
+        let system = Arc::new(FakeSystem::new(instances()).respawning());
+        system.set_opted_out(7);
+        let mut monitor = restarting(&system, "opt_out_instances.exe").build();
+        monitor.check();
+        // 只提示选择了不重启的用户，重启另一个会话中的实例
+        assert_eq!(system.messages().len(), 1);
+        assert_eq!(system.messages()[0].0, 7);
+        assert_eq!(system.pids(), vec![7, 1008]);
+
+        // 管理员关闭了用户的选择时照常重启
+        let system = Arc::new(FakeSystem::new(instances()).respawning());
+        system.set_opted_out(7);
+        let mut monitor = restarting(&system, "opt_out_instances.exe")
+            .deferrals(Deferrals {
+                user_opt_out: false,
+                ..Default::default()
+            })
+            .build();
+        monitor.check();
+        assert!(system.messages().is_empty());
+        assert_eq!(system.pids(), vec![1007, 1008]);
+    }
+
+    #[test]
+    fn test_restart_held_while_reboot_pending() {
+        let system = Arc::new(FakeSystem::new(vec![process("reboot_test.exe", 2048)]).respawning());
+        system.set_reboot_pending(true);
+        let mut monitor = ProcessMonitor::builder()
+            .api(system.clone())
+            .hold_restarts_for_reboot(true)
+            .monitored(&monitored("reboot_test.exe", 1024))
+            .build();
+
+        monitor.check();
+        assert!(system.terminated().is_empty());
+        assert_eq!(
+            monitor.state("reboot_test.exe"),
+            Some(TargetState::Breached)
+        );
+
+        system.set_reboot_pending(false);
+        monitor.check();
+        assert_eq!(system.terminated(), vec!["reboot_test.exe"]);
+    }
+
+    #[test]
+    fn test_restart_deferred_while_capturing() {
+        let system = Arc::new(
+            FakeSystem::new(vec![
+                process("capture_test.exe", 1200),
+                ProcessInfo {
+                    pid: 9,
+                    ..process("OBS64.EXE", 100)
+                },
+            ])
+            .respawning(),
+        );
+        let mut monitor = restarting(&system, "capture_test.exe")
+            .deferrals(Deferrals {
+                games_and_capture: true,
+                capture_processes: vec!["obs*.exe".to_string()],
+                ..Default::default()
+            })
+            .build();
+
+        monitor.check();
+        assert!(system.terminated().is_empty());
+        assert_eq!(
+            monitor.state("capture_test.exe"),
+            Some(TargetState::Breached)
+        );
+
+        system.set_private_bytes("capture_test.exe", 2048);
+        monitor.check();
+        assert_eq!(system.terminated(), vec!["capture_test.exe"]);
+    }
+
+    #[test]
+    fn test_restart_deferred_for_full_screen_session() {
+        let system =
+            Arc::new(FakeSystem::new(vec![process("full_screen_test.exe", 1200)]).respawning());
+        system.set_session_id(7, 911);
+        let mut monitor = restarting(&system, "full_screen_test.exe")
+            .deferrals(Deferrals {
+                games_and_capture: true,
+                ..Default::default()
+            })
+            .build();
+
+        // 其他会话中的全屏程序不影响重启
+        monitor_state::set_full_screen(912, true);
+        monitor_state::set_full_screen(911, true);
+        monitor.check();
+        assert!(system.terminated().is_empty());
+
+        monitor_state::set_full_screen(911, false);
+        monitor.check();
+        assert_eq!(system.terminated(), vec!["full_screen_test.exe"]);
+    }
+
+    #[test]
+    fn test_etw_trace_saved_before_restart() {
+        let system = Arc::new(FakeSystem::new(vec![process("trace_test.exe", 900)]).respawning());
+        let settings = TraceSettings {
+            dir: std::env::temp_dir().join("process_guard_traces"),
+            ..Default::default()
+        };
+        let mut monitor = restarting(&system, "trace_test.exe")
+            .etw_trace(settings.clone())
+            .build();
+
+        monitor.check();
+        assert!(system.tracing());
+
+        // 内存回落时取消跟踪，不保存文件
+        system.set_private_bytes("trace_test.exe", 100);
+        monitor.check();
+        assert!(!system.tracing());
+        assert!(system.traces().is_empty());
+
+        system.set_private_bytes("trace_test.exe", 900);
+        monitor.check();
+        system.set_private_bytes("trace_test.exe", 1200);
+        monitor.check();
+        assert_eq!(system.terminated(), vec!["trace_test.exe"]);
+        assert!(!system.tracing());
+        let traces = system.traces();
+        assert_eq!(traces.len(), 1);
+        assert!(traces[0].starts_with(&settings.dir));
+        assert!(traces[0]
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("trace_test_"));
+
+        // 用户推迟重启时保留已保存的跟踪，并重新开始记录；ETW 会话全局唯一，放在同一个测试中
+        let system =
+            Arc::new(FakeSystem::new(vec![process("trace_postpone_test.exe", 1200)]).respawning());
+        system.set_postpone(true);
+        let mut monitor = ProcessMonitor::builder()
+            .api(system.clone())
+            .restart_warning(Duration::from_secs(30))
+            .restart_confirmation(true)
+            .trace_settings(settings)
+            .monitored(&MonitoredProcess {
+                etw_trace: true,
+                ..monitored("trace_postpone_test.exe", 1024)
+            })
+            .build();
+        monitor.check();
+        assert!(system.terminated().is_empty());
+        assert_eq!(system.traces().len(), 1);
+        assert!(system.tracing());
+    }
+
+    #[test]
+    fn test_restart_deferred_during_call() {
+        let processes = || {
+            vec![
+                process("call_test.exe", 1200),
+                ProcessInfo {
+                    pid: 9,
+                    ..process("Zoom.exe", 100)
+                },
+            ]
+        };
+        let deferrals = Deferrals {
+            do_not_disturb_processes: vec!["zoom.exe".to_string()],
+            do_not_disturb_requires_media: true,
+            ..Default::default()
+        };
+        let system = Arc::new(FakeSystem::new(processes()).respawning());
+        system.set_media_in_use(7);
+        let mut monitor = restarting(&system, "call_test.exe")
+            .deferrals(deferrals.clone())
+            .build();
+
+        monitor.check();
+        assert!(system.terminated().is_empty());
+        assert_eq!(monitor.state("call_test.exe"), Some(TargetState::Breached));
+
+        system.set_private_bytes("call_test.exe", 2048);
+        monitor.check();
+        assert_eq!(system.terminated(), vec!["call_test.exe"]);
+
+        // 会议软件在运行但没有使用摄像头和麦克风时照常重启
+        let system = Arc::new(FakeSystem::new(processes()).respawning());
+        let mut monitor = restarting(&system, "call_test.exe")
+            .deferrals(deferrals)
+            .build();
+        monitor.check();
+        assert_eq!(system.terminated(), vec!["call_test.exe"]);
+    }
+
+    #[test]
+    fn test_restart_deferred_on_battery() {
+        let system =
+            Arc::new(FakeSystem::new(vec![process("battery_test.exe", 1200)]).respawning());
+        system.set_on_battery(true);
+        let mut monitor = restarting(&system, "battery_test.exe")
+            .deferrals(Deferrals {
+                on_battery: true,
+                ..Default::default()
+            })
+            .build();
+
+        monitor.check();
+        assert!(system.terminated().is_empty());
+        assert_eq!(
+            monitor.state("battery_test.exe"),
+            Some(TargetState::Breached)
+        );
+
+        // 达到阈值的 150% 时使用电池也立即重启
+        system.set_private_bytes("battery_test.exe", 2048);
+        monitor.check();
+        assert_eq!(system.terminated(), vec!["battery_test.exe"]);
+    }
+
+    #[test]
+    fn test_state_follows_restart_lifecycle() {
+        let system =
+            Arc::new(FakeSystem::new(vec![process("state_machine_test.exe", 900)]).respawning());
+        let mut monitor = ProcessMonitor::builder()
+            .api(system.clone())
+            .monitored(&monitored("state_machine_test.exe", 1000))
+            .build();
+        let name = "state_machine_test.exe";
+        assert_eq!(monitor.state(name), Some(TargetState::Discovering));
+
+        monitor.check();
+        assert_eq!(monitor.state(name), Some(TargetState::Warning));
+        system.set_private_bytes(name, 2048);
+        monitor.check();
+        assert_eq!(monitor.state(name), Some(TargetState::Verifying));
+        monitor.check();
+        assert_eq!(monitor.state(name), Some(TargetState::Healthy));
+
+        let status = monitor_state::snapshot();
+        let entry = status.processes.iter().find(|p| p.name == name).unwrap();
+        assert_eq!(entry.state, TargetState::Healthy);
+        // 重启后第一次采样计算回收量
+        assert_eq!(entry.restart_stats.mean_reclaimed_bytes(), Some(2048));
+        assert_eq!(entry.restart_stats.mean_rebreach_seconds(), None);
+    }
+
+    #[test]
+    fn test_failed_action_backs_off() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let mut monitor = ProcessMonitor::builder()
+            .sampler(FakeSampler(vec![process("backoff_test.exe", 2048)]))
+            .process("backoff_test.exe", 1024)
+            .action(FailingAction(count.clone()))
+            .build();
+
+        monitor.check();
+        assert_eq!(
+            monitor.state("backoff_test.exe"),
+            Some(TargetState::Backoff)
+        );
+        monitor.check();
+        assert_eq!(
+            monitor.state("backoff_test.exe"),
+            Some(TargetState::Backoff)
+        );
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_backoff_resumed_from_last_restart() {
+        monitor_state::record_restart("resume_test.exe", &[]);
+        let count = Arc::new(AtomicUsize::new(0));
+        let mut monitor = ProcessMonitor::builder()
+            .sampler(FakeSampler(vec![process("resume_test.exe", 2048)]))
+            .process("resume_test.exe", 1024)
+            .action(FailingAction(count.clone()))
+            .build();
+        monitor.resume_backoff();
+
+        monitor.check();
+        assert_eq!(monitor.state("resume_test.exe"), Some(TargetState::Backoff));
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_state_transitions() {
+        let running = Sample::new("transition_test.exe", Some(&process("t", 2048)), 1024);
+        let missing = Sample::new("transition_test.exe", None, 1024);
+        assert_eq!(
+            TargetState::Healthy.next(&running, true, 80, 10, true),
+            TargetState::Breached
+        );
+        // 重启后仍超限进入 Backoff，冷却期结束后才重新进入 Breached
+        assert_eq!(
+            TargetState::Verifying.next(&running, true, 80, 10, true),
+            TargetState::Backoff
+        );
+        assert_eq!(
+            TargetState::Backoff.next(&running, true, 80, 10, false),
+            TargetState::Backoff
+        );
+        assert_eq!(
+            TargetState::Backoff.next(&running, true, 80, 10, true),
+            TargetState::Breached
+        );
+        assert_eq!(
+            TargetState::Healthy.next(&missing, false, 80, 10, true),
+            TargetState::Discovering
+        );
+        // 回落到恢复线以下才离开 Warning 和 Backoff
+        let at = |bytes| Sample::new("transition_test.exe", Some(&process("t", bytes)), 1000);
+        assert_eq!(
+            TargetState::Backoff.next(&at(950), false, 80, 10, true),
+            TargetState::Backoff
+        );
+        assert_eq!(
+            TargetState::Backoff.next(&at(850), false, 80, 10, true),
+            TargetState::Warning
+        );
+        assert_eq!(
+            TargetState::Warning.next(&at(750), false, 80, 10, true),
+            TargetState::Warning
+        );
+        assert_eq!(
+            TargetState::Healthy.next(&at(750), false, 80, 10, true),
+            TargetState::Healthy
+        );
+        assert_eq!(
+            TargetState::Warning.next(&at(650), false, 80, 10, true),
+            TargetState::Healthy
+        );
+    }
+
+    #[test]
+    fn test_restart_gives_up_when_process_does_not_return() {
+        let system = FakeSystem::new(vec![process("restart_gone_test.exe", 2048)]);
+        assert!(!restart_with(
+            &system,
+            "restart_gone_test.exe",
+            &[],
+            &ProcessType::System
+        ));
+        assert_eq!(system.terminated(), vec!["restart_gone_test.exe"]);
+    }
+
+    struct FixedRule(Decision);
+
+    impl Rule for FixedRule {
+        fn decide(&mut self, _sample: &Sample) -> Decision {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_rule_overrides_threshold() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let mut ignored = ProcessMonitor::builder()
+            .sampler(FakeSampler(vec![process("rule_test_big.exe", 2048)]))
+            .process("rule_test_big.exe", 1024)
+            .action(CountAction(count.clone()))
+            .rule(FixedRule(Decision::Ignore))
+            .build();
+        ignored.check();
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+
+        let mut restarted = ProcessMonitor::builder()
+            .sampler(FakeSampler(vec![process("rule_test_small.exe", 512)]))
+            .process("rule_test_small.exe", 1024)
+            .action(CountAction(count.clone()))
+            .rule(FixedRule(Decision::Restart))
+            .build();
+        restarted.check();
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_on_event_reports_breach_and_restart() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let mut monitor = ProcessMonitor::builder()
+            .sampler(FakeSampler(vec![
+                process("event_test_ok.exe", 2048),
+                process("event_test_fail.exe", 2048),
+            ]))
+            .process("event_test_ok.exe", 1024)
+            .action(CountAction(count.clone()))
+            .process("event_test_fail.exe", 1024)
+            .action(FailingAction(count.clone()))
+            .build();
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let received = events.clone();
+        monitor.on_event(move |event| received.lock().unwrap().push(event));
+
+        let samples = monitor.check();
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                MonitorEvent::ThresholdBreached(samples[0].clone()),
+                MonitorEvent::RestartStarted(samples[0].clone()),
+                MonitorEvent::RestartSucceeded(samples[0].clone()),
+                MonitorEvent::ThresholdBreached(samples[1].clone()),
+                MonitorEvent::RestartStarted(samples[1].clone()),
+                MonitorEvent::RestartFailed(samples[1].clone()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_memory_sampler_streams_samples() {
+        use tokio_stream::StreamExt;
+
+        let mut samples = MemorySampler::new()
+            .sampler(FakeSampler(vec![process("stream_test.exe", 2048)]))
+            .process("stream_test.exe", 1024)
+            .process("stream_test_missing.exe", 1024)
+            .interval(Duration::from_millis(10))
+            .stream();
+        let first: Vec<Sample> = (&mut samples).take(4).collect().await;
+        assert_eq!(first.len(), 4);
+        assert_eq!(first[0].name, "stream_test.exe");
+        assert!(first[0].exceeds_threshold());
+        assert!(!first[1].is_running());
+        assert_eq!(first[0], first[2]);
+    }
+}
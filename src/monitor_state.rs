@@ -0,0 +1,704 @@
+use lazy_static::lazy_static;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
+
+use crate::baseline::Baseline;
+use crate::config_manager::program_file_path;
+use crate::monitor::TargetState;
+use crate::process_manager::ProcessInfo;
+use crate::process_name;
+use crate::tr;
+
+pub const STATE_FILE_NAME: &str = "process_guard_state.json";
+
+// 集中上报失败时只保留最近的重启事件
+const MAX_RESTART_EVENTS: usize = 1000;
+
+// 本机普通用户（托盘程序）从第一次暂缓起 USER_SNOOZE_WINDOW_SECS 内最多暂缓到
+// MAX_USER_SNOOZE_SECS 之后，避免反复续期长期关闭自动重启；管理员不受限制
+pub const MAX_USER_SNOOZE_SECS: u64 = 4 * 60 * 60;
+const USER_SNOOZE_WINDOW_SECS: u64 = 24 * 60 * 60;
+
+// 托盘程序每 5 秒上报一次窗口数和全屏状态，超过该时间未上报（托盘程序已退出）时不再使用旧值
+const TRAY_REPORT_MAX_AGE_SECS: u64 = 60;
+
+static PERSIST: AtomicBool = AtomicBool::new(true);
+// 上次上报窗口数的时间（Unix 时间戳）
+static VISIBLE_WINDOWS_AT: AtomicU64 = AtomicU64::new(0);
+
+lazy_static! {
+    pub static ref MONITOR_STATUS: Mutex<MonitorStatus> = Mutex::new(MonitorStatus::default());
+    // 用于提前唤醒各监控任务（如从睡眠恢复后立即采样）
+    static ref WAKEUP: watch::Sender<()> = watch::channel(()).0;
+    // 服务停止时通知所有后台任务退出
+    static ref SHUTDOWN: watch::Sender<bool> = watch::channel(false).0;
+    // 阻塞线程（重启流程、看门狗）中的等待通过它在停止时提前返回
+    static ref SHUTDOWN_LOCK: Mutex<()> = Mutex::new(());
+    static ref SHUTDOWN_CONDVAR: Condvar = Condvar::new();
+    // 尚未上报到集中收集端的重启事件
+    static ref RESTART_EVENTS: Mutex<VecDeque<RestartEvent>> = Mutex::new(VecDeque::new());
+    // 托盘程序报告有全屏游戏或演示的会话，以及上报的时间
+    static ref FULL_SCREEN_SESSIONS: Mutex<HashMap<u32, u64>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ProcessStatus {
+    pub name: String,
+    pub pid: Option<u32>,
+    pub private_bytes: u64,
+    pub working_set: u64,
+    pub memory_threshold_bytes: u64,
+    // Unix 时间戳（秒）
+    pub last_sample: Option<u64>,
+    pub last_restart: Option<u64>,
+    // 该进程的监控任务异常退出后被重新启动的次数
+    #[serde(default)]
+    pub worker_restarts: u32,
+    // 看门狗发现采样停滞，下一次采样成功后清除
+    #[serde(default)]
+    pub stalled: bool,
+    #[serde(default)]
+    pub state: TargetState,
+    // 插件指标的最新值
+    #[serde(default)]
+    pub metrics: BTreeMap<String, f64>,
+    // 上次重启时被结束的进程所在的会话及用户，如 "session 2 (DOMAIN\alice)"
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub restart_sessions: Vec<String>,
+    #[serde(default)]
+    pub restart_stats: RestartStats,
+    // 基线学习的进度，学习结束后清除
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub baseline: Option<Baseline>,
+    // 用户在重启确认中选择推迟的截止时间（Unix 时间戳），期间不重启该进程
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub postponed_until: Option<u64>,
+}
+
+/// 自动重启的效果：每次重启回收了多少内存，多久之后再次超过阈值，用于判断重启是否能有效缓解。
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RestartStats {
+    // 测量到回收量的重启次数和回收的 Private Bytes 总量
+    pub reclaimed_count: u32,
+    pub reclaimed_bytes: u64,
+    // 重启后再次超过阈值的次数和间隔总秒数
+    pub rebreach_count: u32,
+    pub rebreach_seconds: u64,
+    // 上次重启前的 Private Bytes，重启后第一次采样时计算回收量
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pending_reclaim_from: Option<u64>,
+    // 上次重启的时间（Unix 时间戳），再次超过阈值时计算间隔
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pending_rebreach_since: Option<u64>,
+}
+
+impl RestartStats {
+    fn restarted(&mut self, private_bytes: u64, time: u64) {
+        self.pending_reclaim_from = Some(private_bytes);
+        self.pending_rebreach_since = Some(time);
+    }
+
+    fn sampled(&mut self, private_bytes: u64, breached: bool, time: u64) {
+        if let Some(before) = self.pending_reclaim_from.take() {
+            self.reclaimed_count += 1;
+            self.reclaimed_bytes += before.saturating_sub(private_bytes);
+        }
+        if breached {
+            if let Some(since) = self.pending_rebreach_since.take() {
+                self.rebreach_count += 1;
+                self.rebreach_seconds += time.saturating_sub(since);
+            }
+        }
+    }
+
+    pub fn mean_reclaimed_bytes(&self) -> Option<u64> {
+        (self.reclaimed_count > 0).then(|| self.reclaimed_bytes / self.reclaimed_count as u64)
+    }
+
+    pub fn mean_rebreach_seconds(&self) -> Option<u64> {
+        (self.rebreach_count > 0).then(|| self.rebreach_seconds / self.rebreach_count as u64)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RestartEvent {
+    pub name: String,
+    // Unix 时间戳（秒）
+    pub time: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sessions: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MonitorStatus {
+    pub paused: bool,
+    // 暂缓重启的截止时间（Unix 时间戳），期间继续采样但不执行重启
+    #[serde(default)]
+    pub snoozed_until: Option<u64>,
+    // 当前的暂缓由管理员设置，普通用户不能缩短或取消
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub snoozed_by_admin: bool,
+    // 普通用户第一次暂缓的时间（Unix 时间戳），之后 USER_SNOOZE_WINDOW_SECS 内的暂缓从它起算上限
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_snooze_since: Option<u64>,
+    pub processes: Vec<ProcessStatus>,
+    // 系统版本号（如 22621.1778），服务启动时设置，附加到各遥测输出
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os_build: Option<String>,
+    // 交互会话中可见的顶层窗口数，由托盘程序上报；dwm 的内存随窗口数增长，可以解释许多短时的上升
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub visible_windows: Option<u32>,
+}
+
+impl MonitorStatus {
+    fn entry(&mut self, name: &str) -> &mut ProcessStatus {
+        let index = match self
+            .processes
+            .iter()
+            .position(|p| process_name::eq(&p.name, name))
+        {
+            Some(index) => index,
+            None => {
+                self.processes.push(ProcessStatus {
+                    name: name.to_string(),
+                    ..Default::default()
+                });
+                self.processes.len() - 1
+            }
+        };
+        &mut self.processes[index]
+    }
+
+    // 普通用户暂缓 duration_secs 秒，为 0 时取消；拒绝时返回原因
+    fn user_snooze(&mut self, duration_secs: u64, now: u64) -> Result<Option<u64>, String> {
+        if self.snoozed_by_admin && snoozed_at(self.snoozed_until, now) {
+            return Err(
+                "Restarts were snoozed by an administrator, only an administrator can change it"
+                    .to_string(),
+            );
+        }
+        self.snoozed_by_admin = false;
+        if duration_secs == 0 {
+            self.snoozed_until = None;
+            return Ok(None);
+        }
+        let since = match self.user_snooze_since {
+            Some(since) if now < since + USER_SNOOZE_WINDOW_SECS => since,
+            _ => now,
+        };
+        self.user_snooze_since = Some(since);
+        let limit = since + MAX_USER_SNOOZE_SECS;
+        if now >= limit {
+            return Err(format!(
+                "Restarts can be snoozed for at most {} hours a day without an administrator",
+                MAX_USER_SNOOZE_SECS / 3600
+            ));
+        }
+        let until = (now + duration_secs).min(limit);
+        self.snoozed_until = Some(until);
+        Ok(Some(until))
+    }
+
+    /// 各进程的某个时间（如上次重启时间），遥测输出用来记录已经发送到哪里。
+    pub fn latest(&self, time: impl Fn(&ProcessStatus) -> Option<u64>) -> HashMap<String, u64> {
+        self.processes
+            .iter()
+            .filter_map(|p| Some((p.name.clone(), time(p)?)))
+            .collect()
+    }
+}
+
+// 某个线程持锁时 panic 不应让其他线程跟着失败
+fn status() -> MutexGuard<'static, MonitorStatus> {
+    MONITOR_STATUS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+pub fn record_metrics(name: &str, metrics: BTreeMap<String, f64>) {
+    status().entry(name).metrics = metrics;
+}
+
+pub fn record_sample(name: &str, process: Option<&ProcessInfo>, memory_threshold_bytes: u64) {
+    let mut status = status();
+    let entry = status.entry(name);
+    entry.pid = process.map(|p| p.pid);
+    entry.private_bytes = process.map_or(0, |p| p.private_bytes as u64);
+    entry.working_set = process.map_or(0, |p| p.working_set as u64);
+    entry.memory_threshold_bytes = memory_threshold_bytes;
+    entry.last_sample = Some(now_secs());
+    entry.stalled = false;
+}
+
+fn restart_events() -> MutexGuard<'static, VecDeque<RestartEvent>> {
+    RESTART_EVENTS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+fn push_restart_event(events: &mut VecDeque<RestartEvent>, event: RestartEvent) {
+    events.push_back(event);
+    while events.len() > MAX_RESTART_EVENTS {
+        events.pop_front();
+    }
+}
+
+// 放回队列头部，保持时间顺序；队列已满时丢弃这些较早的事件
+fn return_events(events: &mut VecDeque<RestartEvent>, returned: Vec<RestartEvent>) {
+    for event in returned.into_iter().rev() {
+        if events.len() >= MAX_RESTART_EVENTS {
+            break;
+        }
+        events.push_front(event);
+    }
+}
+
+// 取出上次上报之后的重启事件
+pub fn take_restart_events() -> Vec<RestartEvent> {
+    restart_events().drain(..).collect()
+}
+
+// 上报失败时放回，下次一并发送
+pub fn return_restart_events(returned: Vec<RestartEvent>) {
+    return_events(&mut restart_events(), returned);
+}
+
+/// 记录一次重启，`sessions` 为被结束的进程所在的会话及用户。
+pub fn record_restart(name: &str, sessions: &[String]) {
+    let time = now_secs();
+    {
+        let mut status = status();
+        let entry = status.entry(name);
+        entry.last_restart = Some(time);
+        entry.restart_sessions = sessions.to_vec();
+    }
+    push_restart_event(
+        &mut restart_events(),
+        RestartEvent {
+            name: name.to_string(),
+            time,
+            sessions: sessions.to_vec(),
+        },
+    );
+    if let Err(e) = save_state() {
+        warn!(
+            "{}",
+            tr!("保存状态文件失败: {}", "Failed to save state file: {}", e)
+        );
+    }
+}
+
+/// 自动重启成功后调用，`private_bytes` 为重启前的采样值。
+pub fn record_restart_started(name: &str, private_bytes: u64) {
+    status()
+        .entry(name)
+        .restart_stats
+        .restarted(private_bytes, now_secs());
+}
+
+/// 每次采样到正在运行的进程时调用，完成上次重启尚未完成的测量。
+pub fn record_restart_effect(name: &str, private_bytes: u64, breached: bool) {
+    status()
+        .entry(name)
+        .restart_stats
+        .sampled(private_bytes, breached, now_secs());
+}
+
+/// 学习期间每次采样到正在运行的进程时调用，返回更新后的学习进度。
+pub fn record_baseline(name: &str, private_bytes: u64, time: u64) -> Baseline {
+    let mut status = status();
+    let baseline = status
+        .entry(name)
+        .baseline
+        .get_or_insert_with(|| Baseline::new(time));
+    baseline.record(private_bytes);
+    baseline.clone()
+}
+
+pub fn clear_baseline(name: &str) {
+    status().entry(name).baseline = None;
+}
+
+pub fn record_worker_restart(name: &str) {
+    status().entry(name).worker_restarts += 1;
+}
+
+pub fn record_state(name: &str, state: TargetState) {
+    status().entry(name).state = state;
+}
+
+pub fn set_stalled(name: &str) {
+    status().entry(name).stalled = true;
+}
+
+pub fn set_paused(paused: bool) {
+    status().paused = paused;
+}
+
+pub fn is_paused() -> bool {
+    status().paused
+}
+
+// 管理员暂缓重启，duration_secs 为 0 时取消暂缓
+pub fn snooze(duration_secs: u64) -> Option<u64> {
+    let until = (duration_secs > 0).then(|| now_secs() + duration_secs);
+    {
+        let mut status = status();
+        status.snoozed_until = until;
+        status.snoozed_by_admin = until.is_some();
+    }
+    if let Err(e) = save_state() {
+        warn!(
+            "{}",
+            tr!("保存状态文件失败: {}", "Failed to save state file: {}", e)
+        );
+    }
+    until
+}
+
+/// 本机普通用户暂缓重启，`duration_secs` 为 0 时取消。不能缩短或取消管理员设置的暂缓，
+/// 截止时间不超过 [`MAX_USER_SNOOZE_SECS`] 的上限；拒绝时返回原因。
+pub fn user_snooze(duration_secs: u64) -> Result<Option<u64>, String> {
+    let until = status().user_snooze(duration_secs, now_secs())?;
+    if let Err(e) = save_state() {
+        warn!(
+            "{}",
+            tr!("保存状态文件失败: {}", "Failed to save state file: {}", e)
+        );
+    }
+    Ok(until)
+}
+
+fn snoozed_at(snoozed_until: Option<u64>, now: u64) -> bool {
+    snoozed_until.is_some_and(|until| now < until)
+}
+
+pub fn is_snoozed() -> bool {
+    snoozed_at(status().snoozed_until, now_secs())
+}
+
+/// 用户推迟了某个进程的重启，返回截止时间。
+pub fn postpone(name: &str, duration_secs: u64) -> u64 {
+    let until = now_secs() + duration_secs;
+    status().entry(name).postponed_until = Some(until);
+    if let Err(e) = save_state() {
+        warn!(
+            "{}",
+            tr!("保存状态文件失败: {}", "Failed to save state file: {}", e)
+        );
+    }
+    until
+}
+
+pub fn is_postponed(name: &str) -> bool {
+    let postponed_until = status()
+        .processes
+        .iter()
+        .find(|p| process_name::eq(&p.name, name))
+        .and_then(|p| p.postponed_until);
+    snoozed_at(postponed_until, now_secs())
+}
+
+pub fn wake_monitor() {
+    WAKEUP.send_replace(());
+}
+
+// 每个监控任务持有一个接收端，订阅之后的唤醒都会收到
+pub fn wakeups() -> watch::Receiver<()> {
+    WAKEUP.subscribe()
+}
+
+// 通知监控循环和其他后台任务在当前一轮结束后退出
+pub fn request_shutdown() {
+    SHUTDOWN.send_replace(true);
+    wake_monitor();
+    // 持锁通知，避免等待方检查状态之后、开始等待之前的通知丢失
+    let _guard = SHUTDOWN_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    SHUTDOWN_CONDVAR.notify_all();
+}
+
+pub fn is_shutting_down() -> bool {
+    *SHUTDOWN.borrow()
+}
+
+// 供阻塞线程使用的 sleep，收到停止请求时立即返回 false
+pub fn sleep_unless_shutdown(duration: Duration) -> bool {
+    let guard = SHUTDOWN_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    let _ = SHUTDOWN_CONDVAR
+        .wait_timeout_while(guard, duration, |_| !is_shutting_down())
+        .unwrap_or_else(PoisonError::into_inner);
+    !is_shutting_down()
+}
+
+// 在 select! 中与其他等待一起使用，停止请求到达时完成
+pub async fn shutdown_requested() {
+    let mut receiver = SHUTDOWN.subscribe();
+    let _ = receiver.wait_for(|shutdown| *shutdown).await;
+}
+
+// 等待下一轮采样，超时或被唤醒时返回，被唤醒时返回 true
+pub async fn wait_for_next_sample(wakeups: &mut watch::Receiver<()>, timeout: Duration) -> bool {
+    tokio::select! {
+        result = wakeups.changed() => result.is_ok(),
+        _ = tokio::time::sleep(timeout) => false,
+    }
+}
+
+pub fn set_os_build(os_build: Option<String>) {
+    status().os_build = os_build;
+}
+
+pub fn set_visible_windows(count: u32) {
+    status().visible_windows = Some(count);
+    VISIBLE_WINDOWS_AT.store(now_secs(), Ordering::Relaxed);
+}
+
+/// 最近上报的窗口数，托盘程序已停止上报时清除并返回 `None`。
+pub fn visible_windows() -> Option<u32> {
+    let mut status = status();
+    if !tray_report_fresh(VISIBLE_WINDOWS_AT.load(Ordering::Relaxed), now_secs()) {
+        status.visible_windows = None;
+    }
+    status.visible_windows
+}
+
+fn full_screen_sessions() -> MutexGuard<'static, HashMap<u32, u64>> {
+    FULL_SCREEN_SESSIONS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+/// 记录会话 `session` 中的托盘程序上报的全屏状态。
+pub fn set_full_screen(session: u32, full_screen: bool) {
+    let mut sessions = full_screen_sessions();
+    if full_screen {
+        sessions.insert(session, now_secs());
+    } else {
+        sessions.remove(&session);
+    }
+}
+
+/// 会话 `session` 中的托盘程序最近是否报告有全屏游戏或演示，托盘程序已停止上报时返回 `false`。
+pub fn full_screen(session: u32) -> bool {
+    full_screen_sessions()
+        .get(&session)
+        .is_some_and(|reported| tray_report_fresh(*reported, now_secs()))
+}
+
+fn tray_report_fresh(reported: u64, now: u64) -> bool {
+    now.saturating_sub(reported) <= TRAY_REPORT_MAX_AGE_SECS
+}
+
+pub fn snapshot() -> MonitorStatus {
+    status().clone()
+}
+
+pub fn state_file_path() -> PathBuf {
+    program_file_path(STATE_FILE_NAME)
+}
+
+// simulate 等临时运行不应覆盖服务的状态文件
+pub fn set_persist(persist: bool) {
+    PERSIST.store(persist, Ordering::SeqCst);
+}
+
+pub fn save_state() -> io::Result<()> {
+    if !PERSIST.load(Ordering::SeqCst) {
+        return Ok(());
+    }
+    let state = serde_json::to_string_pretty(&snapshot())?;
+    std::fs::write(state_file_path(), state)
+}
+
+// 服务重启后恢复上次重启时间等记录，暂停状态不跨进程保留
+pub fn load_state() {
+    let path = state_file_path();
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(_) => return,
+    };
+    match serde_json::from_str::<MonitorStatus>(&text) {
+        Ok(mut state) => {
+            state.paused = false;
+            state.visible_windows = None;
+            *status() = state;
+            info!(
+                "{}",
+                tr!("已从 {} 加载状态", "Loaded state from {}", path.display())
+            );
+        }
+        Err(e) => warn!(
+            "{}",
+            tr!(
+                "忽略无效的状态文件 {}: {}",
+                "Ignoring invalid state file {}: {}",
+                path.display(),
+                e
+            )
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_sample_and_restart() {
+        let process = ProcessInfo {
+            name: "state_test.exe".to_string(),
+            pid: 42,
+            thread_count: 1,
+            private_bytes: 2048,
+            pagefile_usage: 2048,
+            working_set: 4096,
+        };
+        record_sample("state_test.exe", Some(&process), 1024);
+        record_restart("STATE_TEST.EXE", &["session 2 (DOMAIN\\alice)".to_string()]);
+        record_sample("state_test.exe", None, 1024);
+
+        let status = snapshot();
+        let entries: Vec<_> = status
+            .processes
+            .iter()
+            .filter(|p| p.name == "state_test.exe")
+            .collect();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].pid, None);
+        assert_eq!(entries[0].private_bytes, 0);
+        assert_eq!(entries[0].memory_threshold_bytes, 1024);
+        assert!(entries[0].last_restart.is_some());
+        assert_eq!(
+            entries[0].restart_sessions,
+            vec!["session 2 (DOMAIN\\alice)"]
+        );
+    }
+
+    #[test]
+    fn test_snooze() {
+        // 不修改全局的暂缓状态，避免影响并行运行的其他测试
+        assert!(snoozed_at(Some(200), 100));
+        assert!(!snoozed_at(Some(100), 100));
+        assert!(!snoozed_at(None, 100));
+    }
+
+    #[test]
+    fn test_user_snooze_is_capped_per_window() {
+        let mut status = MonitorStatus::default();
+        let hour = 60 * 60;
+        assert_eq!(status.user_snooze(hour, 1000), Ok(Some(1000 + hour)));
+        // 续期不能超过第一次暂缓后的 4 小时
+        assert_eq!(
+            status.user_snooze(4 * hour, 1000 + hour),
+            Ok(Some(1000 + MAX_USER_SNOOZE_SECS))
+        );
+        assert!(status
+            .user_snooze(hour, 1000 + MAX_USER_SNOOZE_SECS)
+            .is_err());
+        assert_eq!(status.user_snooze(0, 1000 + 5 * hour), Ok(None));
+        // 窗口结束后重新起算
+        let next = 1000 + USER_SNOOZE_WINDOW_SECS;
+        assert_eq!(status.user_snooze(hour, next), Ok(Some(next + hour)));
+    }
+
+    #[test]
+    fn test_user_snooze_keeps_admin_snooze() {
+        let mut status = MonitorStatus {
+            snoozed_until: Some(10_000),
+            snoozed_by_admin: true,
+            ..Default::default()
+        };
+        assert!(status.user_snooze(60, 1000).is_err());
+        assert!(status.user_snooze(0, 1000).is_err());
+        assert_eq!(status.snoozed_until, Some(10_000));
+        // 管理员的暂缓结束后普通用户可以再暂缓
+        assert_eq!(status.user_snooze(60, 10_000), Ok(Some(10_060)));
+        assert!(!status.snoozed_by_admin);
+    }
+
+    #[test]
+    fn test_restart_stats() {
+        let mut stats = RestartStats::default();
+        stats.restarted(1000, 100);
+        stats.sampled(300, false, 160);
+        stats.sampled(900, false, 1000);
+        // 只有第一次采样计算回收量
+        assert_eq!(stats.mean_reclaimed_bytes(), Some(700));
+        assert_eq!(stats.mean_rebreach_seconds(), None);
+        stats.sampled(1200, true, 3700);
+        stats.restarted(1200, 3700);
+        stats.sampled(200, false, 3760);
+        stats.sampled(1100, true, 4700);
+        assert_eq!(stats.mean_reclaimed_bytes(), Some(850));
+        assert_eq!(stats.mean_rebreach_seconds(), Some(2300));
+        assert_eq!(stats.pending_reclaim_from, None);
+    }
+
+    #[test]
+    fn test_visible_windows_expire() {
+        assert!(tray_report_fresh(100, 160));
+        assert!(!tray_report_fresh(100, 161));
+        // 从未上报
+        assert!(!tray_report_fresh(0, now_secs()));
+    }
+
+    #[test]
+    fn test_full_screen_per_session() {
+        set_full_screen(901, true);
+        assert!(full_screen(901));
+        assert!(!full_screen(902));
+        set_full_screen(901, false);
+        assert!(!full_screen(901));
+    }
+
+    #[tokio::test]
+    async fn test_wake_monitor_interrupts_wait() {
+        let mut first = wakeups();
+        let mut second = wakeups();
+        wake_monitor();
+        let start = std::time::Instant::now();
+        assert!(wait_for_next_sample(&mut first, Duration::from_secs(5)).await);
+        assert!(wait_for_next_sample(&mut second, Duration::from_secs(5)).await);
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(!wait_for_next_sample(&mut first, Duration::from_millis(10)).await);
+    }
+
+    fn event(time: u64) -> RestartEvent {
+        RestartEvent {
+            name: "dwm.exe".to_string(),
+            time,
+            sessions: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_unreported_restart_events_are_kept() {
+        let mut events = VecDeque::new();
+        push_restart_event(&mut events, event(3));
+        return_events(&mut events, vec![event(1), event(2)]);
+        let times: Vec<u64> = events.iter().map(|e| e.time).collect();
+        assert_eq!(times, vec![1, 2, 3]);
+
+        for time in 10..10 + MAX_RESTART_EVENTS as u64 {
+            push_restart_event(&mut events, event(time));
+        }
+        assert_eq!(events.len(), MAX_RESTART_EVENTS);
+        assert_eq!(events.front().unwrap().time, 10);
+        return_events(&mut events, vec![event(1)]);
+        assert_eq!(events.front().unwrap().time, 10);
+    }
+}
@@ -0,0 +1,97 @@
+//! 系统版本公告：部分 Windows 版本的 dwm 存在已知的内存泄漏，由之后的累积更新修复。
+//! 启动时检测系统版本，命中时提示安装对应的更新；版本号附加到各遥测输出，便于在机群中按版本比较。
+
+use log::{info, warn};
+use std::fmt;
+
+use crate::process_manager;
+use crate::tr;
+
+/// 某个版本上已知的 dwm 内存泄漏，修订号低于 `fixed_revision` 的版本受影响。
+#[derive(Debug, PartialEq)]
+pub struct KnownIssue {
+    pub build: u32,
+    pub fixed_revision: u32,
+    pub release: &'static str,
+    // 修复该问题的累积更新
+    pub fixed_in: &'static str,
+}
+
+pub const KNOWN_ISSUES: &[KnownIssue] = &[
+    KnownIssue {
+        build: 22000,
+        fixed_revision: 348,
+        release: "Windows 11 21H2",
+        fixed_in: "KB5007262",
+    },
+    KnownIssue {
+        build: 22621,
+        fixed_revision: 1778,
+        release: "Windows 11 22H2",
+        fixed_in: "KB5026446",
+    },
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OsBuild {
+    // 内部版本号，如 22621
+    pub build: u32,
+    // 修订号（UBR），如 1778
+    pub revision: u32,
+}
+
+impl fmt::Display for OsBuild {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.build, self.revision)
+    }
+}
+
+impl OsBuild {
+    pub fn known_issue(&self) -> Option<&'static KnownIssue> {
+        KNOWN_ISSUES
+            .iter()
+            .find(|issue| issue.build == self.build && self.revision < issue.fixed_revision)
+    }
+}
+
+/// 启动时调用：检测并记录系统版本，存在已知泄漏时警告。
+pub fn check() -> Option<OsBuild> {
+    let Some(build) = process_manager::current_os_build() else {
+        warn!(
+            "{}",
+            tr!("无法读取系统版本号", "Failed to read the OS build number")
+        );
+        return None;
+    };
+    match build.known_issue() {
+        Some(issue) => warn!(
+            "{}",
+            tr!(
+                "当前系统版本 ({}, {}) 存在已知的 DWM 内存泄漏，已在 {} 中修复，建议安装该更新或更新的累积更新",
+                "This build ({}, {}) has a known DWM memory leak fixed in {}, install that or a later cumulative update",
+                build,
+                issue.release,
+                issue.fixed_in
+            )
+        ),
+        None => info!("{}", tr!("系统版本: {}", "OS build: {}", build)),
+    }
+    Some(build)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_issue_matches_builds_before_fix() {
+        let build = |build, revision| OsBuild { build, revision };
+        assert_eq!(
+            build(22621, 1702).known_issue().map(|issue| issue.fixed_in),
+            Some("KB5026446")
+        );
+        assert_eq!(build(22621, 1778).known_issue(), None);
+        assert_eq!(build(22631, 100).known_issue(), None);
+        assert_eq!(build(22621, 1702).to_string(), "22621.1702");
+    }
+}
@@ -0,0 +1,190 @@
+//! 插件：在不修改监控核心的情况下添加指标和超限后的动作。
+//!
+//! 插件在编译时注册：在程序启动、构建监控器之前调用 [`register_metric`] 和 [`register_action`]
+//! （服务程序在 `main.rs` 的 `register_plugins` 中注册）。指标在每次采样到正在运行的进程时读取，
+//! 写入日志并显示在 `status` 中；动作在配置的进程项中按名称引用，在重启之后执行：
+//!
+//! ```json
+//! "actions": [{ "name": "command", "options": { "command": "notify.cmd" } }]
+//! ```
+
+use lazy_static::lazy_static;
+use log::{info, warn};
+use std::collections::{BTreeMap, HashMap};
+use std::process::Command;
+use std::sync::{Arc, PoisonError, RwLock};
+
+use crate::config_manager::ActionConfig;
+use crate::monitor::{Action, Sample};
+use crate::tr;
+
+/// 按进程读取的附加指标，如显卡厂商接口提供的显存用量。
+pub trait Metric: Send + Sync {
+    /// 指标名称，用作 `status` 中的键。
+    fn name(&self) -> &str;
+
+    /// 读取指定进程的指标，无法读取时返回 `None`。
+    fn read(&self, sample: &Sample) -> Option<f64>;
+}
+
+/// 按配置项中的 `options` 创建动作，选项无效时返回错误信息。
+pub type ActionFactory = fn(options: &serde_json::Value) -> Result<Box<dyn Action>, String>;
+
+lazy_static! {
+    static ref METRICS: RwLock<Vec<Arc<dyn Metric>>> = RwLock::new(Vec::new());
+    static ref ACTIONS: RwLock<HashMap<String, ActionFactory>> = RwLock::new(HashMap::new());
+}
+
+pub fn register_metric<M: Metric + 'static>(metric: M) {
+    METRICS
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push(Arc::new(metric));
+}
+
+/// 注册名为 `name` 的动作，同名时替换之前的注册。
+pub fn register_action(name: &str, factory: ActionFactory) {
+    ACTIONS
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(name.to_string(), factory);
+}
+
+/// 读取所有已注册的指标，跳过无法读取的指标。
+pub fn read_metrics(sample: &Sample) -> BTreeMap<String, f64> {
+    let metrics = METRICS.read().unwrap_or_else(PoisonError::into_inner);
+    metrics
+        .iter()
+        .filter_map(|metric| Some((metric.name().to_string(), metric.read(sample)?)))
+        .collect()
+}
+
+pub fn create_action(config: &ActionConfig) -> Result<Box<dyn Action>, String> {
+    let factory = ACTIONS
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&config.name)
+        .copied()
+        .ok_or_else(|| {
+            tr!(
+                "未注册的动作 {}",
+                "Action {} is not registered",
+                config.name
+            )
+        })?;
+    factory(&config.options)
+}
+
+/// 内置动作 `command`：通过 `cmd /C` 执行 `options.command`，进程信息通过环境变量
+/// `DWM_PROCESS`、`DWM_PID`、`DWM_PRIVATE_BYTES`、`DWM_THRESHOLD_BYTES` 传入，退出码为 0 视为成功。
+pub fn command_action(options: &serde_json::Value) -> Result<Box<dyn Action>, String> {
+    match options.get("command").and_then(|c| c.as_str()) {
+        Some(command) if !command.is_empty() => Ok(Box::new(CommandAction {
+            command: command.to_string(),
+        })),
+        _ => Err(tr!("缺少 options.command", "options.command is missing")),
+    }
+}
+
+struct CommandAction {
+    command: String,
+}
+
+impl Action for CommandAction {
+    fn run(&self, sample: &Sample) -> bool {
+        let result = Command::new("cmd")
+            .arg("/C")
+            .arg(&self.command)
+            .env("DWM_PROCESS", &sample.name)
+            .env("DWM_PID", sample.pid.unwrap_or(0).to_string())
+            .env("DWM_PRIVATE_BYTES", sample.private_bytes.to_string())
+            .env("DWM_THRESHOLD_BYTES", sample.threshold_bytes.to_string())
+            .status();
+        match result {
+            Ok(status) if status.success() => {
+                info!(
+                    "{}",
+                    tr!(
+                        "成功执行动作命令: {}",
+                        "Action command succeeded: {}",
+                        self.command
+                    )
+                );
+                true
+            }
+            Ok(status) => {
+                warn!(
+                    "{}",
+                    tr!(
+                        "动作命令 {} 执行失败: {}",
+                        "Action command {} failed: {}",
+                        self.command,
+                        status
+                    )
+                );
+                false
+            }
+            Err(e) => {
+                warn!(
+                    "{}",
+                    tr!(
+                        "无法执行动作命令 {}: {}",
+                        "Failed to run action command {}: {}",
+                        self.command,
+                        e
+                    )
+                );
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    struct DoubledMemory;
+
+    impl Metric for DoubledMemory {
+        fn name(&self) -> &str {
+            "plugin_test_doubled"
+        }
+
+        fn read(&self, sample: &Sample) -> Option<f64> {
+            sample.pid.map(|_| sample.private_bytes as f64 * 2.0)
+        }
+    }
+
+    #[test]
+    fn test_registered_plugins_are_used() {
+        register_metric(DoubledMemory);
+        let sample = Sample {
+            name: "plugin_test.exe".to_string(),
+            pid: Some(7),
+            private_bytes: 100,
+            working_set: 100,
+            threshold_bytes: 1000,
+            instances: vec![(7, 100)],
+            instance_thresholds: HashMap::new(),
+        };
+        assert_eq!(
+            read_metrics(&sample).get("plugin_test_doubled"),
+            Some(&200.0)
+        );
+
+        register_action("plugin_test_command", command_action);
+        let config = |options| ActionConfig {
+            name: "plugin_test_command".to_string(),
+            options,
+        };
+        assert!(create_action(&config(json!({ "command": "echo" }))).is_ok());
+        assert!(create_action(&config(json!({}))).is_err());
+        assert!(create_action(&ActionConfig {
+            name: "plugin_test_missing".to_string(),
+            options: json!(null),
+        })
+        .is_err());
+    }
+}
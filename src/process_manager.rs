@@ -1,49 +1,128 @@
+use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::Mutex;
 use std::{
-    ffi::OsStr, io, os::windows::ffi::OsStrExt, process::Command, ptr::null_mut, thread,
-    time::Duration,
+    ffi::OsStr, io, os::windows::ffi::OsStrExt, os::windows::process::CommandExt, process::Command,
+    ptr::null_mut, time::Duration,
 };
-use winapi::{
-    shared::minwindef::{DWORD, HMODULE},
-    um::{
-        handleapi::CloseHandle,
-        processthreadsapi::{CreateProcessAsUserW, OpenProcess, PROCESS_INFORMATION, STARTUPINFOW},
-        psapi::{
-            EnumProcessModules, EnumProcesses, GetModuleBaseNameW, GetProcessMemoryInfo,
-            PROCESS_MEMORY_COUNTERS,
+use windows::{
+    core::{w, Owned, PCWSTR, PWSTR},
+    Win32::{
+        Foundation::{
+            GetLastError, ERROR_NOT_ALL_ASSIGNED, ERROR_SUCCESS, HANDLE, LUID, WAIT_OBJECT_0,
+            WAIT_TIMEOUT, WIN32_ERROR,
+        },
+        Security::{
+            AdjustTokenPrivileges, DuplicateTokenEx, GetTokenInformation, LookupPrivilegeNameW,
+            LookupPrivilegeValueW, SecurityIdentification, TokenPrimary, TokenPrivileges,
+            LUID_AND_ATTRIBUTES, SE_PRIVILEGE_ENABLED, SE_PRIVILEGE_REMOVED, TOKEN_ACCESS_MASK,
+            TOKEN_ADJUST_PRIVILEGES, TOKEN_PRIVILEGES, TOKEN_PRIVILEGES_ATTRIBUTES, TOKEN_QUERY,
+        },
+        System::{
+            Diagnostics::ToolHelp::{
+                CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W,
+                TH32CS_SNAPPROCESS,
+            },
+            Environment::{CreateEnvironmentBlock, DestroyEnvironmentBlock},
+            Performance::{
+                PdhAddEnglishCounterW, PdhCollectQueryData, PdhGetFormattedCounterArrayW,
+                PdhOpenQueryW, PDH_FMT_COUNTERVALUE_ITEM_W, PDH_FMT_LARGE, PDH_HCOUNTER,
+                PDH_HQUERY, PDH_MORE_DATA,
+            },
+            Power::{GetSystemPowerStatus, SYSTEM_POWER_STATUS},
+            ProcessStatus::{
+                EmptyWorkingSet, EnumProcesses, GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS,
+                PROCESS_MEMORY_COUNTERS_EX,
+            },
+            Registry::{
+                RegCloseKey, RegGetValueW, RegOpenKeyExW, HKEY, HKEY_LOCAL_MACHINE, KEY_READ,
+                RRF_RT_REG_DWORD, RRF_RT_REG_SZ,
+            },
+            RemoteDesktop::{
+                ProcessIdToSessionId, WTSQueryUserToken, WTSSendMessageW, WTS_CURRENT_SERVER_HANDLE,
+            },
+            SystemServices::MAXIMUM_ALLOWED,
+            Threading::{
+                CreateProcessAsUserW, GetCurrentProcess, GetProcessHandleCount, OpenProcess,
+                OpenProcessToken, QueryFullProcessImageNameW, SetPriorityClass,
+                WaitForSingleObject, BELOW_NORMAL_PRIORITY_CLASS, CREATE_UNICODE_ENVIRONMENT,
+                NORMAL_PRIORITY_CLASS, PROCESS_ACCESS_RIGHTS, PROCESS_INFORMATION,
+                PROCESS_MODE_BACKGROUND_BEGIN, PROCESS_NAME_WIN32,
+                PROCESS_QUERY_LIMITED_INFORMATION, PROCESS_SYNCHRONIZE, STARTUPINFOW,
+            },
+        },
+        UI::WindowsAndMessaging::{
+            IDNO, MB_ICONQUESTION, MB_ICONWARNING, MB_OK, MB_SETFOREGROUND, MB_TOPMOST, MB_YESNO,
+            MESSAGEBOX_RESULT, MESSAGEBOX_STYLE,
         },
-        securitybaseapi::DuplicateTokenEx,
-        tlhelp32::*,
-        userenv::CreateEnvironmentBlock,
-        winbase::CREATE_UNICODE_ENVIRONMENT,
-        winnt::*,
-        wtsapi32::*,
     },
 };
 
 use crate::config_manager::Config;
-use crate::db_manager::DB_CONNECTION;
-use crate::system_info_printer::print_memory_status;
+use crate::monitor::ProcessMonitor;
+use crate::monitor_state;
+use crate::os_build::OsBuild;
+use crate::process_name;
+use crate::self_usage::Usage;
+use crate::system_api::{SystemApi, WindowsApi};
+use crate::tr;
 use log::{error, info, warn};
-use winapi::um::handleapi::INVALID_HANDLE_VALUE;
+use wmi::{COMLibrary, Variant, WMIConnection};
+
+lazy_static! {
+    // 监控循环与 IPC 触发的重启互斥执行
+    pub static ref RESTART_LOCK: Mutex<()> = Mutex::new(());
+}
+
 #[derive(Clone)]
 pub struct ProcessInfo {
     pub name: String,
-    pub pid: DWORD,
+    pub pid: u32,
     pub thread_count: i32,
+    // PrivateUsage，即任务管理器的“提交大小”列，内存阈值与它比较
     pub private_bytes: usize,
+    // PagefileUsage，旧版本用作 Private Bytes，保留以便对照
+    pub pagefile_usage: usize,
     pub working_set: usize,
 }
 
 impl ProcessInfo {
-    fn print_process_memory_info(&self) {
-        info!("Working Set Size: {} MB", self.working_set / 1024 / 1024);
-        info!("Private Bytes: {} MB", self.private_bytes / 1024 / 1024);
+    pub fn print_process_memory_info(&self) {
+        info!(
+            "{}",
+            tr!(
+                "工作集: {} MB",
+                "Working Set Size: {} MB",
+                self.working_set / 1024 / 1024
+            )
+        );
+        info!(
+            "{}",
+            tr!(
+                "专用字节 (PrivateUsage，阈值计数器): {} MB",
+                "Private Bytes (PrivateUsage, threshold counter): {} MB",
+                self.private_bytes / 1024 / 1024
+            )
+        );
+        info!(
+            "{}",
+            tr!(
+                "页面文件使用量: {} MB",
+                "Pagefile Usage: {} MB",
+                self.pagefile_usage / 1024 / 1024
+            )
+        );
+    }
+
+    fn set_memory(&mut self, counters: &PROCESS_MEMORY_COUNTERS_EX) {
+        self.private_bytes = counters.PrivateUsage;
+        self.pagefile_usage = counters.PagefileUsage;
+        self.working_set = counters.WorkingSetSize;
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum ProcessType {
     System,
     Service(String),
@@ -56,8 +135,10 @@ impl Default for ProcessType {
 }
 impl ProcessType {
     fn execute_cmd(cmd: &str) -> Result<String, io::Error> {
+        // 服务自身以低优先级运行，启动的命令和进程恢复正常优先级
         let output = Command::new("powershell")
             .args(&["-Command", cmd])
+            .creation_flags(NORMAL_PRIORITY_CLASS.0)
             .output()?;
         if output.status.success() {
             Ok(String::from_utf8_lossy(&output.stdout).to_string())
@@ -77,6 +158,14 @@ impl ProcessType {
         ProcessType::execute_cmd(&terminate_cmd)
     }
 
+    // 只结束指定的实例
+    pub fn kill_pids(&self, pids: &[u32]) -> Result<String, io::Error> {
+        let terminate_cmd = pids.iter().fold("taskkill /F".to_string(), |cmd, pid| {
+            format!("{} /PID {}", cmd, pid)
+        });
+        ProcessType::execute_cmd(&terminate_cmd)
+    }
+
     pub fn execute(&self) -> Result<String, io::Error> {
         match self {
             ProcessType::System => Ok("".to_string()),
@@ -92,332 +181,909 @@ impl ProcessType {
         let full_command = format!("powershell -Command \"{}\"", powershell_cmd);
         unsafe {
             println!("Launching process as user: {}", full_command);
-            let mut h_token = null_mut();
-            if WTSQueryUserToken(session_id, &mut h_token) == 0 {
-                return Err(io::Error::last_os_error());
-            }
+            let mut h_token = HANDLE::default();
+            WTSQueryUserToken(session_id, &mut h_token)?;
+            let h_token = Owned::new(h_token);
             println!("WTSQueryUserToken success");
-            let mut duplicate_token = null_mut();
-            if DuplicateTokenEx(
-                h_token,
-                MAXIMUM_ALLOWED,
-                null_mut(),
+            let mut duplicate_token = HANDLE::default();
+            DuplicateTokenEx(
+                *h_token,
+                TOKEN_ACCESS_MASK(MAXIMUM_ALLOWED),
+                None,
                 SecurityIdentification,
                 TokenPrimary,
                 &mut duplicate_token,
-            ) == 0
-            {
-                CloseHandle(h_token);
-                return Err(io::Error::last_os_error());
-            }
+            )?;
+            let duplicate_token = Owned::new(duplicate_token);
             println!("DuplicateTokenEx success");
-            let mut env_block = std::ptr::null_mut();
-            if CreateEnvironmentBlock(&mut env_block, duplicate_token, 0) == 0 {
-                CloseHandle(h_token);
-                CloseHandle(duplicate_token);
-                return Err(io::Error::last_os_error());
-            }
+            let mut env_block = null_mut();
+            CreateEnvironmentBlock(&mut env_block, Some(*duplicate_token), false)?;
             println!("CreateEnvironmentBlock success");
-            let mut startup_info = STARTUPINFOW {
+            let mut desktop = to_wide_string("winsta0\\default");
+            let startup_info = STARTUPINFOW {
                 cb: std::mem::size_of::<STARTUPINFOW>() as u32,
-                lpDesktop: to_wide_string("winsta0\\default").as_ptr() as *mut u16,
-                lpReserved: null_mut(),
-                lpTitle: null_mut(),
-                dwX: 0,
-                dwY: 0,
-                dwXSize: 0,
-                dwYSize: 0,
-                dwXCountChars: 0,
-                dwYCountChars: 0,
-                dwFillAttribute: 0,
-                dwFlags: 0,
-                wShowWindow: 0,
-                cbReserved2: 0,
-                lpReserved2: null_mut(),
-                hStdInput: null_mut(),
-                hStdOutput: null_mut(),
-                hStdError: null_mut(),
+                lpDesktop: PWSTR(desktop.as_mut_ptr()),
+                ..Default::default()
             };
-            let mut process_info = PROCESS_INFORMATION {
-                hProcess: null_mut(),
-                hThread: null_mut(),
-                dwProcessId: 0,
-                dwThreadId: 0,
-            };
-            let command_line = to_wide_string(&full_command);
-            let success = CreateProcessAsUserW(
-                duplicate_token,
-                null_mut(),
-                command_line.as_ptr() as *mut u16,
-                null_mut(),
-                null_mut(),
-                0,
-                CREATE_UNICODE_ENVIRONMENT,
-                env_block,
-                null_mut(),
-                &mut startup_info,
+            let mut process_info = PROCESS_INFORMATION::default();
+            let mut command_line = to_wide_string(&full_command);
+            let result = CreateProcessAsUserW(
+                Some(*duplicate_token),
+                PCWSTR::null(),
+                Some(PWSTR(command_line.as_mut_ptr())),
+                None,
+                None,
+                false,
+                CREATE_UNICODE_ENVIRONMENT | NORMAL_PRIORITY_CLASS,
+                Some(env_block),
+                PCWSTR::null(),
+                &startup_info,
                 &mut process_info,
             );
-            CloseHandle(h_token);
-            CloseHandle(duplicate_token);
-            if success == 0 {
-                return Err(io::Error::last_os_error());
-            }
-            CloseHandle(process_info.hProcess);
-            CloseHandle(process_info.hThread);
+            let _ = DestroyEnvironmentBlock(env_block);
+            result?;
+            drop(Owned::new(process_info.hProcess));
+            drop(Owned::new(process_info.hThread));
         }
         Ok(())
     }
 }
 
-fn to_wide_string(s: &str) -> Vec<u16> {
+pub fn to_wide_string(s: &str) -> Vec<u16> {
     OsStr::new(s).encode_wide().chain(Some(0)).collect()
 }
 
+/// 同名进程的所有实例，如多个会话各自的 dwm 或同一程序的多个实例。
+pub fn process_instances<'a>(name: &str, processes: &'a [ProcessInfo]) -> Vec<&'a ProcessInfo> {
+    processes
+        .iter()
+        .filter(|process| process_name::eq(&process.name, name))
+        .collect()
+}
+
+// 有多个实例时返回内存最多的一个
 pub fn is_process_running(name: &str, processes: &[ProcessInfo]) -> Option<ProcessInfo> {
-    for process in processes {
-        if process.name.eq_ignore_ascii_case(name) {
-            return Some(process.clone());
-        }
-    }
-    None
+    process_instances(name, processes)
+        .into_iter()
+        .max_by_key(|process| process.private_bytes)
+        .cloned()
 }
 
-pub fn restart_processing(name: &str, process_type: &ProcessType) {
-    info!("正在重启 {} 进程...", name);
-    let result = process_type.kill_process(name);
-    match result {
-        Err(e) => {
-            error!("执行 taskkill 命令失败: {:?}", e);
-            return;
-        }
-        Ok(output) => info!("成功执行 taskkill 命令: {:?}", output),
+pub fn restart_processing(name: &str, process_type: &ProcessType) -> bool {
+    restart_with(&WindowsApi::default(), name, &[], process_type)
+}
+
+// 多用户主机上每个会话各有一个 dwm，记录受影响的会话和用户
+fn process_sessions(api: &dyn SystemApi, pids: &[u32]) -> Vec<String> {
+    pids.iter().filter_map(|pid| api.session(*pid)).collect()
+}
+
+// 重启前就存在的实例都不算重新拉起的进程，只重启部分实例时其余实例仍在运行
+fn process_returned(name: &str, existing: &[u32], processes: &[ProcessInfo]) -> bool {
+    process_instances(name, processes)
+        .iter()
+        .any(|process| !existing.contains(&process.pid))
+}
+
+/// 结束进程、按类型重新拉起，并等待进程重新出现。`pids` 为空时结束所有同名进程（`taskkill /IM`），
+/// 否则只结束这些实例，同名的其他实例不受影响。
+pub fn restart_with(
+    api: &dyn SystemApi,
+    name: &str,
+    pids: &[u32],
+    process_type: &ProcessType,
+) -> bool {
+    if process_name::is_protected(name) {
+        error!(
+            "{}",
+            tr!(
+                "{} 是受保护的系统进程，拒绝重启",
+                "{} is a protected system process, refusing to restart it",
+                name
+            )
+        );
+        return false;
+    }
+    let _guard = RESTART_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let existing: Vec<u32> = process_instances(name, &api.processes().unwrap_or_default())
+        .iter()
+        .map(|process| process.pid)
+        .collect();
+    let ending: Vec<u32> = if pids.is_empty() {
+        existing.clone()
+    } else {
+        pids.to_vec()
+    };
+    let sessions = process_sessions(api, &ending);
+    let mut owners = if sessions.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", sessions.join(", "))
+    };
+    if !pids.is_empty() && existing.len() > pids.len() {
+        let pids: Vec<String> = pids.iter().map(u32::to_string).collect();
+        owners = format!(" PID {}{}", pids.join(", "), owners);
+    }
+    info!(
+        "{}",
+        tr!(
+            "正在重启 {} 进程{}...",
+            "Restarting {} process{}...",
+            name,
+            owners
+        )
+    );
+    let terminated = if pids.is_empty() {
+        api.terminate(name)
+    } else {
+        api.terminate_pids(pids)
+    };
+    if let Err(e) = terminated {
+        error!(
+            "{}",
+            tr!("执行 taskkill 命令失败: {:?}", "taskkill failed: {:?}", e)
+        );
+        return false;
+    }
+    // taskkill 返回时进程可能还没退出，等它真正退出后再拉起
+    if !api.wait_for_exit(&ending, Duration::from_secs(10)) {
+        warn!(
+            "{}",
+            tr!(
+                "{} 进程结束后 10 秒内仍未退出",
+                "{} process did not exit within 10 seconds of being ended",
+                name
+            )
+        );
     }
 
     let result = process_type.execute();
     match result {
         Ok(output) => {
-            info!("成功执行命令:{:?}", output)
+            info!(
+                "{}",
+                tr!("成功执行命令: {:?}", "Command executed: {:?}", output)
+            )
         }
         Err(error) => {
-            error!("执行命令失败：{:?}", error)
+            error!(
+                "{}",
+                tr!("执行命令失败: {:?}", "Command failed: {:?}", error)
+            )
         }
     }
-    thread::sleep(Duration::from_secs(10));
-    let process_infos = match get_all_processes() {
+    monitor_state::record_restart(name, &sessions);
+    api.sleep(Duration::from_secs(10));
+    if monitor_state::is_shutting_down() {
+        // 结束和拉起都已完成，停止时不再等待确认
+        warn!(
+            "{}",
+            tr!(
+                "服务正在停止，不再等待确认 {} 已重启",
+                "Service is stopping, no longer waiting for {} to restart",
+                name
+            )
+        );
+        return false;
+    }
+    let process_infos = match api.processes() {
         Some(infos) => infos,
         None => {
-            error!("Failed to retrieve process information");
-            return;
+            error!(
+                "{}",
+                tr!("获取进程信息失败", "Failed to retrieve process information")
+            );
+            return false;
         }
     };
-    if is_process_running(name, process_infos.as_slice()).is_some() {
-        info!("{} 进程已成功重启", name);
+    if process_returned(name, &existing, &process_infos) {
+        info!(
+            "{}",
+            tr!(
+                "{} 进程已成功重启{}",
+                "{} process restarted successfully{}",
+                name,
+                owners
+            )
+        );
+        true
     } else {
-        warn!("{} 进程未自动重启，等待系统处理...", name);
+        warn!(
+            "{}",
+            tr!(
+                "{} 进程未自动重启，等待系统处理...",
+                "{} process did not restart automatically, waiting for the system...",
+                name
+            )
+        );
         let mut loop_count = 0;
         loop {
-            thread::sleep(Duration::from_secs(1));
+            api.sleep(Duration::from_secs(1));
+            if monitor_state::is_shutting_down() {
+                warn!(
+                    "{}",
+                    tr!(
+                        "服务正在停止，不再等待确认 {} 已重启",
+                        "Service is stopping, no longer waiting for {} to restart",
+                        name
+                    )
+                );
+                return false;
+            }
 
-            let process_infos = match get_all_processes() {
+            let process_infos = match api.processes() {
                 Some(infos) => infos,
                 None => {
-                    error!("Failed to retrieve process information");
-                    return;
+                    error!(
+                        "{}",
+                        tr!("获取进程信息失败", "Failed to retrieve process information")
+                    );
+                    return false;
                 }
             };
-            if is_process_running(name, process_infos.as_slice()).is_some() {
-                info!("{} 进程已成功启动", name);
-                break;
+            if process_returned(name, &existing, &process_infos) {
+                info!(
+                    "{}",
+                    tr!(
+                        "{} 进程已成功启动{}",
+                        "{} process started successfully{}",
+                        name,
+                        owners
+                    )
+                );
+                return true;
             }
             loop_count += 1;
             if loop_count > 30 {
-                warn!("{} 进程未自动重启，等待系统处理...", name);
-                break;
+                warn!(
+                    "{}",
+                    tr!(
+                        "{} 进程未自动重启，等待系统处理...",
+                        "{} process did not restart automatically, waiting for the system...",
+                        name
+                    )
+                );
+                return false;
             }
         }
     }
 }
-fn get_pid_thread_count_map() -> HashMap<DWORD, i32> {
+// GetProcessMemoryInfo 只需要受限查询权限，不需要 PROCESS_VM_READ；
+// 受进程缓解策略保护、拒绝完整查询权限的进程（如 dwm）仍可读取内存计数
+const MEMORY_QUERY_ACCESS: PROCESS_ACCESS_RIGHTS = PROCESS_QUERY_LIMITED_INFORMATION;
+
+fn handle_memory(process_handle: HANDLE) -> Option<PROCESS_MEMORY_COUNTERS_EX> {
+    let mut counters = PROCESS_MEMORY_COUNTERS_EX::default();
+    unsafe {
+        // 传入 EX 结构体的大小即可获得 PrivateUsage
+        GetProcessMemoryInfo(
+            process_handle,
+            &mut counters as *mut _ as *mut PROCESS_MEMORY_COUNTERS,
+            std::mem::size_of::<PROCESS_MEMORY_COUNTERS_EX>() as u32,
+        )
+        .ok()?;
+    }
+    Some(counters)
+}
+
+// 进程无法打开（权限不足、受保护进程）时返回 None，但进程本身仍会出现在列表中
+fn process_memory(pid: u32) -> Option<PROCESS_MEMORY_COUNTERS_EX> {
+    let process_handle = unsafe { Owned::new(OpenProcess(MEMORY_QUERY_ACCESS, false, pid).ok()?) };
+    handle_memory(*process_handle)
+}
+
+pub fn own_usage() -> Option<Usage> {
+    // 伪句柄，无需关闭
+    let process_handle = unsafe { GetCurrentProcess() };
+    let counters = handle_memory(process_handle)?;
+    let mut handle_count = 0;
+    unsafe { GetProcessHandleCount(process_handle, &mut handle_count) }.ok()?;
+    Some(Usage {
+        private_bytes: counters.PrivateUsage as u64,
+        working_set: counters.WorkingSetSize as u64,
+        handle_count,
+    })
+}
+
+// 在进程所在的会话中弹出提示框，timeout 后自动关闭，不等待用户响应；会话 0 没有桌面，直接跳过
+pub fn send_session_message(
+    pid: u32,
+    title: &str,
+    message: &str,
+    timeout: Duration,
+) -> io::Result<()> {
+    let style = MB_OK | MB_ICONWARNING | MB_SETFOREGROUND | MB_TOPMOST;
+    session_message(pid, title, message, style, timeout, false).map(|_| ())
+}
+
+/// 在进程所在的会话中询问是否继续，等待用户选择或 `timeout` 到期；只有用户选择“否”时返回 `false`。
+pub fn ask_in_session(pid: u32, title: &str, message: &str, timeout: Duration) -> io::Result<bool> {
+    let style = MB_YESNO | MB_ICONQUESTION | MB_SETFOREGROUND | MB_TOPMOST;
+    let response = session_message(pid, title, message, style, timeout, true)?;
+    Ok(response != IDNO)
+}
+
+// 会话 0 中没有用户，不显示并返回默认结果
+fn session_message(
+    pid: u32,
+    title: &str,
+    message: &str,
+    style: MESSAGEBOX_STYLE,
+    timeout: Duration,
+    wait: bool,
+) -> io::Result<MESSAGEBOX_RESULT> {
+    let mut session_id = 0;
+    unsafe { ProcessIdToSessionId(pid, &mut session_id) }.map_err(win32_error)?;
+    if session_id == 0 {
+        return Ok(MESSAGEBOX_RESULT::default());
+    }
+    let title = to_wide_string(title);
+    let message = to_wide_string(message);
+    let mut response = MESSAGEBOX_RESULT::default();
+    // 长度以字节计，不含结尾的 0
     unsafe {
-        let mut result = HashMap::new();
-        // Create a snapshot of the processes to get thread count
-        let snapshot: HANDLE = CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0);
-        if snapshot == INVALID_HANDLE_VALUE {
-            eprintln!("Failed to create snapshot of threads");
-            return result;
+        WTSSendMessageW(
+            Some(WTS_CURRENT_SERVER_HANDLE),
+            session_id,
+            PCWSTR(title.as_ptr()),
+            ((title.len() - 1) * 2) as u32,
+            PCWSTR(message.as_ptr()),
+            ((message.len() - 1) * 2) as u32,
+            style,
+            timeout.as_secs() as u32,
+            &mut response,
+            wait,
+        )
+    }
+    .map_err(win32_error)?;
+    Ok(response)
+}
+
+/// 系统是否在等待重启以完成更新：组件更新（CBS）或 Windows 更新安装后会创建对应的注册表项，重启后删除。
+pub fn reboot_pending() -> bool {
+    [
+        w!(r"SOFTWARE\Microsoft\Windows\CurrentVersion\Component Based Servicing\RebootPending"),
+        w!(r"SOFTWARE\Microsoft\Windows\CurrentVersion\WindowsUpdate\Auto Update\RebootRequired"),
+    ]
+    .into_iter()
+    .any(|key| {
+        let mut handle = HKEY::default();
+        let result = unsafe { RegOpenKeyExW(HKEY_LOCAL_MACHINE, key, None, KEY_READ, &mut handle) };
+        if result != ERROR_SUCCESS {
+            return false;
         }
+        unsafe {
+            let _ = RegCloseKey(handle);
+        }
+        true
+    })
+}
 
-        let mut thread_entry = THREADENTRY32 {
-            dwSize: std::mem::size_of::<THREADENTRY32>() as DWORD,
-            cntUsage: 0,
-            th32ThreadID: 0,
-            th32OwnerProcessID: 0,
-            tpBasePri: 0,
-            tpDeltaPri: 0,
-            dwFlags: 0,
-        };
+/// 是否正在使用电池供电；台式机、电源状态未知或读取失败时返回 `false`。
+pub fn on_battery() -> bool {
+    let mut status = SYSTEM_POWER_STATUS::default();
+    // ACLineStatus: 0 为电池，1 为交流电源，255 为未知
+    unsafe { GetSystemPowerStatus(&mut status) }.is_ok() && status.ACLineStatus == 0
+}
 
-        if Thread32First(snapshot, &mut thread_entry) != 0 {
-            loop {
-                *result.entry(thread_entry.th32OwnerProcessID).or_insert(0) += 1;
-                if Thread32Next(snapshot, &mut thread_entry) == 0 {
-                    break;
-                }
-            }
+const CURRENT_VERSION_KEY: PCWSTR = w!(r"SOFTWARE\Microsoft\Windows NT\CurrentVersion");
+
+/// 系统的内部版本号和修订号（UBR，随每月的累积更新增加），读取失败时返回 `None`。
+pub fn current_os_build() -> Option<OsBuild> {
+    let mut buffer = [0u16; 32];
+    let mut size = std::mem::size_of_val(&buffer) as u32;
+    let result = unsafe {
+        RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            CURRENT_VERSION_KEY,
+            w!("CurrentBuildNumber"),
+            RRF_RT_REG_SZ,
+            None,
+            Some(buffer.as_mut_ptr().cast()),
+            Some(&mut size),
+        )
+    };
+    if result != ERROR_SUCCESS {
+        return None;
+    }
+    let len = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+    let build = String::from_utf16_lossy(&buffer[..len])
+        .trim()
+        .parse()
+        .ok()?;
+
+    // 早期的 Windows 10 没有 UBR
+    let mut revision = 0u32;
+    let mut size = std::mem::size_of::<u32>() as u32;
+    let result = unsafe {
+        RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            CURRENT_VERSION_KEY,
+            w!("UBR"),
+            RRF_RT_REG_DWORD,
+            None,
+            Some((&mut revision as *mut u32).cast()),
+            Some(&mut size),
+        )
+    };
+    if result != ERROR_SUCCESS {
+        revision = 0;
+    }
+    Some(OsBuild { build, revision })
+}
+
+// 以低于正常的优先级和后台模式（同时降低 I/O 和内存优先级）运行，不与被保护的程序争抢资源
+pub fn enter_background_mode() -> io::Result<()> {
+    unsafe {
+        let process = GetCurrentProcess();
+        SetPriorityClass(process, BELOW_NORMAL_PRIORITY_CLASS).map_err(win32_error)?;
+        SetPriorityClass(process, PROCESS_MODE_BACKGROUND_BEGIN).map_err(win32_error)
+    }
+}
+
+// 把自身工作集换出到页面文件，空闲时的常驻内存更小
+pub fn trim_own_working_set() -> io::Result<()> {
+    unsafe { EmptyWorkingSet(GetCurrentProcess()) }.map_err(win32_error)
+}
+
+// 服务令牌中的 SeDebugPrivilege 默认处于禁用状态，启用后 OpenProcess 才能打开更多系统进程
+pub fn enable_debug_privilege() -> io::Result<()> {
+    let name = to_wide_string("SeDebugPrivilege");
+    let token = open_own_token()?;
+    let mut luid = LUID::default();
+    unsafe { LookupPrivilegeValueW(PCWSTR::null(), PCWSTR(name.as_ptr()), &mut luid) }
+        .map_err(win32_error)?;
+    adjust_privilege(&token, luid, SE_PRIVILEGE_ENABLED)
+}
+
+// 初始化完成后从令牌中移除 keep 以外的特权（移除后无法再启用），返回被移除的特权名称
+pub fn remove_privileges_except(keep: &[&str]) -> io::Result<Vec<String>> {
+    let token = open_own_token()?;
+    let mut size = 0;
+    // 第一次调用只获取缓冲区大小
+    let _ = unsafe { GetTokenInformation(*token, TokenPrivileges, None, 0, &mut size) };
+    // 按 u64 分配以满足 TOKEN_PRIVILEGES 的对齐要求
+    let mut buffer = vec![0u64; (size as usize).div_ceil(8)];
+    unsafe {
+        GetTokenInformation(
+            *token,
+            TokenPrivileges,
+            Some(buffer.as_mut_ptr() as _),
+            size,
+            &mut size,
+        )
+    }
+    .map_err(win32_error)?;
+    let privileges = buffer.as_ptr() as *const TOKEN_PRIVILEGES;
+    let entries = unsafe {
+        std::slice::from_raw_parts(
+            std::ptr::addr_of!((*privileges).Privileges) as *const LUID_AND_ATTRIBUTES,
+            (*privileges).PrivilegeCount as usize,
+        )
+    };
+    let mut removed = Vec::new();
+    for entry in entries {
+        let name = privilege_name(&entry.Luid)?;
+        if keep.iter().any(|keep| keep.eq_ignore_ascii_case(&name)) {
+            continue;
         }
-        CloseHandle(snapshot);
-        return result;
+        adjust_privilege(&token, entry.Luid, SE_PRIVILEGE_REMOVED)?;
+        removed.push(name);
     }
+    Ok(removed)
 }
-pub fn get_all_processes() -> Option<Vec<ProcessInfo>> {
-    let mut process_ids: [DWORD; 2048] = [0; 2048];
-    let mut bytes_returned: DWORD = 0;
 
+fn open_own_token() -> io::Result<Owned<HANDLE>> {
+    let mut token = HANDLE::default();
     unsafe {
-        if EnumProcesses(
-            process_ids.as_mut_ptr(),
-            std::mem::size_of_val(&process_ids) as DWORD,
-            &mut bytes_returned,
-        ) == 0
-        {
-            error!("Failed to enumerate processes");
-            return None;
+        OpenProcessToken(
+            GetCurrentProcess(),
+            TOKEN_ADJUST_PRIVILEGES | TOKEN_QUERY,
+            &mut token,
+        )
+        .map_err(win32_error)?;
+        Ok(Owned::new(token))
+    }
+}
+
+fn privilege_name(luid: &LUID) -> io::Result<String> {
+    let mut name = [0u16; 64];
+    let mut len = name.len() as u32;
+    unsafe {
+        LookupPrivilegeNameW(
+            PCWSTR::null(),
+            luid,
+            Some(PWSTR(name.as_mut_ptr())),
+            &mut len,
+        )
+    }
+    .map_err(win32_error)?;
+    Ok(String::from_utf16_lossy(&name[..len as usize]))
+}
+
+fn adjust_privilege(
+    token: &Owned<HANDLE>,
+    luid: LUID,
+    attributes: TOKEN_PRIVILEGES_ATTRIBUTES,
+) -> io::Result<()> {
+    let privileges = TOKEN_PRIVILEGES {
+        PrivilegeCount: 1,
+        Privileges: [LUID_AND_ATTRIBUTES {
+            Luid: luid,
+            Attributes: attributes,
+        }],
+    };
+    unsafe {
+        AdjustTokenPrivileges(**token, false, Some(&privileges), 0, None, None)
+            .map_err(win32_error)?;
+        // 令牌中没有该特权时调用仍然成功，只能通过 GetLastError 判断
+        let last_error = GetLastError();
+        if last_error == ERROR_NOT_ALL_ASSIGNED {
+            return Err(io::Error::from_raw_os_error(last_error.0 as i32));
         }
+    }
+    Ok(())
+}
 
-        let num_processes = bytes_returned / std::mem::size_of::<DWORD>() as DWORD;
-        let mut result = Vec::new();
-
-        info!("Found {} processes", num_processes);
-        let mut can_not_open_count = 0;
-        let pid_thread_count_map = get_pid_thread_count_map();
-        for i in 0..num_processes as usize {
-            let pid = process_ids[i];
-            let process_handle = OpenProcess(PROCESS_QUERY_INFORMATION | PROCESS_VM_READ, 0, pid);
-            if process_handle.is_null() {
-                // let error_code =  winapi::um::errhandlingapi::GetLastError() ;
-                // error!("Failed to open process with PID {}: Error code {}", pid, error_code);
-                can_not_open_count += 1;
-                continue;
-            }
+// HRESULT 还原为 Win32 错误码，以便按 io::ErrorKind 判断
+fn win32_error(e: windows::core::Error) -> io::Error {
+    match WIN32_ERROR::from_error(&e) {
+        Some(code) => io::Error::from_raw_os_error(code.0 as i32),
+        None => e.into(),
+    }
+}
 
-            let mut module: HMODULE = std::ptr::null_mut();
-            let mut cb_needed: DWORD = 0;
-            if EnumProcessModules(
-                process_handle,
-                &mut module,
-                std::mem::size_of::<HMODULE>() as DWORD,
-                &mut cb_needed,
-            ) != 0
-            {
-                let mut process_name: [u16; 260] = [0; 260];
-                if GetModuleBaseNameW(
-                    process_handle,
-                    module,
-                    process_name.as_mut_ptr(),
-                    process_name.len() as DWORD,
-                ) > 0
-                {
-                    let name = String::from_utf16_lossy(&process_name)
-                        .trim_end_matches('\0')
-                        .to_string();
-                    // info!("Found process: {}", name);
-                    // Get memory information
-                    let mut mem_counters: PROCESS_MEMORY_COUNTERS = std::mem::zeroed();
-                    let mut private_bytes = 0;
-                    let mut working_set = 0;
-                    if GetProcessMemoryInfo(
-                        process_handle,
-                        &mut mem_counters as *mut _,
-                        std::mem::size_of::<PROCESS_MEMORY_COUNTERS>() as DWORD,
-                    ) != 0
-                    {
-                        private_bytes = mem_counters.PagefileUsage as usize;
-                        working_set = mem_counters.WorkingSetSize as usize;
-                    }
-                    let thread_count = match pid_thread_count_map.get(&pid) {
-                        Some(count) => *count,
-                        None => 0,
-                    };
-                    result.push(ProcessInfo {
-                        name,
-                        pid,
-                        thread_count,
-                        private_bytes,
-                        working_set,
-                    });
-                }
-            }
-            CloseHandle(process_handle);
+thread_local! {
+    // WMI 连接只能在创建它的线程上使用
+    static WMI_CONNECTION: Option<WMIConnection> = COMLibrary::new()
+        .and_then(WMIConnection::new)
+        .map_err(|e| error!("{}", tr!("连接 WMI 失败: {}", "Failed to connect to WMI: {}", e)))
+        .ok();
+}
+
+fn variant_u64(value: &Variant) -> Option<u64> {
+    match value {
+        Variant::UI8(n) => Some(*n),
+        Variant::UI4(n) => Some(*n as u64),
+        // WMI 以字符串返回 uint64 属性
+        Variant::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+// 进程无法打开时（如新版 Windows 上受保护的 dwm）改从性能计数器读取，
+// 只有 Private Bytes 和工作集；进程已退出时返回 None
+pub fn counter_memory(info: &ProcessInfo) -> Option<ProcessInfo> {
+    let query = format!(
+        "SELECT PrivateBytes, WorkingSet FROM Win32_PerfFormattedData_PerfProc_Process WHERE IDProcess = {}",
+        info.pid
+    );
+    let results: Vec<HashMap<String, Variant>> = WMI_CONNECTION.with(|connection| {
+        connection
+            .as_ref()?
+            .raw_query(&query)
+            .map_err(|e| {
+                error!(
+                    "{}",
+                    tr!(
+                        "查询性能计数器失败: {}",
+                        "Failed to query performance counters: {}",
+                        e
+                    )
+                )
+            })
+            .ok()
+    })?;
+    let row = results.first()?;
+    let private_bytes = variant_u64(row.get("PrivateBytes")?)? as usize;
+    let working_set = variant_u64(row.get("WorkingSet")?)? as usize;
+    Some(ProcessInfo {
+        private_bytes,
+        pagefile_usage: private_bytes,
+        working_set,
+        ..info.clone()
+    })
+}
+
+// 对已找到的进程保持一个句柄，之后直接通过句柄采样，不必每轮重新枚举所有进程
+pub struct ProcessHandle {
+    info: ProcessInfo,
+    handle: Owned<HANDLE>,
+}
+
+// 进程句柄可以在线程间传递
+unsafe impl Send for ProcessHandle {}
+
+impl ProcessHandle {
+    // 无法打开受保护的进程时返回 io::ErrorKind::PermissionDenied
+    pub fn open(info: &ProcessInfo) -> io::Result<ProcessHandle> {
+        // 额外的 SYNCHRONIZE 用于检测进程退出
+        let access = MEMORY_QUERY_ACCESS | PROCESS_SYNCHRONIZE;
+        let handle = unsafe { OpenProcess(access, false, info.pid) }.map_err(win32_error)?;
+        Ok(ProcessHandle {
+            info: info.clone(),
+            handle: unsafe { Owned::new(handle) },
+        })
+    }
+
+    // 进程已退出时返回 io::ErrorKind::NotFound，调用方应重新枚举
+    pub fn sample(&mut self) -> io::Result<ProcessInfo> {
+        if unsafe { WaitForSingleObject(*self.handle, 0) } != WAIT_TIMEOUT {
+            return Err(process_exited(self.info.pid));
         }
-        info!(
-            "Finaly open {} processes ,{} can not open",
-            result.len(),
-            can_not_open_count
-        );
-        Some(result)
+        let counters = handle_memory(*self.handle).ok_or_else(io::Error::last_os_error)?;
+        self.info.set_memory(&counters);
+        Ok(self.info.clone())
+    }
+
+    // 等待进程退出，超时返回 false
+    pub fn wait_for_exit(&self, timeout: Duration) -> bool {
+        let millis = timeout.as_millis().min(u32::MAX as u128) as u32;
+        unsafe { WaitForSingleObject(*self.handle, millis) == WAIT_OBJECT_0 }
     }
 }
-pub fn monitor_process(config: &Config) {
-    let process_infos = match get_all_processes() {
-        Some(infos) => infos,
-        None => {
-            error!("Failed to retrieve process information");
-            return;
+
+fn process_exited(pid: u32) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("PID {} is no longer running", pid),
+    )
+}
+
+// 重新创建进程快照确认进程仍在运行，再临时打开进程读取内存，不保留句柄
+pub fn snapshot_memory(info: &ProcessInfo) -> io::Result<ProcessInfo> {
+    let snapshot = unsafe {
+        Owned::new(CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0).map_err(win32_error)?)
+    };
+    let mut entry = PROCESSENTRY32W {
+        dwSize: std::mem::size_of::<PROCESSENTRY32W>() as u32,
+        ..Default::default()
+    };
+    unsafe { Process32FirstW(*snapshot, &mut entry) }.map_err(win32_error)?;
+    while entry.th32ProcessID != info.pid {
+        if unsafe { Process32NextW(*snapshot, &mut entry) }.is_err() {
+            return Err(process_exited(info.pid));
         }
+    }
+    let counters = process_memory(info.pid).ok_or_else(io::Error::last_os_error)?;
+    let mut info = ProcessInfo {
+        thread_count: entry.cntThreads as i32,
+        ..info.clone()
     };
+    info.set_memory(&counters);
+    Ok(info)
+}
 
-    if config.db_config.insert_into_db {
-        match DB_CONNECTION.lock() {
-            Ok(mut conn) => {
-                if let Err(e) = conn.execute_batch_insert(process_infos.as_slice()) {
-                    error!("Failed to insert process info into DB: {:?}", e)
-                }
-            }
-            Err(e) => error!("Failed to get DB connection: {:?}", e),
+fn pdh_result(status: u32) -> io::Result<()> {
+    if status == ERROR_SUCCESS.0 {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("PDH error 0x{:08X}", status)))
+    }
+}
+
+// 通配符计数器在最近一次采集中各实例的值：(实例名, 值)
+unsafe fn pdh_values(counter: PDH_HCOUNTER) -> io::Result<Vec<(String, i64)>> {
+    let mut size = 0u32;
+    let mut count = 0u32;
+    let status = PdhGetFormattedCounterArrayW(counter, PDH_FMT_LARGE, &mut size, &mut count, None);
+    if status != PDH_MORE_DATA {
+        pdh_result(status)?;
+        return Ok(Vec::new());
+    }
+    // 缓冲区开头是条目数组，之后是条目引用的实例名，按 8 字节对齐分配
+    let mut buffer = vec![0u64; (size as usize).div_ceil(8)];
+    let items = buffer.as_mut_ptr() as *mut PDH_FMT_COUNTERVALUE_ITEM_W;
+    pdh_result(PdhGetFormattedCounterArrayW(
+        counter,
+        PDH_FMT_LARGE,
+        &mut size,
+        &mut count,
+        Some(items),
+    ))?;
+    Ok(std::slice::from_raw_parts(items, count as usize)
+        .iter()
+        .map(|item| {
+            (
+                item.szName.to_string().unwrap_or_default(),
+                item.FmtValue.Anonymous.largeValue,
+            )
+        })
+        .collect())
+}
+
+// 从 PDH 的 Process 计数器读取 Private Bytes 和工作集，不需要打开进程。计数器实例以进程名加序号
+// 命名（如 svchost#3），按同一次采集中的 ID Process 找到 PID 对应的实例
+pub fn pdh_memory(info: &ProcessInfo) -> io::Result<ProcessInfo> {
+    let paths = [
+        w!(r"\Process(*)\ID Process"),
+        w!(r"\Process(*)\Private Bytes"),
+        w!(r"\Process(*)\Working Set"),
+    ];
+    unsafe {
+        let mut query = PDH_HQUERY::default();
+        pdh_result(PdhOpenQueryW(PCWSTR::null(), 0, &mut query))?;
+        let query = Owned::new(query);
+        let mut counters = [PDH_HCOUNTER::default(); 3];
+        for (counter, path) in counters.iter_mut().zip(paths) {
+            pdh_result(PdhAddEnglishCounterW(*query, path, 0, counter))?;
         }
+        pdh_result(PdhCollectQueryData(*query))?;
+        let instance = pdh_values(counters[0])?
+            .into_iter()
+            .find(|(_, pid)| *pid == info.pid as i64)
+            .map(|(instance, _)| instance)
+            .ok_or_else(|| process_exited(info.pid))?;
+        let value = |counter| -> io::Result<usize> {
+            pdh_values(counter)?
+                .into_iter()
+                .find(|(name, _)| *name == instance)
+                .map(|(_, value)| value as usize)
+                .ok_or_else(|| process_exited(info.pid))
+        };
+        let private_bytes = value(counters[1])?;
+        let working_set = value(counters[2])?;
+        Ok(ProcessInfo {
+            private_bytes,
+            pagefile_usage: private_bytes,
+            working_set,
+            ..info.clone()
+        })
     }
+}
 
-    for process_config in config.get_monitor_processes() {
-        if let Some(process) = is_process_running(&process_config.name, process_infos.as_slice()) {
-            info!(
-                "{} 进程 ID: {}, memory_threshold_MB：{}",
-                &process_config.name,
-                process.pid,
-                process_config.memory_threshold_bytes / 1024 / 1024
+// 优先使用快照；快照失败时改为逐个打开进程查询名称，避免整轮采样找不到 dwm
+pub fn get_all_processes() -> Option<Vec<ProcessInfo>> {
+    snapshot_processes().or_else(|| {
+        warn!(
+            "{}",
+            tr!(
+                "改用 EnumProcesses 和 QueryFullProcessImageNameW",
+                "Falling back to EnumProcesses and QueryFullProcessImageNameW"
+            )
+        );
+        enumerate_processes()
+    })
+}
+
+// 一次快照即可拿到所有进程的名称、PID 和线程数，无需逐个打开进程
+fn snapshot_processes() -> Option<Vec<ProcessInfo>> {
+    let snapshot = match unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) } {
+        Ok(snapshot) => unsafe { Owned::new(snapshot) },
+        Err(e) => {
+            error!(
+                "{}",
+                tr!(
+                    "创建进程快照失败: {}",
+                    "Failed to create process snapshot: {}",
+                    e
+                )
             );
-            process.print_process_memory_info();
-            let private_bytes = process.private_bytes as u64;
-            if private_bytes > process_config.memory_threshold_bytes {
-                warn!(
-                    "内存使用超过阈值 {} MB，正在重启 {}",
-                    process_config.memory_threshold_bytes / 1024 / 1024,
-                    &process_config.name
-                );
-                restart_processing(&process_config.name, &process_config.process_type);
-            }
-        } else {
-            warn!("未找到 {} 进程...", &process_config.name);
-            if process_config.auto_start {
-                info!("正在启动 {} 进程...", &process_config.name);
-                let result = process_config.process_type.execute();
-                match result {
-                    Ok(output) => {
-                        info!("成功执行命令:{:?}", output)
-                    }
-                    Err(error) => {
-                        error!("执行命令失败：{:?}", error)
-                    }
-                }
-            }
+            return None;
         }
+    };
+    let mut entry = PROCESSENTRY32W {
+        dwSize: std::mem::size_of::<PROCESSENTRY32W>() as u32,
+        ..Default::default()
+    };
+    if let Err(e) = unsafe { Process32FirstW(*snapshot, &mut entry) } {
+        error!(
+            "{}",
+            tr!("枚举进程失败: {}", "Failed to enumerate processes: {}", e)
+        );
+        return None;
     }
+
+    let mut result = Vec::new();
+    let mut can_not_open_count = 0;
+    loop {
+        let len = entry
+            .szExeFile
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or(entry.szExeFile.len());
+        let mut info = ProcessInfo {
+            name: String::from_utf16_lossy(&entry.szExeFile[..len]),
+            pid: entry.th32ProcessID,
+            thread_count: entry.cntThreads as i32,
+            private_bytes: 0,
+            pagefile_usage: 0,
+            working_set: 0,
+        };
+        match process_memory(entry.th32ProcessID) {
+            Some(counters) => info.set_memory(&counters),
+            None => can_not_open_count += 1,
+        }
+        result.push(info);
+        if unsafe { Process32NextW(*snapshot, &mut entry) }.is_err() {
+            break;
+        }
+    }
+    info!(
+        "{}",
+        tr!(
+            "找到 {} 个进程，其中 {} 个无法读取内存",
+            "Found {} processes, memory of {} can not be read",
+            result.len(),
+            can_not_open_count
+        )
+    );
+    Some(result)
+}
+
+// QueryFullProcessImageNameW 只需要受限查询权限，能取得受保护进程的名称
+fn image_name(process_handle: HANDLE) -> Option<String> {
+    // 按长路径的上限分配，不会因路径过长而失败
+    let mut buffer = vec![0u16; 32 * 1024];
+    let mut len = buffer.len() as u32;
+    unsafe {
+        QueryFullProcessImageNameW(
+            process_handle,
+            PROCESS_NAME_WIN32,
+            PWSTR(buffer.as_mut_ptr()),
+            &mut len,
+        )
+        .ok()?;
+    }
+    let path = String::from_utf16_lossy(&buffer[..len as usize]);
+    path.rsplit('\\').next().map(str::to_string)
 }
 
-pub fn monitor_processes(config: &Config) {
+// 不含线程数；无法打开的进程（如 System、Idle）取不到名称，不在结果中
+fn enumerate_processes() -> Option<Vec<ProcessInfo>> {
+    let mut pids = vec![0u32; 1024];
     loop {
-        monitor_process(config);
-        print_memory_status();
-        thread::sleep(Duration::from_secs(config.interval_seconds));
+        let size = (pids.len() * std::mem::size_of::<u32>()) as u32;
+        let mut needed = 0u32;
+        if let Err(e) = unsafe { EnumProcesses(pids.as_mut_ptr(), size, &mut needed) } {
+            error!(
+                "{}",
+                tr!("枚举进程失败: {}", "Failed to enumerate processes: {}", e)
+            );
+            return None;
+        }
+        // 缓冲区被填满时可能还有更多进程
+        if needed < size {
+            pids.truncate(needed as usize / std::mem::size_of::<u32>());
+            break;
+        }
+        pids.resize(pids.len() * 2, 0);
+    }
+
+    let mut result = Vec::new();
+    for pid in pids {
+        let process_handle = match unsafe { OpenProcess(MEMORY_QUERY_ACCESS, false, pid) } {
+            Ok(handle) => unsafe { Owned::new(handle) },
+            Err(_) => continue,
+        };
+        let name = match image_name(*process_handle) {
+            Some(name) => name,
+            None => continue,
+        };
+        let mut info = ProcessInfo {
+            name,
+            pid,
+            thread_count: 0,
+            private_bytes: 0,
+            pagefile_usage: 0,
+            working_set: 0,
+        };
+        if let Some(counters) = handle_memory(*process_handle) {
+            info.set_memory(&counters);
+        }
+        result.push(info);
     }
+    info!(
+        "{}",
+        tr!(
+            "按 PID 找到 {} 个进程",
+            "Found {} processes by PID",
+            result.len()
+        )
+    );
+    Some(result)
+}
+
+pub fn monitor_process(config: &Config) {
+    ProcessMonitor::from_config(config).check();
 }
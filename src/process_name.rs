@@ -0,0 +1,100 @@
+//! 进程名比较。Windows 的文件名不区分大小写，这里逐字符转为 Unicode 大写后比较，
+//! 不依赖当前区域设置，非 ASCII 的进程名也能匹配。
+//!
+//! 另外维护一份受保护进程的列表：结束这些进程会使系统崩溃、注销用户或关闭本服务，
+//! 无论配置如何都不会重启它们，防止配置中的笔误结束 lsass 或 csrss。
+
+// 只做一对一的大小写转换（与文件系统一致），ß 这类会变成多个字符的保持不变
+fn fold(c: char) -> char {
+    let mut upper = c.to_uppercase();
+    match (upper.next(), upper.next()) {
+        (Some(u), None) => u,
+        _ => c,
+    }
+}
+
+pub fn eq(a: &str, b: &str) -> bool {
+    a.chars().map(fold).eq(b.chars().map(fold))
+}
+
+/// 用作 `HashMap`/`HashSet` 键的规范形式，`eq` 相等的名称键也相等。
+pub fn key(name: &str) -> String {
+    name.chars().map(fold).collect()
+}
+
+/// 无论配置如何都不会重启的进程，`*` 匹配任意多个字符。
+pub const PROTECTED_PROCESSES: &[&str] = &[
+    "System",
+    "Registry",
+    "[System Process]",
+    "smss.exe",
+    "csrss.exe",
+    "wininit.exe",
+    "winlogon.exe",
+    "services.exe",
+    "lsass.exe",
+    "lsaiso.exe",
+    "svchost.exe",
+    "MsMpEng.exe",
+    "process_guard*",
+];
+
+/// 不区分大小写的通配符匹配，`*` 匹配任意多个字符。
+pub fn matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().map(fold).collect();
+    let name: Vec<char> = name.chars().map(fold).collect();
+    // 记录最近一个 `*` 的位置，失配时让它多匹配一个字符后重试
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == name[n] {
+            p += 1;
+            n += 1;
+        } else if let Some((star_p, star_n)) = star {
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// 是否为受保护的进程。名称两端的空白会被忽略，`lsass` 这样省略扩展名的写法同样视为受保护。
+pub fn is_protected(name: &str) -> bool {
+    let name = name.trim();
+    PROTECTED_PROCESSES
+        .iter()
+        .any(|pattern| matches(pattern, name) || matches(pattern, &format!("{}.exe", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eq() {
+        assert!(eq("dwm.exe", "DWM.EXE"));
+        assert!(eq("Ärger.exe", "äRGER.exe"));
+        assert!(eq("Фото.exe", "фото.EXE"));
+        assert!(!eq("straße.exe", "STRASSE.EXE"));
+        assert!(!eq("dwm.exe", "dwm.exe "));
+        assert_eq!(key("Ärger.exe"), key("äRGER.EXE"));
+    }
+
+    #[test]
+    fn test_protected_processes() {
+        assert!(is_protected("LSASS.EXE"));
+        assert!(is_protected("csrss"));
+        assert!(is_protected(" winlogon.exe"));
+        assert!(is_protected("process_guard_tray.exe"));
+        assert!(!is_protected("dwm.exe"));
+        assert!(!is_protected("explorer.exe"));
+        assert!(matches("a*c*e", "abcde"));
+        assert!(!matches("a*c", "abcd"));
+    }
+}
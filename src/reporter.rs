@@ -0,0 +1,100 @@
+//! 集中上报：按 `report_interval_seconds` 把本机各进程的最新采样和此后发生的重启事件
+//! 以 JSON POST 到 `report_url`，由收集端汇总大量工作站的 dwm 状况。
+
+use serde::Serialize;
+use std::io;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use crate::config_manager::Config;
+use crate::monitor_state::{self, now_secs, MonitorStatus, RestartEvent};
+use crate::sink::{blocking, run_sink, FailureLog, Sink};
+use crate::tr;
+use crate::version;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 每次上报的内容；字段只增不改，收集端按 `machine` 区分节点。
+#[derive(Serialize, Debug)]
+pub struct Report {
+    pub machine: String,
+    pub version: &'static str,
+    pub timestamp: u64,
+    pub status: MonitorStatus,
+    pub restarts: Vec<RestartEvent>,
+}
+
+pub fn machine_name() -> String {
+    std::env::var("COMPUTERNAME").unwrap_or_else(|_| "unknown".to_string())
+}
+
+pub fn build_report() -> Report {
+    Report {
+        machine: machine_name(),
+        version: version::VERSION,
+        timestamp: now_secs(),
+        status: monitor_state::snapshot(),
+        restarts: monitor_state::take_restart_events(),
+    }
+}
+
+fn send(url: &str, report: &Report) -> io::Result<()> {
+    let body = serde_json::to_string(report)?;
+    ureq::AgentBuilder::new()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .post(url)
+        .set("Content-Type", "application/json")
+        .send_string(&body)
+        .map_err(|e| io::Error::other(e.to_string()))?;
+    Ok(())
+}
+
+struct Reporter;
+
+impl Sink for Reporter {
+    fn interval_seconds(config: &Config) -> u64 {
+        config.report_interval_seconds
+    }
+
+    async fn round(&mut self, config: &Mutex<Config>, failures: &mut FailureLog) {
+        let url = config
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .report_url
+            .clone();
+        if url.is_empty() {
+            return;
+        }
+        let report = build_report();
+        let Some((url, report, result)) = blocking(move || {
+            let result = send(&url, &report);
+            (url, report, result)
+        })
+        .await
+        else {
+            return;
+        };
+        match result {
+            Ok(()) => {
+                failures.succeeded(|| tr!("已恢复向 {} 上报", "Resumed reporting to {}", url))
+            }
+            Err(e) => {
+                failures.failed(|| {
+                    tr!(
+                        "向 {} 上报失败: {}",
+                        "Failed to send report to {}: {}",
+                        url,
+                        e
+                    )
+                });
+                monitor_state::return_restart_events(report.restarts);
+            }
+        }
+    }
+}
+
+/// 未配置 `report_url` 时空转，配置重新加载后下一轮生效；服务停止时返回。
+pub async fn run(config: Arc<Mutex<Config>>) {
+    run_sink(config, Reporter).await
+}
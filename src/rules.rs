@@ -0,0 +1,268 @@
+//! 脚本规则：`rules_script` 指定的 Rhai 脚本逐个接收采样并决定动作，用于按阈值无法表达的策略。
+//!
+//! 脚本需要定义 `fn decide(sample)`，`sample` 包含 `name`、`pid`、`private_bytes`、`working_set`、
+//! `threshold_bytes`、`hour`（本地时间的小时）。返回 `"restart"` 按超限处理，`"ignore"` 本轮不按超限处理，
+//! `"default"` 或不返回值时按阈值判断：
+//!
+//! ```text
+//! fn decide(sample) {
+//!     // 工作时间放宽到阈值的 1.5 倍
+//!     if sample.hour >= 9 && sample.hour < 18 && sample.private_bytes < sample.threshold_bytes * 3 / 2 {
+//!         return "ignore";
+//!     }
+//!     "default"
+//! }
+//! ```
+
+use chrono::Timelike;
+use log::{info, warn};
+use rhai::{Dynamic, Engine, Map, Scope, AST};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::config_manager::{program_file_path, Config};
+use crate::file_security;
+use crate::monitor::{Decision, Rule, Sample};
+use crate::tr;
+
+// 防止脚本中的死循环卡住监控
+const MAX_OPERATIONS: u64 = 100_000;
+
+/// 配置的脚本路径，相对路径相对于程序目录；未配置时返回 `None`。
+pub fn script_path(config: &Config) -> Option<PathBuf> {
+    if config.rules_script.is_empty() {
+        return None;
+    }
+    let path = Path::new(&config.rules_script);
+    Some(if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        program_file_path(&config.rules_script)
+    })
+}
+
+fn new_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine
+}
+
+fn sample_map(sample: &Sample) -> Map {
+    let mut map = Map::new();
+    map.insert("name".into(), sample.name.clone().into());
+    map.insert("pid".into(), (sample.pid.unwrap_or(0) as i64).into());
+    map.insert("private_bytes".into(), (sample.private_bytes as i64).into());
+    map.insert("working_set".into(), (sample.working_set as i64).into());
+    map.insert(
+        "threshold_bytes".into(),
+        (sample.threshold_bytes as i64).into(),
+    );
+    map.insert("hour".into(), (chrono::Local::now().hour() as i64).into());
+    map
+}
+
+fn parse_decision(value: Dynamic) -> Result<Decision, String> {
+    if value.is_unit() {
+        return Ok(Decision::Default);
+    }
+    match value.into_string().as_deref() {
+        Ok("default") => Ok(Decision::Default),
+        Ok("restart") => Ok(Decision::Restart),
+        Ok("ignore") => Ok(Decision::Ignore),
+        Ok(other) => Err(tr!(
+            "未知的返回值 \"{}\"",
+            "Unknown return value \"{}\"",
+            other
+        )),
+        Err(type_name) => Err(tr!(
+            "返回值类型 {} 不是字符串",
+            "Return value of type {} is not a string",
+            type_name
+        )),
+    }
+}
+
+/// 用 Rhai 脚本决定是否超限。脚本文件修改后在下一次采样时重新加载；脚本不存在、
+/// 无法编译或执行出错时按阈值判断，同一版本的脚本只记录一次错误。
+pub struct ScriptRule {
+    path: PathBuf,
+    engine: Engine,
+    ast: Option<AST>,
+    modified: Option<SystemTime>,
+    error_logged: bool,
+    // 文件不存在，已经记录过警告；文件重新出现后再加载
+    missing: bool,
+}
+
+impl ScriptRule {
+    pub fn new(path: PathBuf) -> ScriptRule {
+        ScriptRule {
+            path,
+            engine: new_engine(),
+            ast: None,
+            modified: None,
+            error_logged: false,
+            missing: false,
+        }
+    }
+
+    /// 编译脚本源码，用于测试和检查脚本。
+    pub fn compile(source: &str) -> Result<ScriptRule, String> {
+        let mut rule = ScriptRule::new(PathBuf::new());
+        rule.ast = Some(rule.engine.compile(source).map_err(|e| e.to_string())?);
+        Ok(rule)
+    }
+
+    fn load(&self) -> io::Result<String> {
+        file_security::verify_trusted(&self.path)?;
+        std::fs::read_to_string(&self.path)
+    }
+
+    fn reload_if_changed(&mut self) {
+        if self.path.as_os_str().is_empty() {
+            return;
+        }
+        let modified = std::fs::metadata(&self.path)
+            .and_then(|m| m.modified())
+            .ok();
+        if modified.is_some() && modified == self.modified || modified.is_none() && self.missing {
+            return;
+        }
+        self.modified = modified;
+        self.missing = modified.is_none();
+        self.error_logged = false;
+        self.ast = match self.load() {
+            Ok(source) => match self.engine.compile(&source) {
+                Ok(ast) => {
+                    info!(
+                        "{}",
+                        tr!(
+                            "已加载规则脚本 {}",
+                            "Loaded rules script {}",
+                            self.path.display()
+                        )
+                    );
+                    Some(ast)
+                }
+                Err(e) => {
+                    warn!(
+                        "{}",
+                        tr!(
+                            "规则脚本 {} 编译失败，按阈值判断: {}",
+                            "Failed to compile rules script {}, using the threshold: {}",
+                            self.path.display(),
+                            e
+                        )
+                    );
+                    None
+                }
+            },
+            Err(e) => {
+                warn!(
+                    "{}",
+                    tr!(
+                        "无法读取规则脚本 {}，按阈值判断: {}",
+                        "Failed to read rules script {}, using the threshold: {}",
+                        self.path.display(),
+                        e
+                    )
+                );
+                None
+            }
+        };
+    }
+
+    fn evaluate(&self, ast: &AST, sample: &Sample) -> Result<Decision, String> {
+        let value: Dynamic = self
+            .engine
+            .call_fn(&mut Scope::new(), ast, "decide", (sample_map(sample),))
+            .map_err(|e| e.to_string())?;
+        parse_decision(value)
+    }
+}
+
+impl Rule for ScriptRule {
+    fn decide(&mut self, sample: &Sample) -> Decision {
+        self.reload_if_changed();
+        let Some(ast) = &self.ast else {
+            return Decision::Default;
+        };
+        match self.evaluate(ast, sample) {
+            Ok(decision) => decision,
+            Err(e) => {
+                if !self.error_logged {
+                    warn!(
+                        "{}",
+                        tr!(
+                            "规则脚本处理 {} 出错，按阈值判断: {}",
+                            "Rules script failed on {}, using the threshold: {}",
+                            sample.name,
+                            e
+                        )
+                    );
+                    self.error_logged = true;
+                }
+                Decision::Default
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(private_bytes: u64) -> Sample {
+        Sample {
+            name: "rules_test.exe".to_string(),
+            pid: Some(7),
+            private_bytes,
+            working_set: private_bytes,
+            threshold_bytes: 1000,
+            instances: vec![(7, private_bytes)],
+            instance_thresholds: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_script_decides_action() {
+        let mut rule = ScriptRule::compile(
+            r#"
+            fn decide(sample) {
+                if sample.private_bytes > sample.threshold_bytes * 2 { return "restart"; }
+                if sample.private_bytes > sample.threshold_bytes { return "ignore"; }
+            }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(rule.decide(&sample(2500)), Decision::Restart);
+        assert_eq!(rule.decide(&sample(1500)), Decision::Ignore);
+        assert_eq!(rule.decide(&sample(500)), Decision::Default);
+    }
+
+    #[test]
+    fn test_script_errors_fall_back_to_threshold() {
+        assert!(ScriptRule::compile("fn decide(sample) {").is_err());
+        let mut rule = ScriptRule::compile(r#"fn decide(sample) { "reboot" }"#).unwrap();
+        assert_eq!(rule.decide(&sample(2500)), Decision::Default);
+        let mut rule = ScriptRule::compile("fn decide(sample) { loop {} }").unwrap();
+        assert_eq!(rule.decide(&sample(2500)), Decision::Default);
+    }
+
+    #[test]
+    fn test_missing_script_loaded_once_created() {
+        let path = std::env::temp_dir().join("process_guard_rules_test.rhai");
+        let _ = std::fs::remove_file(&path);
+        let mut rule = ScriptRule::new(path.clone());
+        rule.reload_if_changed();
+        assert!(rule.missing);
+        assert!(rule.ast.is_none());
+
+        std::fs::write(&path, r#"fn decide(sample) { "restart" }"#).unwrap();
+        rule.reload_if_changed();
+        assert!(!rule.missing);
+        assert_eq!(rule.decide(&sample(500)), Decision::Restart);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,110 @@
+//! 监控程序自身的资源占用：定期记录内存和句柄数，持续增长时告警，避免监控程序本身泄漏。
+
+use log::{info, warn};
+
+use crate::process_manager::{own_usage, trim_own_working_set};
+use crate::tr;
+
+// 比启动后的第一次采样多出这么多时告警，之后每再增长这么多再告警一次
+const PRIVATE_BYTES_GROWTH: u64 = 100 * 1024 * 1024;
+const HANDLE_COUNT_GROWTH: u32 = 1000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Usage {
+    pub private_bytes: u64,
+    pub working_set: u64,
+    pub handle_count: u32,
+}
+
+#[derive(Default)]
+pub struct GrowthCheck {
+    // 超过时告警的用量，第一次采样时按基线设置
+    alert_at: Option<(u64, u32)>,
+}
+
+impl GrowthCheck {
+    /// 用量超过告警线时返回 `true`，并把告警线提高到当前用量之上。
+    pub fn check(&mut self, usage: &Usage) -> bool {
+        let next = (
+            usage.private_bytes + PRIVATE_BYTES_GROWTH,
+            usage.handle_count + HANDLE_COUNT_GROWTH,
+        );
+        let (private_bytes, handle_count) = match self.alert_at {
+            Some(alert_at) => alert_at,
+            None => {
+                self.alert_at = Some(next);
+                return false;
+            }
+        };
+        if usage.private_bytes <= private_bytes && usage.handle_count <= handle_count {
+            return false;
+        }
+        self.alert_at = Some((private_bytes.max(next.0), handle_count.max(next.1)));
+        true
+    }
+}
+
+pub fn record(check: &mut GrowthCheck, trim_working_set: bool) {
+    let usage = match own_usage() {
+        Some(usage) => usage,
+        None => return,
+    };
+    info!(
+        "{}",
+        tr!(
+            "ProcessGuard 自身: 专用字节 {} MB，工作集 {} MB，句柄数 {}",
+            "ProcessGuard itself: Private Bytes {} MB, Working Set {} MB, handles {}",
+            usage.private_bytes / 1024 / 1024,
+            usage.working_set / 1024 / 1024,
+            usage.handle_count
+        )
+    );
+    if check.check(&usage) {
+        warn!(
+            "{}",
+            tr!(
+                "!!! ProcessGuard 自身的内存或句柄数持续增长（专用字节 {} MB，句柄数 {}），可能存在泄漏",
+                "!!! ProcessGuard's own memory or handle count keeps growing (Private Bytes {} MB, handles {}), it may be leaking",
+                usage.private_bytes / 1024 / 1024,
+                usage.handle_count
+            )
+        );
+    }
+    if trim_working_set {
+        if let Err(e) = trim_own_working_set() {
+            warn!(
+                "{}",
+                tr!(
+                    "裁剪自身工作集失败: {}",
+                    "Failed to trim own working set: {}",
+                    e
+                )
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(private_mb: u64, handle_count: u32) -> Usage {
+        Usage {
+            private_bytes: private_mb * 1024 * 1024,
+            working_set: 0,
+            handle_count,
+        }
+    }
+
+    #[test]
+    fn test_growth_check() {
+        let mut check = GrowthCheck::default();
+        assert!(!check.check(&usage(20, 300)));
+        assert!(!check.check(&usage(120, 1300)));
+        assert!(check.check(&usage(121, 300)));
+        // 告警后只有继续增长才再次告警
+        assert!(!check.check(&usage(200, 300)));
+        assert!(check.check(&usage(20, 1301)));
+        assert!(!check.check(&usage(20, 2000)));
+    }
+}
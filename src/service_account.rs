@@ -0,0 +1,190 @@
+use std::io;
+use windows::core::{Owned, PCWSTR, PWSTR};
+use windows::Win32::Foundation::{ERROR_FILE_NOT_FOUND, ERROR_NONE_MAPPED, NTSTATUS};
+use windows::Win32::Security::Authentication::Identity::{
+    LsaAddAccountRights, LsaNtStatusToWinError, LsaOpenPolicy, LsaRemoveAccountRights, LSA_HANDLE,
+    LSA_OBJECT_ATTRIBUTES, LSA_UNICODE_STRING, POLICY_CREATE_ACCOUNT, POLICY_LOOKUP_NAMES,
+};
+use windows::Win32::Security::{LookupAccountNameW, PSID, SID_NAME_USE};
+use windows::Win32::System::Services::{
+    ChangeServiceConfig2W, SC_HANDLE, SERVICE_CONFIG_REQUIRED_PRIVILEGES_INFO,
+    SERVICE_CONFIG_SERVICE_SID_INFO, SERVICE_REQUIRED_PRIVILEGES_INFOW, SERVICE_SID_INFO,
+    SERVICE_SID_TYPE_UNRESTRICTED,
+};
+use windows_service::service::Service;
+
+use crate::process_manager::to_wide_string;
+use crate::SERVICE_NAME;
+
+// 打开 dwm、结束进程、在用户会话中重新启动进程、记录带调用栈的 ETW 跟踪所需的特权。
+// 不包含 SeTcbPrivilege（“以操作系统方式执行”）：持有它的账户等同于 SYSTEM，
+// 因此 WTSQueryUserToken 只有 LocalSystem 能调用，其他账户无法在用户会话中重启进程
+pub const REQUIRED_PRIVILEGES: &[&str] = &[
+    "SeDebugPrivilege",
+    "SeAssignPrimaryTokenPrivilege",
+    "SeIncreaseQuotaPrivilege",
+    "SeSystemProfilePrivilege",
+];
+
+// 账户为空或为 LocalSystem 时使用默认的 LocalSystem
+pub fn is_local_system(account: &str) -> bool {
+    let account = account.trim();
+    account.is_empty()
+        || account.eq_ignore_ascii_case("LocalSystem")
+        || account.eq_ignore_ascii_case("NT AUTHORITY\\SYSTEM")
+        || account.eq_ignore_ascii_case(".\\LocalSystem")
+}
+
+fn lsa_result(status: NTSTATUS) -> io::Result<()> {
+    if status.is_ok() {
+        Ok(())
+    } else {
+        let code = unsafe { LsaNtStatusToWinError(status) };
+        Err(io::Error::from_raw_os_error(code as i32))
+    }
+}
+
+fn lookup_account_sid(account: &str) -> io::Result<Vec<u8>> {
+    let name = to_wide_string(account);
+    let mut sid_size: u32 = 0;
+    let mut domain_size: u32 = 0;
+    let mut sid_use = SID_NAME_USE::default();
+    unsafe {
+        // 第一次调用只获取缓冲区大小
+        if let Err(e) = LookupAccountNameW(
+            PCWSTR::null(),
+            PCWSTR(name.as_ptr()),
+            None,
+            &mut sid_size,
+            None,
+            &mut domain_size,
+            &mut sid_use,
+        ) {
+            if sid_size == 0 {
+                return Err(e.into());
+            }
+        }
+        let mut sid = vec![0u8; sid_size as usize];
+        let mut domain = vec![0u16; domain_size as usize];
+        LookupAccountNameW(
+            PCWSTR::null(),
+            PCWSTR(name.as_ptr()),
+            Some(PSID(sid.as_mut_ptr() as _)),
+            &mut sid_size,
+            Some(PWSTR(domain.as_mut_ptr())),
+            &mut domain_size,
+            &mut sid_use,
+        )?;
+        Ok(sid)
+    }
+}
+
+// 服务专用的虚拟账户。特权只授予它，不会影响以同一账户（如 LocalService）运行的其他服务
+pub fn service_sid_name() -> String {
+    format!("NT SERVICE\\{}", SERVICE_NAME)
+}
+
+// 让服务令牌包含服务 SID，授予服务 SID 的特权才会生效
+pub fn enable_service_sid(service: &Service) -> io::Result<()> {
+    let info = SERVICE_SID_INFO {
+        dwServiceSidType: SERVICE_SID_TYPE_UNRESTRICTED,
+    };
+    unsafe {
+        ChangeServiceConfig2W(
+            SC_HANDLE(service.raw_handle() as _),
+            SERVICE_CONFIG_SERVICE_SID_INFO,
+            Some(&info as *const _ as *const _),
+        )?;
+    }
+    Ok(())
+}
+
+fn open_policy() -> io::Result<Owned<LSA_HANDLE>> {
+    let attributes = LSA_OBJECT_ATTRIBUTES::default();
+    let mut policy = LSA_HANDLE::default();
+    unsafe {
+        lsa_result(LsaOpenPolicy(
+            None,
+            &attributes,
+            (POLICY_CREATE_ACCOUNT | POLICY_LOOKUP_NAMES) as u32,
+            &mut policy,
+        ))?;
+        // drop 时调用 LsaClose
+        Ok(Owned::new(policy))
+    }
+}
+
+// 通过 LSA 为服务 SID 授予所需特权（相当于本地安全策略中的“用户权限分配”），
+// 需要先创建服务并调用 enable_service_sid
+pub fn grant_privileges() -> io::Result<()> {
+    let mut sid = lookup_account_sid(&service_sid_name())?;
+    let mut names: Vec<Vec<u16>> = REQUIRED_PRIVILEGES
+        .iter()
+        .map(|name| name.encode_utf16().collect())
+        .collect();
+    let rights: Vec<LSA_UNICODE_STRING> = names
+        .iter_mut()
+        .map(|name| LSA_UNICODE_STRING {
+            Length: (name.len() * 2) as u16,
+            MaximumLength: (name.len() * 2) as u16,
+            Buffer: PWSTR(name.as_mut_ptr()),
+        })
+        .collect();
+    let policy = open_policy()?;
+    unsafe {
+        lsa_result(LsaAddAccountRights(
+            *policy,
+            PSID(sid.as_mut_ptr() as _),
+            &rights,
+        ))
+    }
+}
+
+// 卸载时收回授予服务 SID 的全部特权，需要在删除服务之前调用；
+// 未使用 --account 安装时服务 SID 没有特权，直接返回成功
+pub fn revoke_privileges() -> io::Result<()> {
+    let not_found = |e: &io::Error| {
+        e.raw_os_error() == Some(ERROR_NONE_MAPPED.0 as i32)
+            || e.raw_os_error() == Some(ERROR_FILE_NOT_FOUND.0 as i32)
+    };
+    let mut sid = match lookup_account_sid(&service_sid_name()) {
+        Ok(sid) => sid,
+        Err(e) if not_found(&e) => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let policy = open_policy()?;
+    let result = unsafe {
+        lsa_result(LsaRemoveAccountRights(
+            *policy,
+            PSID(sid.as_mut_ptr() as _),
+            true,
+            None,
+        ))
+    };
+    match result {
+        Err(e) if not_found(&e) => Ok(()),
+        result => result,
+    }
+}
+
+// 限制服务进程令牌只保留所需特权
+pub fn set_required_privileges(service: &Service) -> io::Result<()> {
+    // REG_MULTI_SZ：以 \0 分隔，以两个 \0 结尾
+    let mut buffer: Vec<u16> = Vec::new();
+    for name in REQUIRED_PRIVILEGES {
+        buffer.extend(name.encode_utf16());
+        buffer.push(0);
+    }
+    buffer.push(0);
+    let info = SERVICE_REQUIRED_PRIVILEGES_INFOW {
+        pmszRequiredPrivileges: PWSTR(buffer.as_mut_ptr()),
+    };
+    unsafe {
+        ChangeServiceConfig2W(
+            SC_HANDLE(service.raw_handle() as _),
+            SERVICE_CONFIG_REQUIRED_PRIVILEGES_INFO,
+            Some(&info as *const _ as *const _),
+        )?;
+    }
+    Ok(())
+}
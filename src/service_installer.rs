@@ -0,0 +1,204 @@
+use std::ffi::OsString;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+use windows::Win32::Foundation::ERROR_SERVICE_DOES_NOT_EXIST;
+use windows_service::{
+    service::{
+        ServiceAccess, ServiceAction, ServiceActionType, ServiceErrorControl,
+        ServiceFailureActions, ServiceFailureResetPeriod, ServiceInfo, ServiceStartType,
+        ServiceState, ServiceType,
+    },
+    service_manager::{ServiceManager, ServiceManagerAccess},
+};
+
+use crate::cli::StartType;
+use crate::config_manager::config_file_path;
+use crate::db_manager::db_file_path;
+use crate::logging::{log_file_candidates, rotated_log_paths};
+use crate::monitor_state::state_file_path;
+use crate::service_account;
+use crate::updater;
+use crate::SERVICE_NAME;
+
+const SERVICE_DISPLAY_NAME: &str = "Process Guard (memory monitor)";
+// 服务异常退出后由 SCM 自动重启，一天内无故障则重置失败计数
+const FAILURE_RESET_PERIOD: Duration = Duration::from_secs(24 * 60 * 60);
+const STOP_TIMEOUT: Duration = Duration::from_secs(60);
+const FAILURE_RESTART_DELAYS: [Duration; 2] = [Duration::from_secs(5), Duration::from_secs(60)];
+
+pub fn install_service(
+    start_type: StartType,
+    account: Option<String>,
+) -> windows_service::Result<()> {
+    let account = account.filter(|a| !service_account::is_local_system(a));
+    let manager_access = ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE;
+    let service_manager = ServiceManager::local_computer(None::<&str>, manager_access)?;
+
+    let executable_path = std::env::current_exe().map_err(windows_service::Error::Winapi)?;
+    let service_info = ServiceInfo {
+        name: OsString::from(SERVICE_NAME),
+        display_name: OsString::from(SERVICE_DISPLAY_NAME),
+        service_type: ServiceType::OWN_PROCESS,
+        start_type: match start_type {
+            StartType::Auto | StartType::DelayedAuto => ServiceStartType::AutoStart,
+            StartType::Manual => ServiceStartType::OnDemand,
+        },
+        error_control: ServiceErrorControl::Normal,
+        executable_path,
+        launch_arguments: vec![],
+        dependencies: vec![],
+        account_name: account.as_ref().map(OsString::from), // None 为 LocalSystem
+        account_password: None,
+    };
+    // 配置重启类恢复操作需要 START 权限
+    let service_access = ServiceAccess::CHANGE_CONFIG | ServiceAccess::START;
+    let service = service_manager.create_service(&service_info, service_access)?;
+    service.set_description(service_description())?;
+    service.update_failure_actions(failure_actions())?;
+    if start_type == StartType::DelayedAuto {
+        // 开机后延迟启动，避开系统启动时的资源争用
+        service.set_delayed_auto_start(true)?;
+    }
+    if let Some(account) = &account {
+        service_account::enable_service_sid(&service).map_err(windows_service::Error::Winapi)?;
+        service_account::grant_privileges().map_err(windows_service::Error::Winapi)?;
+        service_account::set_required_privileges(&service)
+            .map_err(windows_service::Error::Winapi)?;
+        println!(
+            "Granted {} to {}; make sure {} can write to {}",
+            service_account::REQUIRED_PRIVILEGES.join(", "),
+            service_account::service_sid_name(),
+            account,
+            config_file_path().parent().unwrap().display()
+        );
+    }
+    println!("{} installed", SERVICE_NAME);
+    Ok(())
+}
+
+// 在 services.msc 中说明服务用途和配置文件位置
+fn service_description() -> String {
+    format!(
+        "Monitors processes such as dwm.exe and restarts them when memory usage exceeds the \
+         configured threshold. Config file: {}",
+        config_file_path().display()
+    )
+}
+
+fn failure_actions() -> ServiceFailureActions {
+    let mut actions: Vec<ServiceAction> = FAILURE_RESTART_DELAYS
+        .iter()
+        .map(|delay| ServiceAction {
+            action_type: ServiceActionType::Restart,
+            delay: *delay,
+        })
+        .collect();
+    // 连续失败两次后不再自动重启，避免崩溃循环
+    actions.push(ServiceAction {
+        action_type: ServiceActionType::None,
+        delay: Duration::default(),
+    });
+    ServiceFailureActions {
+        reset_period: ServiceFailureResetPeriod::After(FAILURE_RESET_PERIOD),
+        reboot_msg: None,
+        command: None,
+        actions: Some(actions),
+    }
+}
+
+fn remove_file_if_exists(path: &Path) {
+    match std::fs::remove_file(path) {
+        Ok(()) => println!("Removed {}", path.display()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => eprintln!("Failed to remove {}: {}", path.display(), e),
+    }
+}
+
+// 删除运行时生成的文件，purge 时连同配置、日志和数据库一起删除
+fn remove_artifacts(purge: bool) {
+    remove_file_if_exists(&state_file_path());
+    for path in updater::leftover_paths() {
+        remove_file_if_exists(&path);
+    }
+    if purge {
+        remove_file_if_exists(&config_file_path());
+        remove_file_if_exists(&db_file_path());
+        for log_path in log_file_candidates() {
+            remove_file_if_exists(&log_path);
+            for path in rotated_log_paths(&log_path) {
+                remove_file_if_exists(&path);
+            }
+        }
+    }
+}
+
+pub fn uninstall_service(purge: bool) -> windows_service::Result<()> {
+    let service_manager =
+        ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+
+    let service_access = ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE;
+    let service = service_manager.open_service(SERVICE_NAME, service_access)?;
+
+    // 服务删除后无法再查到服务 SID，先收回安装时授予的特权
+    if let Err(e) = service_account::revoke_privileges() {
+        eprintln!(
+            "Failed to revoke the privileges of {}: {}",
+            service_account::service_sid_name(),
+            e
+        );
+    }
+    // 先标记删除，服务停止且所有句柄关闭后才会真正从 SCM 中移除
+    service.delete()?;
+    if service.query_status()?.current_state != ServiceState::Stopped {
+        service.stop()?;
+    }
+    drop(service);
+
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(5) {
+        if let Err(windows_service::Error::Winapi(e)) =
+            service_manager.open_service(SERVICE_NAME, ServiceAccess::QUERY_STATUS)
+        {
+            if e.raw_os_error() == Some(ERROR_SERVICE_DOES_NOT_EXIST.0 as i32) {
+                println!("{} uninstalled", SERVICE_NAME);
+                remove_artifacts(purge);
+                return Ok(());
+            }
+        }
+        thread::sleep(Duration::from_secs(1));
+    }
+    println!("{} is marked for deletion", SERVICE_NAME);
+    remove_artifacts(purge);
+    Ok(())
+}
+
+// 停止后重新启动服务，用于更新程序后加载新版本
+pub fn restart_service() -> windows_service::Result<()> {
+    let service_manager =
+        ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    let service_access = ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::START;
+    let service = service_manager.open_service(SERVICE_NAME, service_access)?;
+    if service.query_status()?.current_state != ServiceState::Stopped {
+        service.stop()?;
+    }
+    let start = Instant::now();
+    while service.query_status()?.current_state != ServiceState::Stopped {
+        if start.elapsed() > STOP_TIMEOUT {
+            return Err(windows_service::Error::Winapi(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "timed out waiting for the service to stop",
+            )));
+        }
+        thread::sleep(Duration::from_millis(500));
+    }
+    service.start::<&str>(&[])?;
+    Ok(())
+}
+
+pub fn query_service_state() -> windows_service::Result<ServiceState> {
+    let service_manager =
+        ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    let service = service_manager.open_service(SERVICE_NAME, ServiceAccess::QUERY_STATUS)?;
+    Ok(service.query_status()?.current_state)
+}
@@ -0,0 +1,75 @@
+use lazy_static::lazy_static;
+use log::error;
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+use windows_service::{
+    service::{ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus, ServiceType},
+    service_control_handler::ServiceStatusHandle,
+};
+
+use crate::tr;
+
+lazy_static! {
+    // 控制事件回调在注册之后才能拿到句柄，这里保存起来供各处更新服务状态
+    static ref STATUS_HANDLE: Mutex<Option<ServiceStatusHandle>> = Mutex::new(None);
+}
+
+pub fn init(handle: ServiceStatusHandle) {
+    *STATUS_HANDLE.lock().unwrap_or_else(PoisonError::into_inner) = Some(handle);
+}
+
+pub fn set_state(state: ServiceState) -> bool {
+    report(state, 0, Duration::default(), 0)
+}
+
+pub fn set_pending(state: ServiceState, checkpoint: u32, wait_hint: Duration) -> bool {
+    report(state, checkpoint, wait_hint, 0)
+}
+
+// 启动失败时以 Win32 错误码报告停止，SCM 会将其记录到系统日志
+pub fn set_stopped_with_error(win32_error: u32) -> bool {
+    report(ServiceState::Stopped, 0, Duration::default(), win32_error)
+}
+
+fn report(state: ServiceState, checkpoint: u32, wait_hint: Duration, win32_error: u32) -> bool {
+    let handle = match *STATUS_HANDLE.lock().unwrap_or_else(PoisonError::into_inner) {
+        Some(handle) => handle,
+        // 控制台模式下没有 SCM
+        None => return true,
+    };
+    let controls_accepted = match state {
+        ServiceState::Running | ServiceState::Paused => {
+            ServiceControlAccept::STOP
+                | ServiceControlAccept::PAUSE_CONTINUE
+                | ServiceControlAccept::PRESHUTDOWN
+                | ServiceControlAccept::SHUTDOWN
+                | ServiceControlAccept::POWER_EVENT
+                | ServiceControlAccept::SESSION_CHANGE
+        }
+        _ => ServiceControlAccept::empty(),
+    };
+    let status = ServiceStatus {
+        service_type: ServiceType::OWN_PROCESS,
+        current_state: state,
+        controls_accepted,
+        exit_code: ServiceExitCode::Win32(win32_error),
+        checkpoint,
+        wait_hint,
+        process_id: None,
+    };
+    match handle.set_service_status(status) {
+        Ok(_) => true,
+        Err(e) => {
+            error!(
+                "{}",
+                tr!(
+                    "设置服务状态 {:?} 失败: {}",
+                    "Failed to set service status {:?}: {}",
+                    state,
+                    e
+                )
+            );
+            false
+        }
+    }
+}
@@ -0,0 +1,291 @@
+use std::io;
+use windows::core::{Owned, PCWSTR, PWSTR};
+use windows::Win32::Foundation::{LocalFree, ERROR_FILE_NOT_FOUND, ERROR_SUCCESS, HANDLE, HLOCAL};
+use windows::Win32::Security::Authorization::ConvertSidToStringSidW;
+use windows::Win32::Security::{GetTokenInformation, TokenUser, TOKEN_USER};
+use windows::Win32::System::Registry::{
+    RegCloseKey, RegDeleteKeyValueW, RegEnumKeyExW, RegGetValueW, RegOpenKeyExW, RegSetKeyValueW,
+    HKEY, HKEY_CURRENT_USER, HKEY_USERS, KEY_READ, REG_SZ, RRF_RT_REG_QWORD, RRF_RT_REG_SZ,
+};
+use windows::Win32::System::RemoteDesktop::{
+    ProcessIdToSessionId, WTSClientProtocolType, WTSDomainName, WTSFreeMemory,
+    WTSQuerySessionInformationW, WTSQueryUserToken, WTSUserName, WTS_INFO_CLASS,
+};
+
+use crate::process_manager::to_wide_string;
+
+// 用户选择当天不自动重启时写入 HKCU 下的该值，内容为当天日期（YYYY-MM-DD），次日自动失效
+const OPT_OUT_KEY: &str = r"Software\ProcessGuard";
+const OPT_OUT_VALUE: &str = "RestartOptOut";
+// 隐私设置记录应用使用摄像头、麦克风的位置，使用中的应用 LastUsedTimeStop 为 0
+const CONSENT_STORE_KEY: &str =
+    r"Software\Microsoft\Windows\CurrentVersion\CapabilityAccessManager\ConsentStore";
+// WTSClientProtocolType 的取值：0 为本地控制台，2 为远程桌面（RDP）
+const WTS_PROTOCOL_TYPE_RDP: u16 = 2;
+
+fn query_session_string(session_id: u32, info_class: WTS_INFO_CLASS) -> Option<String> {
+    let mut buffer = PWSTR::null();
+    let mut bytes_returned: u32 = 0;
+    unsafe {
+        if WTSQuerySessionInformationW(
+            None,
+            session_id,
+            info_class,
+            &mut buffer,
+            &mut bytes_returned,
+        )
+        .is_err()
+            || buffer.is_null()
+        {
+            return None;
+        }
+        let value = buffer.to_string().ok();
+        WTSFreeMemory(buffer.as_ptr() as _);
+        value
+    }
+}
+
+// 返回 DOMAIN\user，会话无人登录时返回 None
+pub fn session_user_name(session_id: u32) -> Option<String> {
+    let user = query_session_string(session_id, WTSUserName).filter(|u| !u.is_empty())?;
+    match query_session_string(session_id, WTSDomainName).filter(|d| !d.is_empty()) {
+        Some(domain) => Some(format!("{}\\{}", domain, user)),
+        None => Some(user),
+    }
+}
+
+/// 进程所在的会话号，进程已退出等无法获取时返回 `None`。
+pub fn process_session_id(pid: u32) -> Option<u32> {
+    let mut session_id = 0;
+    unsafe { ProcessIdToSessionId(pid, &mut session_id) }.ok()?;
+    Some(session_id)
+}
+
+/// 进程所在的会话及登录用户，如 `session 2 (DOMAIN\alice)`，会话无人登录时只有会话号；
+/// 多用户主机上每个会话有各自的 dwm，用于在日志和告警中说明影响了哪个用户。
+pub fn describe_process_session(pid: u32) -> Option<String> {
+    let session_id = process_session_id(pid)?;
+    Some(match session_user_name(session_id) {
+        Some(user) => format!("session {} ({})", session_id, user),
+        None => format!("session {}", session_id),
+    })
+}
+
+/// 进程是否在远程桌面会话中；本地控制台会话或无法判断时返回 `false`。
+pub fn is_remote_session(pid: u32) -> bool {
+    let Some(session_id) = process_session_id(pid) else {
+        return false;
+    };
+    let mut buffer = PWSTR::null();
+    let mut bytes_returned: u32 = 0;
+    unsafe {
+        if WTSQuerySessionInformationW(
+            None,
+            session_id,
+            WTSClientProtocolType,
+            &mut buffer,
+            &mut bytes_returned,
+        )
+        .is_err()
+            || buffer.is_null()
+        {
+            return false;
+        }
+        let protocol = *(buffer.as_ptr() as *const u16);
+        WTSFreeMemory(buffer.as_ptr() as _);
+        protocol == WTS_PROTOCOL_TYPE_RDP
+    }
+}
+
+fn today() -> String {
+    chrono::Local::now().format("%Y-%m-%d").to_string()
+}
+
+fn read_opt_out(root: HKEY, key: &str) -> Option<String> {
+    let mut buffer = [0u16; 64];
+    let mut size = std::mem::size_of_val(&buffer) as u32;
+    let key = to_wide_string(key);
+    let value = to_wide_string(OPT_OUT_VALUE);
+    let result = unsafe {
+        RegGetValueW(
+            root,
+            PCWSTR(key.as_ptr()),
+            PCWSTR(value.as_ptr()),
+            RRF_RT_REG_SZ,
+            None,
+            Some(buffer.as_mut_ptr().cast()),
+            Some(&mut size),
+        )
+    };
+    if result != ERROR_SUCCESS {
+        return None;
+    }
+    let len = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+    Some(String::from_utf16_lossy(&buffer[..len]).trim().to_string())
+}
+
+// 会话登录用户的 SID 字符串，用于访问 HKEY_USERS 下该用户的配置单元；
+// 服务以 SYSTEM 运行，dwm 以 DWM-n 运行，都不是会话的用户，需要通过会话取得用户令牌
+fn session_user_sid(session_id: u32) -> Option<String> {
+    unsafe {
+        let mut token = HANDLE::default();
+        WTSQueryUserToken(session_id, &mut token).ok()?;
+        let token = Owned::new(token);
+        let mut size = 0;
+        // 第一次调用只获取缓冲区大小
+        let _ = GetTokenInformation(*token, TokenUser, None, 0, &mut size);
+        // 按 u64 分配以满足 TOKEN_USER 的对齐要求
+        let mut buffer = vec![0u64; (size as usize).div_ceil(8)];
+        GetTokenInformation(
+            *token,
+            TokenUser,
+            Some(buffer.as_mut_ptr() as _),
+            size,
+            &mut size,
+        )
+        .ok()?;
+        let user = buffer.as_ptr() as *const TOKEN_USER;
+        let mut sid = PWSTR::null();
+        ConvertSidToStringSidW((*user).User.Sid, &mut sid).ok()?;
+        let value = sid.to_string().ok();
+        let _ = LocalFree(Some(HLOCAL(sid.0 as _)));
+        value
+    }
+}
+
+/// 进程所在会话的登录用户是否选择了今天不自动重启（托盘程序的菜单或脚本写入
+/// `HKCU\Software\ProcessGuard` 的 `RestartOptOut`）；会话无人登录或读取失败时返回 `false`。
+pub fn restart_opted_out(pid: u32) -> bool {
+    let Some(sid) = process_user_sid(pid) else {
+        return false;
+    };
+    read_opt_out(HKEY_USERS, &format!("{}\\{}", sid, OPT_OUT_KEY))
+        .is_some_and(|date| date == today())
+}
+
+fn process_user_sid(pid: u32) -> Option<String> {
+    session_user_sid(process_session_id(pid)?)
+}
+
+/// 进程所在会话的登录用户是否正在使用摄像头或麦克风（Windows 隐私设置中“最近使用”记录的
+/// 使用中的应用）；会话无人登录或读取失败时返回 `false`。
+pub fn media_in_use(pid: u32) -> bool {
+    let Some(sid) = process_user_sid(pid) else {
+        return false;
+    };
+    ["webcam", "microphone"].iter().any(|device| {
+        let key = format!("{}\\{}\\{}", sid, CONSENT_STORE_KEY, device);
+        // 商店应用直接位于设备键下，桌面应用位于 NonPackaged 下
+        subkeys(&key)
+            .into_iter()
+            .flat_map(|app| {
+                if app == "NonPackaged" {
+                    let key = format!("{}\\NonPackaged", key);
+                    subkeys(&key)
+                        .into_iter()
+                        .map(|app| format!("{}\\{}", key, app))
+                        .collect()
+                } else {
+                    vec![format!("{}\\{}", key, app)]
+                }
+            })
+            .any(|app| {
+                read_qword(&app, "LastUsedTimeStart").is_some_and(|start| start > 0)
+                    && read_qword(&app, "LastUsedTimeStop") == Some(0)
+            })
+    })
+}
+
+fn subkeys(key: &str) -> Vec<String> {
+    let wide = to_wide_string(key);
+    let mut handle = HKEY::default();
+    if unsafe {
+        RegOpenKeyExW(
+            HKEY_USERS,
+            PCWSTR(wide.as_ptr()),
+            None,
+            KEY_READ,
+            &mut handle,
+        )
+    } != ERROR_SUCCESS
+    {
+        return Vec::new();
+    }
+    let mut names = Vec::new();
+    let mut buffer = [0u16; 512];
+    for index in 0.. {
+        let mut len = buffer.len() as u32;
+        let result = unsafe {
+            RegEnumKeyExW(
+                handle,
+                index,
+                Some(PWSTR(buffer.as_mut_ptr())),
+                &mut len,
+                None,
+                None,
+                None,
+                None,
+            )
+        };
+        if result != ERROR_SUCCESS {
+            break;
+        }
+        names.push(String::from_utf16_lossy(&buffer[..len as usize]));
+    }
+    let _ = unsafe { RegCloseKey(handle) };
+    names
+}
+
+fn read_qword(key: &str, value: &str) -> Option<u64> {
+    let key = to_wide_string(key);
+    let value = to_wide_string(value);
+    let mut data = 0u64;
+    let mut size = std::mem::size_of::<u64>() as u32;
+    let result = unsafe {
+        RegGetValueW(
+            HKEY_USERS,
+            PCWSTR(key.as_ptr()),
+            PCWSTR(value.as_ptr()),
+            RRF_RT_REG_QWORD,
+            None,
+            Some(&mut data as *mut u64 as *mut _),
+            Some(&mut size),
+        )
+    };
+    (result == ERROR_SUCCESS).then_some(data)
+}
+
+/// 当前用户今天是否已选择不自动重启，供托盘程序显示菜单。
+pub fn current_user_opted_out() -> bool {
+    read_opt_out(HKEY_CURRENT_USER, OPT_OUT_KEY).is_some_and(|date| date == today())
+}
+
+/// 为当前用户设置或取消今天不自动重启，在用户的会话中（托盘程序）调用。
+pub fn set_current_user_opt_out(opt_out: bool) -> io::Result<()> {
+    let key = to_wide_string(OPT_OUT_KEY);
+    let value = to_wide_string(OPT_OUT_VALUE);
+    let result = unsafe {
+        if opt_out {
+            let date = to_wide_string(&today());
+            RegSetKeyValueW(
+                HKEY_CURRENT_USER,
+                PCWSTR(key.as_ptr()),
+                PCWSTR(value.as_ptr()),
+                REG_SZ.0,
+                Some(date.as_ptr().cast()),
+                (date.len() * 2) as u32,
+            )
+        } else {
+            RegDeleteKeyValueW(
+                HKEY_CURRENT_USER,
+                PCWSTR(key.as_ptr()),
+                PCWSTR(value.as_ptr()),
+            )
+        }
+    };
+    if result == ERROR_SUCCESS || (!opt_out && result == ERROR_FILE_NOT_FOUND) {
+        Ok(())
+    } else {
+        Err(io::Error::from_raw_os_error(result.0 as i32))
+    }
+}
@@ -0,0 +1,189 @@
+use log::info;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config_manager::{
+    default_capture_processes, default_db_config, default_etw_trace_dir,
+    default_etw_trace_max_files, default_etw_trace_max_mb, default_etw_trace_max_minutes,
+    default_learn_threshold_factor, default_near_threshold_interval_seconds,
+    default_near_threshold_percent, default_recovery_margin_percent,
+    default_urgent_restart_percent, Config, DBConfig, LogLanguage, MonitoredProcess,
+    SamplingBackend,
+};
+use crate::monitor_state;
+use crate::process_manager::{monitor_process, ProcessType};
+use crate::process_name;
+use crate::tr;
+
+const BALLOON_EXE_NAME: &str = "process_guard_balloon.exe";
+const SIMULATE_INTERVAL: Duration = Duration::from_secs(5);
+const SIMULATE_TIMEOUT: Duration = Duration::from_secs(180);
+// 避免测试进程无限占用内存
+const BALLOON_MAX_BYTES: u64 = 4 * 1024 * 1024 * 1024;
+
+// 复制一份程序，使测试进程有独立的进程名，不会误杀正在运行的监控
+fn balloon_exe_path() -> PathBuf {
+    std::env::temp_dir().join(BALLOON_EXE_NAME)
+}
+
+fn start_command(exe: &Path, step_bytes: u64) -> String {
+    format!(
+        "Start-Process -FilePath '{}' -ArgumentList 'balloon','{}' -WindowStyle Hidden",
+        exe.display(),
+        step_bytes
+    )
+}
+
+// 每秒多申请 step_bytes 内存并写入，使其计入 Private Bytes
+pub fn balloon(step_bytes: u64) -> ! {
+    let mut blocks: Vec<Vec<u8>> = Vec::new();
+    let mut total = 0;
+    loop {
+        if total + step_bytes <= BALLOON_MAX_BYTES {
+            blocks.push(vec![1u8; step_bytes as usize]);
+            total += step_bytes;
+        }
+        thread::sleep(Duration::from_secs(1));
+    }
+}
+
+// 用一个不断膨胀的测试进程走一遍完整的监控、启动和重启流程
+pub fn run(threshold_bytes: u64, step_bytes: u64) -> i32 {
+    let exe = balloon_exe_path();
+    if let Err(e) = std::fs::copy(std::env::current_exe().unwrap(), &exe) {
+        eprintln!(
+            "Failed to copy the test process to {}: {}",
+            exe.display(),
+            e
+        );
+        return 1;
+    }
+    // 模拟过程不写入服务的状态文件
+    monitor_state::set_persist(false);
+    let process_type = ProcessType::Service(start_command(&exe, step_bytes));
+    let config = Config {
+        processes: vec![MonitoredProcess {
+            name: BALLOON_EXE_NAME.to_string(),
+            memory_threshold_bytes: threshold_bytes,
+            process_type: process_type.clone(),
+            auto_start: true,
+            actions: Vec::new(),
+            learn_threshold_hours: 0,
+            learn_threshold_factor: default_learn_threshold_factor(),
+            require_growth_samples: 0,
+            remote_session: None,
+            etw_trace: false,
+        }],
+        interval_seconds: SIMULATE_INTERVAL.as_secs(),
+        near_threshold_percent: default_near_threshold_percent(),
+        near_threshold_interval_seconds: default_near_threshold_interval_seconds(),
+        recovery_margin_percent: default_recovery_margin_percent(),
+        align_samples: false,
+        trim_own_working_set: false,
+        sampling_backend: SamplingBackend::Auto,
+        drop_privileges: true,
+        background_mode: true,
+        remote_control: false,
+        db_config: DBConfig {
+            insert_into_db: false,
+            ..default_db_config()
+        },
+        update_url: String::new(),
+        report_url: String::new(),
+        report_interval_seconds: 60,
+        log_analytics_workspace_id: String::new(),
+        log_analytics_shared_key: String::new(),
+        log_analytics_log_type: "ProcessGuard".to_string(),
+        textfile_path: String::new(),
+        cloudwatch_namespace: String::new(),
+        cloudwatch_region: String::new(),
+        kafka_brokers: String::new(),
+        kafka_topic: String::new(),
+        kafka_properties: Default::default(),
+        grpc_address: String::new(),
+        grpc_tls_cert: String::new(),
+        grpc_tls_key: String::new(),
+        grpc_client_ca: String::new(),
+        grpc_token_file: String::new(),
+        pagerduty_routing_key: String::new(),
+        opsgenie_api_key: String::new(),
+        opsgenie_api_url: "https://api.opsgenie.com".to_string(),
+        restart_warning_seconds: 0,
+        restart_confirmation: false,
+        suppress_restarts_when_reboot_pending: false,
+        defer_restarts_on_battery: false,
+        urgent_restart_percent: default_urgent_restart_percent(),
+        battery_interval_seconds: 0,
+        defer_restarts_for_games_and_capture: false,
+        capture_processes: default_capture_processes(),
+        do_not_disturb_processes: Vec::new(),
+        do_not_disturb_requires_media: false,
+        allow_restart_opt_out: true,
+        etw_trace_dir: default_etw_trace_dir(),
+        etw_trace_max_minutes: default_etw_trace_max_minutes(),
+        etw_trace_max_files: default_etw_trace_max_files(),
+        etw_trace_max_mb: default_etw_trace_max_mb(),
+        rules_script: String::new(),
+        log_language: LogLanguage::ZhCn,
+        message_templates: String::new(),
+        maintenance_window: String::new(),
+        maintenance_window_weekdays: Default::default(),
+        holidays: Vec::new(),
+        restarts_only_in_maintenance_window: false,
+        quiet_hours: String::new(),
+    };
+    info!(
+        "{}",
+        tr!(
+            "模拟 {} 以每秒 {} MB 增长，阈值 {} MB",
+            "Simulating {} growing by {} MB/s with a threshold of {} MB",
+            BALLOON_EXE_NAME,
+            step_bytes / 1024 / 1024,
+            threshold_bytes / 1024 / 1024
+        )
+    );
+
+    let start = Instant::now();
+    let mut restarted = false;
+    while start.elapsed() < SIMULATE_TIMEOUT {
+        monitor_process(&config);
+        let status = monitor_state::snapshot();
+        let entry = status
+            .processes
+            .iter()
+            .find(|p| process_name::eq(&p.name, BALLOON_EXE_NAME));
+        // 重启后进程重新出现并被采样，说明整条流程可用
+        if let Some(entry) = entry {
+            if entry.last_restart.is_some() && entry.pid.is_some() {
+                restarted = true;
+                break;
+            }
+        }
+        thread::sleep(SIMULATE_INTERVAL);
+    }
+
+    let _ = process_type.kill_process(BALLOON_EXE_NAME);
+    thread::sleep(Duration::from_secs(1));
+    let _ = std::fs::remove_file(&exe);
+    if restarted {
+        info!(
+            "{}",
+            tr!(
+                "模拟通过: 测试进程超过阈值后被重启",
+                "Simulation passed: the test process was restarted after exceeding the threshold"
+            )
+        );
+        0
+    } else {
+        info!(
+            "{}",
+            tr!(
+                "模拟失败: {} 秒内没有重启",
+                "Simulation failed: no restart within {} seconds",
+                SIMULATE_TIMEOUT.as_secs()
+            )
+        );
+        1
+    }
+}
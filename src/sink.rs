@@ -0,0 +1,194 @@
+//! 各输出任务（集中上报、指标文件、Log Analytics、CloudWatch、Kafka、告警）共用的运行循环：
+//! 每轮从配置读取目标和间隔，配置重新加载后下一轮生效，服务停止时返回；以及逐条发送采样和重启的
+//! 输出共用的记录生成。
+
+use log::{info, warn};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use crate::config_manager::Config;
+use crate::monitor_state::{self, MonitorStatus};
+
+/// 输出任务的失败日志：连续失败时只记录第一次，恢复后再记录一次。
+#[derive(Default)]
+pub struct FailureLog {
+    failing: bool,
+}
+
+impl FailureLog {
+    pub fn succeeded(&mut self, resumed: impl FnOnce() -> String) {
+        if self.failing {
+            info!("{}", resumed());
+            self.failing = false;
+        }
+    }
+
+    pub fn failed(&mut self, message: impl FnOnce() -> String) {
+        if !self.failing {
+            warn!("{}", message());
+            self.failing = true;
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordKind {
+    Sample,
+    Restart,
+}
+
+/// 逐条发送采样和重启的输出（Kafka、Log Analytics）共用的记录，各输出按自己的格式序列化。
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Record {
+    #[serde(rename = "type")]
+    pub kind: RecordKind,
+    pub host: String,
+    pub process: String,
+    // Unix 时间戳（秒）
+    pub timestamp: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub private_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub working_set: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threshold_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub os_build: Option<String>,
+    // 只有采样记录包含
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub visible_windows: Option<u32>,
+}
+
+/// 生成本轮要发送的记录：只包含比 `sent_samples` / `sent_restarts` 中记录的时间更新的采样和重启。
+/// 调用方在发送成功后用 [`MonitorStatus::latest`] 更新这两个记录。
+pub fn build_records(
+    host: &str,
+    status: &MonitorStatus,
+    sent_samples: &HashMap<String, u64>,
+    sent_restarts: &HashMap<String, u64>,
+) -> Vec<Record> {
+    let mut records = Vec::new();
+    for process in &status.processes {
+        if let Some(last_sample) = process.last_sample {
+            if sent_samples.get(&process.name) != Some(&last_sample) {
+                records.push(Record {
+                    kind: RecordKind::Sample,
+                    host: host.to_string(),
+                    process: process.name.clone(),
+                    timestamp: last_sample,
+                    pid: process.pid,
+                    private_bytes: Some(process.private_bytes),
+                    working_set: Some(process.working_set),
+                    threshold_bytes: Some(process.memory_threshold_bytes),
+                    state: Some(format!("{:?}", process.state)),
+                    os_build: status.os_build.clone(),
+                    visible_windows: status.visible_windows,
+                });
+            }
+        }
+        if let Some(last_restart) = process.last_restart {
+            if sent_restarts.get(&process.name) != Some(&last_restart) {
+                records.push(Record {
+                    kind: RecordKind::Restart,
+                    host: host.to_string(),
+                    process: process.name.clone(),
+                    timestamp: last_restart,
+                    pid: None,
+                    private_bytes: None,
+                    working_set: None,
+                    threshold_bytes: None,
+                    state: None,
+                    os_build: status.os_build.clone(),
+                    visible_windows: None,
+                });
+            }
+        }
+    }
+    records
+}
+
+/// 在阻塞线程池中执行发送等阻塞调用；panic 时传回当前任务，由 `restart_on_panic` 重新启动，
+/// 运行时正在关闭时返回 `None`。
+pub async fn blocking<T, F>(f: F) -> Option<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(value) => Some(value),
+        Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+        Err(_) => None,
+    }
+}
+
+/// 定期输出监控状态的任务，由 [`run_sink`] 驱动。
+pub trait Sink: Send {
+    /// 两轮之间的间隔，每轮从配置读取。
+    fn interval_seconds(config: &Config) -> u64;
+
+    /// 执行一轮输出，未配置时直接返回；失败通过 `failures` 记录。
+    fn round(
+        &mut self,
+        config: &Mutex<Config>,
+        failures: &mut FailureLog,
+    ) -> impl Future<Output = ()> + Send;
+}
+
+/// 各输出任务共用的循环：每轮执行 [`Sink::round`] 后等待配置的间隔，
+/// 配置重新加载后下一轮生效；服务停止时返回。
+pub async fn run_sink<S: Sink>(config: Arc<Mutex<Config>>, mut sink: S) {
+    let mut failures = FailureLog::default();
+    while !monitor_state::is_shutting_down() {
+        sink.round(&config, &mut failures).await;
+        let interval =
+            S::interval_seconds(&config.lock().unwrap_or_else(PoisonError::into_inner)).max(1);
+        tokio::select! {
+            _ = monitor_state::shutdown_requested() => break,
+            _ = tokio::time::sleep(Duration::from_secs(interval)) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor_state::ProcessStatus;
+
+    #[test]
+    fn test_build_records_sends_new_samples_and_restarts_once() {
+        let status = MonitorStatus {
+            processes: vec![ProcessStatus {
+                name: "dwm.exe".to_string(),
+                pid: Some(7),
+                private_bytes: 1024,
+                last_sample: Some(100),
+                last_restart: Some(90),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let records = build_records("KIOSK-01", &status, &HashMap::new(), &HashMap::new());
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            serde_json::to_value(&records[1]).unwrap(),
+            serde_json::json!({
+                "type": "restart",
+                "host": "KIOSK-01",
+                "process": "dwm.exe",
+                "timestamp": 90,
+            })
+        );
+
+        let sent_samples = status.latest(|p| p.last_sample);
+        let sent_restarts = status.latest(|p| p.last_restart);
+        assert!(build_records("KIOSK-01", &status, &sent_samples, &sent_restarts).is_empty());
+    }
+}
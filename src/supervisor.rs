@@ -0,0 +1,569 @@
+//! 每个被监控的进程由单独的任务采样，互不阻塞；任务 panic 或采样停滞后由 [`supervise`] 重新启动。
+
+use log::{error, info, warn};
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+use tokio::task::{AbortHandle, Id, JoinSet};
+
+use crate::baseline;
+use crate::config_manager::{Config, MonitoredProcess, SamplingBackend};
+use crate::etw_trace::TraceSettings;
+use crate::maintenance::{self, MaintenanceSchedule};
+use crate::monitor::{Deferrals, ProcessMonitor};
+use crate::monitor_state;
+use crate::process_manager;
+use crate::process_name;
+use crate::rules::{self, ScriptRule};
+use crate::self_usage::{self, GrowthCheck};
+use crate::system_api::WindowsApi;
+use crate::system_info_printer::print_memory_status;
+use crate::tr;
+use crate::watchdog;
+
+pub const WORKER_RESTART_DELAY: Duration = Duration::from_secs(5);
+// 这些配置不变时复用监控器
+#[derive(PartialEq)]
+struct MonitorSettings {
+    target: MonitoredProcess,
+    backend: SamplingBackend,
+    near_threshold_percent: u64,
+    recovery_margin_percent: u64,
+    restart_warning_seconds: u64,
+    restart_confirmation: bool,
+    rules_script: Option<PathBuf>,
+    trace: TraceSettings,
+    deferrals: Deferrals,
+    maintenance_schedule: Option<MaintenanceSchedule>,
+}
+
+impl MonitorSettings {
+    // 进程已不在配置中时返回 None
+    fn from_config(config: &Config, name: &str) -> Option<MonitorSettings> {
+        Some(MonitorSettings {
+            target: config.find_monitor_process(name)?.clone(),
+            backend: config.sampling_backend,
+            near_threshold_percent: config.near_threshold_percent,
+            recovery_margin_percent: config.recovery_margin_percent,
+            restart_warning_seconds: config.restart_warning_seconds,
+            restart_confirmation: config.restart_confirmation,
+            rules_script: rules::script_path(config),
+            trace: TraceSettings::from_config(config),
+            deferrals: Deferrals::from_config(config),
+            maintenance_schedule: maintenance::schedule(config),
+        })
+    }
+
+    fn build_monitor(&self) -> ProcessMonitor {
+        let builder = ProcessMonitor::builder()
+            .api(Arc::new(WindowsApi::new(self.backend)))
+            .near_threshold_percent(self.near_threshold_percent)
+            .recovery_margin_percent(self.recovery_margin_percent)
+            .restart_warning(Duration::from_secs(self.restart_warning_seconds))
+            .restart_confirmation(self.restart_confirmation)
+            .trace_settings(self.trace.clone())
+            .deferrals(self.deferrals.clone())
+            .maintenance_schedule(self.maintenance_schedule.clone())
+            .monitored(&self.target);
+        match &self.rules_script {
+            Some(path) => builder.rule(ScriptRule::new(path.clone())),
+            None => builder,
+        }
+        .build()
+    }
+}
+
+// 重启效果的汇总每小时记录一次
+const RESTART_STATS_LOG_INTERVAL: Duration = Duration::from_secs(3600);
+const STARTUP_FIRST_DELAY: Duration = Duration::from_secs(1);
+
+/// 服务的监控主体：每个进程一个任务，另有一个任务记录系统内存和数据库，收到停止请求后返回。
+pub async fn run(config: Arc<Mutex<Config>>) {
+    let names: Vec<String> = config
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get_monitor_processes()
+        .iter()
+        .map(|p| p.name.clone())
+        .collect();
+    let (stall_sender, stalls) = mpsc::unbounded_channel();
+    if let Err(e) = watchdog::spawn(config.clone(), stall_sender) {
+        error!(
+            "{}",
+            tr!("启动看门狗失败: {}", "Failed to start watchdog: {}", e)
+        );
+    }
+    let worker_config = config.clone();
+    tokio::join!(
+        supervise(names, WORKER_RESTART_DELAY, stalls, move |name| {
+            run_worker(worker_config.clone(), name)
+        }),
+        record_system(config),
+    );
+    info!("{}", tr!("监控循环已退出", "Monitoring loop exited"));
+}
+
+/// 在单独的任务中反复运行 `task` 直到正常返回；panic 时（停止过程中除外）延迟后重新运行，
+/// 避免服务显示为“正在运行”却已不再监控。
+pub async fn restart_on_panic<F, Fut>(name: &str, restart_delay: Duration, task: F)
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    loop {
+        match tokio::spawn(task()).await {
+            Ok(()) => return,
+            Err(e) if !e.is_panic() || monitor_state::is_shutting_down() => {
+                error!(
+                    "{}",
+                    tr!("{} 意外停止: {}", "{} stopped unexpectedly: {}", name, e)
+                );
+                return;
+            }
+            Err(e) => error!(
+                "{}",
+                tr!(
+                    "{} 异常退出，{} 秒后重启: {}",
+                    "{} panicked, restarting in {} seconds: {}",
+                    name,
+                    restart_delay.as_secs(),
+                    e
+                )
+            ),
+        }
+        tokio::select! {
+            _ = monitor_state::shutdown_requested() => return,
+            _ = tokio::time::sleep(restart_delay) => {}
+        }
+    }
+}
+
+// 为每个名称运行一个任务：任务 panic 时（停止过程中除外）延迟后重新启动，
+// 看门狗报告采样停滞时取消该任务并立即重新启动
+async fn supervise<F, Fut>(
+    names: Vec<String>,
+    restart_delay: Duration,
+    mut stalls: UnboundedReceiver<String>,
+    worker: F,
+) where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let mut workers = JoinSet::new();
+    let mut running: HashMap<Id, (String, AbortHandle)> = HashMap::new();
+    let spawn = |workers: &mut JoinSet<()>,
+                 running: &mut HashMap<Id, (String, AbortHandle)>,
+                 name: String,
+                 delay: Duration| {
+        let handle = workers.spawn(delayed(delay, worker(name.clone())));
+        running.insert(handle.id(), (name, handle));
+    };
+    for name in names {
+        spawn(&mut workers, &mut running, name, Duration::ZERO);
+    }
+    loop {
+        tokio::select! {
+            joined = workers.join_next_with_id() => {
+                let (id, error) = match joined {
+                    Some(Ok((id, ()))) => (id, None),
+                    Some(Err(e)) => (e.id(), Some(e)),
+                    None => break,
+                };
+                let name = match running.remove(&id) {
+                    Some((name, _)) => name,
+                    None => continue,
+                };
+                match error {
+                    None => info!(
+                        "{}",
+                        tr!("{} 的监控任务已退出", "Monitoring task for {} exited", name)
+                    ),
+                    Some(e) if monitor_state::is_shutting_down() => {
+                        error!(
+                            "{}",
+                            tr!(
+                                "{} 的监控任务异常退出: {}",
+                                "Monitoring task for {} panicked: {}",
+                                name,
+                                e
+                            )
+                        )
+                    }
+                    Some(e) if e.is_cancelled() => {
+                        warn!(
+                            "{}",
+                            tr!(
+                                "{} 的监控任务已被看门狗取消，正在重新启动",
+                                "Monitoring task for {} was cancelled by the watchdog, restarting",
+                                name
+                            )
+                        );
+                        monitor_state::record_worker_restart(&name);
+                        spawn(&mut workers, &mut running, name, Duration::ZERO);
+                    }
+                    Some(e) => {
+                        error!(
+                            "{}",
+                            tr!(
+                                "{} 的监控任务异常退出，{} 秒后重启: {}",
+                                "Monitoring task for {} panicked, restarting in {} seconds: {}",
+                                name,
+                                restart_delay.as_secs(),
+                                e
+                            )
+                        );
+                        monitor_state::record_worker_restart(&name);
+                        spawn(&mut workers, &mut running, name, restart_delay);
+                    }
+                }
+            }
+            Some(stalled) = stalls.recv() => {
+                // 卡住的阻塞调用无法中断，取消后由新的任务接手采样
+                for (name, handle) in running.values() {
+                    if process_name::eq(name, &stalled) {
+                        handle.abort();
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn delayed<Fut>(delay: Duration, worker: Fut)
+where
+    Fut: Future<Output = ()>,
+{
+    tokio::select! {
+        _ = monitor_state::shutdown_requested() => return,
+        _ = tokio::time::sleep(delay) => {}
+    }
+    worker.await
+}
+
+async fn run_worker(config: Arc<Mutex<Config>>, name: String) {
+    let mut wakeups = monitor_state::wakeups();
+    // 进程配置不变时复用监控器，保留采样器已找到的进程和生命周期状态
+    let mut current: Option<(MonitorSettings, ProcessMonitor)> = None;
+    // 上一轮内存是否已接近阈值，接近时缩短采样间隔
+    let mut near_threshold = false;
+    // 上一轮是否使用电池供电，只在配置了 battery_interval_seconds 时检查
+    let mut on_battery = false;
+    // 开机时服务可能先于 dwm 和用户会话启动：第一次找到进程之前从 1 秒开始加倍等待，
+    // 不超过正常间隔，既能尽快开始监控也不会每秒刷日志
+    let mut startup_delay = Some(STARTUP_FIRST_DELAY);
+    while !monitor_state::is_shutting_down() {
+        // 每轮取一份快照，IPC 修改的配置在下一轮生效
+        let settings = MonitorSettings::from_config(
+            &config.lock().unwrap_or_else(PoisonError::into_inner),
+            &name,
+        );
+        let Some(settings) = settings else {
+            info!(
+                "{}",
+                tr!(
+                    "{} 已不在配置中，停止监控",
+                    "{} is no longer in the config, stopping monitoring",
+                    name
+                )
+            );
+            break;
+        };
+        if monitor_state::is_paused() {
+            info!(
+                "{}",
+                tr!(
+                    "监控已暂停，跳过 {} 本轮检查",
+                    "Monitoring is paused, skipping this check of {}",
+                    name
+                )
+            );
+        } else {
+            let mut monitor = match current.take() {
+                Some((current_settings, monitor)) if current_settings == settings => monitor,
+                _ => settings.build_monitor(),
+            };
+            // 采样和重启都是阻塞调用，放到阻塞线程池中执行
+            let result = tokio::task::spawn_blocking(move || {
+                let samples = monitor.check();
+                (monitor, samples)
+            })
+            .await;
+            match result {
+                Ok((monitor, samples)) => {
+                    // 已加快采样时回落到回差以下才恢复正常间隔
+                    let percent = if near_threshold {
+                        settings
+                            .near_threshold_percent
+                            .saturating_sub(settings.recovery_margin_percent)
+                    } else {
+                        settings.near_threshold_percent
+                    };
+                    let near = samples.iter().any(|s| s.is_near_threshold(percent));
+                    if near != near_threshold {
+                        if near {
+                            info!(
+                                "{}",
+                                tr!(
+                                    "{} 内存已达到阈值的 {}%，缩短采样间隔",
+                                    "{} memory reached {}% of the threshold, sampling more often",
+                                    name,
+                                    settings.near_threshold_percent
+                                )
+                            );
+                        } else {
+                            info!(
+                                "{}",
+                                tr!(
+                                    "{} 内存已回落，恢复正常采样间隔",
+                                    "{} memory dropped back, resuming the normal sampling interval",
+                                    name
+                                )
+                            );
+                        }
+                        near_threshold = near;
+                    }
+                    if samples.iter().any(|s| s.is_running()) {
+                        startup_delay = None;
+                    }
+                    for sample in &samples {
+                        baseline::observe(&config, &settings.target, sample);
+                    }
+                    current = Some((settings, monitor));
+                }
+                Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+                Err(_) => break,
+            }
+        }
+        let delay = {
+            let config = config.lock().unwrap_or_else(PoisonError::into_inner);
+            let battery = config.battery_interval_seconds > 0 && process_manager::on_battery();
+            if battery != on_battery {
+                if battery {
+                    info!(
+                        "{}",
+                        tr!(
+                            "正在使用电池供电，{} 的采样间隔改为 {} 秒",
+                            "Running on battery, sampling {} every {} seconds",
+                            name,
+                            config.battery_interval_seconds
+                        )
+                    );
+                } else {
+                    info!(
+                        "{}",
+                        tr!(
+                            "已接通电源，恢复 {} 的正常采样间隔",
+                            "Back on AC power, resuming the normal sampling interval of {}",
+                            name
+                        )
+                    );
+                }
+                on_battery = battery;
+            }
+            let interval =
+                Duration::from_secs(config.sample_interval_seconds(near_threshold, on_battery));
+            match startup_delay {
+                Some(delay) if delay < interval => {
+                    startup_delay = Some(delay * 2);
+                    delay
+                }
+                _ => next_sample_delay(interval, config.align_samples, SystemTime::now()),
+            }
+        };
+        monitor_state::wait_for_next_sample(&mut wakeups, delay).await;
+    }
+}
+
+// 记录系统内存状态和自身用量，并按配置把所有进程写入数据库
+async fn record_system(config: Arc<Mutex<Config>>) {
+    let growth_check = Arc::new(Mutex::new(GrowthCheck::default()));
+    let mut stats_logged = Instant::now();
+    while !monitor_state::is_shutting_down() {
+        let (interval_seconds, align_samples, insert_into_db, trim_own_working_set) = {
+            let config = config.lock().unwrap_or_else(PoisonError::into_inner);
+            (
+                config.interval_seconds,
+                config.align_samples,
+                config.db_config.insert_into_db,
+                config.trim_own_working_set,
+            )
+        };
+        if !monitor_state::is_paused() {
+            let growth_check = growth_check.clone();
+            let result = tokio::task::spawn_blocking(move || {
+                if insert_into_db {
+                    // 不含被监控进程的监控器只负责把全部进程写入数据库
+                    ProcessMonitor::builder()
+                        .insert_into_db(true)
+                        .build()
+                        .check();
+                }
+                print_memory_status();
+                let mut growth_check = growth_check.lock().unwrap_or_else(PoisonError::into_inner);
+                self_usage::record(&mut growth_check, trim_own_working_set);
+            })
+            .await;
+            if let Err(e) = result {
+                error!(
+                    "{}",
+                    tr!(
+                        "记录系统状态失败: {}",
+                        "Failed to record system status: {}",
+                        e
+                    )
+                );
+            }
+        }
+        if stats_logged.elapsed() >= RESTART_STATS_LOG_INTERVAL {
+            log_restart_stats();
+            stats_logged = Instant::now();
+        }
+        tokio::select! {
+            _ = monitor_state::shutdown_requested() => break,
+            _ = tokio::time::sleep(next_sample_delay(
+                Duration::from_secs(interval_seconds),
+                align_samples,
+                SystemTime::now(),
+            )) => {}
+        }
+    }
+}
+
+// 各进程自动重启的平均效果，便于判断重启是否能有效缓解；还没有测量结果的进程不记录
+fn log_restart_stats() {
+    for process in monitor_state::snapshot().processes {
+        let stats = &process.restart_stats;
+        let Some(reclaimed) = stats.mean_reclaimed_bytes() else {
+            continue;
+        };
+        match stats.mean_rebreach_seconds() {
+            Some(seconds) => info!(
+                "{}",
+                tr!(
+                    "{} 重启效果: {} 次重启平均回收 {} MB，平均 {} 分钟后再次超过阈值（{} 次）",
+                    "{} restart effectiveness: {} restarts reclaimed {} MB on average, threshold hit again after {} minutes on average ({} times)",
+                    process.name,
+                    stats.reclaimed_count,
+                    reclaimed / 1024 / 1024,
+                    seconds / 60,
+                    stats.rebreach_count
+                )
+            ),
+            None => info!(
+                "{}",
+                tr!(
+                    "{} 重启效果: {} 次重启平均回收 {} MB，之后尚未再次超过阈值",
+                    "{} restart effectiveness: {} restarts reclaimed {} MB on average, threshold not hit again since",
+                    process.name,
+                    stats.reclaimed_count,
+                    reclaimed / 1024 / 1024
+                )
+            ),
+        }
+    }
+}
+
+// 对齐时等到下一个整数倍间隔的时刻，否则固定等待一个间隔
+fn next_sample_delay(interval: Duration, align: bool, now: SystemTime) -> Duration {
+    let interval_millis = interval.as_millis();
+    if !align || interval_millis == 0 {
+        return interval;
+    }
+    let now_millis = now.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis());
+    Duration::from_millis((interval_millis - now_millis % interval_millis) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_supervise_restarts_crashed_worker() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counter = runs.clone();
+        supervise(
+            vec!["supervisor_test.exe".to_string()],
+            Duration::ZERO,
+            mpsc::unbounded_channel().1,
+            move |_| {
+                let runs = counter.clone();
+                async move {
+                    if runs.fetch_add(1, Ordering::SeqCst) == 0 {
+                        panic!("worker crashed");
+                    }
+                }
+            },
+        )
+        .await;
+
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+        let status = monitor_state::snapshot();
+        let entry = status
+            .processes
+            .iter()
+            .find(|p| p.name == "supervisor_test.exe")
+            .unwrap();
+        assert_eq!(entry.worker_restarts, 1);
+    }
+
+    #[tokio::test]
+    async fn test_supervise_replaces_stalled_worker() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counter = runs.clone();
+        let (stall_sender, stalls) = mpsc::unbounded_channel();
+        supervise(
+            vec!["supervisor_stall_test.exe".to_string()],
+            Duration::ZERO,
+            stalls,
+            move |name| {
+                let runs = counter.clone();
+                let stall_sender = stall_sender.clone();
+                async move {
+                    // 第一次运行报告停滞后一直卡住，直到被取消
+                    if runs.fetch_add(1, Ordering::SeqCst) == 0 {
+                        stall_sender.send(name).unwrap();
+                        std::future::pending::<()>().await;
+                    }
+                }
+            },
+        )
+        .await;
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_next_sample_delay() {
+        let minute = Duration::from_secs(60);
+        let now = UNIX_EPOCH + Duration::from_millis(120_000 + 15_500);
+        assert_eq!(next_sample_delay(minute, false, now), minute);
+        assert_eq!(
+            next_sample_delay(minute, true, now),
+            Duration::from_millis(44_500)
+        );
+        // 正好在边界上时等待一个完整间隔
+        assert_eq!(
+            next_sample_delay(minute, true, UNIX_EPOCH + Duration::from_secs(180)),
+            minute
+        );
+    }
+
+    #[tokio::test]
+    async fn test_restart_on_panic() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counter = runs.clone();
+        restart_on_panic("test task", Duration::ZERO, move || {
+            let runs = counter.clone();
+            async move {
+                if runs.fetch_add(1, Ordering::SeqCst) < 2 {
+                    panic!("task crashed");
+                }
+            }
+        })
+        .await;
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+    }
+}
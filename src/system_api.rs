@@ -0,0 +1,449 @@
+//! 监控用到的操作系统接口：枚举进程、读取内存、结束进程。
+//!
+//! 服务使用 [`WindowsApi`]；测试中替换为假实现，不必真的结束 dwm 就能覆盖监控循环和重启流程。
+
+use log::info;
+use std::io;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use crate::config_manager::SamplingBackend;
+use crate::etw_trace;
+use crate::memory_sampler::SamplerChain;
+use crate::monitor_state;
+use crate::process_manager::{
+    ask_in_session, get_all_processes, on_battery, reboot_pending, send_session_message,
+    ProcessInfo, ProcessType,
+};
+use crate::session_manager;
+use crate::tr;
+
+pub trait SystemApi: Send + Sync {
+    /// 枚举所有进程及其内存，失败时返回 `None`。
+    fn processes(&self) -> Option<Vec<ProcessInfo>>;
+
+    /// 重新读取一个已知进程的内存；进程已退出或无法读取时返回 `None`，调用方应重新枚举。
+    fn memory(&self, process: &ProcessInfo) -> Option<ProcessInfo>;
+
+    /// 结束所有指定名称的进程。
+    fn terminate(&self, name: &str) -> io::Result<()>;
+
+    /// 只结束指定 PID 的进程，同名的其他实例不受影响。
+    fn terminate_pids(&self, pids: &[u32]) -> io::Result<()>;
+
+    /// 等待之前采样过的这些进程退出，超时返回 `false`；没有可等待的进程时立即返回 `true`。
+    fn wait_for_exit(&self, pids: &[u32], timeout: Duration) -> bool {
+        let _ = (pids, timeout);
+        true
+    }
+
+    /// 在进程所在的会话中显示提示，`timeout` 后自动关闭，不等待用户响应。
+    fn send_session_message(&self, pid: u32, message: &str, timeout: Duration) -> io::Result<()> {
+        let _ = (pid, message, timeout);
+        Ok(())
+    }
+
+    /// 在进程所在的会话中询问是否立即重启，最多等待 `timeout`；用户选择推迟时返回 `false`，
+    /// 同意或到时未选择时返回 `true`。
+    fn ask_in_session(&self, pid: u32, message: &str, timeout: Duration) -> io::Result<bool> {
+        let _ = (pid, message, timeout);
+        Ok(true)
+    }
+
+    /// 进程是否在远程桌面会话中，见 [`session_manager::is_remote_session`]。
+    fn is_remote_session(&self, pid: u32) -> bool {
+        let _ = pid;
+        false
+    }
+
+    /// 进程所在会话的用户是否选择了今天不自动重启，见 [`session_manager::restart_opted_out`]。
+    fn restart_opted_out(&self, pid: u32) -> bool {
+        let _ = pid;
+        false
+    }
+
+    /// 进程所在会话的用户是否正在使用摄像头或麦克风，见 [`session_manager::media_in_use`]。
+    fn media_in_use(&self, pid: u32) -> bool {
+        let _ = pid;
+        false
+    }
+
+    /// 进程所在的会话及登录用户，如 `session 2 (DOMAIN\alice)`，无法获取时返回 `None`。
+    fn session(&self, pid: u32) -> Option<String> {
+        let _ = pid;
+        None
+    }
+
+    /// 进程所在的会话号，见 [`session_manager::process_session_id`]。
+    fn session_id(&self, pid: u32) -> Option<u32> {
+        let _ = pid;
+        None
+    }
+
+    /// 系统是否在等待重启以完成更新；重启会顺带释放泄漏的内存。
+    fn reboot_pending(&self) -> bool {
+        false
+    }
+
+    /// 是否正在使用电池供电。
+    fn on_battery(&self) -> bool {
+        false
+    }
+
+    /// 开始记录 ETW 跟踪，见 [`etw_trace`]。
+    fn start_trace(&self) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    /// 停止 ETW 跟踪并保存到 `etl`，为 `None` 时丢弃。
+    fn stop_trace(&self, etl: Option<&Path>) -> io::Result<()> {
+        let _ = etl;
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    /// 重启流程中等待进程恢复时调用，服务停止时提前返回。
+    fn sleep(&self, duration: Duration) {
+        monitor_state::sleep_unless_shutdown(duration);
+    }
+}
+
+/// 真实的 Windows 实现，通过 [`SamplerChain`] 读取内存：先用 [`SamplingBackend`] 配置的方式，
+/// 失败时依次改用其他方式。PSAPI 方式按 PID 缓存进程句柄，重启时也通过同一个句柄等待进程退出。
+pub struct WindowsApi {
+    samplers: Mutex<SamplerChain>,
+}
+
+impl WindowsApi {
+    pub fn new(backend: SamplingBackend) -> WindowsApi {
+        WindowsApi {
+            samplers: Mutex::new(SamplerChain::from_backend(backend)),
+        }
+    }
+}
+
+impl Default for WindowsApi {
+    fn default() -> WindowsApi {
+        WindowsApi::new(SamplingBackend::default())
+    }
+}
+
+impl SystemApi for WindowsApi {
+    fn processes(&self) -> Option<Vec<ProcessInfo>> {
+        get_all_processes()
+    }
+
+    fn memory(&self, process: &ProcessInfo) -> Option<ProcessInfo> {
+        self.samplers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .sample(process)
+    }
+
+    fn terminate(&self, name: &str) -> io::Result<()> {
+        let output = ProcessType::System.kill_process(name)?;
+        info!(
+            "{}",
+            tr!(
+                "成功执行 taskkill 命令: {:?}",
+                "taskkill succeeded: {:?}",
+                output
+            )
+        );
+        Ok(())
+    }
+
+    fn terminate_pids(&self, pids: &[u32]) -> io::Result<()> {
+        let output = ProcessType::System.kill_pids(pids)?;
+        info!(
+            "{}",
+            tr!(
+                "成功执行 taskkill 命令: {:?}",
+                "taskkill succeeded: {:?}",
+                output
+            )
+        );
+        Ok(())
+    }
+
+    fn wait_for_exit(&self, pids: &[u32], timeout: Duration) -> bool {
+        // 取出句柄后再等待，不在持锁时阻塞；进程退出后句柄不再可用
+        let exiting = self
+            .samplers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take_handles(pids);
+        exiting.iter().all(|handle| handle.wait_for_exit(timeout))
+    }
+
+    fn send_session_message(&self, pid: u32, message: &str, timeout: Duration) -> io::Result<()> {
+        send_session_message(pid, "ProcessGuard", message, timeout)
+    }
+
+    fn ask_in_session(&self, pid: u32, message: &str, timeout: Duration) -> io::Result<bool> {
+        ask_in_session(pid, "ProcessGuard", message, timeout)
+    }
+
+    fn is_remote_session(&self, pid: u32) -> bool {
+        session_manager::is_remote_session(pid)
+    }
+
+    fn restart_opted_out(&self, pid: u32) -> bool {
+        session_manager::restart_opted_out(pid)
+    }
+
+    fn media_in_use(&self, pid: u32) -> bool {
+        session_manager::media_in_use(pid)
+    }
+
+    fn session(&self, pid: u32) -> Option<String> {
+        session_manager::describe_process_session(pid)
+    }
+
+    fn session_id(&self, pid: u32) -> Option<u32> {
+        session_manager::process_session_id(pid)
+    }
+
+    fn reboot_pending(&self) -> bool {
+        reboot_pending()
+    }
+
+    fn on_battery(&self) -> bool {
+        on_battery()
+    }
+
+    fn start_trace(&self) -> io::Result<()> {
+        etw_trace::start_wpr()
+    }
+
+    fn stop_trace(&self, etl: Option<&Path>) -> io::Result<()> {
+        etw_trace::stop_wpr(etl)
+    }
+}
+
+#[cfg(test)]
+pub mod fake {
+    use super::*;
+    use crate::process_name;
+    use std::collections::{HashMap, HashSet};
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    /// 内存中的进程表，记录枚举次数和被结束的进程。
+    #[derive(Default)]
+    pub struct FakeSystem {
+        processes: Mutex<Vec<ProcessInfo>>,
+        terminated: Mutex<Vec<String>>,
+        messages: Mutex<Vec<(u32, String)>>,
+        sessions: Mutex<HashMap<u32, String>>,
+        session_ids: Mutex<HashMap<u32, u32>>,
+        // 在远程桌面会话中的进程
+        remote: Mutex<HashSet<u32>>,
+        // 所在会话的用户选择了今天不自动重启的进程
+        opted_out: Mutex<HashSet<u32>>,
+        // 所在会话正在使用摄像头或麦克风的进程
+        media_in_use: Mutex<HashSet<u32>>,
+        // 正在记录的 ETW 跟踪和已保存的跟踪文件
+        tracing: AtomicBool,
+        traces: Mutex<Vec<PathBuf>>,
+        enumerations: AtomicUsize,
+        reboot_pending: AtomicBool,
+        on_battery: AtomicBool,
+        // 询问是否立即重启时模拟用户选择推迟
+        postpone: AtomicBool,
+        // 模拟 dwm 被结束后由系统自动拉起
+        respawn: bool,
+    }
+
+    impl FakeSystem {
+        pub fn new(processes: Vec<ProcessInfo>) -> FakeSystem {
+            FakeSystem {
+                processes: Mutex::new(processes),
+                ..Default::default()
+            }
+        }
+
+        pub fn respawning(mut self) -> FakeSystem {
+            self.respawn = true;
+            self
+        }
+
+        pub fn set_private_bytes(&self, name: &str, private_bytes: usize) {
+            for process in self.processes.lock().unwrap().iter_mut() {
+                if process_name::eq(&process.name, name) {
+                    process.private_bytes = private_bytes;
+                }
+            }
+        }
+
+        pub fn terminated(&self) -> Vec<String> {
+            self.terminated.lock().unwrap().clone()
+        }
+
+        pub fn tracing(&self) -> bool {
+            self.tracing.load(Ordering::SeqCst)
+        }
+
+        pub fn traces(&self) -> Vec<PathBuf> {
+            self.traces.lock().unwrap().clone()
+        }
+
+        pub fn set_session(&self, pid: u32, session: &str) {
+            self.sessions
+                .lock()
+                .unwrap()
+                .insert(pid, session.to_string());
+        }
+
+        pub fn set_session_id(&self, pid: u32, session_id: u32) {
+            self.session_ids.lock().unwrap().insert(pid, session_id);
+        }
+
+        pub fn set_remote(&self, pid: u32) {
+            self.remote.lock().unwrap().insert(pid);
+        }
+
+        pub fn set_opted_out(&self, pid: u32) {
+            self.opted_out.lock().unwrap().insert(pid);
+        }
+
+        pub fn set_media_in_use(&self, pid: u32) {
+            self.media_in_use.lock().unwrap().insert(pid);
+        }
+
+        pub fn set_reboot_pending(&self, pending: bool) {
+            self.reboot_pending.store(pending, Ordering::SeqCst);
+        }
+
+        pub fn set_on_battery(&self, on_battery: bool) {
+            self.on_battery.store(on_battery, Ordering::SeqCst);
+        }
+
+        pub fn set_postpone(&self, postpone: bool) {
+            self.postpone.store(postpone, Ordering::SeqCst);
+        }
+
+        pub fn messages(&self) -> Vec<(u32, String)> {
+            self.messages.lock().unwrap().clone()
+        }
+
+        pub fn enumerations(&self) -> usize {
+            self.enumerations.load(Ordering::SeqCst)
+        }
+
+        pub fn pids(&self) -> Vec<u32> {
+            self.processes
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|p| p.pid)
+                .collect()
+        }
+
+        // 结束符合条件的进程，返回它们的名称
+        fn kill(&self, matches: impl Fn(&ProcessInfo) -> bool) -> Vec<String> {
+            let mut processes = self.processes.lock().unwrap();
+            let (killed, alive): (Vec<_>, Vec<_>) = processes.drain(..).partition(|p| matches(p));
+            *processes = alive;
+            let names = killed.iter().map(|p| p.name.clone()).collect();
+            if self.respawn {
+                processes.extend(killed.into_iter().map(|p| ProcessInfo {
+                    pid: p.pid + 1000,
+                    private_bytes: 0,
+                    ..p
+                }));
+            }
+            names
+        }
+    }
+
+    impl SystemApi for FakeSystem {
+        fn processes(&self) -> Option<Vec<ProcessInfo>> {
+            self.enumerations.fetch_add(1, Ordering::SeqCst);
+            Some(self.processes.lock().unwrap().clone())
+        }
+
+        fn memory(&self, process: &ProcessInfo) -> Option<ProcessInfo> {
+            let processes = self.processes.lock().unwrap();
+            processes
+                .iter()
+                .find(|p| p.pid == process.pid && p.name == process.name)
+                .cloned()
+        }
+
+        fn terminate(&self, name: &str) -> io::Result<()> {
+            self.kill(|p| process_name::eq(&p.name, name));
+            self.terminated.lock().unwrap().push(name.to_string());
+            Ok(())
+        }
+
+        fn terminate_pids(&self, pids: &[u32]) -> io::Result<()> {
+            for name in self.kill(|p| pids.contains(&p.pid)) {
+                self.terminated.lock().unwrap().push(name);
+            }
+            Ok(())
+        }
+
+        fn send_session_message(
+            &self,
+            pid: u32,
+            message: &str,
+            _timeout: Duration,
+        ) -> io::Result<()> {
+            self.messages
+                .lock()
+                .unwrap()
+                .push((pid, message.to_string()));
+            Ok(())
+        }
+
+        fn ask_in_session(&self, pid: u32, message: &str, timeout: Duration) -> io::Result<bool> {
+            self.send_session_message(pid, message, timeout)?;
+            Ok(!self.postpone.load(Ordering::SeqCst))
+        }
+
+        fn is_remote_session(&self, pid: u32) -> bool {
+            self.remote.lock().unwrap().contains(&pid)
+        }
+
+        fn restart_opted_out(&self, pid: u32) -> bool {
+            self.opted_out.lock().unwrap().contains(&pid)
+        }
+
+        fn media_in_use(&self, pid: u32) -> bool {
+            self.media_in_use.lock().unwrap().contains(&pid)
+        }
+
+        fn session(&self, pid: u32) -> Option<String> {
+            self.sessions.lock().unwrap().get(&pid).cloned()
+        }
+
+        fn session_id(&self, pid: u32) -> Option<u32> {
+            self.session_ids.lock().unwrap().get(&pid).copied()
+        }
+
+        fn reboot_pending(&self) -> bool {
+            self.reboot_pending.load(Ordering::SeqCst)
+        }
+
+        fn on_battery(&self) -> bool {
+            self.on_battery.load(Ordering::SeqCst)
+        }
+
+        fn start_trace(&self) -> io::Result<()> {
+            self.tracing.store(true, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn stop_trace(&self, etl: Option<&Path>) -> io::Result<()> {
+            self.tracing.store(false, Ordering::SeqCst);
+            self.traces
+                .lock()
+                .unwrap()
+                .extend(etl.map(Path::to_path_buf));
+            Ok(())
+        }
+
+        fn sleep(&self, _duration: Duration) {}
+    }
+}
@@ -1,56 +1,85 @@
-extern crate winapi;
-
 use log::{error, info};
 use std::mem;
-use winapi::shared::ntdef::NTSTATUS;
-use winapi::um::{
-    libloaderapi::{GetModuleHandleW, GetProcAddress},
-    sysinfoapi::{GetSystemInfo, GlobalMemoryStatusEx, MEMORYSTATUSEX, SYSTEM_INFO},
-    winnt::RTL_OSVERSIONINFOW,
+use windows::core::{s, w};
+use windows::Win32::Foundation::NTSTATUS;
+use windows::Win32::System::{
+    LibraryLoader::{GetModuleHandleW, GetProcAddress},
+    SystemInformation::{
+        GetSystemInfo, GlobalMemoryStatusEx, MEMORYSTATUSEX, OSVERSIONINFOW, SYSTEM_INFO,
+    },
 };
 use wmi::{COMLibrary, WMIConnection};
 
-type RtlGetVersionFn = unsafe extern "system" fn(&mut RTL_OSVERSIONINFOW) -> NTSTATUS;
-
-fn print_os_version() {
-    unsafe {
-        let ntdll = GetModuleHandleW("ntdll.dll\0".encode_utf16().collect::<Vec<u16>>().as_ptr());
-        if ntdll.is_null() {
-            error!("Failed to load ntdll.dll");
-            return;
-        }
+use crate::tr;
 
-        let rtl_get_version: RtlGetVersionFn = std::mem::transmute(GetProcAddress(
-            ntdll,
-            "RtlGetVersion\0".as_ptr() as *const _,
-        ));
-        if rtl_get_version as usize == 0 {
-            info!("Failed to get RtlGetVersion function address");
-            return;
-        }
+type RtlGetVersionFn = unsafe extern "system" fn(&mut OSVERSIONINFOW) -> NTSTATUS;
 
-        let mut vi: RTL_OSVERSIONINFOW = mem::zeroed();
-        vi.dwOSVersionInfoSize = mem::size_of::<RTL_OSVERSIONINFOW>() as u32;
-        if rtl_get_version(&mut vi) == 0 {
-            info!(
+fn os_version() -> Option<String> {
+    unsafe {
+        let ntdll = match GetModuleHandleW(w!("ntdll.dll")) {
+            Ok(ntdll) => ntdll,
+            Err(_) => {
+                error!("{}", tr!("加载 ntdll.dll 失败", "Failed to load ntdll.dll"));
+                return None;
+            }
+        };
+
+        let rtl_get_version: RtlGetVersionFn = match GetProcAddress(ntdll, s!("RtlGetVersion")) {
+            Some(address) => {
+                mem::transmute::<unsafe extern "system" fn() -> isize, RtlGetVersionFn>(address)
+            }
+            None => {
+                info!(
+                    "{}",
+                    tr!(
+                        "获取 RtlGetVersion 函数地址失败",
+                        "Failed to get RtlGetVersion function address"
+                    )
+                );
+                return None;
+            }
+        };
+
+        let mut vi = OSVERSIONINFOW {
+            dwOSVersionInfoSize: mem::size_of::<OSVERSIONINFOW>() as u32,
+            ..Default::default()
+        };
+        if rtl_get_version(&mut vi).is_ok() {
+            Some(format!(
                 "Windows Version: {}.{} (Build {})",
                 vi.dwMajorVersion, vi.dwMinorVersion, vi.dwBuildNumber
-            );
+            ))
         } else {
-            error!("Failed to get version");
+            error!("{}", tr!("获取系统版本失败", "Failed to get version"));
+            None
         }
     }
 }
 
-fn print_system_info() {
-    let mut sys_info: SYSTEM_INFO = unsafe { mem::zeroed() };
+fn print_os_version() {
+    if let Some(version) = os_version() {
+        info!("{}", version);
+    }
+}
+
+fn system_info() -> Vec<String> {
+    let mut sys_info = SYSTEM_INFO::default();
     unsafe { GetSystemInfo(&mut sys_info) };
-    info!("Number of Processors: {}", sys_info.dwNumberOfProcessors);
-    info!("Processor Architecture: {}", unsafe {
-        sys_info.u.s().wProcessorArchitecture
-    });
+    vec![
+        format!("Number of Processors: {}", sys_info.dwNumberOfProcessors),
+        format!("Processor Architecture: {}", unsafe {
+            sys_info.Anonymous.Anonymous.wProcessorArchitecture.0
+        }),
+    ]
 }
-fn log_memory_status(mem_status: &MEMORYSTATUSEX) {
+
+fn print_system_info() {
+    for line in system_info() {
+        info!("{}", line);
+    }
+}
+
+fn memory_status_lines(mem_status: &MEMORYSTATUSEX) -> Vec<String> {
     const MB: u64 = 1024 * 1024;
 
     let available_physical_memory = mem_status.ullAvailPhys / MB;
@@ -63,41 +92,87 @@ fn log_memory_status(mem_status: &MEMORYSTATUSEX) {
     let total_virtual_memory_mb = mem_status.ullTotalPageFile / MB;
     let available_virtual_memory_mb = mem_status.ullAvailPageFile / MB;
     let used_virtual_memory_mb = total_virtual_memory_mb - available_virtual_memory_mb;
-    info!("Memory Load: {}%", mem_status.dwMemoryLoad);
-    info!(
-        "Physical Used / Total (MB) : {:>5} / {:>5} , Available {:>5}",
-        used_physical_memory, total_physical_memory, available_physical_memory
-    );
-    info!(
-        "Virtual Used / Total  (MB) : {:>5} / {:>5} , Available {:>5}",
-        used_virtual_memory_mb, total_virtual_memory_mb, available_virtual_memory_mb
-    );
+    vec![
+        format!("Memory Load: {}%", mem_status.dwMemoryLoad),
+        format!(
+            "Physical Used / Total (MB) : {:>5} / {:>5} , Available {:>5}",
+            used_physical_memory, total_physical_memory, available_physical_memory
+        ),
+        format!(
+            "Virtual Used / Total  (MB) : {:>5} / {:>5} , Available {:>5}",
+            used_virtual_memory_mb, total_virtual_memory_mb, available_virtual_memory_mb
+        ),
+    ]
 }
-pub fn print_memory_status() {
+
+/// 物理内存总量（字节），读取失败时为 `None`。
+pub fn total_physical_memory() -> Option<u64> {
+    let mut mem_status = MEMORYSTATUSEX {
+        dwLength: mem::size_of::<MEMORYSTATUSEX>() as u32,
+        ..Default::default()
+    };
+    unsafe { GlobalMemoryStatusEx(&mut mem_status) }.ok()?;
+    Some(mem_status.ullTotalPhys)
+}
+
+fn memory_status() -> Vec<String> {
     let mut mem_status = MEMORYSTATUSEX {
         dwLength: mem::size_of::<MEMORYSTATUSEX>() as u32,
-        ..unsafe { mem::zeroed() }
+        ..Default::default()
     };
 
-    if unsafe { GlobalMemoryStatusEx(&mut mem_status) } != 0 {
+    if unsafe { GlobalMemoryStatusEx(&mut mem_status) }.is_ok() {
         // 物理内存百分比
-        log_memory_status(&mem_status);
+        memory_status_lines(&mem_status)
     } else {
-        error!("Failed to retrieve memory status!");
+        error!(
+            "{}",
+            tr!("获取内存状态失败！", "Failed to retrieve memory status!")
+        );
+        Vec::new()
     }
 }
 
-fn print_display_driver_version() {
-    let com_con = COMLibrary::new().unwrap();
-    let wmi_con = WMIConnection::new(com_con).unwrap();
-    let results: Vec<std::collections::HashMap<String, wmi::Variant>> = wmi_con
-        .raw_query("SELECT DriverVersion FROM Win32_VideoController")
-        .unwrap();
-
-    for result in results {
-        if let Some(wmi::Variant::String(version)) = result.get("DriverVersion") {
-            info!("Display driver version: {}", version);
+pub fn print_memory_status() {
+    for line in memory_status() {
+        info!("{}", line);
+    }
+}
+
+// WMI 不可用时只记录错误，不影响服务启动
+fn display_driver_versions() -> Vec<String> {
+    let results: Vec<std::collections::HashMap<String, wmi::Variant>> = match COMLibrary::new()
+        .and_then(WMIConnection::new)
+        .and_then(|wmi_con| wmi_con.raw_query("SELECT DriverVersion FROM Win32_VideoController"))
+    {
+        Ok(results) => results,
+        Err(e) => {
+            error!(
+                "{}",
+                tr!(
+                    "查询显示驱动版本失败: {}",
+                    "Failed to query display driver version: {}",
+                    e
+                )
+            );
+            return Vec::new();
         }
+    };
+
+    results
+        .iter()
+        .filter_map(|result| match result.get("DriverVersion") {
+            Some(wmi::Variant::String(version)) => {
+                Some(format!("Display driver version: {}", version))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn print_display_driver_version() {
+    for line in display_driver_versions() {
+        info!("{}", line);
     }
 }
 
@@ -108,6 +183,15 @@ pub fn print_all_system_info() {
     print_display_driver_version();
 }
 
+// 与 print_all_system_info 相同的内容，以文本形式返回（用于诊断包）
+pub fn system_info_report() -> String {
+    let mut lines: Vec<String> = os_version().into_iter().collect();
+    lines.extend(system_info());
+    lines.extend(memory_status());
+    lines.extend(display_driver_versions());
+    lines.join("\n") + "\n"
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,240 @@
+//! 消息模板：`message_templates` 指定的 JSON 文件可以覆盖发给用户和值班人员的消息文本，
+//! 便于各组织使用自己的措辞和语言。模板中的 `{name}` 等占位符替换为当时的进程和内存数值，
+//! 未在文件中给出的消息使用内置文本。
+//!
+//! 模板在加载和重新加载配置时读取，修改模板文件后需要重新加载配置。
+
+use lazy_static::lazy_static;
+use log::{info, warn};
+use serde::Deserialize;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{PoisonError, RwLock};
+
+use crate::config_manager::{program_file_path, Config};
+use crate::file_security;
+use crate::tr;
+
+lazy_static! {
+    static ref TEMPLATES: RwLock<MessageTemplates> = RwLock::new(MessageTemplates::default());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Message {
+    // 重启前在进程所在会话中弹出的提示
+    RestartWarning,
+    // 设置 restart_confirmation 时询问用户是否立即重启的提示
+    RestartConfirmation,
+    // 用户选择了今天不自动重启时，代替重启发给用户的提示
+    RestartOptedOut,
+    // 开始重启时的日志
+    RestartLog,
+    // PagerDuty 事件摘要和 Opsgenie 告警消息
+    AlertSummary,
+}
+
+impl Message {
+    /// 该消息可以使用的占位符。
+    pub fn placeholders(self) -> &'static [&'static str] {
+        match self {
+            Message::RestartWarning | Message::RestartConfirmation => &[
+                "name",
+                "pid",
+                "seconds",
+                "private_mb",
+                "working_set_mb",
+                "threshold_mb",
+            ],
+            Message::RestartOptedOut | Message::RestartLog => &[
+                "name",
+                "pid",
+                "private_mb",
+                "working_set_mb",
+                "threshold_mb",
+            ],
+            Message::AlertSummary => &[
+                "name",
+                "host",
+                "sessions",
+                "private_mb",
+                "working_set_mb",
+                "threshold_mb",
+            ],
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct MessageTemplates {
+    #[serde(default)]
+    pub restart_warning: Option<String>,
+    #[serde(default)]
+    pub restart_confirmation: Option<String>,
+    #[serde(default)]
+    pub restart_opted_out: Option<String>,
+    #[serde(default)]
+    pub restart_log: Option<String>,
+    #[serde(default)]
+    pub alert_summary: Option<String>,
+}
+
+impl MessageTemplates {
+    /// 解析模板文件，拼错的消息名或该消息不支持的占位符都视为错误，避免发出未替换的文本。
+    pub fn parse(text: &str) -> Result<MessageTemplates, String> {
+        let templates: MessageTemplates = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        for message in [
+            Message::RestartWarning,
+            Message::RestartConfirmation,
+            Message::RestartOptedOut,
+            Message::RestartLog,
+            Message::AlertSummary,
+        ] {
+            let Some(template) = templates.get(message) else {
+                continue;
+            };
+            if let Some(unknown) = placeholders(template)
+                .into_iter()
+                .find(|placeholder| !message.placeholders().contains(placeholder))
+            {
+                return Err(format!(
+                    "{:?} does not support the placeholder {{{}}}, use one of {}",
+                    message,
+                    unknown,
+                    message.placeholders().join(", ")
+                ));
+            }
+        }
+        Ok(templates)
+    }
+
+    fn get(&self, message: Message) -> Option<&str> {
+        match message {
+            Message::RestartWarning => self.restart_warning.as_deref(),
+            Message::RestartConfirmation => self.restart_confirmation.as_deref(),
+            Message::RestartOptedOut => self.restart_opted_out.as_deref(),
+            Message::RestartLog => self.restart_log.as_deref(),
+            Message::AlertSummary => self.alert_summary.as_deref(),
+        }
+    }
+}
+
+fn placeholders(template: &str) -> Vec<&str> {
+    let mut found = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rest = &rest[start + 1..];
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+        found.push(&rest[..end]);
+        rest = &rest[end + 1..];
+    }
+    found
+}
+
+/// 把模板中的 `{占位符}` 替换为对应的值。
+pub fn render(template: &str, values: &[(&str, String)]) -> String {
+    let mut text = template.to_string();
+    for (name, value) in values {
+        text = text.replace(&format!("{{{}}}", name), value);
+    }
+    text
+}
+
+/// 配置了该消息的模板时按模板生成，否则使用 `default` 生成的内置文本。
+pub fn format(
+    message: Message,
+    values: &[(&str, String)],
+    default: impl FnOnce() -> String,
+) -> String {
+    let templates = TEMPLATES.read().unwrap_or_else(PoisonError::into_inner);
+    match templates.get(message) {
+        Some(template) => render(template, values),
+        None => default(),
+    }
+}
+
+// 模板文件路径与规则脚本相同，相对路径相对于程序目录
+fn templates_path(config: &Config) -> Option<PathBuf> {
+    if config.message_templates.is_empty() {
+        return None;
+    }
+    let path = Path::new(&config.message_templates);
+    Some(if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        program_file_path(&config.message_templates)
+    })
+}
+
+fn read(path: &Path) -> io::Result<String> {
+    file_security::verify_trusted(path)?;
+    std::fs::read_to_string(path)
+}
+
+/// 加载或重新加载配置后调用；模板文件无效时记录警告并使用内置文本。
+pub fn load(config: &Config) {
+    let templates = match templates_path(config) {
+        None => MessageTemplates::default(),
+        Some(path) => match read(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| MessageTemplates::parse(&text))
+        {
+            Ok(templates) => {
+                info!(
+                    "{}",
+                    tr!(
+                        "已加载消息模板 {}",
+                        "Loaded message templates {}",
+                        path.display()
+                    )
+                );
+                templates
+            }
+            Err(e) => {
+                warn!(
+                    "{}",
+                    tr!(
+                        "消息模板 {} 无效，使用内置文本: {}",
+                        "Invalid message templates {}, using the built-in text: {}",
+                        path.display(),
+                        e
+                    )
+                );
+                MessageTemplates::default()
+            }
+        },
+    };
+    *TEMPLATES.write().unwrap_or_else(PoisonError::into_inner) = templates;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_render_templates() {
+        let templates = MessageTemplates::parse(
+            r#"{ "restart_warning": "{name} uses {private_mb} MB and restarts in {seconds}s" }"#,
+        )
+        .unwrap();
+        let template = templates.get(Message::RestartWarning).unwrap();
+        assert_eq!(
+            render(
+                template,
+                &[
+                    ("name", "dwm.exe".to_string()),
+                    ("private_mb", "2048".to_string()),
+                    ("seconds", "30".to_string()),
+                ]
+            ),
+            "dwm.exe uses 2048 MB and restarts in 30s"
+        );
+        assert_eq!(templates.get(Message::AlertSummary), None);
+
+        // host 只在告警中可用
+        assert!(MessageTemplates::parse(r#"{ "restart_log": "{host}: {name}" }"#).is_err());
+        assert!(MessageTemplates::parse(r#"{ "restart_notice": "{name}" }"#).is_err());
+    }
+}
@@ -17,18 +17,22 @@ mod tests {
         info!("{:#?}", config);
         assert_eq!(config.processes.len(), 2);
         assert_eq!(config.processes[0].name, "dwm.exe");
-        assert_eq!(
-            config.processes[0].memory_threshold_bytes,
-            1000 * 1024 * 1024
-        );
+        // 默认配置不写 dwm 的阈值，运行时按物理内存计算
+        assert_eq!(config.processes[0].memory_threshold_bytes, 0);
         let mut config = config_manager.load_or_create_default();
         config.processes.push(MonitoredProcess {
             name: "RF_Guide.exe".to_string(),
             memory_threshold_bytes: 50 * 1024 * 1024,
             process_type: ProcessType::User("powershell -Command \"Start-Process -FilePath 'D:\\ISV\\rf_guide\\RF_Guide.exe' -WorkingDirectory 'D:\\ISV\\rf_guide'\"".to_string(), 1),
             auto_start: true,
+            actions: Vec::new(),
+            learn_threshold_hours: 0,
+            learn_threshold_factor: default_learn_threshold_factor(),
+            require_growth_samples: 0,
+            remote_session: None,
+            etw_trace: false,
         });
-        config_manager.save(&config);
+        config_manager.save(&config).unwrap();
         let config = config_manager.load_or_create_default();
         assert_eq!(config.processes.len(), 3);
         assert_eq!(config.processes[2].name, "RF_Guide.exe");
@@ -50,11 +54,53 @@ mod tests {
         let config = config_manager.load_or_create_default();
         assert_eq!(config.processes.len(), 2);
         assert_eq!(config.processes[0].name, "dwm.exe");
+        // 默认配置不写 dwm 的阈值，运行时按物理内存计算
+        assert_eq!(config.processes[0].memory_threshold_bytes, 0);
+        assert_eq!(config.interval_seconds, 60);
+        assert_eq!(config.sample_interval_seconds(true, false), 10);
+    }
+    #[test]
+    fn test_default_threshold_scales_with_memory() {
+        const GB: u64 = 1024 * 1024 * 1024;
+        assert_eq!(default_threshold_bytes(4 * GB), 4 * GB / 10);
+        assert_eq!(default_threshold_bytes(64 * GB), 4 * GB);
+
+        let mut config: Config = serde_json::from_str(DEFAULT_CONFIG_JSON).unwrap();
+        let photos_threshold = config.processes[1].memory_threshold_bytes;
+        config.resolve_default_thresholds(Some(16 * GB));
+        assert_eq!(config.processes[0].memory_threshold_bytes, 16 * GB / 10);
+        assert_eq!(config.processes[1].memory_threshold_bytes, photos_threshold);
+        // 未配置的阈值不写回配置文件
+        let mut config: Config = serde_json::from_str(DEFAULT_CONFIG_JSON).unwrap();
+        config.processes[1].memory_threshold_bytes = 0;
+        let text = serde_json::to_string(&config).unwrap();
+        assert!(!text.contains("memory_threshold_bytes"));
+        config.resolve_default_thresholds(None);
         assert_eq!(
             config.processes[0].memory_threshold_bytes,
             1000 * 1024 * 1024
         );
-        assert_eq!(config.interval_seconds, 60);
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("1048576"), Ok(1024 * 1024));
+        assert_eq!(parse_size("1500MB"), Ok(1500 * 1024 * 1024));
+        assert_eq!(parse_size("1.5gb"), Ok(1536 * 1024 * 1024));
+        assert_eq!(parse_size("512 KB"), Ok(512 * 1024));
+        assert!(parse_size("MB").is_err());
+        assert!(parse_size("10XB").is_err());
+        assert!(parse_size("0").is_err());
+        assert!(parse_size("0MB").is_err());
+    }
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90"), Ok(90));
+        assert_eq!(parse_duration("2h"), Ok(2 * 60 * 60));
+        assert_eq!(parse_duration("1.5h"), Ok(90 * 60));
+        assert_eq!(parse_duration("30m"), Ok(30 * 60));
+        assert_eq!(parse_duration("1d"), Ok(24 * 60 * 60));
+        assert!(parse_duration("2w").is_err());
     }
     #[test]
     fn test_get_all_process() {
@@ -73,9 +119,67 @@ mod tests {
                 memory_threshold_bytes: 1000 * 1024 * 1024,
                 process_type: ProcessType::System,
                 auto_start: false,
+                actions: Vec::new(),
+                learn_threshold_hours: 0,
+                learn_threshold_factor: default_learn_threshold_factor(),
+                require_growth_samples: 0,
+                remote_session: None,
+                etw_trace: false,
             }],
             interval_seconds: 10,
+            near_threshold_percent: default_near_threshold_percent(),
+            near_threshold_interval_seconds: default_near_threshold_interval_seconds(),
+            recovery_margin_percent: default_recovery_margin_percent(),
+            align_samples: false,
+            trim_own_working_set: false,
+            sampling_backend: SamplingBackend::Auto,
+            drop_privileges: true,
+            background_mode: true,
+            remote_control: false,
             db_config: default_db_config(),
+            update_url: String::new(),
+            report_url: String::new(),
+            report_interval_seconds: 60,
+            log_analytics_workspace_id: String::new(),
+            log_analytics_shared_key: String::new(),
+            log_analytics_log_type: "ProcessGuard".to_string(),
+            textfile_path: String::new(),
+            cloudwatch_namespace: String::new(),
+            cloudwatch_region: String::new(),
+            kafka_brokers: String::new(),
+            kafka_topic: String::new(),
+            kafka_properties: Default::default(),
+            grpc_address: String::new(),
+            grpc_tls_cert: String::new(),
+            grpc_tls_key: String::new(),
+            grpc_client_ca: String::new(),
+            grpc_token_file: String::new(),
+            pagerduty_routing_key: String::new(),
+            opsgenie_api_key: String::new(),
+            opsgenie_api_url: "https://api.opsgenie.com".to_string(),
+            restart_warning_seconds: 0,
+            restart_confirmation: false,
+            suppress_restarts_when_reboot_pending: false,
+            defer_restarts_on_battery: false,
+            urgent_restart_percent: default_urgent_restart_percent(),
+            battery_interval_seconds: 0,
+            defer_restarts_for_games_and_capture: false,
+            capture_processes: default_capture_processes(),
+            do_not_disturb_processes: Vec::new(),
+            do_not_disturb_requires_media: false,
+            allow_restart_opt_out: true,
+            etw_trace_dir: default_etw_trace_dir(),
+            etw_trace_max_minutes: default_etw_trace_max_minutes(),
+            etw_trace_max_files: default_etw_trace_max_files(),
+            etw_trace_max_mb: default_etw_trace_max_mb(),
+            rules_script: String::new(),
+            log_language: LogLanguage::ZhCn,
+            message_templates: String::new(),
+            maintenance_window: String::new(),
+            maintenance_window_weekdays: Default::default(),
+            holidays: Vec::new(),
+            restarts_only_in_maintenance_window: false,
+            quiet_hours: String::new(),
         };
         monitor_process(&config);
     }
@@ -0,0 +1,271 @@
+//! 为 windows_exporter 的 textfile 收集器输出指标：每隔 `interval_seconds` 把各进程的最新采样写入
+//! `textfile_path`（OpenMetrics 文本格式），已经在采集 windows_exporter 的环境无需新增端点。
+
+use std::fmt::Write as _;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::config_manager::Config;
+use crate::monitor::TargetState;
+use crate::monitor_state::{self, now_secs, MonitorStatus, ProcessStatus};
+use crate::sink::{run_sink, FailureLog, Sink};
+use crate::tr;
+
+const STATES: &[TargetState] = &[
+    TargetState::Discovering,
+    TargetState::Healthy,
+    TargetState::Warning,
+    TargetState::Breached,
+    TargetState::Restarting,
+    TargetState::Verifying,
+    TargetState::Backoff,
+];
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn header(out: &mut String, name: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+}
+
+// 每个进程一行
+fn per_process(
+    out: &mut String,
+    status: &MonitorStatus,
+    name: &str,
+    help: &str,
+    value: impl Fn(&ProcessStatus) -> Option<f64>,
+) {
+    header(out, name, help);
+    for process in &status.processes {
+        if let Some(value) = value(process) {
+            let _ = writeln!(
+                out,
+                "{}{{process=\"{}\"}} {}",
+                name,
+                escape_label(&process.name),
+                value
+            );
+        }
+    }
+}
+
+pub fn render(status: &MonitorStatus, now: u64) -> String {
+    let mut out = String::new();
+    let flag = |value: bool| if value { 1 } else { 0 };
+    header(
+        &mut out,
+        "process_guard_paused",
+        "Whether monitoring is paused.",
+    );
+    let _ = writeln!(out, "process_guard_paused {}", flag(status.paused));
+    header(
+        &mut out,
+        "process_guard_snoozed",
+        "Whether restarts are snoozed.",
+    );
+    let snoozed = status.snoozed_until.is_some_and(|until| now < until);
+    let _ = writeln!(out, "process_guard_snoozed {}", flag(snoozed));
+    if let Some(os_build) = &status.os_build {
+        header(
+            &mut out,
+            "process_guard_os_build_info",
+            "Windows build number.",
+        );
+        let _ = writeln!(
+            out,
+            "process_guard_os_build_info{{build=\"{}\"}} 1",
+            escape_label(os_build)
+        );
+    }
+    if let Some(count) = status.visible_windows {
+        header(
+            &mut out,
+            "process_guard_visible_windows",
+            "Visible top-level windows in the interactive session, reported by the tray helper.",
+        );
+        let _ = writeln!(out, "process_guard_visible_windows {}", count);
+    }
+    per_process(
+        &mut out,
+        status,
+        "process_guard_process_running",
+        "Whether the monitored process was found in the last sample.",
+        |p| Some(flag(p.pid.is_some()) as f64),
+    );
+    per_process(
+        &mut out,
+        status,
+        "process_guard_private_bytes",
+        "Private bytes (commit size) of the monitored process.",
+        |p| Some(p.private_bytes as f64),
+    );
+    per_process(
+        &mut out,
+        status,
+        "process_guard_working_set_bytes",
+        "Working set of the monitored process.",
+        |p| Some(p.working_set as f64),
+    );
+    per_process(
+        &mut out,
+        status,
+        "process_guard_threshold_bytes",
+        "Private bytes threshold that triggers a restart.",
+        |p| Some(p.memory_threshold_bytes as f64),
+    );
+    per_process(
+        &mut out,
+        status,
+        "process_guard_last_sample_timestamp_seconds",
+        "Unix time of the last sample.",
+        |p| p.last_sample.map(|t| t as f64),
+    );
+    per_process(
+        &mut out,
+        status,
+        "process_guard_last_restart_timestamp_seconds",
+        "Unix time of the last restart.",
+        |p| p.last_restart.map(|t| t as f64),
+    );
+    per_process(
+        &mut out,
+        status,
+        "process_guard_worker_restarts",
+        "Times the monitoring worker was restarted after crashing or stalling.",
+        |p| Some(p.worker_restarts as f64),
+    );
+    header(
+        &mut out,
+        "process_guard_state",
+        "Lifecycle state of the monitored process (1 for the current state).",
+    );
+    for process in &status.processes {
+        for state in STATES {
+            let _ = writeln!(
+                out,
+                "process_guard_state{{process=\"{}\",state=\"{:?}\"}} {}",
+                escape_label(&process.name),
+                state,
+                flag(process.state == *state)
+            );
+        }
+    }
+    if status.processes.iter().any(|p| !p.metrics.is_empty()) {
+        header(
+            &mut out,
+            "process_guard_plugin_metric",
+            "Values reported by metric plugins.",
+        );
+        for process in &status.processes {
+            for (metric, value) in &process.metrics {
+                let _ = writeln!(
+                    out,
+                    "process_guard_plugin_metric{{process=\"{}\",metric=\"{}\"}} {}",
+                    escape_label(&process.name),
+                    escape_label(metric),
+                    value
+                );
+            }
+        }
+    }
+    // OpenMetrics 要求以 EOF 行结尾
+    out.push_str("# EOF\n");
+    out
+}
+
+// 先写入临时文件再替换，收集器不会读到写了一半的文件；临时文件不以 .prom 结尾，不会被读取
+fn write(path: &Path, contents: &str) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    std::fs::write(&tmp, contents)?;
+    std::fs::rename(&tmp, path)
+}
+
+struct Exporter;
+
+impl Sink for Exporter {
+    fn interval_seconds(config: &Config) -> u64 {
+        config.interval_seconds
+    }
+
+    async fn round(&mut self, config: &Mutex<Config>, failures: &mut FailureLog) {
+        let path = config
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .textfile_path
+            .clone();
+        if path.is_empty() {
+            return;
+        }
+        let contents = render(&monitor_state::snapshot(), now_secs());
+        match write(Path::new(&path), &contents) {
+            Ok(()) => failures.succeeded(|| {
+                tr!(
+                    "已恢复写入指标文件 {}",
+                    "Resumed writing metrics file {}",
+                    path
+                )
+            }),
+            Err(e) => failures.failed(|| {
+                tr!(
+                    "写入指标文件 {} 失败: {}",
+                    "Failed to write metrics to {}: {}",
+                    path,
+                    e
+                )
+            }),
+        }
+    }
+}
+
+/// 未配置 `textfile_path` 时空转，配置重新加载后下一轮生效；服务停止时返回。
+pub async fn run(config: Arc<Mutex<Config>>) {
+    run_sink(config, Exporter).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_textfile() {
+        let status = MonitorStatus {
+            snoozed_until: Some(200),
+            os_build: Some("22621.1778".to_string()),
+            visible_windows: Some(12),
+            processes: vec![ProcessStatus {
+                name: "dwm.exe".to_string(),
+                pid: Some(7),
+                private_bytes: 1024,
+                memory_threshold_bytes: 4096,
+                last_restart: Some(50),
+                state: TargetState::Warning,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let text = render(&status, 100);
+        assert!(text.contains("process_guard_snoozed 1\n"));
+        assert!(text.contains("process_guard_os_build_info{build=\"22621.1778\"} 1\n"));
+        assert!(text.contains("process_guard_visible_windows 12\n"));
+        assert!(text.contains("process_guard_private_bytes{process=\"dwm.exe\"} 1024\n"));
+        assert!(
+            text.contains("process_guard_last_restart_timestamp_seconds{process=\"dwm.exe\"} 50\n")
+        );
+        assert!(text.contains("process_guard_state{process=\"dwm.exe\",state=\"Warning\"} 1\n"));
+        assert!(text.contains("process_guard_state{process=\"dwm.exe\",state=\"Healthy\"} 0\n"));
+        // 没有采样时间的进程不输出该指标
+        assert!(!text.contains("process_guard_last_sample_timestamp_seconds{"));
+        assert!(!text.contains("process_guard_plugin_metric"));
+        assert!(text.ends_with(" 0\n# EOF\n"));
+        assert_eq!(escape_label("a\"b\\c"), "a\\\"b\\\\c");
+    }
+}
@@ -0,0 +1,281 @@
+use base64::Engine;
+use log::{info, warn};
+use ring::signature::{UnparsedPublicKey, ED25519};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use crate::tr;
+use crate::version::VERSION;
+
+// 发布者的 Ed25519 公钥（base64），构建发布版本时通过环境变量 PROCESS_GUARD_UPDATE_PUBLIC_KEY
+// 写入程序；未设置时不能自动更新，清单和下载地址被篡改也无法替换程序
+const UPDATE_PUBLIC_KEY: Option<&str> = option_env!("PROCESS_GUARD_UPDATE_PUBLIC_KEY");
+
+// 更新清单，例如 GitHub Release 中附带的 update.json：
+// {"version": "0.2.0", "url": "https://.../process_guard.exe", "sha256": "...", "signature": "..."}
+#[derive(Deserialize, Debug)]
+pub struct UpdateManifest {
+    pub version: String,
+    pub url: String,
+    pub sha256: String,
+    // 用发布者私钥对 signed_payload(version, sha256) 的 Ed25519 签名（base64），
+    // 版本号和程序文件一起签名，清单被篡改时无法把旧版本标成新版本
+    pub signature: String,
+}
+
+pub enum UpdateResult {
+    UpToDate(String),
+    Updated(String),
+}
+
+fn parse_version(version: &str) -> Vec<u64> {
+    version
+        .trim_start_matches('v')
+        .split(['.', '-'])
+        .map_while(|part| part.parse().ok())
+        .collect()
+}
+
+pub fn is_newer(candidate: &str, current: &str) -> bool {
+    parse_version(candidate) > parse_version(current)
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+// 清单和程序只通过 https 下载
+pub fn require_https(url: &str) -> io::Result<()> {
+    let scheme = url.split_once("://").map(|(scheme, _)| scheme);
+    if scheme.is_some_and(|scheme| scheme.eq_ignore_ascii_case("https")) {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("update URLs must use https: {}", url),
+        ))
+    }
+}
+
+// 签名的内容：版本号和小写的 sha256，以换行分隔
+pub fn signed_payload(version: &str, sha256: &str) -> String {
+    format!("{}\n{}", version.trim(), sha256.trim().to_ascii_lowercase())
+}
+
+// 用固定的公钥校验签名
+pub fn verify_signature(data: &[u8], signature: &str, public_key: &str) -> io::Result<()> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let engine = base64::engine::general_purpose::STANDARD;
+    let public_key = engine
+        .decode(public_key.trim())
+        .map_err(|_| invalid("invalid update public key"))?;
+    let signature = engine
+        .decode(signature.trim())
+        .map_err(|_| invalid("invalid signature encoding"))?;
+    UnparsedPublicKey::new(&ED25519, public_key)
+        .verify(data, &signature)
+        .map_err(|_| invalid("signature verification failed"))
+}
+
+// 校验清单的签名；通过后清单中的版本号和 sha256 才可信，下载的程序再与 sha256 比较
+pub fn verify_manifest(manifest: &UpdateManifest, public_key: &str) -> io::Result<()> {
+    verify_signature(
+        signed_payload(&manifest.version, &manifest.sha256).as_bytes(),
+        &manifest.signature,
+        public_key,
+    )
+}
+
+fn http_error(e: ureq::Error) -> io::Error {
+    io::Error::other(e.to_string())
+}
+
+fn fetch_manifest(url: &str) -> io::Result<UpdateManifest> {
+    let response = ureq::get(url).call().map_err(http_error)?;
+    serde_json::from_reader(response.into_reader()).map_err(io::Error::other)
+}
+
+fn download(url: &str) -> io::Result<Vec<u8>> {
+    let response = ureq::get(url).call().map_err(http_error)?;
+    let mut data = Vec::new();
+    response.into_reader().read_to_end(&mut data)?;
+    Ok(data)
+}
+
+fn sibling_path(exe: &Path, extension: &str) -> PathBuf {
+    let mut name = exe.file_name().unwrap_or_default().to_os_string();
+    name.push(extension);
+    exe.with_file_name(name)
+}
+
+pub fn leftover_paths() -> Vec<PathBuf> {
+    match std::env::current_exe() {
+        Ok(exe) => vec![sibling_path(&exe, ".old"), sibling_path(&exe, ".new")],
+        Err(e) => {
+            warn!(
+                "{}",
+                tr!("无法定位程序: {}", "Failed to locate the program: {}", e)
+            );
+            Vec::new()
+        }
+    }
+}
+
+// 上次更新留下的旧版本文件在新版本启动后才能删除
+pub fn remove_previous_binary() {
+    let old = match std::env::current_exe() {
+        Ok(exe) => sibling_path(&exe, ".old"),
+        Err(e) => {
+            warn!(
+                "{}",
+                tr!("无法定位程序: {}", "Failed to locate the program: {}", e)
+            );
+            return;
+        }
+    };
+    if old.exists() {
+        match std::fs::remove_file(&old) {
+            Ok(()) => info!(
+                "{}",
+                tr!(
+                    "已删除旧版本程序 {}",
+                    "Removed previous binary {}",
+                    old.display()
+                )
+            ),
+            Err(e) => warn!(
+                "{}",
+                tr!(
+                    "删除 {} 失败: {}",
+                    "Failed to remove {}: {}",
+                    old.display(),
+                    e
+                )
+            ),
+        }
+    }
+}
+
+// 下载并校验新版本后替换当前程序，正在运行的 exe 不能覆盖但可以重命名；开始下载前用清单调用
+// `downloading`，由调用方显示进度。`force` 时重新安装相同的版本，但不会安装更旧的版本
+pub fn update(
+    manifest_url: &str,
+    force: bool,
+    downloading: impl FnOnce(&UpdateManifest),
+) -> io::Result<UpdateResult> {
+    let public_key = UPDATE_PUBLIC_KEY.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            "this build has no update signing key, install new versions manually",
+        )
+    })?;
+    require_https(manifest_url)?;
+    let manifest = fetch_manifest(manifest_url)?;
+    verify_manifest(&manifest, public_key)?;
+    if is_newer(VERSION, &manifest.version) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "refusing to downgrade from {} to {}",
+                VERSION, manifest.version
+            ),
+        ));
+    }
+    if !force && !is_newer(&manifest.version, VERSION) {
+        return Ok(UpdateResult::UpToDate(manifest.version));
+    }
+    require_https(&manifest.url)?;
+    downloading(&manifest);
+    let data = download(&manifest.url)?;
+    let checksum = sha256_hex(&data);
+    if !checksum.eq_ignore_ascii_case(manifest.sha256.trim()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "checksum mismatch: expected {}, got {}",
+                manifest.sha256, checksum
+            ),
+        ));
+    }
+
+    let exe = std::env::current_exe()?;
+    let new = sibling_path(&exe, ".new");
+    let old = sibling_path(&exe, ".old");
+    std::fs::write(&new, &data)?;
+    let _ = std::fs::remove_file(&old);
+    std::fs::rename(&exe, &old)?;
+    if let Err(e) = std::fs::rename(&new, &exe) {
+        // 还原旧版本
+        let _ = std::fs::rename(&old, &exe);
+        return Err(e);
+    }
+    Ok(UpdateResult::Updated(manifest.version))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_newer() {
+        assert!(is_newer("0.2.0", "0.1.9"));
+        assert!(is_newer("v1.0.0", "0.9.0"));
+        assert!(is_newer("0.1.10", "0.1.9"));
+        assert!(!is_newer("0.1.0", "0.1.0"));
+        assert!(!is_newer("0.1.0-beta", "0.1.1"));
+    }
+
+    #[test]
+    fn test_sha256_hex() {
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_require_https() {
+        assert!(require_https("https://example.com/update.json").is_ok());
+        assert!(require_https("HTTPS://example.com/update.json").is_ok());
+        assert!(require_https("http://example.com/update.json").is_err());
+        assert!(require_https("example.com/update.json").is_err());
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        // RFC 8032 7.1 的测试向量 2
+        let public_key = "PUAXw+hDiVqStwqnTRt+vJyYLM8uxJaMwM1V8Sr0Zgw=";
+        let signature =
+            "kqAJqfDUyrhyDoILX2QlQKKye1QWUD+Ps3YiI+vbadoIWsHkPhWZbkWPNhPQ8R2MOHsurrQwKu6wDSkWErsMAA==";
+        assert!(verify_signature(&[0x72], signature, public_key).is_ok());
+        assert!(verify_signature(&[0x73], signature, public_key).is_err());
+        assert!(verify_signature(&[0x72], "not base64", public_key).is_err());
+    }
+
+    #[test]
+    fn test_verify_manifest_covers_the_version() {
+        use ring::signature::{Ed25519KeyPair, KeyPair};
+        let engine = base64::engine::general_purpose::STANDARD;
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(&[7; 32]).unwrap();
+        let public_key = engine.encode(key_pair.public_key());
+        let sha256 = sha256_hex(b"process_guard.exe");
+        let mut manifest = UpdateManifest {
+            version: "0.2.0".to_string(),
+            url: "https://example.com/process_guard.exe".to_string(),
+            sha256: sha256.to_ascii_uppercase(),
+            signature: engine.encode(key_pair.sign(signed_payload("0.2.0", &sha256).as_bytes())),
+        };
+        assert!(verify_manifest(&manifest, &public_key).is_ok());
+        // 旧版本的签名不能用于新的版本号
+        manifest.version = "9.0.0".to_string();
+        assert!(verify_manifest(&manifest, &public_key).is_err());
+        manifest.version = "0.2.0".to_string();
+        manifest.sha256 = sha256_hex(b"other.exe");
+        assert!(verify_manifest(&manifest, &public_key).is_err());
+    }
+}
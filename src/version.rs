@@ -0,0 +1,12 @@
+// 由 build.rs 在编译时写入
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+pub const GIT_COMMIT: &str = env!("GIT_COMMIT");
+pub const BUILD_DATE: &str = env!("BUILD_DATE");
+pub const BUILD_TARGET: &str = env!("BUILD_TARGET");
+
+pub fn version_banner() -> String {
+    format!(
+        "process_guard {} (commit {}, built {}, {})",
+        VERSION, GIT_COMMIT, BUILD_DATE, BUILD_TARGET
+    )
+}
@@ -0,0 +1,129 @@
+//! 看门狗线程：检查每个被监控进程是否按时完成采样，停滞时（例如卡在等待进程重启）记录错误、
+//! 在状态中标记，并通知 supervisor 重新启动对应的监控任务。
+
+use log::{error, info};
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::config_manager::Config;
+use crate::etw_trace::WPR_TIMEOUT;
+use crate::monitor_state::{self, now_secs};
+use crate::process_name;
+use crate::tr;
+
+const CHECK_PERIOD: Duration = Duration::from_secs(30);
+// 一次重启流程最长约 40 秒，再留出余量
+const RESTART_ALLOWANCE_SECS: u64 = 120;
+
+pub fn spawn(config: Arc<Mutex<Config>>, stalls: UnboundedSender<String>) -> io::Result<()> {
+    thread::Builder::new()
+        .name("watchdog".to_string())
+        .spawn(move || run(&config, &stalls))?;
+    Ok(())
+}
+
+// 允许多久没有新的采样：两个采样间隔加上一次重启的时间，重启前还要等待用户看到提示或作出选择。
+// 使用电池供电时按 battery_interval_seconds 采样，按较长的间隔计算。
+// 重启前保存 ETW 跟踪时，wpr 停止超时后还要取消跟踪，最多再等两次 WPR_TIMEOUT
+fn stall_allowance(config: &Config) -> u64 {
+    let interval_seconds = config.interval_seconds.max(config.battery_interval_seconds);
+    let trace_seconds = if config.processes.iter().any(|process| process.etw_trace) {
+        WPR_TIMEOUT.as_secs() * 2
+    } else {
+        0
+    };
+    interval_seconds * 2 + RESTART_ALLOWANCE_SECS + config.restart_warning_seconds + trace_seconds
+}
+
+// 超过允许的时间仍没有新的采样，认为监控已停滞
+fn is_stalled(last_sample: Option<u64>, since: u64, allowance: u64, now: u64) -> bool {
+    let last = last_sample.unwrap_or(0).max(since);
+    now.saturating_sub(last) > allowance
+}
+
+fn run(config: &Mutex<Config>, stalls: &UnboundedSender<String>) {
+    info!("{}", tr!("看门狗已启动", "Watchdog started"));
+    // 每个进程的计时起点：启动、暂停或上次恢复的时间，早于此的采样不计
+    let mut since: HashMap<String, u64> = HashMap::new();
+    let started = now_secs();
+    while monitor_state::sleep_unless_shutdown(CHECK_PERIOD) {
+        let now = now_secs();
+        let (names, allowance): (Vec<String>, u64) = {
+            let config = config.lock().unwrap_or_else(PoisonError::into_inner);
+            (
+                config.processes.iter().map(|p| p.name.clone()).collect(),
+                stall_allowance(&config),
+            )
+        };
+        if monitor_state::is_paused() {
+            for name in names {
+                since.insert(name, now);
+            }
+            continue;
+        }
+        let status = monitor_state::snapshot();
+        for name in names {
+            let last_sample = status
+                .processes
+                .iter()
+                .find(|p| process_name::eq(&p.name, &name))
+                .and_then(|p| p.last_sample);
+            let start = *since.get(&name).unwrap_or(&started);
+            if !is_stalled(last_sample, start, allowance, now) {
+                continue;
+            }
+            error!(
+                "{}",
+                tr!(
+                    "!!! {} 已有 {} 秒没有完成采样，监控任务可能已卡住，正在重新启动该任务",
+                    "!!! {} has not completed a sample in {} seconds, its monitoring task may be stuck, restarting it",
+                    name,
+                    now - last_sample.unwrap_or(0).max(start)
+                )
+            );
+            monitor_state::set_stalled(&name);
+            if stalls.send(name.clone()).is_err() {
+                // supervisor 已退出
+                return;
+            }
+            since.insert(name, now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_stalled() {
+        assert!(!is_stalled(Some(1000), 0, 240, 1240));
+        assert!(is_stalled(Some(1000), 0, 240, 1241));
+        // 从未采样时从启动时间开始计算
+        assert!(!is_stalled(None, 1000, 240, 1100));
+        assert!(is_stalled(None, 1000, 240, 1300));
+        // 恢复之后重新计时
+        assert!(!is_stalled(Some(1000), 2000, 240, 2100));
+    }
+
+    #[test]
+    fn test_stall_allowance() {
+        let mut config: Config =
+            serde_json::from_str(crate::config_manager::DEFAULT_CONFIG_JSON).unwrap();
+        config.interval_seconds = 60;
+        config.battery_interval_seconds = 0;
+        config.restart_warning_seconds = 0;
+        // 间隔 60 秒时允许 240 秒没有采样
+        assert_eq!(stall_allowance(&config), 240);
+        config.battery_interval_seconds = 300;
+        assert_eq!(stall_allowance(&config), 720);
+        config.restart_warning_seconds = 30;
+        assert_eq!(stall_allowance(&config), 750);
+        config.processes[0].etw_trace = true;
+        assert_eq!(stall_allowance(&config), 990);
+    }
+}